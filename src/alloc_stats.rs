@@ -0,0 +1,71 @@
+#![allow(unsafe_code)]
+
+//! Hooks that let embedders observe sled's large allocations.
+//!
+//! Embedders that run a custom global allocator (jemalloc, mimalloc,
+//! etc.) often want to attribute a process's memory use to the
+//! subsystems responsible for it. When the `alloc_stats` feature is
+//! enabled, sled reports its large allocations - node buffers, remote
+//! `IVec` backing stores, and cache admissions - to a process-wide
+//! callback registered with [`set_allocation_callback`], tagged with
+//! an [`AllocationKind`] so that they can be tallied separately.
+//!
+//! The callback is invoked from hot paths, so it should do little
+//! more than bump counters.
+
+use std::sync::atomic::{
+    AtomicPtr,
+    Ordering::{Acquire, Release},
+};
+
+/// The category of a large allocation reported to the
+/// allocation callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationKind {
+    /// An immutable tree node buffer, allocated at exactly
+    /// its computed size.
+    NodeBuffer,
+    /// The reference-counted backing store of a remote (non-inline)
+    /// `IVec` or other shared buffer, including its header and
+    /// alignment padding.
+    IVec,
+    /// A page admitted to the in-memory cache; the reported size is
+    /// the total size of the page's log fragments.
+    CacheEntry,
+}
+
+/// A callback invoked with the kind and size in bytes of each large
+/// allocation sled performs. It may be called concurrently from many
+/// threads.
+pub type AllocationCallback = fn(kind: AllocationKind, size: usize);
+
+// the callback is stored behind a data pointer rather than being
+// cast to an integer, as nothing guarantees that function pointers
+// and integers share a representation on every supported target.
+// replaced callbacks are intentionally leaked (one word each), so
+// that a pointer observed by a concurrent `record` call remains
+// valid forever.
+static CALLBACK: AtomicPtr<AllocationCallback> =
+    AtomicPtr::new(std::ptr::null_mut());
+
+/// Registers a process-wide callback that will be invoked for every
+/// large allocation sled performs from this point on, replacing any
+/// previously registered callback.
+pub fn set_allocation_callback(callback: AllocationCallback) {
+    CALLBACK.store(Box::into_raw(Box::new(callback)), Release);
+}
+
+/// Removes the registered allocation callback, if any.
+pub fn clear_allocation_callback() {
+    CALLBACK.store(std::ptr::null_mut(), Release);
+}
+
+pub(crate) fn record(kind: AllocationKind, size: usize) {
+    let ptr = CALLBACK.load(Acquire);
+    if !ptr.is_null() {
+        // registered callbacks are never freed, so a non-null
+        // pointer is always valid to read.
+        let callback: AllocationCallback = unsafe { *ptr };
+        callback(kind, size);
+    }
+}
@@ -0,0 +1,242 @@
+//! An append-only, sequence-indexed log layered over a hidden
+//! tree, tailored to append-mostly, scan-by-time workloads.
+//!
+//! Entries are keyed by a monotonically increasing sequence
+//! number, so appends always land at the right edge of the tree
+//! and writes go straight to the write-ahead log without
+//! disturbing existing pages. Each entry carries the wall-clock
+//! time it was appended, and because timestamps are kept
+//! non-decreasing, seeking to a point in time is a binary search
+//! over the sequence space rather than a scan. Old entries are
+//! reclaimed from the front with
+//! [`truncate_before`](AppendLog::truncate_before).
+
+use std::convert::TryFrom;
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::Ordering::SeqCst;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::arc::Arc;
+use crate::atomic_shim::AtomicU64;
+use crate::{pin, Batch, IVec, Result, Tree};
+
+const TRUNCATE_BATCH_SIZE: usize = 1024;
+
+/// A single entry read back from an [`AppendLog`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    /// The sequence number assigned by
+    /// [`append`](AppendLog::append).
+    pub seq: u64,
+    /// Milliseconds since the unix epoch when the entry was
+    /// appended.
+    pub timestamp_ms: u64,
+    /// The appended bytes.
+    pub value: IVec,
+}
+
+/// An append-only log opened via `Db::open_append_log`, for
+/// workloads that write immutable records and read them back in
+/// order or by time: event streams, change feeds, metrics.
+///
+/// Entries are assigned dense, monotonically increasing sequence
+/// numbers and stamped with a non-decreasing wall-clock time.
+/// They can be read individually by sequence, scanned by
+/// sequence range, or scanned from a point in time via
+/// [`scan_since`](AppendLog::scan_since). The only supported
+/// deletion is trimming a prefix of the log with
+/// [`truncate_before`](AppendLog::truncate_before).
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// let events = db.open_append_log("events")?;
+///
+/// let first = events.append(b"started")?;
+/// let second = events.append(b"finished")?;
+/// assert_eq!(second, first + 1);
+///
+/// assert_eq!(&events.get(first)?.unwrap(), b"started");
+///
+/// let entries: Vec<_> = events.scan(..).collect::<Result<_, _>>()?;
+/// assert_eq!(entries.len(), 2);
+/// assert_eq!(&entries[1].value, b"finished");
+///
+/// // trim everything before the second entry
+/// assert_eq!(events.truncate_before(second)?, 1);
+/// assert_eq!(events.get(first)?, None);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AppendLog {
+    pub(crate) tree: Tree,
+    next_seq: Arc<AtomicU64>,
+    last_ts: Arc<AtomicU64>,
+}
+
+impl AppendLog {
+    /// Recovers the sequence and timestamp high-water marks from
+    /// the last entry, if the log already contains data.
+    pub(crate) fn open(tree: Tree) -> Result<AppendLog> {
+        let (next_seq, last_ts) = match tree.last()? {
+            Some((key, value)) => {
+                (decode_seq(&key) + 1, decode_timestamp(&value))
+            }
+            None => (0, 0),
+        };
+
+        Ok(AppendLog {
+            tree,
+            next_seq: Arc::new(AtomicU64::new(next_seq)),
+            last_ts: Arc::new(AtomicU64::new(last_ts)),
+        })
+    }
+
+    /// Appends an entry to the log, returning its sequence
+    /// number.
+    pub fn append(&self, value: &[u8]) -> Result<u64> {
+        let seq = self.next_seq.fetch_add(1, SeqCst);
+        let ts = self.monotonic_now();
+
+        let mut record = Vec::with_capacity(8 + value.len());
+        record.extend_from_slice(&ts.to_le_bytes());
+        record.extend_from_slice(value);
+
+        self.tree.insert(seq.to_be_bytes(), record)?;
+        Ok(seq)
+    }
+
+    /// Retrieves the entry with the given sequence number, if it
+    /// has not been truncated.
+    pub fn get(&self, seq: u64) -> Result<Option<IVec>> {
+        let record = self.tree.get(seq.to_be_bytes())?;
+        Ok(record.map(|record| record.subslice(8, record.len() - 8)))
+    }
+
+    /// Returns the sequence number that the next appended entry
+    /// will be assigned.
+    pub fn next_seq(&self) -> u64 {
+        self.next_seq.load(SeqCst)
+    }
+
+    /// Iterates over entries whose sequence numbers fall in the
+    /// given range, in sequence order.
+    pub fn scan<R: RangeBounds<u64>>(
+        &self,
+        range: R,
+    ) -> impl DoubleEndedIterator<Item = Result<LogEntry>> {
+        let start = match range.start_bound() {
+            Bound::Included(seq) => Bound::Included(seq.to_be_bytes()),
+            Bound::Excluded(seq) => Bound::Excluded(seq.to_be_bytes()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(seq) => Bound::Included(seq.to_be_bytes()),
+            Bound::Excluded(seq) => Bound::Excluded(seq.to_be_bytes()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        self.tree.range((start, end)).map(|res| {
+            res.map(|(key, value)| LogEntry {
+                seq: decode_seq(&key),
+                timestamp_ms: decode_timestamp(&value),
+                value: value.subslice(8, value.len() - 8),
+            })
+        })
+    }
+
+    /// Iterates over entries appended at or after the given
+    /// number of milliseconds since the unix epoch. Because
+    /// timestamps are non-decreasing in sequence order, the
+    /// starting point is found with a binary search over the
+    /// sequence space rather than a scan.
+    pub fn scan_since(
+        &self,
+        unix_millis: u64,
+    ) -> Result<impl DoubleEndedIterator<Item = Result<LogEntry>>> {
+        let mut lo = match self.tree.first()? {
+            Some((key, _)) => decode_seq(&key),
+            None => 0,
+        };
+        let mut hi = self.next_seq.load(SeqCst);
+
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.tree.get(mid.to_be_bytes())? {
+                Some(record) if decode_timestamp(&record) >= unix_millis => {
+                    hi = mid;
+                }
+                // older than the cutoff, or concurrently truncated
+                _ => lo = mid + 1,
+            }
+        }
+
+        Ok(self.scan(lo..))
+    }
+
+    /// Removes all entries with sequence numbers below `seq`,
+    /// returning how many were removed. Deletion is paced in
+    /// batches so that large truncations do not stall concurrent
+    /// appends.
+    pub fn truncate_before(&self, seq: u64) -> Result<u64> {
+        let mut removed = 0;
+
+        loop {
+            let mut batch = Batch::default();
+            let mut batched = 0;
+
+            for res in self.scan(..seq).take(TRUNCATE_BATCH_SIZE) {
+                let entry = res?;
+                batch.remove(&entry.seq.to_be_bytes()[..]);
+                batched += 1;
+            }
+
+            if batched == 0 {
+                return Ok(removed);
+            }
+
+            self.tree.apply_batch(batch)?;
+            removed += batched;
+
+            // give the epoch-based reclamation system a chance to
+            // free what we just removed before queueing more
+            pin().flush();
+            std::thread::yield_now();
+        }
+    }
+
+    /// Returns the current time in unix milliseconds, clamped so
+    /// that entry timestamps never decrease in sequence order.
+    fn monotonic_now(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| u64::try_from(d.as_millis()).unwrap_or(u64::max_value()))
+            .unwrap_or(0);
+
+        let mut last = self.last_ts.load(SeqCst);
+        loop {
+            if now <= last {
+                return last;
+            }
+            match self.last_ts.compare_exchange(last, now, SeqCst, SeqCst) {
+                Ok(_) => return now,
+                Err(actual) => last = actual,
+            }
+        }
+    }
+}
+
+fn decode_seq(key: &IVec) -> u64 {
+    let mut arr = [0; 8];
+    arr.copy_from_slice(&key[..8]);
+    u64::from_be_bytes(arr)
+}
+
+fn decode_timestamp(record: &IVec) -> u64 {
+    let mut arr = [0; 8];
+    arr.copy_from_slice(&record[..8]);
+    u64::from_le_bytes(arr)
+}
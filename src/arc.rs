@@ -47,10 +47,10 @@ impl<T> Arc<T> {
     // "Unsafe because the caller must either take ownership or bind `T: Copy`"
     unsafe fn copy_from_slice(s: &[T]) -> Arc<[T]> {
         let align =
-            std::cmp::max(mem::align_of::<T>(), mem::align_of::<AtomicUsize>());
+            std::cmp::max(align_of::<T>(), align_of::<AtomicUsize>());
 
-        let rc_width = std::cmp::max(align, mem::size_of::<AtomicUsize>());
-        let data_width = mem::size_of::<T>().checked_mul(s.len()).unwrap();
+        let rc_width = std::cmp::max(align, size_of::<AtomicUsize>());
+        let data_width = size_of::<T>().checked_mul(s.len()).unwrap();
 
         let size_unpadded = rc_width.checked_add(data_width).unwrap();
         // Pad size out to alignment
@@ -58,6 +58,12 @@ impl<T> Arc<T> {
 
         let layout = Layout::from_size_align(size_padded, align).unwrap();
 
+        #[cfg(feature = "alloc_stats")]
+        crate::alloc_stats::record(
+            crate::alloc_stats::AllocationKind::IVec,
+            size_padded,
+        );
+
         let ptr = alloc(layout);
 
         assert!(!ptr.is_null(), "failed to allocate Arc");
@@ -93,9 +99,9 @@ impl<T> Arc<T> {
 
     pub unsafe fn from_raw(ptr: *const T) -> Arc<T> {
         let align =
-            std::cmp::max(mem::align_of::<T>(), mem::align_of::<AtomicUsize>());
+            std::cmp::max(align_of::<T>(), align_of::<AtomicUsize>());
 
-        let rc_width = std::cmp::max(align, mem::size_of::<AtomicUsize>());
+        let rc_width = std::cmp::max(align, size_of::<AtomicUsize>());
 
         let sub_ptr = (ptr as *const u8).sub(rc_width) as *mut ArcInner<T>;
 
@@ -178,15 +184,22 @@ impl<T> From<Box<[T]>> for Arc<[T]> {
             let value_layout = Layout::for_value(&*src);
             let align = std::cmp::max(
                 value_layout.align(),
-                mem::align_of::<AtomicUsize>(),
+                align_of::<AtomicUsize>(),
             );
-            let rc_width = std::cmp::max(align, mem::size_of::<AtomicUsize>());
+            let rc_width = std::cmp::max(align, size_of::<AtomicUsize>());
             let unpadded_size =
                 rc_width.checked_add(value_layout.size()).unwrap();
             // pad the total `Arc` allocation size to the alignment of
             // `max(value, AtomicUsize)`
             let size = (unpadded_size + align - 1) & !(align - 1);
             let dst_layout = Layout::from_size_align(size, align).unwrap();
+
+            #[cfg(feature = "alloc_stats")]
+            crate::alloc_stats::record(
+                crate::alloc_stats::AllocationKind::IVec,
+                size,
+            );
+
             let dst = alloc(dst_layout);
             assert!(!dst.is_null(), "failed to allocate Arc");
 
@@ -0,0 +1,76 @@
+//! Scratch-memory arena for transaction write sets and batch
+//! internals.
+//!
+//! Every transaction attempt needs a write set and a read cache
+//! per participating tree, and a conflicted transaction rebuilds
+//! them from nothing on each retry. For large transactions the
+//! container allocations behind those maps show up prominently
+//! in profiles. This module keeps a small thread-local arena of
+//! recycled scratch structures: the transaction machinery takes
+//! them at the start of an attempt and resets them back at
+//! commit or abort, so the backing memory is reused by the next
+//! attempt on the same thread instead of being reallocated.
+//!
+//! The keys and values staged inside a write set are installed
+//! into tree nodes when the transaction commits and outlive it,
+//! so their bytes cannot live in the arena; only the scratch
+//! containers are recycled.
+
+use std::cell::RefCell;
+
+use crate::{Batch, IVec, Map};
+
+// an arena holds at most this many of each scratch structure,
+// and refuses to retain one whose capacity grew past this many
+// entries, so a single huge transaction cannot pin memory on
+// every thread that later touches the arena
+const MAX_POOLED_STRUCTURES: usize = 4;
+const MAX_RETAINED_ENTRIES: usize = 4096;
+
+thread_local! {
+    static BATCHES: RefCell<Vec<Batch>> = RefCell::new(Vec::new());
+    static READ_CACHES: RefCell<Vec<Map<IVec, Option<IVec>>>> =
+        RefCell::new(Vec::new());
+}
+
+pub(crate) fn take_batch() -> Batch {
+    BATCHES
+        .with(|pool| pool.borrow_mut().pop())
+        .unwrap_or_default()
+}
+
+pub(crate) fn take_read_cache() -> Map<IVec, Option<IVec>> {
+    READ_CACHES
+        .with(|pool| pool.borrow_mut().pop())
+        .unwrap_or_default()
+}
+
+pub(crate) fn recycle_batch(mut batch: Batch) {
+    if batch.writes.len() > MAX_RETAINED_ENTRIES
+        || batch.ttls.len() > MAX_RETAINED_ENTRIES
+    {
+        return;
+    }
+    batch.writes.clear();
+    batch.ttls.clear();
+    batch.range_removes.clear();
+    BATCHES.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        if pool.len() < MAX_POOLED_STRUCTURES {
+            pool.push(batch);
+        }
+    });
+}
+
+pub(crate) fn recycle_read_cache(mut read_cache: Map<IVec, Option<IVec>>) {
+    if read_cache.len() > MAX_RETAINED_ENTRIES {
+        return;
+    }
+    read_cache.clear();
+    READ_CACHES.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        if pool.len() < MAX_POOLED_STRUCTURES {
+            pool.push(read_cache);
+        }
+    });
+}
@@ -0,0 +1,212 @@
+//! Async wrappers for use from `tokio` services, enabled by the
+//! `tokio` feature.
+//!
+//! sled's operations are blocking: reads may fault pages in from
+//! disk and writes may stall on segment rotation, so calling them
+//! directly on an async executor thread can starve unrelated
+//! tasks. [`AsyncDb`] and [`AsyncTree`] wrap their blocking
+//! counterparts and route every call through a spawner —
+//! `tokio::task::spawn_blocking` by default — so async services
+//! don't have to wrap each call site by hand. A custom [`Spawn`]
+//! implementation can redirect the blocking work to a dedicated
+//! thread pool instead.
+
+use std::sync::Arc;
+
+use crate::{
+    oneshot::OneShot, Batch, Db, Error, IVec, Result, Subscriber, Tree,
+};
+
+/// Routes blocking closures off the async executor. Implement
+/// this to run an [`AsyncDb`]'s blocking work on a thread pool of
+/// your choosing rather than `tokio::task::spawn_blocking`.
+pub trait Spawn: Send + Sync + 'static {
+    /// Runs the provided closure on a thread where blocking is
+    /// acceptable. The closure must eventually be executed (or
+    /// dropped), or futures awaiting its result will hang.
+    fn spawn_blocking(&self, work: Box<dyn FnOnce() + Send>);
+}
+
+// the default spawner. requires a tokio runtime context at call
+// time, like `tokio::task::spawn_blocking` itself.
+struct TokioSpawner;
+
+impl Spawn for TokioSpawner {
+    fn spawn_blocking(&self, work: Box<dyn FnOnce() + Send>) {
+        drop(tokio::task::spawn_blocking(work));
+    }
+}
+
+fn spawner_dropped_work<T>() -> Result<T> {
+    Err(Error::ReportableBug(
+        "the configured spawner dropped a blocking \
+         work item without running it"
+            .to_string(),
+    ))
+}
+
+/// An async view of a [`Db`], created via [`AsyncDb::new`] or
+/// [`AsyncDb::with_spawner`]. Derefs to an [`AsyncTree`] over the
+/// default keyspace, mirroring how [`Db`] derefs to [`Tree`].
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// let rt = tokio::runtime::Builder::new_current_thread().build()?;
+/// rt.block_on(async {
+///     let async_db = sled::AsyncDb::new(db);
+///
+///     async_db.insert(b"k1", b"v1").await?;
+///     assert_eq!(
+///         async_db.get(b"k1").await?,
+///         Some(sled::IVec::from(b"v1")),
+///     );
+///     async_db.flush().await?;
+///
+///     let tree = async_db.open_tree(b"index").await?;
+///     tree.insert(b"k2", b"v2").await?;
+///     Ok::<_, sled::Error>(())
+/// })?;
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct AsyncDb {
+    db: Db,
+    default: AsyncTree,
+}
+
+impl AsyncDb {
+    /// Wraps a [`Db`], routing blocking work through
+    /// `tokio::task::spawn_blocking`. Calls must be made from
+    /// within a tokio runtime context.
+    pub fn new(db: Db) -> AsyncDb {
+        AsyncDb::with_spawner(db, Arc::new(TokioSpawner))
+    }
+
+    /// Wraps a [`Db`], routing blocking work through the
+    /// provided spawner instead of
+    /// `tokio::task::spawn_blocking`.
+    pub fn with_spawner(db: Db, spawner: Arc<dyn Spawn>) -> AsyncDb {
+        let default = AsyncTree { tree: (*db).clone(), spawner };
+        AsyncDb { db, default }
+    }
+
+    /// Opens or creates a new disk-backed [`AsyncTree`] with its
+    /// own keyspace, accessible from the `Db` via the provided
+    /// identifier.
+    pub async fn open_tree<V: AsRef<[u8]>>(
+        &self,
+        name: V,
+    ) -> Result<AsyncTree> {
+        let db = self.db.clone();
+        let name = name.as_ref().to_vec();
+        let tree =
+            self.default.run(move || db.open_tree(name)).await?;
+        Ok(AsyncTree { tree, spawner: self.default.spawner.clone() })
+    }
+
+    /// Returns the inner [`Db`] for blocking use.
+    pub fn into_inner(self) -> Db {
+        self.db
+    }
+}
+
+impl std::fmt::Debug for AsyncDb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncDb").field("db", &self.db).finish()
+    }
+}
+
+impl std::ops::Deref for AsyncDb {
+    type Target = AsyncTree;
+
+    fn deref(&self) -> &AsyncTree {
+        &self.default
+    }
+}
+
+/// An async view of a [`Tree`] whose `get`, `insert`, `remove`,
+/// `apply_batch`, and `flush` return futures, with the blocking
+/// work running on the owning [`AsyncDb`]'s spawner.
+#[derive(Clone)]
+pub struct AsyncTree {
+    tree: Tree,
+    spawner: Arc<dyn Spawn>,
+}
+
+impl std::fmt::Debug for AsyncTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncTree").field("tree", &self.tree).finish()
+    }
+}
+
+impl AsyncTree {
+    async fn run<F, T>(&self, work: F) -> Result<T>
+    where
+        F: FnOnce() -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let (filler, future) = OneShot::pair();
+        self.spawner.spawn_blocking(Box::new(move || filler.fill(work())));
+        match future.await {
+            Some(result) => result,
+            None => spawner_dropped_work(),
+        }
+    }
+
+    /// Retrieve a value from the `Tree` if it exists.
+    pub async fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        let tree = self.tree.clone();
+        let key = IVec::from(key.as_ref());
+        self.run(move || tree.get(key)).await
+    }
+
+    /// Insert a key to a new value, returning the last value if
+    /// it was set.
+    pub async fn insert<K, V>(&self, key: K, value: V) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: Into<IVec>,
+    {
+        let tree = self.tree.clone();
+        let key = IVec::from(key.as_ref());
+        let value = value.into();
+        self.run(move || tree.insert(key, value)).await
+    }
+
+    /// Delete a value, returning the old value if it existed.
+    pub async fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        let tree = self.tree.clone();
+        let key = IVec::from(key.as_ref());
+        self.run(move || tree.remove(key)).await
+    }
+
+    /// Atomically apply multiple inserts and removals.
+    pub async fn apply_batch(&self, batch: Batch) -> Result<()> {
+        let tree = self.tree.clone();
+        self.run(move || tree.apply_batch(batch)).await
+    }
+
+    /// Synchronously flushes all dirty IO buffers and calls
+    /// fsync, returning the number of bytes written. This is the
+    /// spawner-routed equivalent of `Tree::flush_async`.
+    pub async fn flush(&self) -> Result<usize> {
+        let tree = self.tree.clone();
+        self.run(move || tree.flush()).await
+    }
+
+    /// Subscribe to `Event`s that happen to keys that have the
+    /// specified prefix. `Subscriber` implements both `Iterator`
+    /// and `Future`, so it can be awaited directly.
+    pub fn watch_prefix<P: AsRef<[u8]>>(&self, prefix: P) -> Subscriber {
+        self.tree.watch_prefix(prefix)
+    }
+
+    /// Returns the inner [`Tree`] for blocking use.
+    pub fn into_inner(self) -> Tree {
+        self.tree
+    }
+}
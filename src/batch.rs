@@ -1,5 +1,7 @@
 #![allow(unused_results)]
 
+use std::ops::{Bound, RangeBounds};
+
 use super::*;
 
 /// A batch of updates that will
@@ -31,6 +33,13 @@ use super::*;
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Batch {
     pub(crate) writes: Map<IVec, Option<IVec>>,
+    /// Time-to-lives in milliseconds for keys written via
+    /// `insert_with_ttl`, measured from when the batch is
+    /// applied.
+    pub(crate) ttls: Map<IVec, u64>,
+    /// Key ranges queued via `remove_range`, resolved against the
+    /// tree's contents when the batch is applied.
+    pub(crate) range_removes: Vec<(Bound<IVec>, Bound<IVec>)>,
 }
 
 impl Batch {
@@ -40,7 +49,26 @@ impl Batch {
         K: Into<IVec>,
         V: Into<IVec>,
     {
-        self.writes.insert(key.into(), Some(value.into()));
+        let key = key.into();
+        self.ttls.remove(&key);
+        self.writes.insert(key, Some(value.into()));
+    }
+
+    /// Set a key to a new value that expires once `ttl` has
+    /// elapsed, measured from when the batch is applied. See
+    /// `Tree::insert_with_ttl` for the expiration semantics.
+    pub fn insert_with_ttl<K, V>(
+        &mut self,
+        key: K,
+        value: V,
+        ttl: std::time::Duration,
+    ) where
+        K: Into<IVec>,
+        V: Into<IVec>,
+    {
+        let key = key.into();
+        self.ttls.insert(key.clone(), tree::duration_to_millis(ttl));
+        self.writes.insert(key, Some(value.into()));
     }
 
     /// Remove a key
@@ -48,7 +76,61 @@ impl Batch {
     where
         K: Into<IVec>,
     {
-        self.writes.insert(key.into(), None);
+        let key = key.into();
+        self.ttls.remove(&key);
+        self.writes.insert(key, None);
+    }
+
+    /// Remove every key in a range of keys.
+    ///
+    /// The range is resolved against the tree's contents at the
+    /// moment the batch is applied, so keys written by concurrent
+    /// traffic before the batch commits are covered. Point writes
+    /// in the same batch take precedence over range removals that
+    /// cover their keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert("a", "1")?;
+    /// db.insert("b", "2")?;
+    /// db.insert("c", "3")?;
+    ///
+    /// let mut batch = sled::Batch::default();
+    /// batch.remove_range("a".."c");
+    /// batch.insert("b", "rewritten");
+    ///
+    /// db.apply_batch(batch)?;
+    /// assert_eq!(db.get("a")?, None);
+    /// assert_eq!(db.get("b")?, Some("rewritten".into()));
+    /// assert_eq!(db.get("c")?, Some("3".into()));
+    /// # Ok(()) }
+    /// ```
+    pub fn remove_range<K, R>(&mut self, range: R)
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let lo = match range.start_bound() {
+            Bound::Included(start) => {
+                Bound::Included(IVec::from(start.as_ref()))
+            }
+            Bound::Excluded(start) => {
+                Bound::Excluded(IVec::from(start.as_ref()))
+            }
+            Bound::Unbounded => Bound::Included(IVec::from(&[])),
+        };
+
+        let hi = match range.end_bound() {
+            Bound::Included(end) => Bound::Included(IVec::from(end.as_ref())),
+            Bound::Excluded(end) => Bound::Excluded(IVec::from(end.as_ref())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        self.range_removes.push((lo, hi));
     }
 
     /// Get a value if it is present in the `Batch`.
@@ -57,4 +139,91 @@ impl Batch {
         let inner = self.writes.get(k.as_ref())?;
         Some(inner.as_ref())
     }
+
+    /// Iterate over the writes staged in this `Batch` in key
+    /// order, as `(key, value)` pairs where a `None` value is a
+    /// deletion.
+    pub fn iter(&self) -> impl Iterator<Item = (&IVec, Option<&IVec>)> {
+        self.writes.iter().map(|(k, v)| (k, v.as_ref()))
+    }
+
+    /// Packs key-value pairs into a sequence of batches, starting
+    /// a new batch whenever adding a pair would push the current
+    /// one past `max_bytes` of combined key and value length.
+    /// Feed the result to [`Tree::apply_batches`](crate::Tree::apply_batches)
+    /// to run a large import with bounded memory: only one batch
+    /// is materialized at a time, and lazily at that, so the
+    /// source iterator may stream from disk or the network.
+    ///
+    /// A single pair larger than `max_bytes` still becomes its
+    /// own batch rather than being dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let pairs = (0u32..100).map(|i| (i.to_be_bytes().to_vec(), b"payload".to_vec()));
+    ///
+    /// db.apply_batches(sled::Batch::from_iter_chunked(pairs, 64))?;
+    ///
+    /// assert_eq!(db.len(), 100);
+    /// # Ok(()) }
+    /// ```
+    pub fn from_iter_chunked<I, K, V>(
+        iter: I,
+        max_bytes: usize,
+    ) -> BatchChunks<I::IntoIter>
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: Into<IVec>,
+        V: Into<IVec>,
+    {
+        BatchChunks { inner: iter.into_iter(), max_bytes, pending: None }
+    }
+}
+
+/// An iterator of size-bounded batches, created by
+/// [`Batch::from_iter_chunked`].
+pub struct BatchChunks<I> {
+    inner: I,
+    max_bytes: usize,
+    pending: Option<(IVec, IVec)>,
+}
+
+impl<I, K, V> Iterator for BatchChunks<I>
+where
+    I: Iterator<Item = (K, V)>,
+    K: Into<IVec>,
+    V: Into<IVec>,
+{
+    type Item = Batch;
+
+    fn next(&mut self) -> Option<Batch> {
+        let mut batch = Batch::default();
+        let mut bytes = 0;
+        loop {
+            let (key, value) = if let Some(pending) = self.pending.take() {
+                pending
+            } else if let Some((key, value)) = self.inner.next() {
+                (key.into(), value.into())
+            } else {
+                break;
+            };
+
+            let size = key.len() + value.len();
+            if !batch.writes.is_empty() && bytes + size > self.max_bytes {
+                self.pending = Some((key, value));
+                return Some(batch);
+            }
+            bytes += size;
+            batch.insert(key, value);
+        }
+        if batch.writes.is_empty() {
+            None
+        } else {
+            Some(batch)
+        }
+    }
 }
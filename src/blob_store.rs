@@ -0,0 +1,181 @@
+//! A content-addressed blob store with refcounting and
+//! deduplication, layered over a hidden tree.
+
+use std::convert::TryFrom;
+use std::hash::Hasher;
+
+use fxhash::FxHasher64;
+
+use crate::{Error, IVec, Result, Tree};
+
+const SECOND_HALF_SEED: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// The 128-bit content hash of a blob, returned by
+/// [`BlobStore::put`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlobHash([u8; 16]);
+
+impl BlobHash {
+    fn compute(bytes: &[u8]) -> BlobHash {
+        let mut first = FxHasher64::default();
+        first.write(bytes);
+
+        let mut second = FxHasher64::default();
+        second.write_u64(SECOND_HALF_SEED);
+        second.write_u64(u64::try_from(bytes.len()).unwrap());
+        second.write(bytes);
+
+        let mut buf = [0; 16];
+        buf[..8].copy_from_slice(&first.finish().to_be_bytes());
+        buf[8..].copy_from_slice(&second.finish().to_be_bytes());
+        BlobHash(buf)
+    }
+
+    /// Returns the raw bytes of this hash, suitable for storing
+    /// in other trees as a reference to the blob.
+    pub fn as_bytes(&self) -> &[u8; 16] {
+        &self.0
+    }
+}
+
+impl From<[u8; 16]> for BlobHash {
+    fn from(buf: [u8; 16]) -> BlobHash {
+        BlobHash(buf)
+    }
+}
+
+/// A content-addressed blob store opened via `Db::blob_store`,
+/// for attachments, artifacts, and other values that may be
+/// referenced from several places.
+///
+/// Blobs are keyed by their content hash: storing the same bytes
+/// twice deduplicates them and bumps a reference count instead,
+/// and [`unref`](BlobStore::unref) removes a blob only once every
+/// [`put`](BlobStore::put) of it has been matched by an `unref`.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// let blobs = db.blob_store("attachments")?;
+///
+/// let hash = blobs.put(b"large artifact")?;
+/// // storing identical bytes deduplicates
+/// assert_eq!(blobs.put(b"large artifact")?, hash);
+/// assert_eq!(&blobs.get(&hash)?.unwrap(), b"large artifact");
+///
+/// assert_eq!(blobs.unref(&hash)?, Some(1));
+/// assert_eq!(blobs.unref(&hash)?, Some(0));
+/// assert_eq!(blobs.get(&hash)?, None);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct BlobStore {
+    pub(crate) tree: Tree,
+}
+
+impl BlobStore {
+    /// Stores `bytes`, returning its content hash. If a blob with
+    /// the same content is already stored, its reference count is
+    /// incremented instead of storing a second copy.
+    pub fn put(&self, bytes: &[u8]) -> Result<BlobHash> {
+        let hash = BlobHash::compute(bytes);
+        loop {
+            match self.tree.get(&hash.0)? {
+                Some(record) => {
+                    if &record[8..] != bytes {
+                        return Err(Error::ReportableBug(
+                            "content hash collision detected \
+                             in BlobStore"
+                                .into(),
+                        ));
+                    }
+                    let refs = decode_refcount(&record);
+                    let new = encode_record(refs + 1, bytes);
+                    if self
+                        .tree
+                        .compare_and_swap(&hash.0, Some(&record), Some(new))?
+                        .is_ok()
+                    {
+                        return Ok(hash);
+                    }
+                }
+                None => {
+                    let new = encode_record(1, bytes);
+                    if self
+                        .tree
+                        .compare_and_swap(&hash.0, None::<&[u8]>, Some(new))?
+                        .is_ok()
+                    {
+                        return Ok(hash);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Retrieves the blob with the given content hash, if it is
+    /// stored.
+    pub fn get(&self, hash: &BlobHash) -> Result<Option<IVec>> {
+        let record = self.tree.get(&hash.0)?;
+        Ok(record.map(|record| record.subslice(8, record.len() - 8)))
+    }
+
+    /// Returns the number of outstanding references to the blob
+    /// with the given content hash, if it is stored.
+    pub fn refcount(&self, hash: &BlobHash) -> Result<Option<u64>> {
+        let record = self.tree.get(&hash.0)?;
+        Ok(record.map(|record| decode_refcount(&record)))
+    }
+
+    /// Drops one reference to the blob with the given content
+    /// hash, removing it entirely when the last reference is
+    /// dropped. Returns the number of remaining references, or
+    /// `None` if no blob with that hash was stored.
+    pub fn unref(&self, hash: &BlobHash) -> Result<Option<u64>> {
+        loop {
+            let record = match self.tree.get(&hash.0)? {
+                Some(record) => record,
+                None => return Ok(None),
+            };
+            let refs = decode_refcount(&record);
+            if refs <= 1 {
+                if self
+                    .tree
+                    .compare_and_swap(
+                        &hash.0,
+                        Some(&record),
+                        None::<&[u8]>,
+                    )?
+                    .is_ok()
+                {
+                    return Ok(Some(0));
+                }
+            } else {
+                let new = encode_record(refs - 1, &record[8..]);
+                if self
+                    .tree
+                    .compare_and_swap(&hash.0, Some(&record), Some(new))?
+                    .is_ok()
+                {
+                    return Ok(Some(refs - 1));
+                }
+            }
+        }
+    }
+}
+
+fn encode_record(refs: u64, bytes: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(8 + bytes.len());
+    record.extend_from_slice(&refs.to_be_bytes());
+    record.extend_from_slice(bytes);
+    record
+}
+
+fn decode_refcount(record: &IVec) -> u64 {
+    let mut arr = [0; 8];
+    arr.copy_from_slice(&record[..8]);
+    u64::from_be_bytes(arr)
+}
@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+
+use parking_lot::Mutex;
+
+use crate::{Batch, IVec, Result, Tree};
+
+/// An in-process branch of a tree, created via `Db::branch`.
+///
+/// A branch is a lightweight fork: writes are buffered in memory
+/// and never touch the underlying tree, while reads of unmodified
+/// keys fall through to it. A branch can be [`diff`](Branch::diff)ed
+/// against the tree it forked from, atomically
+/// [`commit`](Branch::commit)ted back into it, or simply dropped
+/// to discard all of its writes - useful for speculative
+/// computation and preview features.
+///
+/// Note that reads of keys the branch has not written fall
+/// through to the live tree, so concurrent writes to the
+/// underlying tree are visible through the branch.
+pub struct Branch {
+    name: String,
+    base: Tree,
+    overlay: Mutex<BTreeMap<IVec, Option<IVec>>>,
+}
+
+impl Branch {
+    pub(crate) fn new(name: String, base: Tree) -> Branch {
+        Branch { name, base, overlay: Mutex::new(BTreeMap::new()) }
+    }
+
+    /// Returns the name this branch was created with.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Retrieves a value, preferring writes made on this branch
+    /// over the state of the underlying tree.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        if let Some(value) = self.overlay.lock().get(key.as_ref()) {
+            return Ok(value.clone());
+        }
+        self.base.get(key)
+    }
+
+    /// Sets a key to a new value on this branch only, returning
+    /// the last value visible through the branch if it was set.
+    pub fn insert<K, V>(&self, key: K, value: V) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: Into<IVec>,
+    {
+        let last = self.get(key.as_ref())?;
+        self.overlay
+            .lock()
+            .insert(key.as_ref().into(), Some(value.into()));
+        Ok(last)
+    }
+
+    /// Deletes a key on this branch only, returning the last
+    /// value visible through the branch if it was set.
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        let last = self.get(key.as_ref())?;
+        self.overlay.lock().insert(key.as_ref().into(), None);
+        Ok(last)
+    }
+
+    /// Returns `true` if a value for the specified key is visible
+    /// through this branch.
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> Result<bool> {
+        self.get(key).map(|value| value.is_some())
+    }
+
+    /// Returns the changes this branch has made relative to the
+    /// current state of the underlying tree, as tuples of the
+    /// key, the value in the underlying tree, and the value on
+    /// the branch. Keys whose branch value matches the underlying
+    /// tree are omitted.
+    #[allow(clippy::type_complexity)]
+    pub fn diff(
+        &self,
+    ) -> Result<Vec<(IVec, Option<IVec>, Option<IVec>)>> {
+        let overlay = self.overlay.lock();
+        let mut changes = Vec::with_capacity(overlay.len());
+        for (key, branch_value) in &*overlay {
+            let base_value = self.base.get(key)?;
+            if base_value != *branch_value {
+                changes.push((
+                    key.clone(),
+                    base_value,
+                    branch_value.clone(),
+                ));
+            }
+        }
+        Ok(changes)
+    }
+
+    /// Atomically applies all of this branch's writes to the
+    /// underlying tree.
+    pub fn commit(self) -> Result<()> {
+        let mut batch = Batch::default();
+        for (key, value) in self.overlay.into_inner() {
+            match value {
+                Some(value) => batch.insert(key, value),
+                None => batch.remove(key),
+            }
+        }
+        self.base.apply_batch(batch)
+    }
+}
+
+impl std::fmt::Debug for Branch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Branch")
+            .field("name", &self.name)
+            .field("pending_writes", &self.overlay.lock().len())
+            .finish()
+    }
+}
@@ -0,0 +1,111 @@
+//! Cooperative cancellation of long-running operations.
+//!
+//! Scans and archive exports can run for minutes on large trees.
+//! A [`CancellationToken`] lets another thread request that such
+//! an operation stop at the next convenient point, so shutdown
+//! does not have to wait for it to run to completion. The
+//! operation checks the token between items, stops cleanly with
+//! `Error::Cancelled`, and the token reports how many items were
+//! processed before the stop took effect.
+
+use std::sync::{
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc,
+};
+
+use crate::{Error, Result};
+
+/// A handle for requesting that a long-running operation stop
+/// early. Cloning the token is cheap and all clones share the
+/// same cancellation state, so one clone can be moved into the
+/// thread driving the operation while another stays behind to
+/// call [`cancel`](CancellationToken::cancel).
+///
+/// Cancellation is cooperative: operations check the token
+/// between items, so an in-flight item is always completed
+/// before the operation stops. The number of items processed
+/// before the stop is available via
+/// [`progress`](CancellationToken::progress).
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// use sled::{CancellationToken, Error};
+///
+/// for i in 0..10_u32 {
+///     db.insert(&i.to_be_bytes(), vec![])?;
+/// }
+///
+/// let token = CancellationToken::new();
+///
+/// // cancel after the third item, as a shutdown path would
+/// let mut observed = 0;
+/// for kv in db.iter().with_cancellation(token.clone()) {
+///     match kv {
+///         Ok(_) => {
+///             observed += 1;
+///             if observed == 3 {
+///                 token.cancel();
+///             }
+///         }
+///         Err(Error::Cancelled) => break,
+///         Err(other) => return Err(other.into()),
+///     }
+/// }
+///
+/// assert_eq!(observed, 3);
+/// assert_eq!(token.progress(), 3);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    inner: Arc<TokenInner>,
+}
+
+#[derive(Debug, Default)]
+struct TokenInner {
+    cancelled: AtomicBool,
+    progress: AtomicU64,
+}
+
+impl CancellationToken {
+    /// Creates a new token in the non-cancelled state.
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    /// Requests that operations observing this token stop at
+    /// their next check point. Idempotent, and may be called
+    /// from any thread.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Release);
+    }
+
+    /// Returns `true` if `cancel` has been called.
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    /// Returns the number of items processed so far by
+    /// operations observing this token, allowing partial
+    /// progress to be reported after a cancellation.
+    pub fn progress(&self) -> u64 {
+        self.inner.progress.load(Ordering::Acquire)
+    }
+
+    // called by observing operations between items
+    pub(crate) fn check(&self) -> Result<()> {
+        if self.is_cancelled() {
+            Err(Error::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    pub(crate) fn note_progress(&self, items: u64) {
+        self.inner.progress.fetch_add(items, Ordering::AcqRel);
+    }
+}
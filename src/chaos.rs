@@ -0,0 +1,179 @@
+//! Seeded fault injection for shaking out timeout and retry
+//! logic before production does it for you.
+//!
+//! When a profile is active, public tree operations roll
+//! against per-operation-class probabilities and either stall
+//! for a configured latency, fail with a transient `Io` error
+//! of kind `Interrupted`, or both. The decision stream is
+//! driven by a seeded generator, so a run that surfaced a bug
+//! in application retry handling can be replayed exactly.
+//!
+//! Nothing is ever injected below the public API: the store
+//! itself stays correct, only its observable latency and
+//! error behavior degrade. This is intended for staging
+//! environments and integration tests, not as a substitute
+//! for the `failpoints` feature, which simulates real crashes
+//! inside the storage engine.
+
+use std::io;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::{Error, Lazy, Result};
+
+/// The operation classes that injection decisions are made
+/// for, each with independent probabilities in a
+/// [`ChaosProfile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// Point reads.
+    Read,
+    /// Inserts, removals, compare-and-swaps, merges, and
+    /// batch applications.
+    Write,
+    /// Explicit flushes.
+    Flush,
+}
+
+/// Injection settings for one operation class.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpChaos {
+    /// The delay added to an operation when a latency
+    /// injection fires.
+    pub latency: Duration,
+    /// The chance in `0.0..=1.0` that an operation is
+    /// delayed by `latency`.
+    pub latency_probability: f64,
+    /// The chance in `0.0..=1.0` that an operation fails
+    /// with a transient `Io` error of kind `Interrupted`.
+    /// The operation has no effect on the tree when this
+    /// fires.
+    pub error_probability: f64,
+}
+
+/// A complete description of how the store should misbehave.
+///
+/// The default profile injects nothing, so individual
+/// operation classes can be opted in with struct update
+/// syntax.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChaosProfile {
+    /// The seed that the injection decision stream derives
+    /// from, making a chaotic run reproducible. A seed of
+    /// `0` is treated as an arbitrary fixed default.
+    pub seed: u64,
+    /// Settings applied to reads.
+    pub read: OpChaos,
+    /// Settings applied to writes.
+    pub write: OpChaos,
+    /// Settings applied to flushes.
+    pub flush: OpChaos,
+}
+
+struct State {
+    profile: ChaosProfile,
+    rng: u64,
+}
+
+type Active = Mutex<Option<State>>;
+
+static ACTIVE: Lazy<Active, fn() -> Active> = Lazy::new(init);
+
+fn init() -> Active {
+    Mutex::new(None)
+}
+
+// xorshift has no valid all-zero state
+const DEFAULT_RNG_STATE: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// Activate chaos mode process-wide with the given profile,
+/// replacing any previous one and restarting the decision
+/// stream from its seed.
+///
+/// # Examples
+///
+/// ```
+/// use std::time::Duration;
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// sled::chaos::set(sled::chaos::ChaosProfile {
+///     seed: 42,
+///     read: sled::chaos::OpChaos {
+///         latency: Duration::from_millis(5),
+///         latency_probability: 0.1,
+///         error_probability: 0.01,
+///     },
+///     ..Default::default()
+/// });
+///
+/// // reads now occasionally stall or fail with a transient
+/// // `Io` error of kind `Interrupted`, so retry loops can
+/// // be exercised deterministically
+/// let _ = db.get(b"k");
+///
+/// sled::chaos::reset();
+/// # Ok(()) }
+/// ```
+pub fn set(profile: ChaosProfile) {
+    let rng = if profile.seed == 0 { DEFAULT_RNG_STATE } else { profile.seed };
+    *ACTIVE.lock() = Some(State { profile, rng });
+}
+
+/// Deactivate chaos mode.
+pub fn reset() {
+    *ACTIVE.lock() = None;
+}
+
+pub(crate) fn inject(op: Op) -> Result<()> {
+    let (delay, fail) = {
+        let mut active = ACTIVE.lock();
+        let state = if let Some(state) = active.as_mut() {
+            state
+        } else {
+            return Ok(());
+        };
+        let settings = match op {
+            Op::Read => state.profile.read,
+            Op::Write => state.profile.write,
+            Op::Flush => state.profile.flush,
+        };
+        let rng = &mut state.rng;
+        let mut roll = || {
+            let mut x = *rng;
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            *rng = x;
+            #[allow(clippy::cast_precision_loss)]
+            {
+                (x >> 11) as f64 / (1_u64 << 53) as f64
+            }
+        };
+        (
+            if roll() < settings.latency_probability {
+                Some(settings.latency)
+            } else {
+                None
+            },
+            roll() < settings.error_probability,
+        )
+    };
+
+    // sleep outside the lock so a long injected latency does
+    // not serialize decisions for unrelated operations
+    if let Some(latency) = delay {
+        std::thread::sleep(latency);
+    }
+
+    if fail {
+        Err(Error::Io(io::Error::new(
+            io::ErrorKind::Interrupted,
+            "chaos mode injected a transient error",
+        )))
+    } else {
+        Ok(())
+    }
+}
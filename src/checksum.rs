@@ -0,0 +1,317 @@
+//! Selectable checksum algorithms for log messages and
+//! snapshot files.
+//!
+//! Every log message and snapshot carries a 32-bit checksum,
+//! and on high-throughput ingest the hashing itself shows up
+//! prominently in profiles. The algorithm is chosen with
+//! [`Config::checksum_algorithm`](crate::Config): the portable
+//! crc32 default, hardware-accelerated crc32c where SSE 4.2 is
+//! available, or xxhash64 reduced to its low 32 bits to fit the
+//! existing header layout.
+//!
+//! Verification always tries the configured algorithm first and
+//! falls back to the other known algorithms before declaring
+//! corruption, so data written under a previous configuration
+//! still verifies after the algorithm changes; the extra hashing
+//! is only ever paid on the corruption path. Segment headers
+//! additionally record the algorithm id their segments were
+//! written with, as an advisory for recovery tooling. The
+//! checksums protecting segment headers themselves and the
+//! persisted configuration stay crc32, since they must be
+//! readable before any configuration is known.
+
+use std::convert::TryFrom;
+
+use crate::{crc32, Lazy};
+
+/// The checksum algorithm applied to log messages and snapshot
+/// files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    /// The portable crc32 default, with hardware acceleration on
+    /// many targets via the `crc32fast` crate.
+    Crc32,
+    /// The Castagnoli crc32c polynomial, computed with the
+    /// dedicated SSE 4.2 instruction where available and a
+    /// table-driven fallback elsewhere.
+    Crc32c,
+    /// xxhash64, reduced to its low 32 bits. The fastest option
+    /// on targets without hardware crc support.
+    XxHash64,
+}
+
+impl Default for ChecksumAlgorithm {
+    fn default() -> ChecksumAlgorithm {
+        ChecksumAlgorithm::Crc32
+    }
+}
+
+const ALL: [ChecksumAlgorithm; 3] = [
+    ChecksumAlgorithm::Crc32,
+    ChecksumAlgorithm::Crc32c,
+    ChecksumAlgorithm::XxHash64,
+];
+
+impl ChecksumAlgorithm {
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            ChecksumAlgorithm::Crc32 => 0,
+            ChecksumAlgorithm::Crc32c => 1,
+            ChecksumAlgorithm::XxHash64 => 2,
+        }
+    }
+}
+
+pub(crate) fn hash(algorithm: ChecksumAlgorithm, buf: &[u8]) -> u32 {
+    match algorithm {
+        ChecksumAlgorithm::Crc32 => crc32(buf),
+        ChecksumAlgorithm::Crc32c => {
+            !crc32c_update(0xFFFF_FFFF, buf)
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        ChecksumAlgorithm::XxHash64 => xxh64(buf, 0) as u32,
+    }
+}
+
+// message checksums cover the body followed by the header with
+// its checksum field skipped, and are stored inverted so that
+// zeroed storage never checksums successfully. for xxhash64 the
+// two parts are chained by seeding the header hash with the body
+// hash, rather than concatenated
+pub(crate) fn hash_message(
+    algorithm: ChecksumAlgorithm,
+    header: &[u8],
+    body: &[u8],
+) -> u32 {
+    let hashed = match algorithm {
+        ChecksumAlgorithm::Crc32 => {
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(body);
+            hasher.update(&header[4..]);
+            hasher.finalize()
+        }
+        ChecksumAlgorithm::Crc32c => {
+            !crc32c_update(crc32c_update(0xFFFF_FFFF, body), &header[4..])
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        ChecksumAlgorithm::XxHash64 => {
+            xxh64(&header[4..], xxh64(body, 0)) as u32
+        }
+    };
+    hashed ^ 0xFFFF_FFFF
+}
+
+/// Returns whether the expected checksum matches under any known
+/// algorithm, trying the configured one first so that the
+/// fallbacks are only computed for data written under a previous
+/// configuration, or actual corruption.
+pub(crate) fn verify(
+    expected: u32,
+    buf: &[u8],
+    configured: ChecksumAlgorithm,
+) -> bool {
+    if hash(configured, buf) == expected {
+        return true;
+    }
+    ALL.iter().any(|&algorithm| {
+        algorithm != configured && hash(algorithm, buf) == expected
+    })
+}
+
+pub(crate) fn verify_message(
+    expected: u32,
+    header: &[u8],
+    body: &[u8],
+    configured: ChecksumAlgorithm,
+) -> bool {
+    if hash_message(configured, header, body) == expected {
+        return true;
+    }
+    ALL.iter().any(|&algorithm| {
+        algorithm != configured
+            && hash_message(algorithm, header, body) == expected
+    })
+}
+
+fn crc32c_table() -> [u32; 256] {
+    // the reflected Castagnoli polynomial
+    const POLY: u32 = 0x82F6_3B78;
+
+    let mut table = [0_u32; 256];
+    for (i, slot) in table.iter_mut().enumerate() {
+        let mut crc = u32::try_from(i).unwrap();
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ POLY } else { crc >> 1 };
+        }
+        *slot = crc;
+    }
+    table
+}
+
+static CRC32C_TABLE: Lazy<[u32; 256], fn() -> [u32; 256]> =
+    Lazy::new(crc32c_table);
+
+fn crc32c_update(crc: u32, buf: &[u8]) -> u32 {
+    #[cfg(target_arch = "x86_64")]
+    {
+        if std::is_x86_feature_detected!("sse4.2") {
+            #[allow(unsafe_code)]
+            return unsafe { crc32c_update_hw(crc, buf) };
+        }
+    }
+
+    crc32c_update_sw(crc, buf)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "sse4.2")]
+#[allow(unsafe_code)]
+#[allow(clippy::cast_possible_truncation)]
+unsafe fn crc32c_update_hw(crc: u32, buf: &[u8]) -> u32 {
+    use std::arch::x86_64::{_mm_crc32_u64, _mm_crc32_u8};
+    use std::convert::TryInto;
+
+    let mut chunks = buf.chunks_exact(8);
+    let mut state = u64::from(crc);
+    for chunk in &mut chunks {
+        state = _mm_crc32_u64(state, u64::from_le_bytes(
+            chunk.try_into().unwrap(),
+        ));
+    }
+    let mut crc = state as u32;
+    for byte in chunks.remainder() {
+        crc = _mm_crc32_u8(crc, *byte);
+    }
+    crc
+}
+
+fn crc32c_update_sw(mut crc: u32, buf: &[u8]) -> u32 {
+    for byte in buf {
+        let idx = usize::try_from((crc ^ u32::from(*byte)) & 0xFF).unwrap();
+        crc = (crc >> 8) ^ CRC32C_TABLE[idx];
+    }
+    crc
+}
+
+const XXH_PRIME_1: u64 = 0x9E37_79B1_85EB_CA87;
+const XXH_PRIME_2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const XXH_PRIME_3: u64 = 0x1656_67B1_9E37_79F9;
+const XXH_PRIME_4: u64 = 0x85EB_CA77_C2B2_AE63;
+const XXH_PRIME_5: u64 = 0x27D4_EB2F_1656_67C5;
+
+fn xxh_round(acc: u64, input: u64) -> u64 {
+    acc.wrapping_add(input.wrapping_mul(XXH_PRIME_2))
+        .rotate_left(31)
+        .wrapping_mul(XXH_PRIME_1)
+}
+
+fn xxh_merge_round(acc: u64, lane: u64) -> u64 {
+    (acc ^ xxh_round(0, lane))
+        .wrapping_mul(XXH_PRIME_1)
+        .wrapping_add(XXH_PRIME_4)
+}
+
+fn read_u64(buf: &[u8], at: usize) -> u64 {
+    use std::convert::TryInto;
+    u64::from_le_bytes(buf[at..at + 8].as_ref().try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], at: usize) -> u32 {
+    use std::convert::TryInto;
+    u32::from_le_bytes(buf[at..at + 4].as_ref().try_into().unwrap())
+}
+
+fn xxh64(buf: &[u8], seed: u64) -> u64 {
+    let len = buf.len();
+    let mut pos = 0_usize;
+
+    let mut acc = if len >= 32 {
+        let mut v1 =
+            seed.wrapping_add(XXH_PRIME_1).wrapping_add(XXH_PRIME_2);
+        let mut v2 = seed.wrapping_add(XXH_PRIME_2);
+        let mut v3 = seed;
+        let mut v4 = seed.wrapping_sub(XXH_PRIME_1);
+
+        while pos + 32 <= len {
+            v1 = xxh_round(v1, read_u64(buf, pos));
+            v2 = xxh_round(v2, read_u64(buf, pos + 8));
+            v3 = xxh_round(v3, read_u64(buf, pos + 16));
+            v4 = xxh_round(v4, read_u64(buf, pos + 24));
+            pos += 32;
+        }
+
+        let mut acc = v1
+            .rotate_left(1)
+            .wrapping_add(v2.rotate_left(7))
+            .wrapping_add(v3.rotate_left(12))
+            .wrapping_add(v4.rotate_left(18));
+        acc = xxh_merge_round(acc, v1);
+        acc = xxh_merge_round(acc, v2);
+        acc = xxh_merge_round(acc, v3);
+        xxh_merge_round(acc, v4)
+    } else {
+        seed.wrapping_add(XXH_PRIME_5)
+    };
+
+    acc = acc.wrapping_add(u64::try_from(len).unwrap());
+
+    while pos + 8 <= len {
+        acc = (acc ^ xxh_round(0, read_u64(buf, pos)))
+            .rotate_left(27)
+            .wrapping_mul(XXH_PRIME_1)
+            .wrapping_add(XXH_PRIME_4);
+        pos += 8;
+    }
+
+    if pos + 4 <= len {
+        acc = (acc ^ u64::from(read_u32(buf, pos)).wrapping_mul(XXH_PRIME_1))
+            .rotate_left(23)
+            .wrapping_mul(XXH_PRIME_2)
+            .wrapping_add(XXH_PRIME_3);
+        pos += 4;
+    }
+
+    while pos < len {
+        acc = (acc ^ u64::from(buf[pos]).wrapping_mul(XXH_PRIME_5))
+            .rotate_left(11)
+            .wrapping_mul(XXH_PRIME_1);
+        pos += 1;
+    }
+
+    acc ^= acc >> 33;
+    acc = acc.wrapping_mul(XXH_PRIME_2);
+    acc ^= acc >> 29;
+    acc = acc.wrapping_mul(XXH_PRIME_3);
+    acc ^= acc >> 32;
+    acc
+}
+
+#[test]
+fn known_answer_vectors() {
+    // crc32c and xxh64 reference values from their specifications
+    assert_eq!(hash(ChecksumAlgorithm::Crc32c, b""), 0);
+    assert_eq!(hash(ChecksumAlgorithm::Crc32c, b"123456789"), 0xE306_9283);
+    assert_eq!(xxh64(b"", 0), 0xEF46_DB37_51D8_E999);
+    assert_eq!(
+        xxh64(b"Nobody inspects the spammish repetition", 0),
+        0xFBCE_A83C_8A37_8BF1
+    );
+}
+
+#[test]
+fn verification_falls_back_across_algorithms() {
+    let buf = b"some bytes that were written long ago";
+    for write_algorithm in &ALL {
+        let expected = hash(*write_algorithm, buf);
+        for read_algorithm in &ALL {
+            assert!(verify(expected, buf, *read_algorithm));
+        }
+        assert!(!verify(expected ^ 1, buf, *write_algorithm));
+
+        let header = [0_u8; 8];
+        let expected = hash_message(*write_algorithm, &header, buf);
+        for read_algorithm in &ALL {
+            assert!(verify_message(expected, &header, buf, *read_algorithm));
+        }
+    }
+}
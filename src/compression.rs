@@ -0,0 +1,853 @@
+//! Pluggable compression codecs for log messages, large values,
+//! and snapshots.
+//!
+//! Compression used to be hard-wired to zstd behind the
+//! `compression` feature. The codec is now chosen at runtime
+//! with [`Config::compression_algorithm`](crate::Config), with
+//! pure-Rust LZ4 and snappy block codecs built in so that
+//! compression works without any optional dependency, and zstd
+//! still available behind the feature flag.
+//!
+//! Every compressed buffer is framed with the single-byte
+//! algorithm id of the codec that produced it, and segment
+//! headers record the codec their segments were written with,
+//! so a database can be recovered after the configured
+//! algorithm changes: old segments decompress with the codec
+//! they were written under, new ones with the current choice.
+
+use std::convert::TryFrom;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::{Error, Lazy, Map, Result};
+
+/// The compression codec applied to log messages, heap values,
+/// and snapshot files when `Config::use_compression` is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionAlgorithm {
+    /// Store buffers uncompressed. Distinct from disabling
+    /// `use_compression` only in that the framing byte is still
+    /// written.
+    None,
+    /// The built-in pure-Rust LZ4 block codec: very fast with
+    /// moderate ratios, and available without any feature flag.
+    Lz4,
+    /// The built-in pure-Rust snappy codec: comparable to LZ4,
+    /// provided for ecosystems standardized on snappy framing.
+    Snappy,
+    /// zstd, with the level taken from
+    /// `Config::compression_factor`. The strongest ratios, at
+    /// the cost of the optional `compression` feature and its
+    /// native dependency.
+    #[cfg(feature = "compression")]
+    Zstd,
+    /// A codec registered at runtime with
+    /// [`register_compressor`], identified by its id.
+    Custom(u8),
+}
+
+impl Default for CompressionAlgorithm {
+    fn default() -> CompressionAlgorithm {
+        #[cfg(feature = "compression")]
+        {
+            CompressionAlgorithm::Zstd
+        }
+        #[cfg(not(feature = "compression"))]
+        {
+            CompressionAlgorithm::Lz4
+        }
+    }
+}
+
+const ID_NONE: u8 = 0;
+const ID_LZ4: u8 = 1;
+const ID_SNAPPY: u8 = 2;
+const ID_ZSTD: u8 = 3;
+const ID_ZSTD_DICT: u8 = 4;
+
+/// The smallest id a custom codec may use; lower values are
+/// reserved for built-in algorithms.
+pub const MIN_CUSTOM_COMPRESSOR_ID: u8 = 64;
+
+impl CompressionAlgorithm {
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            CompressionAlgorithm::None => ID_NONE,
+            CompressionAlgorithm::Lz4 => ID_LZ4,
+            CompressionAlgorithm::Snappy => ID_SNAPPY,
+            #[cfg(feature = "compression")]
+            CompressionAlgorithm::Zstd => ID_ZSTD,
+            CompressionAlgorithm::Custom(id) => id,
+        }
+    }
+}
+
+/// A compression codec that can be registered at runtime and
+/// selected with `CompressionAlgorithm::Custom`, letting
+/// embedders bring codecs that sled does not build in.
+///
+/// The codec must be registered, under the same id, in every
+/// process that will recover data written with it.
+pub trait Compressor: Send + Sync + 'static {
+    /// Compresses a raw buffer. The compression factor from the
+    /// configuration is passed through for codecs with tunable
+    /// effort levels, and may be ignored.
+    fn compress(&self, raw: &[u8], factor: i32) -> Vec<u8>;
+
+    /// Decompresses a buffer produced by
+    /// [`compress`](Compressor::compress). `raw_len` is the
+    /// recorded length of the original buffer.
+    fn decompress(&self, compressed: &[u8], raw_len: usize)
+        -> Result<Vec<u8>>;
+}
+
+type Registry = RwLock<Map<u8, Arc<dyn Compressor>>>;
+
+static REGISTRY: Lazy<Registry, fn() -> Registry> = Lazy::new(init_registry);
+
+fn init_registry() -> Registry {
+    RwLock::new(Map::default())
+}
+
+/// Registers a custom compression codec process-wide under the
+/// given id, replacing any codec previously registered under it.
+///
+/// Returns `Error::Unsupported` for ids below
+/// [`MIN_CUSTOM_COMPRESSOR_ID`], which are reserved for the
+/// built-in algorithms.
+pub fn register_compressor(
+    id: u8,
+    compressor: Arc<dyn Compressor>,
+) -> Result<()> {
+    if id < MIN_CUSTOM_COMPRESSOR_ID {
+        return Err(Error::Unsupported(format!(
+            "custom compressor ids must be >= {}, \
+             but {} was requested",
+            MIN_CUSTOM_COMPRESSOR_ID, id
+        )));
+    }
+    REGISTRY.write().insert(id, compressor);
+    Ok(())
+}
+
+// the name of the trained dictionary file inside the database
+// directory. the dictionary lives beside the log rather than in a
+// tree, because log recovery itself needs it before any tree
+// contents can be read
+#[cfg(feature = "compression")]
+pub(crate) const DICTIONARY_FILE_NAME: &str = "zstd.dict";
+
+/// The shared slot holding a database's trained zstd dictionary,
+/// hung off of the `Config` so that every compression and
+/// decompression site can reach it. Empty until a dictionary is
+/// loaded at open or trained with
+/// `Db::train_compression_dictionary`.
+#[doc(hidden)]
+#[derive(Default, Clone)]
+pub struct DictionarySlot(Arc<RwLock<Option<Arc<Vec<u8>>>>>);
+
+impl DictionarySlot {
+    pub(crate) fn get(&self) -> Option<Arc<Vec<u8>>> {
+        self.0.read().clone()
+    }
+
+    // only `Db::train_compression_dictionary` stores dictionaries
+    #[cfg(feature = "compression")]
+    pub(crate) fn set(&self, dictionary: Vec<u8>) {
+        *self.0.write() = Some(Arc::new(dictionary));
+    }
+}
+
+impl std::fmt::Debug for DictionarySlot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self.0.read() {
+            Some(ref dictionary) => {
+                write!(f, "DictionarySlot({} bytes)", dictionary.len())
+            }
+            None => write!(f, "DictionarySlot(empty)"),
+        }
+    }
+}
+
+// frame layout: [algorithm id: u8][raw length: u64 LE][payload].
+// the id travels with every buffer so that recovery works even
+// when the configured algorithm has changed since it was written
+const FRAME_HEADER_LEN: usize = 9;
+
+#[cfg_attr(
+    not(feature = "compression"),
+    allow(unused_variables)
+)]
+pub(crate) fn compress_frame(
+    algorithm: CompressionAlgorithm,
+    raw: &[u8],
+    factor: i32,
+    dictionary: Option<&[u8]>,
+) -> Vec<u8> {
+    let (id, payload) = match algorithm {
+        CompressionAlgorithm::None => (ID_NONE, raw.to_vec()),
+        CompressionAlgorithm::Lz4 => (ID_LZ4, lz4::compress(raw)),
+        CompressionAlgorithm::Snappy => (ID_SNAPPY, snappy::compress(raw)),
+        #[cfg(feature = "compression")]
+        CompressionAlgorithm::Zstd => {
+            if let Some(dictionary) = dictionary {
+                (
+                    ID_ZSTD_DICT,
+                    zstd::block::Compressor::with_dict(dictionary.to_vec())
+                        .compress(raw, factor)
+                        .expect("zstd compression failed"),
+                )
+            } else {
+                (
+                    ID_ZSTD,
+                    zstd::block::compress(raw, factor)
+                        .expect("zstd compression failed"),
+                )
+            }
+        }
+        CompressionAlgorithm::Custom(id) => {
+            let compressor = REGISTRY
+                .read()
+                .get(&id)
+                .cloned()
+                .unwrap_or_else(|| {
+                    panic!(
+                        "CompressionAlgorithm::Custom({}) selected \
+                         but no compressor is registered under that \
+                         id; call register_compressor before opening \
+                         the database",
+                        id
+                    )
+                });
+            (id, compressor.compress(raw, factor))
+        }
+    };
+
+    // an incompressible buffer is stored raw rather than grown
+    let (id, payload) =
+        if payload.len() >= raw.len() && id != ID_NONE {
+            (ID_NONE, raw.to_vec())
+        } else {
+            (id, payload)
+        };
+
+    let mut out = Vec::with_capacity(FRAME_HEADER_LEN + payload.len());
+    out.push(id);
+    out.extend_from_slice(&(raw.len() as u64).to_le_bytes());
+    out.extend_from_slice(&payload);
+    out
+}
+
+pub(crate) fn decompress_frame(
+    frame: &[u8],
+    dictionary: Option<&[u8]>,
+) -> Result<Vec<u8>> {
+    match decompress_framed(frame, dictionary) {
+        Ok(raw) => Ok(raw),
+        Err(e) => {
+            // databases written before the codec id framing was
+            // introduced hold bare zstd streams, whose first byte
+            // (the zstd magic) does not decode as a valid frame;
+            // try the legacy format before declaring the data
+            // unreadable.
+            #[cfg(feature = "compression")]
+            if let Ok(raw) = zstd::stream::decode_all(frame) {
+                return Ok(raw);
+            }
+            Err(e)
+        }
+    }
+}
+
+#[cfg_attr(
+    not(feature = "compression"),
+    allow(unused_variables)
+)]
+fn decompress_framed(
+    frame: &[u8],
+    dictionary: Option<&[u8]>,
+) -> Result<Vec<u8>> {
+    use std::convert::TryInto;
+
+    if frame.len() < FRAME_HEADER_LEN {
+        return Err(Error::corruption(None));
+    }
+    let id = frame[0];
+    let raw_len = usize::try_from(u64::from_le_bytes(
+        frame[1..FRAME_HEADER_LEN].as_ref().try_into().unwrap(),
+    ))
+    .unwrap();
+    let payload = &frame[FRAME_HEADER_LEN..];
+
+    match id {
+        ID_NONE => {
+            if payload.len() != raw_len {
+                return Err(Error::corruption(None));
+            }
+            Ok(payload.to_vec())
+        }
+        ID_LZ4 => lz4::decompress(payload, raw_len),
+        ID_SNAPPY => snappy::decompress(payload, raw_len),
+        #[cfg(feature = "compression")]
+        ID_ZSTD => zstd::block::decompress(payload, raw_len)
+            .map_err(|_| Error::corruption(None)),
+        #[cfg(feature = "compression")]
+        ID_ZSTD_DICT => {
+            let dictionary = dictionary.ok_or_else(|| {
+                Error::Unsupported(
+                    "data was compressed with a trained zstd \
+                     dictionary, but no dictionary is loaded; the \
+                     zstd.dict file may have been deleted from the \
+                     database directory"
+                        .to_string(),
+                )
+            })?;
+            zstd::block::Decompressor::with_dict(dictionary.to_vec())
+                .decompress(payload, raw_len)
+                .map_err(|_| Error::corruption(None))
+        }
+        #[cfg(not(feature = "compression"))]
+        ID_ZSTD | ID_ZSTD_DICT => Err(Error::Unsupported(
+            "data was compressed with zstd, which requires \
+             the 'compression' feature to be enabled"
+                .to_string(),
+        )),
+        id => {
+            let compressor = REGISTRY.read().get(&id).cloned();
+            if let Some(compressor) = compressor {
+                compressor.decompress(payload, raw_len)
+            } else {
+                Err(Error::Unsupported(format!(
+                    "data was compressed with codec id {}, which \
+                     has no registered compressor in this process",
+                    id
+                )))
+            }
+        }
+    }
+}
+
+/// The built-in LZ4 block codec: a greedy matcher over the
+/// standard sequence format of token, literals, and 16-bit
+/// match offsets.
+mod lz4 {
+    use std::convert::TryFrom;
+
+    use super::{Error, Result};
+
+    const MIN_MATCH: usize = 4;
+    const HASH_LOG: u32 = 16;
+
+    // matches may not begin in the final 12 bytes of input, and
+    // may not extend into the final 5, per the block format spec
+    const TAIL_LITERALS: usize = 5;
+    const MATCH_GUARD: usize = 12;
+
+    fn read_u32(buf: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([
+            buf[at],
+            buf[at + 1],
+            buf[at + 2],
+            buf[at + 3],
+        ])
+    }
+
+    fn hash(word: u32) -> usize {
+        (word.wrapping_mul(2_654_435_761) >> (32 - HASH_LOG)) as usize
+    }
+
+    fn write_length(out: &mut Vec<u8>, mut len: usize) {
+        while len >= 255 {
+            out.push(255);
+            len -= 255;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        out.push(len as u8);
+    }
+
+    fn emit(
+        out: &mut Vec<u8>,
+        literals: &[u8],
+        offset: u16,
+        match_len: usize,
+    ) {
+        let lit_token =
+            if literals.len() >= 15 { 15 } else { literals.len() as u8 };
+        let match_token = if match_len - MIN_MATCH >= 15 {
+            15
+        } else {
+            (match_len - MIN_MATCH) as u8
+        };
+        out.push((lit_token << 4) | match_token);
+        if lit_token == 15 {
+            write_length(out, literals.len() - 15);
+        }
+        out.extend_from_slice(literals);
+        out.extend_from_slice(&offset.to_le_bytes());
+        if match_token == 15 {
+            write_length(out, match_len - MIN_MATCH - 15);
+        }
+    }
+
+    fn emit_last(out: &mut Vec<u8>, literals: &[u8]) {
+        let lit_token =
+            if literals.len() >= 15 { 15 } else { literals.len() as u8 };
+        out.push(lit_token << 4);
+        if lit_token == 15 {
+            write_length(out, literals.len() - 15);
+        }
+        out.extend_from_slice(literals);
+    }
+
+    pub(super) fn compress(input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(input.len() / 2 + 16);
+        if input.len() <= MATCH_GUARD {
+            emit_last(&mut out, input);
+            return out;
+        }
+
+        // position + 1, so that zero means empty
+        let mut table = vec![0_u32; 1 << HASH_LOG];
+        let match_limit = input.len() - MATCH_GUARD;
+        let extend_limit = input.len() - TAIL_LITERALS;
+        let mut anchor = 0_usize;
+        let mut pos = 0_usize;
+
+        while pos <= match_limit {
+            let word = read_u32(input, pos);
+            let slot = &mut table[hash(word)];
+            let candidate = *slot as usize;
+            *slot = u32::try_from(pos + 1).unwrap();
+
+            if candidate > 0 {
+                let cand = candidate - 1;
+                if pos - cand <= usize::from(u16::max_value())
+                    && read_u32(input, cand) == word
+                {
+                    let mut len = MIN_MATCH;
+                    while pos + len < extend_limit
+                        && input[cand + len] == input[pos + len]
+                    {
+                        len += 1;
+                    }
+                    emit(
+                        &mut out,
+                        &input[anchor..pos],
+                        u16::try_from(pos - cand).unwrap(),
+                        len,
+                    );
+                    pos += len;
+                    anchor = pos;
+                    continue;
+                }
+            }
+            pos += 1;
+        }
+
+        emit_last(&mut out, &input[anchor..]);
+        out
+    }
+
+    fn read_length(
+        input: &[u8],
+        pos: &mut usize,
+        token: usize,
+    ) -> Result<usize> {
+        let mut len = token;
+        if token == 15 {
+            loop {
+                let byte =
+                    *input.get(*pos).ok_or_else(|| Error::corruption(None))?;
+                *pos += 1;
+                len += usize::from(byte);
+                if byte != 255 {
+                    break;
+                }
+            }
+        }
+        Ok(len)
+    }
+
+    pub(super) fn decompress(
+        input: &[u8],
+        raw_len: usize,
+    ) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(raw_len);
+        let mut pos = 0_usize;
+
+        loop {
+            let token =
+                *input.get(pos).ok_or_else(|| Error::corruption(None))?;
+            pos += 1;
+
+            let lit_len =
+                read_length(input, &mut pos, usize::from(token >> 4))?;
+            let literals = input
+                .get(pos..pos + lit_len)
+                .ok_or_else(|| Error::corruption(None))?;
+            out.extend_from_slice(literals);
+            pos += lit_len;
+
+            if pos == input.len() {
+                break;
+            }
+
+            let offset_bytes = input
+                .get(pos..pos + 2)
+                .ok_or_else(|| Error::corruption(None))?;
+            let offset = usize::from(u16::from_le_bytes([
+                offset_bytes[0],
+                offset_bytes[1],
+            ]));
+            pos += 2;
+
+            let match_len =
+                read_length(input, &mut pos, usize::from(token & 0xF))?
+                    + MIN_MATCH;
+
+            if offset == 0 || offset > out.len() {
+                return Err(Error::corruption(None));
+            }
+            let mut from = out.len() - offset;
+            // copies may overlap their own output, so go byte
+            // by byte rather than through a slice copy
+            for _ in 0..match_len {
+                let byte = out[from];
+                out.push(byte);
+                from += 1;
+            }
+        }
+
+        if out.len() != raw_len {
+            return Err(Error::corruption(None));
+        }
+        Ok(out)
+    }
+}
+
+/// The built-in snappy codec, over the standard raw (unframed)
+/// format: a varint length header followed by literal and copy
+/// elements.
+mod snappy {
+    use std::convert::TryFrom;
+
+    use super::{Error, Result};
+
+    const HASH_LOG: u32 = 14;
+
+    fn read_u32(buf: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes([
+            buf[at],
+            buf[at + 1],
+            buf[at + 2],
+            buf[at + 3],
+        ])
+    }
+
+    fn hash(word: u32) -> usize {
+        (word.wrapping_mul(0x1e35_a7bd) >> (32 - HASH_LOG)) as usize
+    }
+
+    fn write_varint(out: &mut Vec<u8>, mut n: usize) {
+        while n >= 0x80 {
+            #[allow(clippy::cast_possible_truncation)]
+            out.push((n as u8) | 0x80);
+            n >>= 7;
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        out.push(n as u8);
+    }
+
+    fn read_varint(input: &[u8], pos: &mut usize) -> Result<usize> {
+        let mut n = 0_usize;
+        let mut shift = 0_u32;
+        loop {
+            let byte =
+                *input.get(*pos).ok_or_else(|| Error::corruption(None))?;
+            *pos += 1;
+            n |= usize::from(byte & 0x7F) << shift;
+            if byte < 0x80 {
+                return Ok(n);
+            }
+            shift += 7;
+            if shift > 28 {
+                return Err(Error::corruption(None));
+            }
+        }
+    }
+
+    fn emit_literal(out: &mut Vec<u8>, literals: &[u8]) {
+        if literals.is_empty() {
+            return;
+        }
+        let n = literals.len() - 1;
+        if n < 60 {
+            #[allow(clippy::cast_possible_truncation)]
+            out.push((n as u8) << 2);
+        } else if n < 1 << 8 {
+            out.push(60 << 2);
+            #[allow(clippy::cast_possible_truncation)]
+            out.push(n as u8);
+        } else if n < 1 << 16 {
+            out.push(61 << 2);
+            #[allow(clippy::cast_possible_truncation)]
+            out.extend_from_slice(&(n as u16).to_le_bytes());
+        } else {
+            out.push(62 << 2);
+            #[allow(clippy::cast_possible_truncation)]
+            out.extend_from_slice(&(n as u32).to_le_bytes()[..3]);
+        }
+        out.extend_from_slice(literals);
+    }
+
+    // a copy element of up to 64 bytes with a 16-bit offset
+    fn emit_copy(out: &mut Vec<u8>, offset: usize, mut len: usize) {
+        while len > 0 {
+            let chunk = std::cmp::min(64, len);
+            // copies of fewer than 4 bytes are not expressible
+            // in the 2-byte-offset form's length field range
+            if chunk < 4 {
+                break;
+            }
+            #[allow(clippy::cast_possible_truncation)]
+            out.push((((chunk - 1) as u8) << 2) | 0b10);
+            #[allow(clippy::cast_possible_truncation)]
+            out.extend_from_slice(&(offset as u16).to_le_bytes());
+            len -= chunk;
+        }
+        debug_assert_eq!(len, 0);
+    }
+
+    pub(super) fn compress(input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(input.len() / 2 + 16);
+        write_varint(&mut out, input.len());
+        if input.len() < 4 {
+            emit_literal(&mut out, input);
+            return out;
+        }
+
+        let mut table = vec![0_u32; 1 << HASH_LOG];
+        let match_limit = input.len() - 4;
+        let mut anchor = 0_usize;
+        let mut pos = 0_usize;
+
+        while pos <= match_limit {
+            let word = read_u32(input, pos);
+            let slot = &mut table[hash(word)];
+            let candidate = *slot as usize;
+            *slot = u32::try_from(pos + 1).unwrap();
+
+            if candidate > 0 {
+                let cand = candidate - 1;
+                if pos - cand <= usize::from(u16::max_value())
+                    && read_u32(input, cand) == word
+                {
+                    let mut len = 4;
+                    while pos + len < input.len()
+                        && input[cand + len] == input[pos + len]
+                    {
+                        len += 1;
+                    }
+                    // emit_copy only expresses lengths of 4..=64
+                    // per element, so trim a remainder below 4
+                    // back into the literal stream
+                    let emitted = len - (len % 64) % 4;
+                    emit_literal(&mut out, &input[anchor..pos]);
+                    emit_copy(&mut out, pos - cand, emitted);
+                    pos += emitted;
+                    anchor = pos;
+                    continue;
+                }
+            }
+            pos += 1;
+        }
+
+        emit_literal(&mut out, &input[anchor..]);
+        out
+    }
+
+    pub(super) fn decompress(
+        input: &[u8],
+        raw_len: usize,
+    ) -> Result<Vec<u8>> {
+        let mut pos = 0_usize;
+        let header_len = read_varint(input, &mut pos)?;
+        if header_len != raw_len {
+            return Err(Error::corruption(None));
+        }
+
+        let mut out = Vec::with_capacity(raw_len);
+        while pos < input.len() {
+            let tag = input[pos];
+            pos += 1;
+            match tag & 0b11 {
+                0b00 => {
+                    let n = usize::from(tag >> 2);
+                    let len = if n < 60 {
+                        n + 1
+                    } else {
+                        let extra = n - 59;
+                        let bytes = input
+                            .get(pos..pos + extra)
+                            .ok_or_else(|| Error::corruption(None))?;
+                        pos += extra;
+                        let mut len = 0_usize;
+                        for (i, byte) in bytes.iter().enumerate() {
+                            len |= usize::from(*byte) << (8 * i);
+                        }
+                        len + 1
+                    };
+                    let literals = input
+                        .get(pos..pos + len)
+                        .ok_or_else(|| Error::corruption(None))?;
+                    out.extend_from_slice(literals);
+                    pos += len;
+                }
+                kind => {
+                    let (len, offset) = match kind {
+                        0b01 => {
+                            let byte = *input
+                                .get(pos)
+                                .ok_or_else(|| Error::corruption(None))?;
+                            pos += 1;
+                            (
+                                usize::from((tag >> 2) & 0b111) + 4,
+                                (usize::from(tag >> 5) << 8)
+                                    | usize::from(byte),
+                            )
+                        }
+                        0b10 => {
+                            let bytes = input
+                                .get(pos..pos + 2)
+                                .ok_or_else(|| Error::corruption(None))?;
+                            pos += 2;
+                            (
+                                usize::from(tag >> 2) + 1,
+                                usize::from(u16::from_le_bytes([
+                                    bytes[0], bytes[1],
+                                ])),
+                            )
+                        }
+                        _ => {
+                            let bytes = input
+                                .get(pos..pos + 4)
+                                .ok_or_else(|| Error::corruption(None))?;
+                            pos += 4;
+                            (
+                                usize::from(tag >> 2) + 1,
+                                usize::try_from(u32::from_le_bytes([
+                                    bytes[0], bytes[1], bytes[2], bytes[3],
+                                ]))
+                                .unwrap(),
+                            )
+                        }
+                    };
+                    if offset == 0 || offset > out.len() {
+                        return Err(Error::corruption(None));
+                    }
+                    let mut from = out.len() - offset;
+                    for _ in 0..len {
+                        let byte = out[from];
+                        out.push(byte);
+                        from += 1;
+                    }
+                }
+            }
+        }
+
+        if out.len() != raw_len {
+            return Err(Error::corruption(None));
+        }
+        Ok(out)
+    }
+}
+
+#[test]
+fn built_in_codec_round_trips() {
+    let redundant: Vec<u8> =
+        (0_u32..10_000).map(|i| (i % 7) as u8).collect();
+    let mut incompressible = vec![0_u8; 10_000];
+    let mut state = 0x9E37_79B9_7F4A_7C15_u64;
+    for byte in &mut incompressible {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        *byte = state as u8;
+    }
+
+    for algorithm in &[
+        CompressionAlgorithm::None,
+        CompressionAlgorithm::Lz4,
+        CompressionAlgorithm::Snappy,
+        #[cfg(feature = "compression")]
+        CompressionAlgorithm::Zstd,
+    ] {
+        for raw in
+            &[&[][..], &b"abc"[..], &redundant[..], &incompressible[..]]
+        {
+            let frame = compress_frame(*algorithm, raw, 3, None);
+            assert_eq!(decompress_frame(&frame, None).unwrap(), *raw);
+        }
+
+        let frame = compress_frame(*algorithm, &redundant, 3, None);
+        if *algorithm != CompressionAlgorithm::None {
+            assert!(frame.len() < redundant.len());
+        }
+    }
+}
+
+#[test]
+#[cfg(feature = "compression")]
+fn legacy_unframed_zstd_is_accepted() {
+    // databases written before the codec id framing was
+    // introduced hold bare zstd streams
+    let raw: Vec<u8> = (0_u32..10_000).map(|i| (i % 7) as u8).collect();
+    let legacy = zstd::stream::encode_all(&raw[..], 3).unwrap();
+    assert_eq!(decompress_frame(&legacy, None).unwrap(), raw);
+}
+
+#[test]
+fn truncated_frames_are_rejected() {
+    for algorithm in
+        &[CompressionAlgorithm::Lz4, CompressionAlgorithm::Snappy]
+    {
+        let frame =
+            compress_frame(*algorithm, &[7; 1000][..].as_ref(), 3, None);
+        assert!(decompress_frame(&frame[..4], None).is_err());
+        assert!(decompress_frame(&frame[..frame.len() - 1], None).is_err());
+    }
+}
+
+#[cfg(test)]
+mod qc {
+    use super::{
+        compress_frame, decompress_frame, CompressionAlgorithm,
+    };
+
+    fn prop_round_trip(raw: &[u8]) -> bool {
+        for algorithm in &[
+            CompressionAlgorithm::None,
+            CompressionAlgorithm::Lz4,
+            CompressionAlgorithm::Snappy,
+        ] {
+            let frame = compress_frame(*algorithm, raw, 3, None);
+            if decompress_frame(&frame, None).unwrap() != raw {
+                println!(
+                    "expected round trip through {:?} to be lossless",
+                    algorithm
+                );
+                return false;
+            }
+        }
+
+        true
+    }
+
+    quickcheck::quickcheck! {
+        #[cfg_attr(miri, ignore)]
+        fn bool(raw: Vec<u8>) -> bool {
+            prop_round_trip(&raw)
+        }
+    }
+}
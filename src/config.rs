@@ -8,6 +8,7 @@ use std::{
     sync::atomic::AtomicUsize,
 };
 
+use crate::db::ArchiveSlot;
 use crate::pagecache::{arr_to_u32, u32_to_arr, Heap};
 use crate::*;
 
@@ -30,6 +31,26 @@ pub enum Mode {
     HighThroughput,
 }
 
+/// How much consistency checking to perform while
+/// opening a database, for use with `Config::open_check`.
+/// Deeper levels trade open time for certainty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckLevel {
+    /// Perform no verification beyond what log recovery
+    /// itself requires, skipping the integrity manifest
+    /// check that `Config::open` normally performs.
+    None,
+    /// Verify the integrity manifest, if one was written.
+    /// This is the level used by `Config::open`.
+    Fast,
+    /// Additionally page in every entry in the page table and
+    /// checksum the contents of every tree before returning,
+    /// surfacing any latent corruption immediately rather than
+    /// when a critical read first hits it. Useful after restoring
+    /// from backups or migrating disks.
+    Full,
+}
+
 /// A persisted configuration about high-level
 /// storage file information
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
@@ -197,6 +218,14 @@ pub struct Inner {
     #[doc(hidden)]
     pub create_new: bool,
     #[doc(hidden)]
+    pub read_only: bool,
+    #[doc(hidden)]
+    pub max_open_files: usize,
+    #[doc(hidden)]
+    pub windows_write_through: bool,
+    #[doc(hidden)]
+    pub macos_barrier_fsync: bool,
+    #[doc(hidden)]
     pub mode: Mode,
     #[doc(hidden)]
     pub temporary: bool,
@@ -205,12 +234,40 @@ pub struct Inner {
     #[doc(hidden)]
     pub compression_factor: i32,
     #[doc(hidden)]
+    pub compression_algorithm: CompressionAlgorithm,
+    #[doc(hidden)]
+    pub zstd_dictionary: compression::DictionarySlot,
+    #[doc(hidden)]
+    pub checksum_algorithm: ChecksumAlgorithm,
+    #[doc(hidden)]
     pub print_profile_on_drop: bool,
     #[doc(hidden)]
     pub idgen_persist_interval: u64,
     #[doc(hidden)]
     pub snapshot_after_ops: u64,
     #[doc(hidden)]
+    pub segment_growth_increment: usize,
+    #[doc(hidden)]
+    pub scrub_segments_per_hour: u64,
+    #[doc(hidden)]
+    pub huge_pages: bool,
+    #[doc(hidden)]
+    pub compressed_cache_capacity: u64,
+    #[doc(hidden)]
+    pub pinned_cache_budget: u64,
+    #[doc(hidden)]
+    pub deterministic_seed: u64,
+    #[doc(hidden)]
+    pub watchdog_stall_threshold_ms: u64,
+    #[doc(hidden)]
+    pub abort_on_internal_panic: bool,
+    #[doc(hidden)]
+    pub speculative_prefetch: bool,
+    #[doc(hidden)]
+    pub gc_target_utilization: f32,
+    #[doc(hidden)]
+    pub mirror_path: Option<PathBuf>,
+    #[doc(hidden)]
     pub version: (usize, usize),
     tmp_path: PathBuf,
     pub(crate) global_error: Arc<Atomic<Error>>,
@@ -226,10 +283,17 @@ impl Default for Inner {
             path: PathBuf::from(DEFAULT_PATH),
             tmp_path: Config::gen_temp_path(),
             create_new: false,
+            read_only: false,
+            max_open_files: 0,
+            windows_write_through: false,
+            macos_barrier_fsync: false,
             cache_capacity: 1024 * 1024 * 1024, // 1gb
             mode: Mode::LowSpace,
             use_compression: false,
             compression_factor: 5,
+            compression_algorithm: CompressionAlgorithm::default(),
+            zstd_dictionary: compression::DictionarySlot::default(),
+            checksum_algorithm: ChecksumAlgorithm::default(),
             temporary: false,
             version: crate_version(),
 
@@ -243,9 +307,20 @@ impl Default for Inner {
             } else {
                 1_000_000
             },
+            segment_growth_increment: 1,
+            scrub_segments_per_hour: 0,
+            huge_pages: false,
+            compressed_cache_capacity: 0,
+            pinned_cache_budget: 0,
+            deterministic_seed: 0,
+            watchdog_stall_threshold_ms: 0,
+            abort_on_internal_panic: false,
+            speculative_prefetch: false,
+            gc_target_utilization: 0.5,
+            mirror_path: None,
             global_error: Arc::new(Atomic::default()),
             #[cfg(feature = "event_log")]
-            event_log: Arc::new(crate::event_log::EventLog::default()),
+            event_log: Arc::new(event_log::EventLog::default()),
         }
     }
 }
@@ -269,13 +344,17 @@ impl Inner {
         self.get_path().join("conf")
     }
 
+    fn config_backup_path(&self) -> PathBuf {
+        self.get_path().join("conf-bak")
+    }
+
     pub(crate) fn normalize<T>(&self, value: T) -> T
     where
         T: Copy
             + TryFrom<usize>
             + std::ops::Div<Output = T>
             + std::ops::Mul<Output = T>,
-        <T as std::convert::TryFrom<usize>>::Error: Debug,
+        <T as TryFrom<usize>>::Error: Debug,
     {
         let segment_size: T = T::try_from(self.segment_size).unwrap();
         value / segment_size * segment_size
@@ -323,6 +402,34 @@ impl Config {
         self
     }
 
+    /// Set a second directory, ideally on a different physical
+    /// disk, that every log segment write is mirrored to
+    /// (builder).
+    ///
+    /// Writes go to the primary first and then to the mirror, and
+    /// reads are served from the primary with a fallback to the
+    /// mirror when a primary read fails or comes back corrupted,
+    /// so data keeps being served through a single-disk failure
+    /// on embedded deployments that can't run real RAID. When a
+    /// mirror is added to an existing database, it is caught up
+    /// to the primary's contents during open.
+    ///
+    /// Only the main log file is mirrored: the snapshot and heap
+    /// directories still live solely under the primary path, and
+    /// recovery reads the primary.
+    pub fn mirror_path<P: AsRef<Path>>(mut self, mirror_path: P) -> Config {
+        if Arc::strong_count(&self.0) != 1 {
+            error!(
+                "config has already been used to start \
+                 the system and probably should not be \
+                 mutated",
+            );
+        }
+        let m = Arc::make_mut(&mut self.0);
+        m.mirror_path = Some(mirror_path.as_ref().to_path_buf());
+        self
+    }
+
     /// A testing-only method for reducing the io-buffer size
     /// to trigger correctness-critical behavior more often
     /// by shrinking the buffer size. Don't rely on this.
@@ -342,26 +449,80 @@ impl Config {
 
     /// Opens a `Db` based on the provided config.
     pub fn open(&self) -> Result<Db> {
+        self.open_check(CheckLevel::Fast)
+    }
+
+    /// Opens a `Db` based on the provided config, performing the
+    /// requested level of consistency checking before returning.
+    /// `CheckLevel::Fast` is what `Config::open` performs; see
+    /// `CheckLevel::Full` for a slower open that verifies every
+    /// page before returning, useful after restoring a database
+    /// from backups or migrating disks.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let config = sled::Config::new().temporary(true);
+    /// let db = config.open_check(sled::CheckLevel::Full)?;
+    /// # Ok(()) }
+    /// ```
+    pub fn open_check(&self, check_level: CheckLevel) -> Result<Db> {
         // only validate, setup directory, and open file once
         self.validate()?;
 
+        #[cfg(any(test, feature = "lock_free_delays"))]
+        if self.deterministic_seed != 0 {
+            debug_delay::set_deterministic_seed(
+                self.deterministic_seed,
+            );
+        }
+
         let mut config = self.clone();
         config.limit_cache_max_memory();
 
         let file = config.open_file()?;
 
+        #[cfg(feature = "compression")]
+        {
+            // a trained zstd dictionary must be loaded before log
+            // recovery begins, because recovered messages may have
+            // been compressed with it
+            let dictionary_path =
+                config.get_path().join(compression::DICTIONARY_FILE_NAME);
+            if dictionary_path.exists() {
+                config.zstd_dictionary.set(fs::read(&dictionary_path)?);
+            }
+        }
+
+        let mirror = if config.mirror_path.is_some() {
+            Some(Arc::new(config.open_mirror_file(&file)?))
+        } else {
+            None
+        };
+
+        let storage_capabilities = config.probe_storage_capabilities();
+        debug!("probed storage capabilities: {:?}", storage_capabilities);
+
         let heap_path = config.get_path().join("heap");
-        let heap = Heap::start(&heap_path)?;
+        let heap = Heap::start(
+            &heap_path,
+            config.max_open_files,
+            storage_capabilities.hole_punching,
+        )?;
         maybe_fsync_directory(heap_path)?;
 
         // seal config in a Config
         let config = RunningConfig {
             inner: config,
             file: Arc::new(file),
+            mirror,
             heap: Arc::new(heap),
+            segment_archive: Arc::default(),
+            storage_capabilities,
         };
 
-        Db::start_inner(config)
+        Db::start_inner(config, check_level)
     }
 
     #[doc(hidden)]
@@ -472,11 +633,21 @@ impl Config {
             Mode,
             "specify whether the system should run in \"small\" or \"fast\" mode"
         ),
-        (use_compression, bool, "whether to use zstd compression"),
+        (use_compression, bool, "whether to compress log messages, large values, and snapshots"),
         (
             compression_factor,
             i32,
-            "the compression factor to use with zstd compression. Ranges from 1 up to 22. Levels >= 20 are 'ultra'."
+            "the compression factor to use with zstd compression. Ranges from 1 up to 22. Levels >= 20 are 'ultra'. Ignored by codecs without effort levels."
+        ),
+        (
+            compression_algorithm,
+            CompressionAlgorithm,
+            "the codec used when use_compression is set. The built-in LZ4 and snappy codecs are always available; zstd requires the compression feature. Data written under a different algorithm remains readable, as every compressed buffer records the codec that produced it."
+        ),
+        (
+            checksum_algorithm,
+            ChecksumAlgorithm,
+            "the checksum applied to log messages and snapshots: the portable crc32 default, hardware crc32c, or xxhash64 truncated to 32 bits. Data written under a different algorithm still verifies, as verification falls back to the other known algorithms before declaring corruption"
         ),
         (
             temporary,
@@ -488,6 +659,26 @@ impl Config {
             bool,
             "attempts to exclusively open the database, failing if it already exists"
         ),
+        (
+            read_only,
+            bool,
+            "opens the database without acquiring the writer lock, refuses mutations with Error::ReadOnly, and skips spawning the flusher thread, so debugging tools and sidecar processes can inspect a live data directory"
+        ),
+        (
+            max_open_files,
+            usize,
+            "the maximum number of file descriptors held open for large-value heap files, evicting the least recently used and reopening on demand past the limit, for deployments with tight fd ulimits. 0 keeps every file open"
+        ),
+        (
+            windows_write_through,
+            bool,
+            "on Windows, opens the log with FILE_FLAG_WRITE_THROUGH, so that completed writes do not additionally wait on FlushFileBuffers during flushes. No effect on other platforms. FILE_FLAG_NO_BUFFERING is deliberately not used, because log messages are read and written at unaligned offsets"
+        ),
+        (
+            macos_barrier_fsync,
+            bool,
+            "on macOS, issue F_BARRIERFSYNC for routine log flushes instead of the F_FULLFSYNC that fsync escalates to on Apple hardware, checkpointing with a true F_FULLFSYNC each time a log segment is sealed. Barrier fsync orders writes through the drive cache at a fraction of the cost of forcing the cache to flush: after a power loss the log may be missing its last few flushes, but is never reordered, and every sealed segment remains fully durable. No effect on other platforms"
+        ),
         (
             print_profile_on_drop,
             bool,
@@ -497,9 +688,70 @@ impl Config {
             snapshot_after_ops,
             u64,
             "take a fuzzy snapshot of pagecache metadata after this many ops"
+        ),
+        (
+            segment_growth_increment,
+            usize,
+            "the number of segments to preallocate at a time when the log file grows, reducing filesystem fragmentation and allocation latency spikes under write-heavy load. 1 disables preallocation"
+        ),
+        (
+            scrub_segments_per_hour,
+            u64,
+            "the number of log segments the background scrubber reads back per hour, surfacing latent media corruption before a critical read hits it. 0 disables scrubbing"
+        ),
+        (
+            huge_pages,
+            bool,
+            "hint to the kernel that large IO buffers should be backed by transparent huge pages, reducing TLB pressure for very large caches. best-effort, and only effective on linux"
+        ),
+        (
+            compressed_cache_capacity,
+            u64,
+            "maximum size in bytes for a second cache tier that holds recently evicted pages in compressed form in memory, letting workloads slightly larger than the cache trade decompression for disk reads. 0 disables the tier"
+        ),
+        (
+            pinned_cache_budget,
+            u64,
+            "maximum total size in bytes of pages that may be exempted from cache eviction via Tree::pin_range. 0 disables pinning"
+        ),
+        (
+            deterministic_seed,
+            u64,
+            "a nonzero seed that internal randomized decisions, such as test failure injection, derive from instead of the clock, making test failures involving sled internals reproducible. 0 leaves them time-seeded"
+        ),
+        (
+            watchdog_stall_threshold_ms,
+            u64,
+            "the number of milliseconds beyond its expected cadence that a background thread may make no progress before the watchdog reports it as stalled through Db::health and the stall callback. 0 disables the watchdog"
+        ),
+        (
+            abort_on_internal_panic,
+            bool,
+            "abort the process when an internal thread panics, instead of poisoning the Db so that subsequent operations fail with Error::Poisoned"
+        ),
+        (
+            speculative_prefetch,
+            bool,
+            "experimental: learn repeated page access sequences and asynchronously read predicted pages' disk ranges ahead of the access. hit rates are reported by Db::prefetch_stats"
+        ),
+        (
+            gc_target_utilization,
+            f32,
+            "the live fraction at or below which a log segment becomes a garbage collection candidate. higher values reclaim space sooner at the cost of more background page rewriting, lower values reduce rewrite bandwidth at the cost of space amplification. clamped to 0.01..=0.99, defaults to 0.5"
         )
     );
 
+    // the codec id recorded in segment headers: the configured
+    // algorithm when compression is on, and 0 (uncompressed)
+    // otherwise
+    pub(crate) fn compression_codec_id(&self) -> u8 {
+        if self.use_compression {
+            self.compression_algorithm.id()
+        } else {
+            0
+        }
+    }
+
     // panics if config options are outside of advised range
     fn validate(&self) -> Result<()> {
         supported!(
@@ -514,10 +766,11 @@ impl Config {
             self.segment_size <= 1 << 24,
             "segment_size should be <= 16mb"
         );
-        if self.use_compression {
+        if let CompressionAlgorithm::Custom(id) = self.compression_algorithm {
             supported!(
-                cfg!(feature = "compression"),
-                "the 'compression' feature must be enabled"
+                id >= MIN_CUSTOM_COMPRESSOR_ID,
+                "custom compressor ids below MIN_CUSTOM_COMPRESSOR_ID \
+                 are reserved for built-in algorithms"
             );
         }
         supported!(
@@ -532,6 +785,20 @@ impl Config {
             self.idgen_persist_interval > 0,
             "idgen_persist_interval must be above 0"
         );
+        supported!(
+            self.segment_growth_increment >= 1,
+            "segment_growth_increment must be >= 1"
+        );
+        if let Some(mirror_path) = &self.mirror_path {
+            supported!(
+                *mirror_path != self.get_path(),
+                "mirror_path must differ from the primary database path"
+            );
+        }
+        supported!(
+            !(self.read_only && self.create_new),
+            "cannot create a new database in read-only mode"
+        );
         Ok(())
     }
 
@@ -555,7 +822,20 @@ impl Config {
             options.create_new(true);
         }
 
-        let _ = std::fs::File::create(
+        #[cfg(windows)]
+        {
+            if self.windows_write_through {
+                use std::os::windows::fs::OpenOptionsExt;
+
+                // push completed writes through the OS write-back
+                // cache so that flushes do not pay for a separate
+                // FlushFileBuffers call
+                const FILE_FLAG_WRITE_THROUGH: u32 = 0x8000_0000;
+                let _ = options.custom_flags(FILE_FLAG_WRITE_THROUGH);
+            }
+        }
+
+        let _ = File::create(
             self.get_path().join("DO_NOT_USE_THIS_DIRECTORY_FOR_ANYTHING"),
         );
 
@@ -564,6 +844,151 @@ impl Config {
         Ok(file)
     }
 
+    // opens the mirror log file, creating it and catching it up
+    // to the primary's current contents when it is missing or
+    // diverged in length, so that a mirror can be added to (or
+    // rebuilt for) an existing database. the primary is the
+    // source of truth during open.
+    fn open_mirror_file(&self, primary: &File) -> Result<File> {
+        use std::io::{Seek, SeekFrom};
+
+        let mirror_dir = self.mirror_path.as_ref().unwrap();
+
+        if !mirror_dir.exists() {
+            fs::create_dir_all(mirror_dir)?;
+        }
+
+        let mut options = fs::OpenOptions::new();
+        let _ = options.create(true);
+        let _ = options.read(true);
+        let _ = options.write(true);
+
+        let mirror = options.open(mirror_dir.join("db"))?;
+
+        let primary_len = primary.metadata()?.len();
+        let mirror_len = mirror.metadata()?.len();
+
+        if mirror_len != primary_len {
+            debug!(
+                "catching mirror log up from length {} to primary length {}",
+                mirror_len, primary_len
+            );
+            mirror.set_len(primary_len)?;
+            let mut reader = primary;
+            let mut writer = &mirror;
+            let _ = reader.seek(SeekFrom::Start(0))?;
+            let _ = writer.seek(SeekFrom::Start(0))?;
+            let _ = io::copy(&mut reader, &mut writer)?;
+            mirror.sync_all()?;
+        }
+
+        maybe_fsync_directory(mirror_dir)?;
+
+        Ok(mirror)
+    }
+
+    // probes the filesystem holding the database directory for
+    // optional features by exercising each syscall against a
+    // scratch file, so that the storage layer can choose its
+    // strategies up-front instead of learning about missing
+    // support from failed calls during normal operation.
+    #[allow(unsafe_code)]
+    fn probe_storage_capabilities(&self) -> StorageCapabilities {
+        let mut capabilities = StorageCapabilities::default();
+
+        if self.read_only {
+            // a read-only opener may not have permission to
+            // create the scratch file, and performs none of the
+            // maintenance that the probed features accelerate
+            return capabilities;
+        }
+
+        #[cfg(all(target_os = "linux", not(miri)))]
+        {
+            use std::{
+                io::Write,
+                os::unix::{fs::MetadataExt, io::AsRawFd},
+            };
+
+            let probe_path = self.get_path().join("capability_probe");
+
+            let mut options = fs::OpenOptions::new();
+            let _ = options.create(true);
+            let _ = options.read(true);
+            let _ = options.write(true);
+            let _ = options.truncate(true);
+
+            let mut file = if let Ok(file) = options.open(&probe_path) {
+                file
+            } else {
+                // probing is best-effort; an unwritable directory
+                // will surface a real error from open_file
+                return capabilities;
+            };
+
+            // hole punching needs allocated blocks to deallocate,
+            // so give the scratch file some real data first
+            let written = file
+                .write_all(&[0; 64 * 1024])
+                .and_then(|_| file.sync_all())
+                .is_ok();
+
+            if written {
+                let fd = file.as_raw_fd();
+
+                let preallocate_ret =
+                    unsafe { libc::fallocate(fd, 0, 0, 128 * 1024) };
+                capabilities.preallocation = preallocate_ret == 0;
+
+                let punch_ret = unsafe {
+                    libc::fallocate(
+                        fd,
+                        libc::FALLOC_FL_KEEP_SIZE
+                            | libc::FALLOC_FL_PUNCH_HOLE,
+                        0,
+                        4096,
+                    )
+                };
+                capabilities.hole_punching = punch_ret == 0;
+
+                let sync_ret = unsafe {
+                    libc::sync_file_range(
+                        fd,
+                        0,
+                        4096,
+                        libc::SYNC_FILE_RANGE_WAIT_BEFORE
+                            | libc::SYNC_FILE_RANGE_WRITE
+                            | libc::SYNC_FILE_RANGE_WAIT_AFTER,
+                    )
+                };
+                capabilities.sync_file_range = sync_ret == 0;
+
+                let mut direct_options = fs::OpenOptions::new();
+                let _ = direct_options.read(true);
+                {
+                    use std::os::unix::fs::OpenOptionsExt;
+                    let _ = direct_options.custom_flags(libc::O_DIRECT);
+                }
+                capabilities.direct_io =
+                    direct_options.open(&probe_path).is_ok();
+
+                if capabilities.direct_io {
+                    // O_DIRECT transfers must be aligned to the
+                    // logical block size of the backing device,
+                    // for which the filesystem block size is a
+                    // safe upper bound
+                    capabilities.direct_io_alignment =
+                        file.metadata().ok().map(|m| m.blksize());
+                }
+            }
+
+            drop(file);
+            let _ = fs::remove_file(&probe_path);
+        }
+
+        capabilities
+    }
+
     fn try_lock(&self, file: File) -> Result<File> {
         #[cfg(all(
             not(miri),
@@ -572,6 +997,13 @@ impl Config {
         {
             use fs2::FileExt;
 
+            if self.read_only {
+                // a read-only instance must be able to open a
+                // data directory whose writer is live and
+                // holding the exclusive lock
+                return Ok(file);
+            }
+
             let try_lock = if cfg!(feature = "testing") {
                 // we block here because during testing
                 // there are many filesystem race condition
@@ -673,13 +1105,48 @@ impl Config {
         io_fail!(self, "write_config dir fsync");
         maybe_fsync_directory(self.get_path())?;
         io_fail!(self, "write_config post");
+
+        // store a redundant copy at a different path, which
+        // read_config falls back to if the primary is corrupt,
+        // so the settings file is not a single point of failure.
+        let backup_temp_path = self.get_path().join("conf-bak.tmp");
+        let mut backup_f = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(&backup_temp_path)?;
+        backup_f.write_all(&*bytes)?;
+        backup_f.write_all(&crc_arr)?;
+        backup_f.sync_all()?;
+        fs::rename(backup_temp_path, self.config_backup_path())?;
+        maybe_fsync_directory(self.get_path())?;
         Ok(())
     }
 
     fn read_config(&self) -> Result<Option<StorageParameters>> {
-        let path = self.config_path();
+        match self.read_config_from(&self.config_path()) {
+            Ok(params) => Ok(params),
+            Err(primary_error) => {
+                let backup_path = self.config_backup_path();
+                warn!(
+                    "failed to read settings file {:?}: {:?}, \
+                     attempting backup copy {:?}",
+                    self.config_path(),
+                    primary_error,
+                    backup_path
+                );
+                match self.read_config_from(&backup_path) {
+                    Ok(Some(params)) => Ok(Some(params)),
+                    _ => Err(primary_error),
+                }
+            }
+        }
+    }
 
-        let f_res = fs::OpenOptions::new().read(true).open(&path);
+    fn read_config_from(
+        &self,
+        path: &Path,
+    ) -> Result<Option<StorageParameters>> {
+        let f_res = fs::OpenOptions::new().read(true).open(path);
 
         let mut f = match f_res {
             Err(ref e) if e.kind() == ErrorKind::NotFound => {
@@ -710,10 +1177,10 @@ impl Config {
 
         if crc_expected != crc_actual {
             warn!(
-                "crc for settings file {:?} failed! \
-                 can't verify that config is safe",
+                "crc for settings file {:?} failed!",
                 path
             );
+            return Err(Error::corruption(None));
         }
 
         StorageParameters::deserialize(&buf).map(Some)
@@ -768,11 +1235,41 @@ impl Config {
     pub fn truncate_corrupt(&self, new_len: u64) {
         self.event_log.reset();
         let path = self.db_path();
-        let f = std::fs::OpenOptions::new().write(true).open(path).unwrap();
+        let f = fs::OpenOptions::new().write(true).open(path).unwrap();
         f.set_len(new_len).expect("should be able to truncate");
     }
 }
 
+/// The optional filesystem features that were detected when a
+/// database was opened, available through
+/// [`Db::storage_capabilities`](crate::Db::storage_capabilities)
+/// for debugging deployments on unfamiliar filesystems.
+///
+/// The storage layer probes for these by exercising each syscall
+/// against a scratch file in the database directory, rather than
+/// by discovering missing support through failed calls during
+/// normal operation. Capabilities are conservatively reported as
+/// absent when the database is opened in read-only mode or on
+/// platforms where probing is not implemented.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageCapabilities {
+    /// whether `fallocate` can punch holes into files, which
+    /// lets freed heap slots return their blocks to the
+    /// filesystem without shrinking the file
+    pub hole_punching: bool,
+    /// whether `fallocate` can preallocate file extents
+    pub preallocation: bool,
+    /// whether `sync_file_range` is available for flushing
+    /// individual written regions instead of whole files
+    pub sync_file_range: bool,
+    /// whether files in the database directory can be opened
+    /// with `O_DIRECT`
+    pub direct_io: bool,
+    /// the block size that `O_DIRECT` transfers must be aligned
+    /// to, when direct IO is available
+    pub direct_io_alignment: Option<u64>,
+}
+
 /// A Configuration that has an associated opened
 /// file.
 #[allow(clippy::module_name_repetitions)]
@@ -780,7 +1277,10 @@ impl Config {
 pub struct RunningConfig {
     inner: Config,
     pub(crate) file: Arc<File>,
+    pub(crate) mirror: Option<Arc<File>>,
     pub(crate) heap: Arc<Heap>,
+    pub(crate) segment_archive: Arc<ArchiveSlot>,
+    pub(crate) storage_capabilities: StorageCapabilities,
 }
 
 impl Deref for RunningConfig {
@@ -826,6 +1326,7 @@ impl RunningConfig {
                 let path_str = &*path.to_string_lossy();
                 if path_str.starts_with(&*absolute_path.to_string_lossy())
                     && !path_str.ends_with(".generating")
+                    && !path_str.ends_with(".bak")
                 {
                     Some(path.to_path_buf())
                 } else {
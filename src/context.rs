@@ -25,6 +25,82 @@ pub struct Context {
         )
     ))]
     pub(crate) flusher: Arc<Mutex<Option<flusher::Flusher>>>,
+    /// Periodically reads back log segments to surface latent
+    /// media corruption, if enabled in the configuration.
+    #[cfg(all(
+        not(miri),
+        any(
+            windows,
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "netbsd",
+        )
+    ))]
+    pub(crate) scrubber: Arc<Mutex<Option<scrubber::Scrubber>>>,
+    /// The number of segments the background scrubber has failed
+    /// to read back since this instance was opened.
+    /// Detects and reports stalled background threads, if
+    /// enabled in the configuration.
+    #[cfg(all(
+        not(miri),
+        any(
+            windows,
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "netbsd",
+        )
+    ))]
+    pub(crate) watchdog: Arc<Mutex<Option<watchdog::Watchdog>>>,
+    #[cfg(all(
+        not(miri),
+        any(
+            windows,
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "netbsd",
+        )
+    ))]
+    pub(crate) stall_callback: Arc<watchdog::CallbackSlot>,
+    /// Milliseconds since the unix epoch of the periodic
+    /// flusher's and scrubber's last passes, refreshed by the
+    /// threads and read by the watchdog.
+    pub(crate) last_flush_progress: Arc<AtomicU64>,
+    pub(crate) last_scrub_progress: Arc<AtomicU64>,
+    /// Database-global change-data-capture state, consulted by
+    /// tree write paths and mutated by `Db::enable_changefeed`
+    /// and `Db::disable_changefeed`.
+    pub(crate) changefeed: Arc<changefeed::ChangefeedState>,
+    /// Set by the watchdog while the corresponding background
+    /// thread is considered stalled.
+    pub(crate) flusher_stalled: Arc<std::sync::atomic::AtomicBool>,
+    pub(crate) scrubber_stalled: Arc<std::sync::atomic::AtomicBool>,
+    /// The report for the internal thread panic that poisoned this
+    /// instance, if one has happened, taken via
+    /// `Db::take_poison_report`.
+    pub(crate) poison: Arc<Mutex<Option<PoisonReport>>>,
+    /// Expiry sweeps registered by trees with key TTLs in use,
+    /// run periodically by the flusher thread. The registry
+    /// itself is owned by the `Db`.
+    pub(crate) expiry_sweeps: Arc<RwLock<tree::WeakExpirySweepRegistry>>,
+    /// Named merge operators registered via
+    /// `Db::register_merge_operator`, resolved by name for trees
+    /// that persisted which operator they require.
+    pub(crate) merge_operators: Arc<tree::MergeOperators>,
+    /// Key orders registered at run time via
+    /// `Db::register_key_order`, resolved by name when a tree is
+    /// viewed through `Tree::ordered_by`.
+    pub(crate) key_orders: Arc<key_order::KeyOrders>,
+    pub(crate) scrub_errors: Arc<AtomicU64>,
+    pub(crate) total_ops: Arc<AtomicU64>,
     #[doc(hidden)]
     pub pagecache: PageCache,
 }
@@ -58,7 +134,61 @@ impl Context {
                     target_os = "netbsd",
                 )
             ))]
-            flusher: Arc::new(parking_lot::Mutex::new(None)),
+            flusher: Arc::new(Mutex::new(None)),
+            #[cfg(all(
+                not(miri),
+                any(
+                    windows,
+                    target_os = "linux",
+                    target_os = "macos",
+                    target_os = "dragonfly",
+                    target_os = "freebsd",
+                    target_os = "openbsd",
+                    target_os = "netbsd",
+                )
+            ))]
+            scrubber: Arc::new(Mutex::new(None)),
+            #[cfg(all(
+                not(miri),
+                any(
+                    windows,
+                    target_os = "linux",
+                    target_os = "macos",
+                    target_os = "dragonfly",
+                    target_os = "freebsd",
+                    target_os = "openbsd",
+                    target_os = "netbsd",
+                )
+            ))]
+            watchdog: Arc::new(Mutex::new(None)),
+            #[cfg(all(
+                not(miri),
+                any(
+                    windows,
+                    target_os = "linux",
+                    target_os = "macos",
+                    target_os = "dragonfly",
+                    target_os = "freebsd",
+                    target_os = "openbsd",
+                    target_os = "netbsd",
+                )
+            ))]
+            stall_callback: Arc::new(watchdog::CallbackSlot::default()),
+            last_flush_progress: Arc::new(AtomicU64::new(0)),
+            last_scrub_progress: Arc::new(AtomicU64::new(0)),
+            changefeed: Arc::new(changefeed::ChangefeedState::default()),
+            flusher_stalled: Arc::new(
+                std::sync::atomic::AtomicBool::new(false),
+            ),
+            scrubber_stalled: Arc::new(
+                std::sync::atomic::AtomicBool::new(false),
+            ),
+            poison: Arc::new(Mutex::new(None)),
+            expiry_sweeps: Arc::new(RwLock::new(std::sync::Weak::new())),
+            merge_operators: Arc::new(tree::MergeOperators::default()),
+            key_orders: Arc::new(key_order::KeyOrders::default()),
+            scrub_errors: Arc::new(AtomicU64::new(0)),
+            total_ops: Arc::new(AtomicU64::new(0)),
         })
     }
 
@@ -1,7 +1,18 @@
-use std::ops::Deref;
+use std::{
+    convert::TryFrom,
+    io::{Read, Write},
+    ops::Deref,
+    time::{Duration, Instant},
+};
 
 use crate::*;
 
+const ARCHIVE_MAGIC: &[u8] = b"sled archive v1\n";
+const ARCHIVE_RAW: u8 = 0;
+const ARCHIVE_ZSTD: u8 = 1;
+
+const INCREMENTAL_MANIFEST: &str = "manifest";
+
 /// The `sled` embedded database! Implements
 /// `Deref<Target = sled::Tree>` to refer to
 /// a default keyspace / namespace / bucket.
@@ -11,6 +22,23 @@ pub struct Db {
     pub context: Context,
     pub(crate) default: Tree,
     tenants: Arc<RwLock<FastMap8<IVec, Tree>>>,
+    /// Cached handle to the hidden tree holding persisted
+    /// lifetime statistics. The tree is only created on the
+    /// first statistics write, so that a fresh database does
+    /// not allocate pages for it at open.
+    stats_tree: Arc<RwLock<Option<Tree>>>,
+    /// Cached handle to the hidden leases tree, created on the
+    /// first `acquire_lease` call. Dropping a per-call handle
+    /// would flush the entire pagecache via `TreeInner::drop`.
+    leases_tree: Arc<RwLock<Option<Tree>>>,
+    stats_state: Arc<Mutex<StatsState>>,
+    /// Sweeps registered by trees with key TTLs in use, run by
+    /// the flusher thread. Owning the registry here releases the
+    /// `Tree` handles held by the sweeps when the last `Db`
+    /// handle is dropped. Never read: the field exists only to
+    /// own the `Arc` that `Context` and the flusher hold weakly.
+    #[allow(dead_code)]
+    expiry_sweeps: tree::ExpirySweepRegistry,
 }
 
 /// Opens a `Db` with a default configuration at the
@@ -25,6 +53,260 @@ pub fn open<P: AsRef<std::path::Path>>(path: P) -> Result<Db> {
     Config::new().path(path).open()
 }
 
+/// Applies a chain of incremental backups written by
+/// `Db::backup_incremental` onto a full backup directory written
+/// by `Db::backup_to`, in place, after which the directory can be
+/// opened as a restored database. The incrementals must be given
+/// in the order they were taken; the chain is validated against
+/// each manifest and an error is returned if it has a gap.
+pub fn restore_incremental<P, Q>(
+    full_backup: P,
+    incrementals: &[Q],
+) -> Result<()>
+where
+    P: AsRef<std::path::Path>,
+    Q: AsRef<std::path::Path>,
+{
+    use std::io::{Seek, SeekFrom};
+
+    let db_path = full_backup.as_ref().join("db");
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(&db_path)?;
+
+    let mut prev_stable: Option<Lsn> = None;
+
+    for dir in incrementals {
+        let dir = dir.as_ref();
+        let manifest =
+            std::fs::read_to_string(dir.join(INCREMENTAL_MANIFEST))?;
+        let (since_lsn, stable_lsn, segments) =
+            parse_incremental_manifest(&manifest)?;
+
+        if let Some(prev) = prev_stable {
+            if since_lsn > prev {
+                return Err(Error::Unsupported(format!(
+                    "gap in incremental backup chain: {:?} begins \
+                     at LSN {} but the previous backup ends at \
+                     LSN {}",
+                    dir, since_lsn, prev
+                )));
+            }
+            if stable_lsn < prev {
+                return Err(Error::Unsupported(format!(
+                    "incremental backup chain out of order: {:?} \
+                     ends at LSN {} but the previous backup \
+                     already ends at LSN {}",
+                    dir, stable_lsn, prev
+                )));
+            }
+        }
+        prev_stable = Some(stable_lsn);
+
+        for (lsn, offset, len) in segments {
+            let mut segment =
+                std::fs::File::open(dir.join(segment_file_name(lsn)))?;
+            file.seek(SeekFrom::Start(offset))?;
+            let copied = std::io::copy(&mut segment, &mut file)?;
+            if copied != len {
+                return Err(Error::Unsupported(format!(
+                    "segment file for LSN {} in {:?} is {} bytes, \
+                     but its manifest records {}",
+                    lsn, dir, copied, len
+                )));
+            }
+        }
+    }
+
+    file.sync_all()?;
+    Ok(())
+}
+
+fn segment_file_name(lsn: Lsn) -> String {
+    format!("seg.{:020}", lsn)
+}
+
+fn parse_incremental_manifest(
+    manifest: &str,
+) -> Result<(Lsn, Lsn, Vec<(Lsn, u64, u64)>)> {
+    fn field<T: std::str::FromStr>(token: Option<&str>) -> Result<T> {
+        token.and_then(|t| t.parse().ok()).ok_or_else(|| {
+            Error::Unsupported(
+                "malformed incremental backup manifest".into(),
+            )
+        })
+    }
+
+    let mut since_lsn: Option<Lsn> = None;
+    let mut stable_lsn: Option<Lsn> = None;
+    let mut segments = vec![];
+
+    for line in manifest.lines() {
+        let mut parts = line.split_whitespace();
+        match parts.next() {
+            Some("since_lsn") => since_lsn = Some(field(parts.next())?),
+            Some("stable_lsn") => stable_lsn = Some(field(parts.next())?),
+            Some("segment") => segments.push((
+                field(parts.next())?,
+                field(parts.next())?,
+                field(parts.next())?,
+            )),
+            _ => {
+                return Err(Error::Unsupported(
+                    "malformed incremental backup manifest".into(),
+                ));
+            }
+        }
+    }
+
+    match (since_lsn, stable_lsn) {
+        (Some(since), Some(stable)) => Ok((since, stable, segments)),
+        _ => Err(Error::Unsupported(
+            "malformed incremental backup manifest".into(),
+        )),
+    }
+}
+
+/// A backend that serves archived log segments by their base
+/// LSN, registered via
+/// [`set_segment_archive`](Db::set_segment_archive) so that
+/// locally unreadable pages can be transparently repaired from a
+/// backup archive, such as one shipped to object storage by a
+/// continuous [`backup_incremental`](Db::backup_incremental)
+/// pipeline.
+pub trait SegmentArchive: Send + Sync + 'static {
+    /// Returns the archived contents of the log segment whose
+    /// base LSN is `lsn`, or `Ok(None)` if the archive does not
+    /// hold that segment. The buffer may be shorter than the
+    /// configured segment size if the segment was archived before
+    /// being fully written.
+    fn fetch_segment(&self, lsn: Lsn) -> std::io::Result<Option<Vec<u8>>>;
+}
+
+// holds the registered archive backend, shared between the `Db`
+// handle it is registered through and the log read path that
+// consults it.
+pub(crate) struct ArchiveSlot {
+    pub(crate) archive: Mutex<Option<Box<dyn SegmentArchive>>>,
+}
+
+impl Debug for ArchiveSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ArchiveSlot {{ .. }}")
+    }
+}
+
+impl Default for ArchiveSlot {
+    fn default() -> ArchiveSlot {
+        ArchiveSlot { archive: Mutex::new(None) }
+    }
+}
+
+/// A [`SegmentArchive`] backed by one or more local or mounted
+/// directories of segment files as written by
+/// [`backup_incremental`](Db::backup_incremental). Directories
+/// are searched newest-first, so when a chain of incremental
+/// backups re-ships a segment, the most recent copy wins.
+#[derive(Debug, Clone)]
+pub struct DirectoryArchive {
+    dirs: Vec<std::path::PathBuf>,
+}
+
+impl DirectoryArchive {
+    /// Creates an archive over the given backup directories,
+    /// ordered oldest to newest.
+    pub fn new<P: AsRef<std::path::Path>>(dirs: &[P]) -> DirectoryArchive {
+        DirectoryArchive {
+            dirs: dirs.iter().map(|dir| dir.as_ref().to_path_buf()).collect(),
+        }
+    }
+}
+
+impl SegmentArchive for DirectoryArchive {
+    fn fetch_segment(&self, lsn: Lsn) -> std::io::Result<Option<Vec<u8>>> {
+        for dir in self.dirs.iter().rev() {
+            match std::fs::read(dir.join(segment_file_name(lsn))) {
+                Ok(buf) => return Ok(Some(buf)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+}
+
+// hidden tree id for the copy of one tree captured by a named
+// checkpoint. the checkpoint name is length-prefixed so that
+// distinct (name, tree) pairs can never collide.
+fn checkpoint_tree_id(name: &[u8], tree_id: &[u8]) -> Vec<u8> {
+    let mut id = CHECKPOINT_TREE_PREFIX.to_vec();
+    id.extend_from_slice(
+        &u32::try_from(name.len()).unwrap().to_be_bytes(),
+    );
+    id.extend_from_slice(name);
+    id.extend_from_slice(tree_id);
+    id
+}
+
+fn parse_checkpoint_manifest(manifest: &[u8]) -> Result<Vec<IVec>> {
+    let mut tree_ids = Vec::new();
+    let mut cursor = manifest;
+    while !cursor.is_empty() {
+        if cursor.len() < 4 {
+            return Err(Error::ReportableBug(
+                "checkpoint manifest is corrupted".into(),
+            ));
+        }
+        let mut len_buf = [0; 4];
+        len_buf.copy_from_slice(&cursor[..4]);
+        let len = usize::try_from(u32::from_be_bytes(len_buf)).unwrap();
+        cursor = &cursor[4..];
+        if cursor.len() < len {
+            return Err(Error::ReportableBug(
+                "checkpoint manifest is corrupted".into(),
+            ));
+        }
+        tree_ids.push(cursor[..len].into());
+        cursor = &cursor[len..];
+    }
+    Ok(tree_ids)
+}
+
+// deletes everything in `tree`. the caller must hold the
+// concurrency control write lock, so page access goes through
+// the internal paths that don't reacquire it.
+fn clear_tree_unprotected(tree: &Tree) -> Result<()> {
+    let mut iter = tree.iter();
+    let mut batch = Batch::default();
+    while let Some(kv) = iter.next_inner() {
+        batch.remove(kv?.0);
+    }
+    let mut guard = pin();
+    tree.apply_batch_inner(batch, None, &mut guard)
+}
+
+// clears `to` and refills it with the contents of `from`, in one
+// atomic batch. the caller must hold the concurrency control
+// write lock.
+fn copy_tree_unprotected(from: &Tree, to: &Tree) -> Result<()> {
+    let mut batch = Batch::default();
+
+    let mut deletes = to.iter();
+    while let Some(kv) = deletes.next_inner() {
+        batch.remove(kv?.0);
+    }
+
+    let mut inserts = from.iter();
+    while let Some(kv) = inserts.next_inner() {
+        let (key, value) = kv?;
+        batch.insert(key, value);
+    }
+
+    let mut guard = pin();
+    to.apply_batch_inner(batch, None, &mut guard)
+}
+
 impl Deref for Db {
     type Target = Tree;
 
@@ -33,6 +315,24 @@ impl Deref for Db {
     }
 }
 
+impl Drop for Db {
+    fn drop(&mut self) {
+        // persist lifetime statistics so that they survive
+        // restarts. best-effort: a failure here only loses
+        // statistics gathered since the last persistence point.
+        let stats = {
+            let state = self.stats_state.lock();
+            self.live_stats(&state)
+        };
+        let persist = self.stats_tree().and_then(|stats_tree| {
+            stats_tree.insert(STATS_KEY, stats_record(&stats).to_vec())
+        });
+        if let Err(e) = persist {
+            debug!("failed to persist lifetime statistics: {:?}", e);
+        }
+    }
+}
+
 impl Debug for Db {
     fn fmt(
         &self,
@@ -58,11 +358,26 @@ impl Db {
         Config::new().path(path).open()
     }
 
-    pub(crate) fn start_inner(config: RunningConfig) -> Result<Self> {
+    pub(crate) fn start_inner(
+        config: RunningConfig,
+        check_level: CheckLevel,
+    ) -> Result<Self> {
         let _measure = Measure::new(&M.tree_start);
 
+        // if an integrity manifest was written by a previous
+        // instance, detect missing or truncated storage files
+        // before attempting recovery.
+        if check_level != CheckLevel::None {
+            manifest::verify_manifest(&config.get_path())?;
+        }
+
         let context = Context::start(config)?;
 
+        let expiry_sweeps: tree::ExpirySweepRegistry =
+            std::sync::Arc::new(RwLock::new(Vec::new()));
+        *context.expiry_sweeps.write() =
+            std::sync::Arc::downgrade(&expiry_sweeps);
+
         #[cfg(all(
             not(miri),
             any(
@@ -76,15 +391,78 @@ impl Db {
             )
         ))]
         {
+            let panic_handler = |thread| poison::PanicHandler {
+                thread,
+                config: RunningConfig::clone(&context),
+                report: context.poison.clone(),
+            };
+
             let flusher_pagecache = context.pagecache.clone();
-            let flusher = context.flush_every_ms.map(move |fem| {
-                flusher::Flusher::new(
-                    "log flusher".to_owned(),
-                    flusher_pagecache,
-                    fem,
-                )
-            });
+            let flusher_progress = context.last_flush_progress.clone();
+            let flusher_expiry_sweeps =
+                std::sync::Arc::downgrade(&expiry_sweeps);
+            let flusher_panic_handler = panic_handler("flusher");
+            let flusher = if context.read_only {
+                // a read-only instance has nothing to flush
+                None
+            } else {
+                context.flush_every_ms.map(move |fem| {
+                    flusher::Flusher::new(
+                        "log flusher".to_owned(),
+                        flusher_pagecache,
+                        fem,
+                        flusher_progress,
+                        flusher_expiry_sweeps,
+                        flusher_panic_handler,
+                    )
+                })
+            };
             *context.flusher.lock() = flusher;
+
+            if context.scrub_segments_per_hour > 0 {
+                let scrubber = scrubber::Scrubber::new(
+                    "log scrubber".to_owned(),
+                    context.pagecache.clone(),
+                    context.scrub_errors.clone(),
+                    context.scrub_segments_per_hour,
+                    context.last_scrub_progress.clone(),
+                    panic_handler("scrubber"),
+                );
+                *context.scrubber.lock() = Some(scrubber);
+            }
+
+            if context.watchdog_stall_threshold_ms > 0 {
+                let mut watched = vec![];
+                if let (Some(fem), false) =
+                    (context.flush_every_ms, context.read_only)
+                {
+                    watched.push(watchdog::WatchedThread {
+                        name: "flusher",
+                        last_progress: context.last_flush_progress.clone(),
+                        cadence: fem,
+                        stalled: context.flusher_stalled.clone(),
+                    });
+                }
+                if context.scrub_segments_per_hour > 0 {
+                    watched.push(watchdog::WatchedThread {
+                        name: "scrubber",
+                        last_progress: context.last_scrub_progress.clone(),
+                        cadence: 60 * 60 * 1000
+                            / context.scrub_segments_per_hour.max(1),
+                        stalled: context.scrubber_stalled.clone(),
+                    });
+                }
+                if !watched.is_empty() {
+                    let watchdog = watchdog::Watchdog::new(
+                        "watchdog".to_owned(),
+                        watched,
+                        context.stall_callback.clone(),
+                        context.watchdog_stall_threshold_ms,
+                        panic_handler("watchdog"),
+                    );
+                    *context.watchdog.lock() = Some(watchdog);
+                }
+            }
         }
 
         // create or open the default tree
@@ -92,10 +470,40 @@ impl Db {
         let default =
             meta::open_tree(&context, DEFAULT_TREE_ID.to_vec(), &guard)?;
 
+        // only open the stats tree if a previous instance
+        // already created it; otherwise it is created lazily
+        // on the first statistics write.
+        let stats_tree =
+            match context.pagecache.meta_pid_for_name(STATS_TREE_ID, &guard) {
+                Ok(_) => Some(meta::open_tree(
+                    &context,
+                    STATS_TREE_ID.to_vec(),
+                    &guard,
+                )?),
+                Err(Error::CollectionNotFound(_)) => None,
+                Err(other) => return Err(other),
+            };
+        let persisted_stats = if let Some(ref stats_tree) = stats_tree {
+            if let Some(record) = stats_tree.get(STATS_KEY)? {
+                parse_stats_record(&record)
+            } else {
+                Stats::default()
+            }
+        } else {
+            Stats::default()
+        };
+
         let ret = Self {
             context: context.clone(),
             default,
             tenants: Arc::new(RwLock::new(FastMap8::default())),
+            stats_tree: Arc::new(RwLock::new(stats_tree)),
+            leases_tree: Arc::new(RwLock::new(None)),
+            stats_state: Arc::new(Mutex::new(StatsState {
+                base: persisted_stats,
+                live_at_reset: Stats::default(),
+            })),
+            expiry_sweeps,
         };
 
         let mut tenants = ret.tenants.write();
@@ -107,7 +515,24 @@ impl Db {
                 context: context.clone(),
                 root: AtomicU64::new(root),
                 merge_operator: RwLock::new(None),
+                indexes: RwLock::new(Vec::new()),
+                pre_commit_validators: RwLock::new(Vec::new()),
+                post_commit_callbacks: RwLock::new(Vec::new()),
+                foreign_keys_out: RwLock::new(Vec::new()),
+                foreign_keys_in: RwLock::new(Vec::new()),
+                soft_delete: RwLock::new(None),
+                audit: RwLock::new(None),
+                changefeed_log: RwLock::new(None),
+                idempotency: RwLock::new(None),
+                versioning: RwLock::new(None),
+                ttl: RwLock::new(None),
+                mutation_count: AtomicU64::new(0),
+                snapshots: RwLock::new(Vec::new()),
+                split_threshold_override: AtomicU64::new(0),
+                merge_threshold_override: AtomicU64::new(0),
             }));
+            tree.attach_persisted_ttl(&guard)?;
+            tree.attach_persisted_options(&guard)?;
             assert!(tenants.insert(id, tree).is_none());
         }
 
@@ -121,6 +546,16 @@ impl Db {
             ret.context.event_log.verify();
         }
 
+        if check_level == CheckLevel::Full {
+            // page in everything reachable from the page table,
+            // which verifies checksums as data is read from disk,
+            // and walk every tree's contents.
+            ret.context.pagecache.space_amplification()?;
+            for (_name, tree) in ret.tenants.read().iter() {
+                tree.checksum()?;
+            }
+        }
+
         Ok(ret)
     }
 
@@ -151,6 +586,293 @@ impl Db {
         Ok(tree)
     }
 
+    /// Open or create a new disk-backed Tree with its own
+    /// keyspace, applying per-tree configuration overrides that
+    /// differ from the global `Config`. The overrides are
+    /// persisted in the meta tree and reapplied when the
+    /// database is reopened, so they only need to be supplied
+    /// once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use std::convert::TryInto;
+    ///
+    /// use sled::TreeOptions;
+    ///
+    /// db.register_merge_operator("counter", |_k, old, new| {
+    ///     let old = old.map_or(0, |v| u64::from_le_bytes(v.try_into().unwrap()));
+    ///     let new = u64::from_le_bytes(new.try_into().unwrap());
+    ///     Some((old + new).to_le_bytes().to_vec())
+    /// });
+    ///
+    /// // the metadata tree has many small hot keys, so split
+    /// // its nodes earlier than the global default
+    /// let metadata = db.open_tree_with_options(
+    ///     "metadata",
+    ///     TreeOptions::new()
+    ///         .split_threshold(512)
+    ///         .merge_operator_name("counter"),
+    /// )?;
+    ///
+    /// metadata.merge(b"hits", &1_u64.to_le_bytes())?;
+    /// metadata.merge(b"hits", &1_u64.to_le_bytes())?;
+    /// assert_eq!(
+    ///     metadata.get(b"hits")?,
+    ///     Some(sled::IVec::from(&2_u64.to_le_bytes())),
+    /// );
+    /// # Ok(()) }
+    /// ```
+    pub fn open_tree_with_options<V: AsRef<[u8]>>(
+        &self,
+        name: V,
+        options: TreeOptions,
+    ) -> Result<Tree> {
+        let tree = self.open_tree(name.as_ref())?;
+
+        let guard = pin();
+        let records = meta::open_tree(
+            &self.context,
+            TREE_OPTIONS_TREE_ID.to_vec(),
+            &guard,
+        )?;
+        records.insert(&tree.tree_id, options.encode())?;
+
+        tree.apply_options(&options)?;
+
+        Ok(tree)
+    }
+
+    /// Open or create many disk-backed Trees in one call, returning
+    /// handles in the same order as the provided names. The tenant
+    /// metadata is locked once for the whole group rather than once
+    /// per tree, making this cheaper at startup than N sequential
+    /// `open_tree` calls for applications with a fixed schema.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let trees = db.open_trees(&["users", "posts", "sessions"])?;
+    /// assert_eq!(trees.len(), 3);
+    /// assert_eq!(trees[1].name(), sled::IVec::from("posts"));
+    /// # Ok(()) }
+    /// ```
+    pub fn open_trees<V: AsRef<[u8]>>(
+        &self,
+        names: &[V],
+    ) -> Result<Vec<Tree>> {
+        let guard = pin();
+
+        let mut tenants = self.tenants.write();
+
+        let mut ret = Vec::with_capacity(names.len());
+        for name in names {
+            let name_ref = name.as_ref();
+            let tree = if let Some(tree) = tenants.get(name_ref) {
+                tree.clone()
+            } else {
+                let tree =
+                    meta::open_tree(&self.context, name_ref.to_vec(), &guard)?;
+                assert!(tenants.insert(name_ref.into(), tree.clone()).is_none());
+                tree
+            };
+            ret.push(tree);
+        }
+
+        Ok(ret)
+    }
+
+    /// Catches a read-only instance up to the writer's latest
+    /// stable LSN by replaying the log segments written since
+    /// this handle's snapshot was taken, returning the log
+    /// offset it has caught up to.
+    ///
+    /// A database opened with `Config::read_only` sees a
+    /// snapshot of the data directory as of open, while a live
+    /// writer in another process keeps appending to the shared
+    /// log. Together they allow one writer plus any number of
+    /// reader processes over one directory, with one caveat:
+    /// the writer's garbage collection may rewrite a log
+    /// segment that a reader's snapshot still references, in
+    /// which case reads that fault in pages from that segment
+    /// fail with `Error::Corruption`. Such a reader is catching
+    /// up to a state that does exist — it should `refresh` (or
+    /// re-open) and retry rather than treat the error as data
+    /// loss. `Tree` handles and clones of this `Db` taken
+    /// before the refresh keep serving the old snapshot and
+    /// should be re-opened from the refreshed handle.
+    ///
+    /// Returns `Error::Unsupported` for instances not opened
+    /// in read-only mode, which follow the log as it is
+    /// written and have nothing to catch up to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let dir = std::env::temp_dir().join("refresh_example");
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// let writer = sled::Config::new().path(&dir).open()?;
+    /// writer.insert(b"a", b"1")?;
+    /// writer.flush()?;
+    ///
+    /// let mut reader =
+    ///     sled::Config::new().path(&dir).read_only(true).open()?;
+    /// assert_eq!(&reader.get(b"a")?.unwrap(), b"1");
+    ///
+    /// writer.insert(b"b", b"2")?;
+    /// writer.flush()?;
+    ///
+    /// // the reader still sees its snapshot-at-open
+    /// assert_eq!(reader.get(b"b")?, None);
+    ///
+    /// reader.refresh()?;
+    /// assert_eq!(&reader.get(b"b")?.unwrap(), b"2");
+    /// # drop(reader);
+    /// # drop(writer);
+    /// # let _ = std::fs::remove_dir_all(&dir);
+    /// # Ok(()) }
+    /// ```
+    pub fn refresh(&mut self) -> Result<Lsn> {
+        if !self.context.read_only {
+            return Err(Error::Unsupported(
+                "Db::refresh is only meaningful for instances \
+                 opened with Config::read_only"
+                    .to_string(),
+            ));
+        }
+
+        // recovery reads the shared log up to its last stable
+        // entry, so re-running it over the live directory is
+        // exactly a replay of everything the writer has made
+        // durable since our snapshot was taken. the manifest
+        // check is skipped because the writer may be mid-write
+        // to it.
+        let config = Config::clone(&self.context);
+        let fresh = config.open_check(CheckLevel::None)?;
+        let stable_lsn = fresh.context.pagecache.log.stable_offset();
+        *self = fresh;
+        Ok(stable_lsn)
+    }
+
+    /// Trains a zstd compression dictionary from a sample of the
+    /// values currently stored in the database, persists it, and
+    /// begins using it for subsequently written values, returning
+    /// the size of the trained dictionary in bytes.
+    ///
+    /// Plain zstd barely helps for small values, because each one
+    /// is compressed in isolation with nothing to refer back to.
+    /// A dictionary trained on a sample of existing values gives
+    /// the compressor that shared context, and routinely doubles
+    /// or triples the ratio achieved on values of around a
+    /// hundred bytes. Call it once the database holds a
+    /// representative population of values.
+    ///
+    /// Previously written data is unaffected and remains
+    /// readable, as every compressed buffer records whether the
+    /// dictionary was used. For the same reason a dictionary can
+    /// only be trained once per database: retraining would leave
+    /// buffers compressed against the old dictionary unreadable,
+    /// so a second call returns `Error::Unsupported`. The
+    /// dictionary is persisted in its own file beside the log
+    /// rather than in a tree, because log recovery itself needs
+    /// it before any tree contents can be read.
+    ///
+    /// Only takes effect when `Config::use_compression` is set
+    /// and `Config::compression_algorithm` is
+    /// `CompressionAlgorithm::Zstd`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new()
+    /// #     .temporary(true)
+    /// #     .use_compression(true);
+    /// # let db = config.open()?;
+    /// for i in 0..1024_u32 {
+    ///     let value = format!("{{\"id\": {}, \"active\": true}}", i);
+    ///     db.insert(&i.to_be_bytes(), value.as_bytes())?;
+    /// }
+    ///
+    /// let dictionary_len = db.train_compression_dictionary()?;
+    /// assert!(dictionary_len > 0);
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "compression")]
+    pub fn train_compression_dictionary(&self) -> Result<usize> {
+        use crate::compression::DICTIONARY_FILE_NAME;
+
+        const MAX_DICTIONARY_LEN: usize = 16 * 1024;
+        const MAX_SAMPLES: usize = 10_000;
+        const MAX_SAMPLE_BYTES: usize = 4 * 1024 * 1024;
+
+        self.ensure_writable()?;
+
+        let dictionary_path =
+            self.context.get_path().join(DICTIONARY_FILE_NAME);
+        if dictionary_path.exists() {
+            return Err(Error::Unsupported(
+                "a compression dictionary has already been trained \
+                 for this database, and retraining would leave \
+                 values compressed against the existing dictionary \
+                 unreadable"
+                    .to_string(),
+            ));
+        }
+
+        let mut trees = vec![self.default.clone()];
+        trees.extend(self.tenants.read().values().cloned());
+
+        let mut samples: Vec<IVec> = vec![];
+        let mut sample_bytes = 0_usize;
+        'sampling: for tree in &trees {
+            for kv in tree.iter() {
+                let (_key, value) = kv?;
+                sample_bytes += value.len();
+                samples.push(value);
+                if samples.len() >= MAX_SAMPLES
+                    || sample_bytes >= MAX_SAMPLE_BYTES
+                {
+                    break 'sampling;
+                }
+            }
+        }
+
+        if samples.len() < 8 {
+            return Err(Error::Unsupported(
+                "too few values are stored to train a useful \
+                 compression dictionary; insert a representative \
+                 population of values first"
+                    .to_string(),
+            ));
+        }
+
+        let dictionary =
+            zstd::dict::from_samples(&samples, MAX_DICTIONARY_LEN)?;
+
+        // persist through a rename so that a crash mid-write can
+        // never leave a truncated dictionary for the next open
+        let tmp_path = self
+            .context
+            .get_path()
+            .join(format!("{}.generating", DICTIONARY_FILE_NAME));
+        std::fs::write(&tmp_path, &dictionary)?;
+        std::fs::File::open(&tmp_path)?.sync_all()?;
+        std::fs::rename(&tmp_path, &dictionary_path)?;
+        maybe_fsync_directory(self.context.get_path())?;
+
+        self.context.zstd_dictionary.set(dictionary.clone());
+
+        Ok(dictionary.len())
+    }
+
     /// Remove a disk-backed collection. This is blocking and fairly slow.
     pub fn drop_tree<V: AsRef<[u8]>>(&self, name: V) -> Result<bool> {
         let name_ref = name.as_ref();
@@ -159,6 +881,7 @@ impl Db {
                 "cannot remove the core structures".into(),
             ));
         }
+        self.ensure_writable()?;
         trace!("dropping tree {:?}", name_ref,);
 
         let mut tenants = self.tenants.write();
@@ -169,8 +892,25 @@ impl Db {
             return Ok(false);
         };
 
+        let mut guard = pin();
+
+        // opened before the exclusive session below, so that the
+        // handle (whose drop flushes) outlives the lock
+        let options_registry = match self
+            .context
+            .pagecache
+            .meta_pid_for_name(TREE_OPTIONS_TREE_ID, &guard)
+        {
+            Ok(_) => Some(meta::open_tree(
+                &self.context,
+                TREE_OPTIONS_TREE_ID.to_vec(),
+                &guard,
+            )?),
+            Err(Error::CollectionNotFound(_)) => None,
+            Err(other) => return Err(other),
+        };
+
         let _cc = concurrency_control::write();
-        let guard = pin();
 
         // peg is for atomic recovery in case we crash
         // half-way through this cleaning operation.
@@ -213,6 +953,14 @@ impl Db {
         // drop writer lock
         drop(tenants);
 
+        // clear any persisted per-tree options so a future tree
+        // created under this name starts from the global config
+        if let Some(registry) = &options_registry {
+            let mut cleanup = Batch::default();
+            cleanup.remove(name_ref);
+            registry.apply_batch_inner(cleanup, None, &mut guard)?;
+        }
+
         tree.gc_pages(leftmost_chain)?;
 
         guard.flush();
@@ -220,77 +968,301 @@ impl Db {
         Ok(true)
     }
 
-    /// Returns the trees names saved in this Db.
-    pub fn tree_names(&self) -> Vec<IVec> {
-        let tenants = self.tenants.read();
-        tenants.iter().map(|(name, _)| name.clone()).collect()
-    }
-
-    /// Returns `true` if the database was
-    /// recovered from a previous process.
-    /// Note that database state is only
-    /// guaranteed to be present up to the
-    /// last call to `flush`! Otherwise state
-    /// is synced to disk periodically if the
-    /// `sync_every_ms` configuration option
-    /// is set to `Some(number_of_ms_between_syncs)`
-    /// or if the IO buffer gets filled to
-    /// capacity before being rotated.
-    pub fn was_recovered(&self) -> bool {
-        self.context.was_recovered()
-    }
-
-    /// Generate a monotonic ID. Not guaranteed to be
-    /// contiguous. Written to disk every `idgen_persist_interval`
-    /// operations, followed by a blocking flush. During recovery, we
-    /// take the last recovered generated ID and add 2x
-    /// the `idgen_persist_interval` to it. While persisting, if the
-    /// previous persisted counter wasn't synced to disk yet, we will do
-    /// a blocking flush to fsync the latest counter, ensuring
-    /// that we will never give out the same counter twice.
-    pub fn generate_id(&self) -> Result<u64> {
-        self.context.generate_id()
-    }
-
-    /// A database export method for all collections in the `Db`,
-    /// for use in sled version upgrades. Can be used in combination
-    /// with the `import` method below on a database running a later
-    /// version.
-    ///
-    /// # Panics
+    /// Registers a merge operator under a name, so that trees
+    /// which persisted that name via `Tree::set_merge_operator_name`
+    /// can resolve it on their first merge after opening.
     ///
-    /// Panics if any IO problems occur while trying
-    /// to perform the export.
+    /// Registration is per-`Db` and must be repeated each time the
+    /// database is opened, before the first `merge` on a tree that
+    /// requires the operator. If a tree requires a named operator
+    /// that has not been registered, `merge` returns an
+    /// `Unsupported` error naming the missing operator.
     ///
     /// # Examples
     ///
-    /// If you want to migrate from one version of sled
-    /// to another, you need to pull in both versions
-    /// by using version renaming:
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.register_merge_operator("maximize", |_k, old, new| {
+    ///     let max = match old {
+    ///         Some(old) if old > new => old.to_vec(),
+    ///         _ => new.to_vec(),
+    ///     };
+    ///     Some(max)
+    /// });
     ///
-    /// `Cargo.toml`:
+    /// let tree = db.open_tree("high_scores")?;
+    /// tree.set_merge_operator_name("maximize")?;
     ///
-    /// ```toml
-    /// [dependencies]
-    /// sled = "0.32"
-    /// old_sled = { version = "0.31", package = "sled" }
+    /// tree.merge(b"k1", vec![5])?;
+    /// tree.merge(b"k1", vec![3])?;
+    /// assert_eq!(tree.get(b"k1")?, Some(sled::IVec::from(vec![5])));
+    /// # Ok(()) }
     /// ```
+    pub fn register_merge_operator<N: Into<String>>(
+        &self,
+        name: N,
+        merge_operator: impl MergeOperator + 'static,
+    ) {
+        self.context
+            .merge_operators
+            .0
+            .write()
+            .insert(name.into(), std::sync::Arc::new(merge_operator));
+    }
+
+    /// Registers a key order under a name, for use by trees
+    /// viewed through [`Tree::ordered_by`](crate::Tree::ordered_by).
+    /// Registering a new order under an existing name replaces
+    /// it.
     ///
-    /// and in your code, remember that old versions of
-    /// sled might have a different way to open them
-    /// than the current `sled::open` method:
+    /// # Examples
     ///
     /// ```
-    /// # use sled as old_sled;
     /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
-    /// let old = old_sled::open("my_old_db")?;
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.register_key_order("ascii_ci", sled::AsciiCaseInsensitive);
     ///
-    /// // may be a different version of sled,
-    /// // the export type is version agnostic.
-    /// let new = sled::open("my_new_db")?;
+    /// let names = db.open_tree("names")?.ordered_by("ascii_ci")?;
+    /// names.insert(b"Walrus", b"1")?;
+    /// names.insert(b"aardvark", b"2")?;
     ///
-    /// let export = old.export();
-    /// new.import(export);
+    /// let (first, _) = names.first()?.unwrap();
+    /// assert_eq!(&first, b"aardvark");
+    /// # Ok(()) }
+    /// ```
+    pub fn register_key_order<N: Into<String>>(
+        &self,
+        name: N,
+        order: impl KeyOrder + 'static,
+    ) {
+        self.context
+            .key_orders
+            .0
+            .write()
+            .insert(name.into(), std::sync::Arc::new(order));
+    }
+
+    /// Merges the contents of two trees into a third with a
+    /// single sorted pass, returning the number of records
+    /// written to `dest`.
+    ///
+    /// Keys present in only one source are copied through; for
+    /// keys present in both, `conflict_resolver` is called with
+    /// the key and both values and returns the value to keep, or
+    /// `None` to leave the key out of `dest`. When `dest` is
+    /// empty its pages are built directly from the merged stream
+    /// via the same machinery as [`Tree::bulk_load`], which is
+    /// far faster than reinserting through the public API; a
+    /// non-empty `dest` falls back to point inserts. The sources
+    /// are read through a racy snapshot, so writes racing the
+    /// merge may or may not be reflected, and the merged stream
+    /// is buffered in memory before `dest` is built.
+    ///
+    /// `dest` may not be one of the sources.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let ours = db.open_tree("ours")?;
+    /// let theirs = db.open_tree("theirs")?;
+    /// let merged = db.open_tree("merged")?;
+    ///
+    /// ours.insert(b"a", b"1")?;
+    /// ours.insert(b"b", b"ours")?;
+    /// theirs.insert(b"b", b"theirs")?;
+    /// theirs.insert(b"c", b"3")?;
+    ///
+    /// // keep our side of any conflict
+    /// let written = db.merge_trees(&ours, &theirs, &merged, |_k, a, _b| {
+    ///     Some(a.to_vec())
+    /// })?;
+    ///
+    /// assert_eq!(written, 3);
+    /// assert_eq!(merged.get(b"b")?, Some(sled::IVec::from(b"ours")));
+    /// assert_eq!(merged.get(b"c")?, Some(sled::IVec::from(b"3")));
+    /// # Ok(()) }
+    /// ```
+    pub fn merge_trees<F>(
+        &self,
+        a: &Tree,
+        b: &Tree,
+        dest: &Tree,
+        conflict_resolver: F,
+    ) -> Result<u64>
+    where
+        F: Fn(&[u8], &[u8], &[u8]) -> Option<Vec<u8>>,
+    {
+        if dest.tree_id == a.tree_id || dest.tree_id == b.tree_id {
+            return Err(Error::Unsupported(
+                "Db::merge_trees destination may not be one of \
+                 the source trees"
+                    .to_owned(),
+            ));
+        }
+
+        let mut iter_a = a.iter();
+        let mut iter_b = b.iter();
+        let mut head_a = iter_a.next().transpose()?;
+        let mut head_b = iter_b.next().transpose()?;
+
+        let mut merged: Vec<(IVec, IVec)> = Vec::new();
+        loop {
+            match (&head_a, &head_b) {
+                (Some((ka, _)), Some((kb, _))) if ka < kb => {
+                    let (k, v) = head_a.take().unwrap();
+                    merged.push((k, v));
+                    head_a = iter_a.next().transpose()?;
+                }
+                (Some((ka, _)), Some((kb, _))) if ka > kb => {
+                    let (k, v) = head_b.take().unwrap();
+                    merged.push((k, v));
+                    head_b = iter_b.next().transpose()?;
+                }
+                (Some(_), Some(_)) => {
+                    let (k, va) = head_a.take().unwrap();
+                    let (_, vb) = head_b.take().unwrap();
+                    if let Some(v) = conflict_resolver(&k, &va, &vb) {
+                        merged.push((k, v.into()));
+                    }
+                    head_a = iter_a.next().transpose()?;
+                    head_b = iter_b.next().transpose()?;
+                }
+                (Some(_), None) => {
+                    let (k, v) = head_a.take().unwrap();
+                    merged.push((k, v));
+                    head_a = iter_a.next().transpose()?;
+                }
+                (None, Some(_)) => {
+                    let (k, v) = head_b.take().unwrap();
+                    merged.push((k, v));
+                    head_b = iter_b.next().transpose()?;
+                }
+                (None, None) => break,
+            }
+        }
+
+        dest.bulk_load(merged)
+    }
+
+    /// Returns the trees names saved in this Db.
+    pub fn tree_names(&self) -> Vec<IVec> {
+        let tenants = self.tenants.read();
+        tenants.iter().map(|(name, _)| name.clone()).collect()
+    }
+
+    /// Returns the number of log segments that the background
+    /// scrubber has failed to read back since this instance was
+    /// opened. Always `0` unless scrubbing was enabled via
+    /// `Config::scrub_segments_per_hour`. A non-zero value
+    /// indicates latent media corruption: details are reported
+    /// through the log, and affected data should be restored
+    /// from backups.
+    pub fn scrub_error_count(&self) -> u64 {
+        self.context.scrub_errors.load(SeqCst)
+    }
+
+    /// Returns the optional filesystem features that were
+    /// detected when this database was opened. The storage
+    /// layer probes for these against a scratch file in the
+    /// database directory and chooses its strategies
+    /// accordingly: for example, freed heap slots are only
+    /// hole-punched on filesystems that confirmed support.
+    /// Useful for debugging performance or space usage on
+    /// unfamiliar filesystems.
+    ///
+    /// All capabilities are reported as absent when the
+    /// database was opened in read-only mode or on platforms
+    /// where probing is not implemented.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let capabilities = db.storage_capabilities();
+    /// println!("storage capabilities: {:?}", capabilities);
+    /// # Ok(()) }
+    /// ```
+    pub fn storage_capabilities(&self) -> StorageCapabilities {
+        self.context.storage_capabilities
+    }
+
+    /// Returns `true` if the database was
+    /// recovered from a previous process.
+    /// Note that database state is only
+    /// guaranteed to be present up to the
+    /// last call to `flush`! Otherwise state
+    /// is synced to disk periodically if the
+    /// `sync_every_ms` configuration option
+    /// is set to `Some(number_of_ms_between_syncs)`
+    /// or if the IO buffer gets filled to
+    /// capacity before being rotated.
+    pub fn was_recovered(&self) -> bool {
+        self.context.was_recovered()
+    }
+
+    /// Generate a monotonic ID. Not guaranteed to be
+    /// contiguous. Written to disk every `idgen_persist_interval`
+    /// operations, followed by a blocking flush. During recovery, we
+    /// take the last recovered generated ID and add 2x
+    /// the `idgen_persist_interval` to it. While persisting, if the
+    /// previous persisted counter wasn't synced to disk yet, we will do
+    /// a blocking flush to fsync the latest counter, ensuring
+    /// that we will never give out the same counter twice.
+    pub fn generate_id(&self) -> Result<u64> {
+        // the counter is periodically persisted through the
+        // shared log, which a read-only instance must not
+        // write to
+        self.ensure_writable()?;
+        self.context.generate_id()
+    }
+
+    /// A database export method for all collections in the `Db`,
+    /// for use in sled version upgrades. Can be used in combination
+    /// with the `import` method below on a database running a later
+    /// version.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any IO problems occur while trying
+    /// to perform the export.
+    ///
+    /// # Examples
+    ///
+    /// If you want to migrate from one version of sled
+    /// to another, you need to pull in both versions
+    /// by using version renaming:
+    ///
+    /// `Cargo.toml`:
+    ///
+    /// ```toml
+    /// [dependencies]
+    /// sled = "0.32"
+    /// old_sled = { version = "0.31", package = "sled" }
+    /// ```
+    ///
+    /// and in your code, remember that old versions of
+    /// sled might have a different way to open them
+    /// than the current `sled::open` method:
+    ///
+    /// ```
+    /// # use sled as old_sled;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let old = old_sled::open("my_old_db")?;
+    ///
+    /// // may be a different version of sled,
+    /// // the export type is version agnostic.
+    /// let new = sled::open("my_new_db")?;
+    ///
+    /// let export = old.export();
+    /// new.import(export);
     ///
     /// assert_eq!(old.checksum()?, new.checksum()?);
     /// # drop(old);
@@ -308,6 +1280,14 @@ impl Db {
         let mut ret = vec![];
 
         for (name, tree) in tenants.iter() {
+            if name.as_ref() == STATS_TREE_ID
+                || name.as_ref() == COORDINATION_TREE_ID
+            {
+                // lifetime statistics and transaction coordination
+                // records describe this instance's storage
+                // activity, not user data
+                continue;
+            }
             ret.push((
                 b"tree".to_vec(),
                 name.to_vec(),
@@ -400,6 +1380,210 @@ impl Db {
         }
     }
 
+    /// Export every collection in the `Db` into a single
+    /// self-contained, checksummed archive written to the provided
+    /// writer, suitable for shipping through object storage or CI
+    /// artifact systems as one file. When the `compression` feature
+    /// is enabled the archive body is zstd-compressed. Restore with
+    /// `Db::import_archive`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"k1", b"v1")?;
+    ///
+    /// let mut archive = vec![];
+    /// db.export_archive(&mut archive)?;
+    ///
+    /// # let config = sled::Config::new().temporary(true);
+    /// let restored = config.open()?;
+    /// restored.import_archive(&archive[..])?;
+    /// assert_eq!(restored.get(b"k1")?, Some(sled::IVec::from(b"v1")));
+    /// # Ok(()) }
+    /// ```
+    pub fn export_archive<W: Write>(&self, writer: W) -> Result<()> {
+        self.export_archive_inner(writer, None)
+    }
+
+    /// Like `Db::export_archive`, but observes the provided
+    /// [`CancellationToken`] so a long export can be stopped
+    /// early during shutdown. The token is checked between
+    /// key-value pairs: on cancellation the export stops cleanly
+    /// with `Error::Cancelled` before anything is written, and
+    /// the token's reported progress is the number of pairs that
+    /// had been serialized.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use sled::{CancellationToken, Error};
+    ///
+    /// db.insert(b"k1", b"v1")?;
+    ///
+    /// let token = CancellationToken::new();
+    /// token.cancel();
+    ///
+    /// let mut archive = vec![];
+    /// let res = db.export_archive_with_cancellation(&mut archive, &token);
+    /// assert_eq!(res, Err(Error::Cancelled));
+    /// assert!(archive.is_empty());
+    /// # Ok(()) }
+    /// ```
+    pub fn export_archive_with_cancellation<W: Write>(
+        &self,
+        writer: W,
+        token: &CancellationToken,
+    ) -> Result<()> {
+        self.export_archive_inner(writer, Some(token))
+    }
+
+    fn export_archive_inner<W: Write>(
+        &self,
+        mut writer: W,
+        token: Option<&CancellationToken>,
+    ) -> Result<()> {
+        let tenants = self.tenants.read();
+
+        // we use a btreemap to ensure lexicographic iteration
+        // over tree names for deterministic archives.
+        let ordered: BTreeMap<_, _> = tenants.iter().collect();
+
+        let mut body = vec![];
+        for (name, tree) in &ordered {
+            if name.as_ref() == STATS_TREE_ID
+                || name.as_ref() == COORDINATION_TREE_ID
+            {
+                // lifetime statistics and transaction coordination
+                // records describe this instance's storage
+                // activity, not user data
+                continue;
+            }
+            let name_len = name.len() as u64;
+            body.extend_from_slice(&name_len.to_le_bytes());
+            body.extend_from_slice(name);
+            for kv in tree.iter() {
+                if let Some(token) = token {
+                    token.check()?;
+                }
+                let (k, v) = kv?;
+                if let Some(token) = token {
+                    token.note_progress(1);
+                }
+                body.extend_from_slice(&(k.len() as u64).to_le_bytes());
+                body.extend_from_slice(&k);
+                body.extend_from_slice(&(v.len() as u64).to_le_bytes());
+                body.extend_from_slice(&v);
+            }
+            // per-tree end sentinel
+            body.extend_from_slice(&u64::max_value().to_le_bytes());
+        }
+
+        #[cfg(feature = "compression")]
+        let (flag, body) = {
+            use zstd::stream::encode_all;
+            let compressed =
+                encode_all(&*body, 3).expect("failed to compress archive");
+            (ARCHIVE_ZSTD, compressed)
+        };
+
+        #[cfg(not(feature = "compression"))]
+        let flag = ARCHIVE_RAW;
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&body);
+
+        writer.write_all(ARCHIVE_MAGIC)?;
+        writer.write_all(&[flag])?;
+        writer.write_all(&(body.len() as u64).to_le_bytes())?;
+        writer.write_all(&body)?;
+        writer.write_all(&hasher.finalize().to_le_bytes())?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Imports the collections from an archive previously produced
+    /// by `Db::export_archive`, verifying its checksum before any
+    /// data is applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Corruption` if the archive is malformed,
+    /// truncated, or fails checksum verification, and
+    /// `Error::Unsupported` if the archive is compressed but this
+    /// build lacks the `compression` feature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the archive would overwrite existing data.
+    pub fn import_archive<R: Read>(&self, mut reader: R) -> Result<()> {
+        let mut magic = [0; 16];
+        reader.read_exact(&mut magic)?;
+        if magic != ARCHIVE_MAGIC {
+            return Err(Error::corruption(None));
+        }
+
+        let mut flag = [0];
+        reader.read_exact(&mut flag)?;
+
+        let mut len_arr = [0; 8];
+        reader.read_exact(&mut len_arr)?;
+        let body_len = usize::try_from(u64::from_le_bytes(len_arr))
+            .map_err(|_| Error::corruption(None))?;
+
+        let mut body = vec![0; body_len];
+        reader.read_exact(&mut body)?;
+
+        let mut crc_arr = [0; 4];
+        reader.read_exact(&mut crc_arr)?;
+
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&body);
+        if hasher.finalize() != u32::from_le_bytes(crc_arr) {
+            return Err(Error::corruption(None));
+        }
+
+        let body = match flag[0] {
+            ARCHIVE_RAW => body,
+            #[cfg(feature = "compression")]
+            ARCHIVE_ZSTD => zstd::stream::decode_all(&body[..])
+                .map_err(|_| Error::corruption(None))?,
+            #[cfg(not(feature = "compression"))]
+            ARCHIVE_ZSTD => {
+                return Err(Error::Unsupported(
+                    "cannot import a compressed archive without \
+                     the compression feature"
+                        .into(),
+                ));
+            }
+            _ => return Err(Error::corruption(None)),
+        };
+
+        let mut cursor = ArchiveCursor { buf: &body, idx: 0 };
+        while !cursor.done() {
+            let name = cursor.read_chunk()?;
+            let tree = self.open_tree(name)?;
+            loop {
+                let k = if let Some(k) = cursor.read_entry_key()? {
+                    k
+                } else {
+                    break;
+                };
+                let v = cursor.read_chunk()?;
+                let old = tree.insert(k, v)?;
+                assert!(old.is_none(), "import is overwriting existing data");
+            }
+        }
+
+        Ok(())
+    }
+
     /// Returns the CRC32 of all keys and values
     /// in this Db.
     ///
@@ -417,6 +1601,14 @@ impl Db {
         let _cc = concurrency_control::write();
 
         for (name, tree) in &tenants {
+            if name.as_ref() != DEFAULT_TREE_ID
+                && name.starts_with(INTERNAL_TREE_PREFIX)
+            {
+                // internal bookkeeping trees are excluded from
+                // `export`, so they must not influence the
+                // checksum either.
+                continue;
+            }
             hasher.update(name);
 
             let mut iter = tree.iter();
@@ -430,19 +1622,1982 @@ impl Db {
         Ok(hasher.finalize())
     }
 
+    /// Flush all dirty data and atomically write an integrity
+    /// manifest recording the length and checksum of every storage
+    /// file in the database directory. On subsequent `Db::open`
+    /// calls (and explicit `verify_integrity_manifest` calls),
+    /// missing or truncated files are detected immediately with an
+    /// error naming the culprit file. Files that have legitimately
+    /// grown through appends since the manifest was written still
+    /// verify, as only the recorded prefix is checksummed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"k1", b"v1")?;
+    /// db.write_integrity_manifest()?;
+    /// db.verify_integrity_manifest()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn write_integrity_manifest(&self) -> Result<()> {
+        self.flush()?;
+        manifest::write_manifest(&self.context.get_path())
+    }
+
+    /// Verify the storage files of this database against the
+    /// integrity manifest, if one has been written. Returns an
+    /// error naming the first file found to be missing, truncated,
+    /// or corrupted, and `Ok(())` if everything verifies (or no
+    /// manifest exists).
+    pub fn verify_integrity_manifest(&self) -> Result<()> {
+        manifest::verify_manifest(&self.context.get_path())
+    }
+
     /// Returns the on-disk size of the storage files
     /// for this database.
+    ///
+    /// This is the apparent size, which can overstate the true
+    /// disk consumption when segments are sparse or hole-punched.
+    /// See `Db::disk_usage` for a block-allocation-aware breakdown.
     pub fn size_on_disk(&self) -> Result<u64> {
         self.context.pagecache.size_on_disk()
     }
 
-    /// Traverses all files and calculates their total physical
-    /// size, then traverses all pages and calculates their
-    /// total logical size, then divides the physical size
-    /// by the logical size.
-    #[doc(hidden)]
-    pub fn space_amplification(&self) -> Result<f64> {
-        self.context.pagecache.space_amplification()
+    /// Returns the live log bytes attributed to each tree this
+    /// instance has open, including the default tree, sorted by
+    /// name. See [`Tree::size_on_disk`](crate::Tree::size_on_disk)
+    /// for what the per-tree figure does and does not include.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let tenant = db.open_tree("tenant")?;
+    /// tenant.insert(b"k", vec![0; 1024])?;
+    /// tenant.flush()?;
+    ///
+    /// let sizes = db.tree_sizes()?;
+    /// assert!(sizes.iter().any(|(name, _)| *name == "tenant"));
+    /// # Ok(()) }
+    /// ```
+    pub fn tree_sizes(&self) -> Result<Vec<(IVec, u64)>> {
+        let tenants: Vec<(IVec, Tree)> = self
+            .tenants
+            .read()
+            .iter()
+            .map(|(name, tree)| (name.clone(), tree.clone()))
+            .collect();
+
+        let mut sizes = Vec::with_capacity(tenants.len());
+        for (name, tree) in tenants {
+            sizes.push((name, tree.size_on_disk()?));
+        }
+        sizes.sort();
+        Ok(sizes)
+    }
+
+    /// Returns a sparse-file-aware breakdown of disk usage:
+    /// the apparent length of all storage files, the size the
+    /// filesystem has actually allocated for them (which can be
+    /// lower when segments are sparse or hole-punched), and the
+    /// logical size of each tree's keys and values for attributing
+    /// usage between keyspaces.
+    ///
+    /// This scans every tree to compute the per-tree attribution,
+    /// so it is O(N) in the total number of entries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"key", b"value")?;
+    /// db.flush()?;
+    ///
+    /// let usage = db.disk_usage()?;
+    /// assert!(usage.apparent_bytes > 0);
+    /// assert_eq!(usage.tree_logical_bytes[b"__sled__default".as_ref()], 8);
+    /// # Ok(()) }
+    /// ```
+    pub fn disk_usage(&self) -> Result<DiskUsage> {
+        let (apparent_bytes, allocated_bytes) =
+            self.context.pagecache.disk_usage()?;
+
+        let tenants = self.tenants.read();
+        let mut tree_logical_bytes = BTreeMap::new();
+        for (name, tree) in tenants.iter() {
+            let mut logical = 0;
+            for kv in tree.iter() {
+                let (k, v) = kv?;
+                logical += (k.len() + v.len()) as u64;
+            }
+            tree_logical_bytes.insert(name.clone(), logical);
+        }
+
+        Ok(DiskUsage { apparent_bytes, allocated_bytes, tree_logical_bytes })
+    }
+
+    /// Summarizes the internal error states of this database so
+    /// that services can wire sled into readiness and liveness
+    /// probes without interpreting individual counters.
+    ///
+    /// A database is `Failed` once a fatal internal error (such as
+    /// an fsync failure or detected corruption) has poisoned it,
+    /// `Degraded` when it is still serving requests but something
+    /// needs operator attention (scrubber-detected latent media
+    /// errors, a stopped background thread), and `Ok` otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// assert_eq!(db.health(), sled::Health::Ok);
+    /// # Ok(()) }
+    /// ```
+    pub fn health(&self) -> Health {
+        let mut failed = vec![];
+        let mut degraded = vec![];
+
+        if let Err(e) = self.context.pagecache.config.global_error() {
+            failed.push(format!(
+                "a fatal error has poisoned this database: {}",
+                e
+            ));
+        }
+
+        if self.scrub_error_count() > 0 {
+            degraded.push(format!(
+                "the background scrubber has failed to read back {} \
+                 log segments, indicating latent media corruption",
+                self.scrub_error_count()
+            ));
+        }
+
+        #[cfg(all(
+            not(miri),
+            any(
+                windows,
+                target_os = "linux",
+                target_os = "macos",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "openbsd",
+                target_os = "netbsd",
+            )
+        ))]
+        self.background_thread_health(&mut degraded);
+
+        if !failed.is_empty() {
+            Health::Failed(failed)
+        } else if !degraded.is_empty() {
+            Health::Degraded(degraded)
+        } else {
+            Health::Ok
+        }
+    }
+
+    /// Runs a quick micro-benchmark against the storage device
+    /// backing this `Db` and uses the results to retune internal
+    /// behavior: flush batching stretches to amortize slow
+    /// fsyncs, the IO thread pool target is matched to device
+    /// speed, and speculative readahead is enabled on
+    /// high-latency devices. Returns the measurements along with
+    /// the settings that were applied, so they can be logged or
+    /// fed into capacity planning.
+    ///
+    /// The benchmark writes and reads a few megabytes through a
+    /// scratch file in the database directory, so expect a brief
+    /// burst of IO while it runs. Typically called once shortly
+    /// after open.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let calibration = db.calibrate()?;
+    /// assert!(calibration.write_throughput > 0);
+    /// println!("storage self-benchmark: {:?}", calibration);
+    /// # Ok(()) }
+    /// ```
+    pub fn calibrate(&self) -> Result<Calibration> {
+        use std::io::{Seek, SeekFrom};
+
+        const CHUNK_LEN: usize = 64 * 1024;
+        const CHUNKS: usize = 64;
+        const FSYNCS: usize = 4;
+        const READS: u64 = 128;
+        const READ_LEN: usize = 4096;
+
+        let scratch_path =
+            self.context.get_path().join("calibration.scratch");
+        let mut scratch = std::fs::OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .truncate(true)
+            .open(&scratch_path)?;
+
+        // sequential write throughput
+        let payload = vec![0xC4_u8; CHUNK_LEN];
+        let before_writes = Instant::now();
+        for _ in 0..CHUNKS {
+            scratch.write_all(&payload)?;
+        }
+        scratch.sync_all()?;
+        let write_elapsed =
+            before_writes.elapsed().max(Duration::from_micros(1));
+        let written = u64::try_from(CHUNK_LEN * CHUNKS).unwrap();
+        let write_throughput = written.saturating_mul(1_000_000)
+            / u64::try_from(write_elapsed.as_micros())
+                .unwrap_or(u64::max_value());
+
+        // fsync latency, the best single signal for the class of
+        // the underlying device: the median of a few small
+        // appends
+        let mut fsyncs = Vec::with_capacity(FSYNCS);
+        for _ in 0..FSYNCS {
+            scratch.write_all(&[0])?;
+            let before_sync = Instant::now();
+            scratch.sync_all()?;
+            fsyncs.push(before_sync.elapsed());
+        }
+        fsyncs.sort();
+        let fsync_latency = fsyncs[FSYNCS / 2];
+
+        // scattered small reads. the scratch file was just
+        // written, so this mostly measures the syscall and page
+        // cache path rather than the medium
+        let mut read_buf = vec![0_u8; READ_LEN];
+        let before_reads = Instant::now();
+        for i in 0..READS {
+            let offset = i.wrapping_mul(2_654_435_761)
+                % (written - u64::try_from(READ_LEN).unwrap());
+            scratch.seek(SeekFrom::Start(offset))?;
+            scratch.read_exact(&mut read_buf)?;
+        }
+        let random_read_latency =
+            before_reads.elapsed() / u32::try_from(READS).unwrap();
+
+        drop(scratch);
+        let _ = std::fs::remove_file(&scratch_path);
+
+        let fsync_micros = u64::try_from(fsync_latency.as_micros())
+            .unwrap_or(u64::max_value());
+
+        // slow fsyncs want more batching per flush; fast devices
+        // can afford tighter durability windows
+        let flush_every_ms =
+            (fsync_micros.saturating_mul(20) / 1000).max(50).min(2000);
+
+        // fast devices reward deeper IO concurrency; a spinning
+        // disk just seeks more when oversubscribed
+        let io_threads = if fsync_micros < 1_000 {
+            16
+        } else if fsync_micros > 10_000 {
+            4
+        } else {
+            7
+        };
+
+        // readahead pays for itself when faulting a page in is
+        // expensive, and is pure overhead on very fast devices
+        let speculative_prefetch = if fsync_micros > 5_000 {
+            true
+        } else if fsync_micros < 500 {
+            false
+        } else {
+            self.context.speculative_prefetch
+        };
+
+        #[cfg(all(
+            not(miri),
+            any(
+                windows,
+                target_os = "linux",
+                target_os = "macos",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "openbsd",
+                target_os = "netbsd",
+            )
+        ))]
+        {
+            if let Some(flusher) = self.context.flusher.lock().as_ref() {
+                flusher.set_interval_ms(flush_every_ms);
+            }
+            threadpool::set_desired_waiting_threads(io_threads);
+        }
+
+        self.context.pagecache.set_speculative_prefetch(speculative_prefetch);
+
+        Ok(Calibration {
+            write_throughput,
+            fsync_latency,
+            random_read_latency,
+            flush_every_ms,
+            io_threads,
+            speculative_prefetch,
+        })
+    }
+
+    /// Takes the report describing the internal thread panic that
+    /// poisoned this database, if one has happened.
+    ///
+    /// When an internal thread panics, the panic is contained at
+    /// the thread boundary: subsequent operations that would write
+    /// to the log fail with [`Error::Poisoned`](crate::Error),
+    /// `health` reports `Health::Failed`, and this method returns
+    /// the details of the panic, rather than behavior being left
+    /// undefined. Set the `abort_on_internal_panic` configuration
+    /// option to abort the process instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// assert!(db.take_poison_report().is_none());
+    /// # Ok(()) }
+    /// ```
+    pub fn take_poison_report(&self) -> Option<PoisonReport> {
+        self.context.poison.lock().take()
+    }
+
+    /// Flushes all dirty data and shuts down this instance's
+    /// background threads, joining them before returning.
+    ///
+    /// Dropping the last handle to a `Db` already does this
+    /// implicitly; `close` makes the shutdown point explicit and
+    /// lets errors from the final flush be observed. Threads in
+    /// the shared IO threadpool are owned by the process rather
+    /// than any one instance, and wind down on their own after
+    /// going idle. Note that other clones of this `Db` remain
+    /// usable, but without background flushing or scrubbing.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"k", b"v")?;
+    /// db.close()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn close(self) -> Result<()> {
+        #[cfg(all(
+            not(miri),
+            any(
+                windows,
+                target_os = "linux",
+                target_os = "macos",
+                target_os = "dragonfly",
+                target_os = "freebsd",
+                target_os = "openbsd",
+                target_os = "netbsd",
+            )
+        ))]
+        {
+            // dropping these performs the shutdown handshake with
+            // each thread and joins it.
+            self.context.watchdog.lock().take();
+            self.context.scrubber.lock().take();
+            self.context.flusher.lock().take();
+        }
+
+        self.flush()?;
+
+        Ok(())
+    }
+
+    /// Creates a named in-process branch of the default tree: a
+    /// lightweight fork whose writes are buffered in memory and
+    /// never touch this database, while reads of unmodified keys
+    /// fall through to it. The branch can be diffed against the
+    /// tree, committed back atomically, or dropped to discard all
+    /// of its writes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"k", b"v1")?;
+    ///
+    /// let branch = db.branch("experiment");
+    /// branch.insert(b"k", b"v2")?;
+    /// branch.insert(b"new", b"n")?;
+    ///
+    /// // the database is untouched until the branch is committed
+    /// assert_eq!(&db.get(b"k")?.unwrap(), b"v1");
+    /// assert_eq!(branch.diff()?.len(), 2);
+    ///
+    /// branch.commit()?;
+    /// assert_eq!(&db.get(b"k")?.unwrap(), b"v2");
+    /// # Ok(()) }
+    /// ```
+    pub fn branch<S: Into<String>>(&self, name: S) -> Branch {
+        Branch::new(name.into(), self.default.clone())
+    }
+
+    /// Creates a new database directory at `target` holding a
+    /// copy of this database, for fast test-fixture duplication
+    /// and branch-per-experiment workflows. The clone must be
+    /// opened with a `Config` using the same settings as this
+    /// database.
+    ///
+    /// Write-once snapshot files are shared with the original via
+    /// hard links, and the log and heap files are copied with
+    /// `std::fs::copy`, which shares extents copy-on-write on
+    /// filesystems with reflink support (such as btrfs and XFS),
+    /// so that data is rewritten only as the two databases
+    /// diverge. On other filesystems this degrades to a plain
+    /// copy. Writes are blocked while the clone is taken, so it
+    /// observes a consistent point in time.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let clone_dir = std::env::temp_dir().join("clone_cow_example");
+    /// # let _ = std::fs::remove_dir_all(&clone_dir);
+    /// db.insert(b"k", b"v")?;
+    /// db.clone_cow(&clone_dir)?;
+    ///
+    /// let clone = sled::Config::new().path(&clone_dir).open()?;
+    /// assert_eq!(&clone.get(b"k")?.unwrap(), b"v");
+    /// # drop(clone);
+    /// # let _ = std::fs::remove_dir_all(&clone_dir);
+    /// # Ok(()) }
+    /// ```
+    pub fn clone_cow<P: AsRef<std::path::Path>>(
+        &self,
+        target: P,
+    ) -> Result<()> {
+        let target = target.as_ref();
+        let source = self.context.get_path();
+
+        if target.exists() {
+            return Err(Error::Unsupported(
+                "clone_cow target path already exists".into(),
+            ));
+        }
+
+        // block writers and bring the on-disk state up to date
+        // while the files are being cloned.
+        let _cc = concurrency_control::write();
+        self.context.pagecache.flush_unprotected()?;
+
+        std::fs::create_dir_all(target)?;
+
+        for entry in std::fs::read_dir(&source)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let name = file_name.to_string_lossy().into_owned();
+            let from = entry.path();
+            let to = target.join(&file_name);
+
+            if entry.file_type()?.is_dir() {
+                if name == "heap" {
+                    std::fs::create_dir_all(&to)?;
+                    for slab in std::fs::read_dir(&from)? {
+                        let slab = slab?;
+                        std::fs::copy(
+                            slab.path(),
+                            to.join(slab.file_name()),
+                        )?;
+                    }
+                }
+                continue;
+            }
+
+            if name.ends_with(".generating") {
+                // partially written snapshot, skip it
+                continue;
+            }
+
+            if name.starts_with("snap.") {
+                // snapshot files are write-once and replaced only
+                // by rename, so the clone can share them via hard
+                // links. fall back to a copy across filesystems.
+                if std::fs::hard_link(&from, &to).is_err() {
+                    std::fs::copy(&from, &to)?;
+                }
+            } else {
+                std::fs::copy(&from, &to)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies a consistent backup of this database into a new
+    /// directory at `target` while writes continue. The backup
+    /// contains everything written before the call; writes that
+    /// race with it are picked up by the next backup. It can be
+    /// restored by opening the directory with a `Config` using
+    /// the same settings as this database. Returns the stable LSN
+    /// the backup captured, which can be passed to
+    /// [`backup_incremental`](Db::backup_incremental) to ship only
+    /// what has been written since.
+    ///
+    /// Unlike [`clone_cow`](Db::clone_cow), writers are not
+    /// blocked. Instead, the length of the log is captured after
+    /// a flush and segments are pinned in the segment accountant
+    /// for the duration of the copy, so that freed segments are
+    /// neither reused for concurrent writes nor truncated away
+    /// while their offsets are being copied. All files are fully
+    /// copied rather than hard-linked, so the backup shares no
+    /// storage with the database it protects.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let backup_dir = std::env::temp_dir().join("backup_to_example");
+    /// # let _ = std::fs::remove_dir_all(&backup_dir);
+    /// db.insert(b"k", b"v")?;
+    /// db.backup_to(&backup_dir)?;
+    ///
+    /// let backup = sled::Config::new().path(&backup_dir).open()?;
+    /// assert_eq!(&backup.get(b"k")?.unwrap(), b"v");
+    /// # drop(backup);
+    /// # let _ = std::fs::remove_dir_all(&backup_dir);
+    /// # Ok(()) }
+    /// ```
+    pub fn backup_to<P: AsRef<std::path::Path>>(
+        &self,
+        target: P,
+    ) -> Result<Lsn> {
+        let target = target.as_ref();
+        let source = self.context.get_path();
+
+        if target.exists() {
+            return Err(Error::Unsupported(
+                "backup_to target path already exists".into(),
+            ));
+        }
+
+        // make everything written so far durable, then capture the
+        // stable length of the log. bytes past it may still be in
+        // flight, and are left for the next backup.
+        self.flush()?;
+        let stable_lsn = self.context.pagecache.log.stable_offset();
+        let stable_len = self.context.pagecache.config.file.metadata()?.len();
+
+        self.context.pagecache.pin_segments_for_backup();
+        let result = self.backup_files(&source, target, stable_len);
+        self.context.pagecache.unpin_segments_for_backup();
+        result.map(|()| stable_lsn)
+    }
+
+    fn backup_files(
+        &self,
+        source: &std::path::Path,
+        target: &std::path::Path,
+        stable_len: u64,
+    ) -> Result<()> {
+        std::fs::create_dir_all(target)?;
+
+        for entry in std::fs::read_dir(source)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let name = file_name.to_string_lossy().into_owned();
+            let from = entry.path();
+            let to = target.join(&file_name);
+
+            if entry.file_type()?.is_dir() {
+                if name == "heap" {
+                    std::fs::create_dir_all(&to)?;
+                    for slab in std::fs::read_dir(&from)? {
+                        let slab = slab?;
+                        std::fs::copy(
+                            slab.path(),
+                            to.join(slab.file_name()),
+                        )?;
+                    }
+                }
+                continue;
+            }
+
+            if name.ends_with(".generating") {
+                // partially written snapshot, skip it
+                continue;
+            }
+
+            if name == "db" {
+                // copy only the stable prefix captured after the
+                // flush. concurrent writes land in segments past
+                // it or in fresh segments allocated from the tip
+                // while the backup pin is held.
+                let from_file = std::fs::File::open(&from)?;
+                let mut limited = from_file.take(stable_len);
+                let mut to_file = std::fs::File::create(&to)?;
+                std::io::copy(&mut limited, &mut to_file)?;
+                to_file.sync_all()?;
+            } else {
+                std::fs::copy(&from, &to)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes an incremental backup into a new directory at
+    /// `target`, containing only the log segments written after
+    /// `since_lsn` together with a manifest describing them.
+    /// Returns the stable LSN the backup captured, to pass as
+    /// `since_lsn` to the next incremental backup. Writers are
+    /// not blocked; segments are pinned as in
+    /// [`backup_to`](Db::backup_to) while they are copied.
+    ///
+    /// A chain beginning with the LSN returned by a full
+    /// [`backup_to`](Db::backup_to) can be restored with
+    /// [`restore_incremental`](crate::restore_incremental), which
+    /// validates that the chain has no gaps. This keeps periodic
+    /// backups of large databases proportional to what changed
+    /// rather than to total size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let base_dir = std::env::temp_dir().join("incr_backup_base");
+    /// let incr_dir = std::env::temp_dir().join("incr_backup_1");
+    /// # let _ = std::fs::remove_dir_all(&base_dir);
+    /// # let _ = std::fs::remove_dir_all(&incr_dir);
+    /// db.insert(b"a", b"1")?;
+    /// let since = db.backup_to(&base_dir)?;
+    ///
+    /// db.insert(b"b", b"2")?;
+    /// db.backup_incremental(&incr_dir, since)?;
+    ///
+    /// // apply the chain onto the full backup, then open it
+    /// sled::restore_incremental(&base_dir, &[&incr_dir])?;
+    /// let restored = sled::Config::new().path(&base_dir).open()?;
+    /// assert_eq!(&restored.get(b"a")?.unwrap(), b"1");
+    /// assert_eq!(&restored.get(b"b")?.unwrap(), b"2");
+    /// # drop(restored);
+    /// # let _ = std::fs::remove_dir_all(&base_dir);
+    /// # let _ = std::fs::remove_dir_all(&incr_dir);
+    /// # Ok(()) }
+    /// ```
+    pub fn backup_incremental<P: AsRef<std::path::Path>>(
+        &self,
+        target: P,
+        since_lsn: Lsn,
+    ) -> Result<Lsn> {
+        let target = target.as_ref();
+
+        if target.exists() {
+            return Err(Error::Unsupported(
+                "backup_incremental target path already exists".into(),
+            ));
+        }
+
+        self.flush()?;
+
+        self.context.pagecache.pin_segments_for_backup();
+        let result = self.backup_segments(target, since_lsn);
+        self.context.pagecache.unpin_segments_for_backup();
+        result
+    }
+
+    fn backup_segments(
+        &self,
+        target: &std::path::Path,
+        since_lsn: Lsn,
+    ) -> Result<Lsn> {
+        use std::io::{Seek, SeekFrom};
+
+        let (stable_lsn, segments) =
+            self.context.pagecache.segments_since(since_lsn);
+        let segment_size = self.context.segment_size as u64;
+
+        std::fs::create_dir_all(target)?;
+
+        let mut manifest =
+            format!("since_lsn {}\nstable_lsn {}\n", since_lsn, stable_lsn);
+        let mut file =
+            std::fs::File::open(self.context.get_path().join("db"))?;
+        let file_len = file.metadata()?.len();
+
+        for (lsn, offset) in segments {
+            // ship each whole segment, as torn bytes past the
+            // durable tail are handled by recovery exactly as
+            // they would be in the live file
+            let len = segment_size.min(file_len.saturating_sub(offset));
+            if len == 0 {
+                continue;
+            }
+
+            file.seek(SeekFrom::Start(offset))?;
+            let mut limited = (&file).take(len);
+            let mut out =
+                std::fs::File::create(target.join(segment_file_name(lsn)))?;
+            std::io::copy(&mut limited, &mut out)?;
+            out.sync_all()?;
+
+            manifest
+                .push_str(&format!("segment {} {} {}\n", lsn, offset, len));
+        }
+
+        // written last, so that an interrupted backup is missing
+        // its manifest rather than silently incomplete
+        std::fs::write(target.join(INCREMENTAL_MANIFEST), &manifest)?;
+
+        Ok(stable_lsn)
+    }
+
+    /// Persists a named checkpoint of the logical contents of
+    /// every tree in the database, which the database can later
+    /// be rolled back to via
+    /// [`restore_checkpoint`](Db::restore_checkpoint) after a bad
+    /// deploy. Checkpoints are stored in hidden trees inside the
+    /// database itself, so no external file copies are involved,
+    /// and they survive restarts. Taking a checkpoint with a name
+    /// that is already in use replaces the previous checkpoint.
+    ///
+    /// Writers are blocked while the checkpoint is taken, and the
+    /// checkpoint is made durable before this call returns.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"rate limit", b"100")?;
+    /// db.checkpoint("before-deploy")?;
+    ///
+    /// // the deploy goes badly
+    /// db.insert(b"rate limit", b"100000")?;
+    /// db.insert(b"new feature", b"on")?;
+    ///
+    /// db.restore_checkpoint("before-deploy")?;
+    /// assert_eq!(&db.get(b"rate limit")?.unwrap(), b"100");
+    /// assert_eq!(db.get(b"new feature")?, None);
+    /// # Ok(()) }
+    /// ```
+    pub fn checkpoint<N: AsRef<[u8]>>(&self, name: N) -> Result<()> {
+        let name = name.as_ref();
+        let guard = pin();
+
+        // handles to the hidden checkpoint trees are parked here
+        // so that they outlive the concurrency control write
+        // lock below: dropping a final `Tree` handle flushes,
+        // which must not happen while the lock is held.
+        let mut held: Vec<Tree> = Vec::new();
+
+        let registry = meta::open_tree(
+            &self.context,
+            CHECKPOINTS_TREE_ID.to_vec(),
+            &guard,
+        )?;
+
+        // block writers so that every tree is captured at the
+        // same consistent point.
+        let _cc = concurrency_control::write();
+
+        let mut recorded: Vec<IVec> = Vec::new();
+        let tenants = self.tenants.read();
+        for (tree_id, tree) in tenants.iter() {
+            if tree_id.as_ref() != DEFAULT_TREE_ID
+                && tree_id.starts_with(INTERNAL_TREE_PREFIX)
+            {
+                continue;
+            }
+            let checkpoint_tree = meta::open_tree(
+                &self.context,
+                checkpoint_tree_id(name, tree_id),
+                &guard,
+            )?;
+            held.push(checkpoint_tree);
+            copy_tree_unprotected(tree, held.last().unwrap())?;
+            recorded.push(tree_id.clone());
+        }
+        drop(tenants);
+
+        // a replaced checkpoint may have captured trees that no
+        // longer exist; their stale copies are cleared out.
+        let previous = self.checkpoint_manifest(&registry, name)?;
+        if let Some(previous) = previous {
+            for tree_id in parse_checkpoint_manifest(&previous)? {
+                if recorded.iter().any(|r| *r == tree_id) {
+                    continue;
+                }
+                let stale = meta::open_tree(
+                    &self.context,
+                    checkpoint_tree_id(name, &tree_id),
+                    &guard,
+                )?;
+                held.push(stale);
+                clear_tree_unprotected(held.last().unwrap())?;
+            }
+        }
+
+        let mut manifest = Vec::new();
+        for tree_id in &recorded {
+            let len = u32::try_from(tree_id.len()).unwrap();
+            manifest.extend_from_slice(&len.to_be_bytes());
+            manifest.extend_from_slice(tree_id);
+        }
+
+        let mut batch = Batch::default();
+        batch.insert(name, manifest);
+        let mut batch_guard = pin();
+        registry.apply_batch_inner(batch, None, &mut batch_guard)?;
+
+        // make the checkpoint durable before reporting success
+        self.context.pagecache.flush_unprotected()?;
+
+        Ok(())
+    }
+
+    /// Rolls every tree in the database back to the state
+    /// captured by a checkpoint previously taken via
+    /// [`checkpoint`](Db::checkpoint). Trees created after the
+    /// checkpoint was taken are emptied, and trees dropped since
+    /// are recreated with their checkpointed contents, so the
+    /// database as a whole returns to its recorded state. The
+    /// checkpoint itself is retained, and may be rolled back to
+    /// again.
+    ///
+    /// Returns an error if no checkpoint with the given name
+    /// exists.
+    pub fn restore_checkpoint<N: AsRef<[u8]>>(&self, name: N) -> Result<()> {
+        let name = name.as_ref();
+        let guard = pin();
+
+        // as in `checkpoint`, hidden tree handles must outlive
+        // the concurrency control write lock taken below.
+        let mut held: Vec<Tree> = Vec::new();
+
+        let registry = meta::open_tree(
+            &self.context,
+            CHECKPOINTS_TREE_ID.to_vec(),
+            &guard,
+        )?;
+
+        let _cf = self.changefeed_guard();
+        let _cc = concurrency_control::write();
+
+        let manifest = if let Some(manifest) =
+            self.checkpoint_manifest(&registry, name)?
+        {
+            manifest
+        } else {
+            return Err(Error::Unsupported(format!(
+                "no checkpoint named {:?} exists",
+                String::from_utf8_lossy(name)
+            )));
+        };
+
+        let recorded = parse_checkpoint_manifest(&manifest)?;
+
+        for tree_id in &recorded {
+            // open through the tenant cache so that handles
+            // already held by callers observe the restore, and
+            // trees dropped since the checkpoint are recreated.
+            let live = self.open_tree(tree_id)?;
+            let checkpoint_tree = meta::open_tree(
+                &self.context,
+                checkpoint_tree_id(name, tree_id),
+                &guard,
+            )?;
+            held.push(checkpoint_tree);
+            copy_tree_unprotected(held.last().unwrap(), &live)?;
+        }
+
+        // trees created after the checkpoint was taken are
+        // emptied, so the database as a whole returns to its
+        // recorded state.
+        let tenants = self.tenants.read();
+        for (tree_id, tree) in tenants.iter() {
+            if tree_id.as_ref() != DEFAULT_TREE_ID
+                && tree_id.starts_with(INTERNAL_TREE_PREFIX)
+            {
+                continue;
+            }
+            if recorded.iter().any(|r| r == tree_id) {
+                continue;
+            }
+            clear_tree_unprotected(tree)?;
+        }
+        drop(tenants);
+
+        self.context.pagecache.flush_unprotected()?;
+
+        Ok(())
+    }
+
+    // reads a checkpoint's manifest through the internal get
+    // path, as the concurrency control write lock is held by the
+    // callers.
+    fn checkpoint_manifest(
+        &self,
+        registry: &Tree,
+        name: &[u8],
+    ) -> Result<Option<IVec>> {
+        let mut guard = pin();
+        loop {
+            if let Ok(get) = registry.get_inner(name, &mut guard)? {
+                return Ok(get);
+            }
+        }
+    }
+
+    /// Returns the names of transactions that were marked as
+    /// prepared via `TransactionalTree::prepare` and have been
+    /// neither committed nor aborted. Prepared transactions are
+    /// durable and survive restarts, so after a crash this is how
+    /// an application discovers in-doubt work and decides whether
+    /// to [`commit_prepared`](Db::commit_prepared) or
+    /// [`abort_prepared`](Db::abort_prepared) it.
+    pub fn list_prepared(&self) -> Result<Vec<IVec>> {
+        let registry = self.prepared_registry()?;
+        let mut names = Vec::new();
+        for name in registry.iter().keys() {
+            names.push(name?);
+        }
+        Ok(names)
+    }
+
+    /// Atomically applies the staged writes of a prepared
+    /// transaction and removes its record. Returns an error if no
+    /// prepared transaction with the given name exists.
+    pub fn commit_prepared<N: AsRef<[u8]>>(&self, name: N) -> Result<()> {
+        let name = name.as_ref();
+        let registry = self.prepared_registry()?;
+
+        let record = if let Some(record) = registry.get(name)? {
+            record
+        } else {
+            return Err(Error::Unsupported(format!(
+                "no prepared transaction named {:?} exists",
+                String::from_utf8_lossy(name)
+            )));
+        };
+
+        let mut batches: Vec<(Tree, Batch)> = Vec::new();
+        for (tree_id, batch) in transaction::decode_prepared(&record)? {
+            batches.push((self.open_tree(tree_id)?, batch));
+        }
+        let event = Event::from_batches(batches.clone());
+
+        let _cf = self.changefeed_guard();
+        let _cc = concurrency_control::write();
+        let mut guard = pin();
+
+        // when the peg drops, it ensures all updates written to
+        // the log since its creation are recovered atomically
+        let peg = self.context.pin_log(&guard)?;
+        for (tree, batch) in batches {
+            tree.apply_batch_inner(batch, Some(event.clone()), &mut guard)?;
+        }
+        peg.seal_batch()?;
+
+        let mut cleanup = Batch::default();
+        cleanup.remove(name);
+        registry.apply_batch_inner(cleanup, None, &mut guard)?;
+        self.context.pagecache.flush_unprotected()?;
+
+        Ok(())
+    }
+
+    /// Discards the staged writes of a prepared transaction and
+    /// removes its record. Returns an error if no prepared
+    /// transaction with the given name exists.
+    pub fn abort_prepared<N: AsRef<[u8]>>(&self, name: N) -> Result<()> {
+        let name = name.as_ref();
+        let registry = self.prepared_registry()?;
+        if registry.remove(name)?.is_none() {
+            return Err(Error::Unsupported(format!(
+                "no prepared transaction named {:?} exists",
+                String::from_utf8_lossy(name)
+            )));
+        }
+        self.flush()?;
+        Ok(())
+    }
+
+    fn prepared_registry(&self) -> Result<Tree> {
+        let guard = pin();
+        meta::open_tree(&self.context, PREPARED_TREE_ID.to_vec(), &guard)
+    }
+
+    /// Returns the identifiers of multi-database transactions
+    /// whose coordination records are still present in this
+    /// database. A record is made durable in every participating
+    /// database before any of them applies writes, and cleared
+    /// after all of them have flushed, so a leftover identifier
+    /// after a crash means the transaction may have been applied
+    /// in some participants but not all, and their contents
+    /// should be reconciled. A cleanly-committed transaction
+    /// leaves nothing here.
+    pub fn pending_coordination_ids(&self) -> Result<Vec<u64>> {
+        let guard = pin();
+        let coordination = meta::open_tree(
+            &self.context,
+            COORDINATION_TREE_ID.to_vec(),
+            &guard,
+        )?;
+        let mut ids = Vec::new();
+        for key in coordination.iter().keys() {
+            let key = key?;
+            let bytes = <[u8; 8]>::try_from(key.as_ref()).map_err(|_| {
+                Error::corruption(None)
+            })?;
+            ids.push(u64::from_be_bytes(bytes));
+        }
+        Ok(ids)
+    }
+
+    #[cfg(all(
+        not(miri),
+        any(
+            windows,
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "netbsd",
+        )
+    ))]
+    fn background_thread_health(&self, degraded: &mut Vec<String>) {
+        if self.context.flush_every_ms.is_some() {
+            let flusher = self.context.flusher.lock();
+            let stopped = match &*flusher {
+                Some(flusher) => flusher.is_stopped(),
+                None => true,
+            };
+            if stopped {
+                degraded.push(
+                    "the background flusher is not running; writes \
+                     will only reach disk on explicit flushes"
+                        .to_owned(),
+                );
+            }
+        }
+
+        if self.context.scrub_segments_per_hour > 0 {
+            let scrubber = self.context.scrubber.lock();
+            let stopped = match &*scrubber {
+                Some(scrubber) => scrubber.is_stopped(),
+                None => true,
+            };
+            if stopped {
+                degraded.push(
+                    "the background scrubber is not running".to_owned(),
+                );
+            }
+        }
+
+        use std::sync::atomic::Ordering::SeqCst as AtomicSeqCst;
+        if self.context.flusher_stalled.load(AtomicSeqCst) {
+            degraded.push(
+                "the watchdog reports that the background flusher                  has stalled"
+                    .to_owned(),
+            );
+        }
+        if self.context.scrubber_stalled.load(AtomicSeqCst) {
+            degraded.push(
+                "the watchdog reports that the background scrubber                  has stalled"
+                    .to_owned(),
+            );
+        }
+    }
+
+    /// Attempts to acquire a named lease, returning `None` if it
+    /// is currently held and unexpired. The returned [`Lease`]
+    /// carries a fencing token that increases monotonically across
+    /// acquisitions of the same name, must be heartbeated to be
+    /// retained past `ttl`, and is released on drop. Leases are
+    /// persisted, so they coordinate exclusive work between
+    /// subsystems, restarts, and co-located processes sharing this
+    /// database.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use std::time::Duration;
+    ///
+    /// let lease = db
+    ///     .acquire_lease(b"compactor", Duration::from_secs(30))?
+    ///     .expect("lease is free");
+    /// assert_eq!(lease.fencing_token(), 1);
+    ///
+    /// // a second acquirer is refused while the lease is held
+    /// assert!(db
+    ///     .acquire_lease(b"compactor", Duration::from_secs(30))?
+    ///     .is_none());
+    ///
+    /// lease.release()?;
+    /// let lease = db
+    ///     .acquire_lease(b"compactor", Duration::from_secs(30))?
+    ///     .expect("lease was released");
+    /// assert_eq!(lease.fencing_token(), 2);
+    /// # Ok(()) }
+    /// ```
+    pub fn acquire_lease<N: AsRef<[u8]>>(
+        &self,
+        name: N,
+        ttl: Duration,
+    ) -> Result<Option<Lease>> {
+        let leases = self.leases_tree()?;
+        lease::acquire(&leases, name.as_ref(), ttl)
+    }
+
+    /// Returns the hidden leases tree, creating and caching a
+    /// handle on first use.
+    fn leases_tree(&self) -> Result<Tree> {
+        {
+            let leases = self.leases_tree.read();
+            if let Some(leases) = &*leases {
+                return Ok(leases.clone());
+            }
+        }
+        let guard = pin();
+        let leases =
+            meta::open_tree(&self.context, LEASES_TREE_ID.to_vec(), &guard)?;
+        *self.leases_tree.write() = Some(leases.clone());
+        Ok(leases)
+    }
+
+    /// Opens a named content-addressed [`BlobStore`] backed by a
+    /// hidden tree in this database, for values keyed by their
+    /// hash with refcounting and deduplication. See the
+    /// [`BlobStore`] documentation for usage.
+    pub fn blob_store<N: AsRef<[u8]>>(&self, name: N) -> Result<BlobStore> {
+        let mut tree_name = BLOBS_TREE_PREFIX.to_vec();
+        tree_name.extend_from_slice(name.as_ref());
+        let guard = pin();
+        let tree = meta::open_tree(&self.context, tree_name, &guard)?;
+        Ok(BlobStore { tree })
+    }
+
+    /// Opens a named append-only [`AppendLog`] backed by a hidden
+    /// tree in this database, for append-mostly workloads that
+    /// read entries back in sequence order or from a point in
+    /// time. See the [`AppendLog`] documentation for usage.
+    pub fn open_append_log<N: AsRef<[u8]>>(
+        &self,
+        name: N,
+    ) -> Result<AppendLog> {
+        let mut tree_name = APPEND_LOG_TREE_PREFIX.to_vec();
+        tree_name.extend_from_slice(name.as_ref());
+        let guard = pin();
+        let tree = meta::open_tree(&self.context, tree_name, &guard)?;
+        drop(guard);
+        AppendLog::open(tree)
+    }
+
+    /// Opens a named [`FixedWidthTree`] backed by a hidden tree
+    /// in this database, for records that all share one small
+    /// fixed value width and are addressed by a dense `u64`
+    /// index. The width is persisted on first open and must match
+    /// on subsequent opens. See the [`FixedWidthTree`]
+    /// documentation for usage.
+    pub fn open_fixed_width_tree<N: AsRef<[u8]>>(
+        &self,
+        name: N,
+        value_width: usize,
+    ) -> Result<FixedWidthTree> {
+        let mut tree_name = FIXED_WIDTH_TREE_PREFIX.to_vec();
+        tree_name.extend_from_slice(name.as_ref());
+        let guard = pin();
+        let tree = meta::open_tree(&self.context, tree_name, &guard)?;
+        drop(guard);
+        FixedWidthTree::open(tree, value_width)
+    }
+
+    /// Opens a named [`LargeKeyTree`] backed by a hidden tree in
+    /// this database: an opt-in mode for keys that can exceed
+    /// node-friendly sizes, which indexes a fixed-size key digest
+    /// with the full key stored alongside the value and
+    /// transparent collision resolution. See the [`LargeKeyTree`]
+    /// documentation for usage and trade-offs.
+    pub fn open_large_key_tree<N: AsRef<[u8]>>(
+        &self,
+        name: N,
+    ) -> Result<LargeKeyTree> {
+        let mut tree_name = LARGE_KEYS_TREE_PREFIX.to_vec();
+        tree_name.extend_from_slice(name.as_ref());
+        let guard = pin();
+        let tree = meta::open_tree(&self.context, tree_name, &guard)?;
+        Ok(LargeKeyTree { tree })
+    }
+
+    /// Opens a named [`ValueLogTree`] backed by a hidden tree in
+    /// this database, which stores values at or above
+    /// `value_threshold` bytes in an append-only value log beside
+    /// the database so that tree rewrites stop copying large
+    /// blobs. Garbage left behind by overwrites is reclaimed with
+    /// [`ValueLogTree::gc`]. See the [`ValueLogTree`]
+    /// documentation for usage and trade-offs.
+    pub fn open_value_log_tree<N: AsRef<[u8]>>(
+        &self,
+        name: N,
+        value_threshold: usize,
+    ) -> Result<ValueLogTree> {
+        let mut tree_name = VALUE_LOG_TREE_PREFIX.to_vec();
+        tree_name.extend_from_slice(name.as_ref());
+        let guard = pin();
+        let tree = meta::open_tree(&self.context, tree_name, &guard)?;
+        drop(guard);
+
+        // segment files live in a directory derived from the
+        // name, which may be arbitrary bytes
+        let mut dir_name = String::from("valuelog-");
+        for byte in name.as_ref() {
+            dir_name.push_str(&format!("{:02x}", byte));
+        }
+        let directory = self.context.get_path().join(dir_name);
+
+        ValueLogTree::open(tree, directory, value_threshold)
+    }
+
+    /// Begin capturing committed updates into a durable
+    /// database-wide changefeed log, so that they can later be
+    /// replayed in order via [`Db::changefeed`]. Every insert,
+    /// removal, batch, and committed transaction against any
+    /// user-visible tree is captured; writes to hidden
+    /// bookkeeping trees are not.
+    ///
+    /// Each captured update is assigned a monotonically
+    /// increasing sequence number, the changefeed LSN. LSNs keep
+    /// increasing across restarts. Records are captured after
+    /// their updates are applied, so a crash can lose the newest
+    /// records while keeping the updates they describe; a
+    /// consumer that must not miss updates should flush the
+    /// database at its synchronization points. Capture roughly
+    /// doubles the write volume while enabled, and the log grows
+    /// until trimmed via [`Db::trim_changefeed`], so most
+    /// deployments should enable it only when a downstream
+    /// consumer exists.
+    ///
+    /// Updates written while capture is disabled are not
+    /// recorded, so a consumer that must not miss updates should
+    /// enable capture before the first write it cares about.
+    pub fn enable_changefeed(&self) -> Result<()> {
+        let guard = pin();
+        let log = meta::open_tree(
+            &self.context,
+            CHANGEFEED_TREE_ID.to_vec(),
+            &guard,
+        )?;
+        drop(guard);
+
+        let next = if let Some((key, _)) = log.last()? {
+            let mut arr = [0; 8];
+            arr.copy_from_slice(&key);
+            u64::from_be_bytes(arr) + 1
+        } else {
+            0
+        };
+        self.context.changefeed.cursor.store(next, SeqCst);
+        self.context.changefeed.enabled.store(true, SeqCst);
+        Ok(())
+    }
+
+    /// Stop capturing updates into the changefeed log.
+    /// Already-captured records remain readable via
+    /// [`Db::changefeed`].
+    pub fn disable_changefeed(&self) {
+        self.context.changefeed.enabled.store(false, SeqCst);
+    }
+
+    /// Returns an ordered iterator over updates captured at or
+    /// after `since_lsn`, each yielded as the changefeed LSN it
+    /// was captured at, the `Tree` it was written to, and the
+    /// update itself as an [`Event`]. Capture must have been
+    /// enabled via [`Db::enable_changefeed`] for updates to be
+    /// present.
+    ///
+    /// A downstream consumer stays in sync without full rescans
+    /// by durably recording the last LSN it applied and resuming
+    /// from the next one.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.enable_changefeed()?;
+    ///
+    /// db.insert(b"k1", b"v1")?;
+    /// db.remove(b"k1")?;
+    ///
+    /// let updates: Vec<_> =
+    ///     db.changefeed(0)?.collect::<sled::Result<Vec<_>>>()?;
+    /// assert_eq!(updates.len(), 2);
+    ///
+    /// let (lsn, tree, event) = &updates[1];
+    /// assert_eq!(*lsn, 1);
+    /// assert_eq!(tree.name(), db.name());
+    /// for (_tree, key, value) in event.iter() {
+    ///     assert_eq!(key, &sled::IVec::from(b"k1"));
+    ///     assert_eq!(value, &None);
+    /// }
+    ///
+    /// // a consumer that has applied everything up to LSN 1
+    /// // resumes from LSN 2 and sees only newer updates
+    /// assert_eq!(db.changefeed(2)?.count(), 0);
+    /// # Ok(()) }
+    /// ```
+    pub fn changefeed(&self, since_lsn: u64) -> Result<Changefeed> {
+        let guard = pin();
+        let log = meta::open_tree(
+            &self.context,
+            CHANGEFEED_TREE_ID.to_vec(),
+            &guard,
+        )?;
+        drop(guard);
+
+        Ok(Changefeed {
+            db: self.clone(),
+            iter: log.range(since_lsn.to_be_bytes()..),
+        })
+    }
+
+    /// Removes captured changefeed records below `up_to_lsn`,
+    /// reclaiming their space. Typically called by a consumer
+    /// after it has durably applied everything below that LSN.
+    /// Returns the number of records removed.
+    pub fn trim_changefeed(&self, up_to_lsn: u64) -> Result<u64> {
+        let guard = pin();
+        let log = meta::open_tree(
+            &self.context,
+            CHANGEFEED_TREE_ID.to_vec(),
+            &guard,
+        )?;
+        drop(guard);
+
+        let mut removed = 0;
+        for kv in log.range(..up_to_lsn.to_be_bytes()) {
+            let (key, _) = kv?;
+            log.remove(key)?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+
+    /// Sets a callback invoked with a human-readable reason when
+    /// the watchdog detects that a background thread has stalled,
+    /// replacing any previously set callback. Only invoked when a
+    /// watchdog is enabled via
+    /// `Config::watchdog_stall_threshold_ms`; the stall is also
+    /// reported through `Db::health`.
+    #[cfg(all(
+        not(miri),
+        any(
+            windows,
+            target_os = "linux",
+            target_os = "macos",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "openbsd",
+            target_os = "netbsd",
+        )
+    ))]
+    pub fn set_stall_callback<F>(&self, callback: F)
+    where
+        F: Fn(&str) + Send + 'static,
+    {
+        *self.context.stall_callback.callback.lock() =
+            Some(Box::new(callback));
+    }
+
+    /// Registers a backend that locally unreadable log segments
+    /// are transparently fetched from, making reads self-healing
+    /// for bit rot when continuous archiving keeps the backend
+    /// complete.
+    ///
+    /// When a read encounters a page that is unreadable or fails
+    /// its checksum, the segment holding it is fetched from the
+    /// archive, verified, rewritten in place (repairing the rot
+    /// for subsequent reads as well), and the read is retried.
+    /// Each repair is logged at `warn` level. Segments the
+    /// archive doesn't hold, or that fail verification, leave the
+    /// original read error in place. Registering replaces any
+    /// previously registered backend.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let backups = std::env::temp_dir().join("segment_archive_example");
+    /// # let _ = std::fs::remove_dir_all(&backups);
+    /// db.insert(b"k", b"v")?;
+    /// db.backup_incremental(&backups, 0)?;
+    ///
+    /// db.set_segment_archive(sled::DirectoryArchive::new(&[&backups]));
+    /// # let _ = std::fs::remove_dir_all(&backups);
+    /// # Ok(()) }
+    /// ```
+    pub fn set_segment_archive<A: SegmentArchive>(&self, archive: A) {
+        *self.context.segment_archive.archive.lock() =
+            Some(Box::new(archive));
+    }
+
+    /// Starts a background thread serving a plain-text status page
+    /// over HTTP on the given address, rendering this database's
+    /// health, lifetime statistics, disk usage, and memory
+    /// footprint. The server shuts down when the returned handle
+    /// is dropped. Binding to port 0 picks a free port, which can
+    /// be retrieved from the handle's `local_addr`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let server = db.serve_status("127.0.0.1:0")?;
+    /// println!("status page at http://{}/", server.local_addr());
+    /// # Ok(()) }
+    /// ```
+    #[cfg(feature = "http_status")]
+    pub fn serve_status<A: std::net::ToSocketAddrs>(
+        &self,
+        addr: A,
+    ) -> Result<http_status::StatusServer> {
+        http_status::StatusServer::start(self, addr)
+    }
+
+    /// Returns cumulative lifetime statistics for this database.
+    /// The counters are persisted in a metadata tree whenever
+    /// `stats` is called and when the `Db` is dropped, so they
+    /// survive restarts rather than resetting on each open. If a
+    /// process crashes, activity since the last persistence point
+    /// is lost from the lifetime totals.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"key", b"value")?;
+    ///
+    /// let stats = db.stats()?;
+    /// assert!(stats.total_ops >= 1);
+    ///
+    /// db.reset_stats()?;
+    /// assert_eq!(db.stats()?.total_ops, 0);
+    /// # Ok(()) }
+    /// ```
+    pub fn stats(&self) -> Result<Stats> {
+        let stats = {
+            let state = self.stats_state.lock();
+            self.live_stats(&state)
+        };
+
+        self.stats_tree()?
+            .insert(STATS_KEY, stats_record(&stats).to_vec())?;
+
+        Ok(stats)
+    }
+
+    /// Zeroes the persisted lifetime statistics returned
+    /// by `Db::stats`.
+    pub fn reset_stats(&self) -> Result<()> {
+        {
+            let mut state = self.stats_state.lock();
+            state.base = Stats::default();
+            state.live_at_reset = Stats {
+                total_ops: self.context.total_ops.load(SeqCst),
+                log_bytes_written: self
+                    .context
+                    .pagecache
+                    .log_bytes_written(),
+                pages_rewritten: self.context.pagecache.pages_rewritten(),
+                scrub_errors: self.context.scrub_errors.load(SeqCst),
+            };
+        }
+
+        self.stats_tree()?
+            .insert(STATS_KEY, stats_record(&Stats::default()).to_vec())?;
+
+        Ok(())
+    }
+
+    /// Returns the hidden statistics tree, creating and caching
+    /// a handle on first use.
+    fn stats_tree(&self) -> Result<Tree> {
+        {
+            let stats_tree = self.stats_tree.read();
+            if let Some(stats_tree) = &*stats_tree {
+                return Ok(stats_tree.clone());
+            }
+        }
+        let guard = pin();
+        let stats_tree =
+            meta::open_tree(&self.context, STATS_TREE_ID.to_vec(), &guard)?;
+        *self.stats_tree.write() = Some(stats_tree.clone());
+        Ok(stats_tree)
+    }
+
+    fn live_stats(&self, state: &StatsState) -> Stats {
+        let base = &state.base;
+        let reset = &state.live_at_reset;
+        Stats {
+            total_ops: base.total_ops
+                + (self.context.total_ops.load(SeqCst) - reset.total_ops),
+            log_bytes_written: base.log_bytes_written
+                + (self.context.pagecache.log_bytes_written()
+                    - reset.log_bytes_written),
+            pages_rewritten: base.pages_rewritten
+                + (self.context.pagecache.pages_rewritten()
+                    - reset.pages_rewritten),
+            scrub_errors: base.scrub_errors
+                + (self.context.scrub_errors.load(SeqCst)
+                    - reset.scrub_errors),
+        }
+    }
+
+    /// Returns a breakdown of the major contributors to this
+    /// database's in-memory footprint, so that RSS can be explained
+    /// and `cache_capacity` tuned with real data.
+    ///
+    /// All values are estimates. Epoch-based reclamation garbage is
+    /// not reported because the underlying epoch collector does not
+    /// expose it; it is bounded by recent mutation activity and
+    /// drains as threads quiesce.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"key", b"value")?;
+    ///
+    /// let memory = db.memory_usage();
+    /// assert!(memory.index_bytes > 0);
+    /// assert_eq!(memory.subscriber_count, 0);
+    /// # Ok(()) }
+    /// ```
+    pub fn memory_usage(&self) -> MemoryBreakdown {
+        let tenants = self.tenants.read();
+        let mut subscriber_count = 0;
+        for tree in tenants.values() {
+            subscriber_count += tree.subscribers.count();
+        }
+
+        MemoryBreakdown {
+            cache_bytes: self.context.pagecache.cache_bytes(),
+            index_bytes: self.context.pagecache.index_bytes(),
+            write_buffer_bytes: self.context.segment_size as u64,
+            subscriber_count,
+        }
+    }
+
+    /// Returns statistics about the experimental speculative
+    /// prefetcher enabled via `Config::speculative_prefetch`.
+    /// All counters are zero when it is disabled.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new()
+    /// #     .temporary(true)
+    /// #     .speculative_prefetch(true);
+    /// # let db = config.open()?;
+    /// for i in 0..100_u32 {
+    ///     db.insert(i.to_be_bytes(), b"value")?;
+    /// }
+    /// for i in 0..100_u32 {
+    ///     db.get(i.to_be_bytes())?;
+    /// }
+    ///
+    /// let prefetches = db.prefetch_stats();
+    /// assert!(prefetches.hits <= prefetches.issued);
+    /// # Ok(()) }
+    /// ```
+    pub fn prefetch_stats(&self) -> PrefetchStats {
+        let (issued, hits) = self.context.pagecache.prefetch_stats();
+        PrefetchStats { issued, hits }
+    }
+
+    /// Returns a snapshot of the log garbage collector's view of
+    /// every segment: its state, how many live bytes it holds,
+    /// whether it is a cleaning candidate under the configured
+    /// `Config::gc_target_utilization`, and how many of its pages
+    /// are queued for rewriting.
+    ///
+    /// Rewrites are paced by the flush thread, which cleans
+    /// queued pages after each periodic flush for at most half
+    /// of the flush interval, so foreground traffic always gets
+    /// the larger share of write bandwidth.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"k", b"v")?;
+    /// db.flush()?;
+    ///
+    /// let gc_info = db.gc_info();
+    /// assert!(gc_info.segments.iter().any(|s| s.state == "active"));
+    /// # Ok(()) }
+    /// ```
+    pub fn gc_info(&self) -> GcInfo {
+        let segments = self
+            .context
+            .pagecache
+            .segment_report()
+            .into_iter()
+            .map(|report| SegmentInfo {
+                offset: report.offset,
+                capacity: self.context.segment_size as u64,
+                live_bytes: report.live_bytes,
+                state: report.state,
+                gc_candidate: report.gc_candidate,
+                pages_to_rewrite: report.pages_to_rewrite,
+            })
+            .collect();
+
+        GcInfo {
+            target_utilization: self
+                .context
+                .gc_target_utilization
+                .max(0.01)
+                .min(0.99),
+            segments,
+        }
+    }
+
+    /// Aggressively compacts the log right now, attempting to
+    /// free at least `target_bytes` of disk space for operators
+    /// responding to a disk-full incident.
+    ///
+    /// Every inactive segment is drained regardless of its
+    /// utilization and all queued page rewrites are performed
+    /// synchronously on the calling thread, so concurrent traffic
+    /// will see degraded latency while this runs. On success the
+    /// inner result holds the number of bytes actually freed; if
+    /// the target could not be met, it holds a description of
+    /// what is pinning the remaining space.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"k", b"v")?;
+    ///
+    /// // a zero-byte target always succeeds
+    /// assert!(db.reclaim_space_now(0)?.is_ok());
+    ///
+    /// // an impossible target explains why it failed
+    /// assert!(db.reclaim_space_now(u64::max_value())?.is_err());
+    /// # Ok(()) }
+    /// ```
+    pub fn reclaim_space_now(
+        &self,
+        target_bytes: u64,
+    ) -> Result<std::result::Result<u64, String>> {
+        let before = self.size_on_disk()?;
+
+        // push buffered writes out so that recently active
+        // segments can deactivate, then rewrite everything the
+        // cleaner can reach and persist the rewrites, which lets
+        // stabilization free and truncate the drained segments
+        self.flush()?;
+        self.context.pagecache.reclaim_pass()?;
+        self.flush()?;
+
+        let after = self.size_on_disk()?;
+        let freed = before.saturating_sub(after);
+        if freed >= target_bytes {
+            return Ok(Ok(freed));
+        }
+
+        let gc_info = self.gc_info();
+        let live_bytes: u64 =
+            gc_info.segments.iter().map(|s| s.live_bytes).sum();
+        let active_segments = gc_info
+            .segments
+            .iter()
+            .filter(|s| s.state == "active")
+            .count();
+        Ok(Err(format!(
+            "freed {} of the requested {} bytes: {} bytes are \
+             still live across {} segments, {} segments are still \
+             actively being written to, and space is only \
+             reclaimed in multiples of the {} byte segment size",
+            freed,
+            target_bytes,
+            live_bytes,
+            gc_info.segments.len(),
+            active_segments,
+            self.context.segment_size,
+        )))
+    }
+
+    /// Traverses all files and calculates their total physical
+    /// size, then traverses all pages and calculates their
+    /// total logical size, then divides the physical size
+    /// by the logical size.
+    #[doc(hidden)]
+    pub fn space_amplification(&self) -> Result<f64> {
+        self.context.pagecache.space_amplification()
+    }
+}
+
+/// A snapshot of the log garbage collector's state, returned by
+/// `Db::gc_info`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GcInfo {
+    /// The configured `Config::gc_target_utilization`, after
+    /// clamping.
+    pub target_utilization: f32,
+    /// One entry per log segment, in offset order.
+    pub segments: Vec<SegmentInfo>,
+}
+
+/// A description of one log segment from the garbage collector's
+/// perspective.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SegmentInfo {
+    /// The segment's byte offset in the log file.
+    pub offset: u64,
+    /// The segment's total capacity, equal to
+    /// `Config::segment_size`.
+    pub capacity: u64,
+    /// The number of bytes in the segment that are still
+    /// referenced by live pages. Zero for free and draining
+    /// segments.
+    pub live_bytes: u64,
+    /// One of `"free"`, `"active"`, `"inactive"`, or
+    /// `"draining"`.
+    pub state: &'static str,
+    /// Whether the segment is eligible for cleaning: draining
+    /// segments, and inactive segments at or below the target
+    /// utilization.
+    pub gc_candidate: bool,
+    /// The number of the segment's pages queued to be rewritten
+    /// into a fresher segment.
+    pub pages_to_rewrite: usize,
+}
+
+/// A sparse-file-aware breakdown of database disk usage,
+/// returned by `Db::disk_usage`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiskUsage {
+    /// The apparent total length of all storage files, in bytes.
+    pub apparent_bytes: u64,
+    /// The number of bytes the filesystem has actually allocated
+    /// for the storage files, which may be lower than
+    /// `apparent_bytes` when segments are sparse or hole-punched.
+    pub allocated_bytes: u64,
+    /// The logical size of keys and values stored in each tree,
+    /// for attributing usage between keyspaces.
+    pub tree_logical_bytes: BTreeMap<IVec, u64>,
+}
+
+/// The measurements and applied tuning from a storage
+/// self-benchmark, returned by `Db::calibrate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Calibration {
+    /// Measured sequential write throughput in bytes per second,
+    /// including one fsync.
+    pub write_throughput: u64,
+    /// The median measured latency of an fsync of a small
+    /// append.
+    pub fsync_latency: Duration,
+    /// The mean measured latency of a scattered 4k read of
+    /// recently-written data. This mostly exercises the syscall
+    /// and OS cache path rather than the medium.
+    pub random_read_latency: Duration,
+    /// The interval between background flushes that was applied,
+    /// in milliseconds. Slower devices get longer intervals so
+    /// each flush batches more work per fsync.
+    pub flush_every_ms: u64,
+    /// The applied target for ready IO threads in the internal
+    /// blocking pool.
+    pub io_threads: usize,
+    /// Whether speculative page readahead was left enabled.
+    pub speculative_prefetch: bool,
+}
+
+/// A summary of a database's internal error states, returned by
+/// `Db::health`. Each reason is a human-readable description
+/// suitable for logging or exposing through a health endpoint.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Health {
+    /// No internal error states have been observed.
+    Ok,
+    /// The database is serving requests, but something needs
+    /// operator attention.
+    Degraded(Vec<String>),
+    /// A fatal error has poisoned the database, and all
+    /// operations will return errors until it is reopened.
+    Failed(Vec<String>),
+}
+
+const STATS_KEY: &[u8] = b"lifetime";
+
+/// Cumulative lifetime statistics for a database, returned by
+/// `Db::stats`. Persisted across restarts in a metadata tree.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The total number of successful insert, remove, merge, and
+    /// compare-and-swap operations applied across all trees.
+    pub total_ops: u64,
+    /// The total number of bytes written to the log, including
+    /// rewritten and padding data. Comparing this against the
+    /// logical data size indicates write amplification.
+    pub log_bytes_written: u64,
+    /// The number of pages rewritten by garbage collection and
+    /// segment defragmentation.
+    pub pages_rewritten: u64,
+    /// The number of log segments that the background scrubber
+    /// failed to read back.
+    pub scrub_errors: u64,
+}
+
+struct StatsState {
+    base: Stats,
+    live_at_reset: Stats,
+}
+
+fn stats_record(stats: &Stats) -> [u8; 32] {
+    let mut buf = [0; 32];
+    buf[..8].copy_from_slice(&stats.total_ops.to_le_bytes());
+    buf[8..16].copy_from_slice(&stats.log_bytes_written.to_le_bytes());
+    buf[16..24].copy_from_slice(&stats.pages_rewritten.to_le_bytes());
+    buf[24..].copy_from_slice(&stats.scrub_errors.to_le_bytes());
+    buf
+}
+
+fn parse_stats_record(buf: &[u8]) -> Stats {
+    if buf.len() != 32 {
+        warn!("persisted statistics record has an unexpected length");
+        return Stats::default();
+    }
+    let read = |idx: usize| {
+        let mut arr = [0; 8];
+        arr.copy_from_slice(&buf[idx..idx + 8]);
+        u64::from_le_bytes(arr)
+    };
+    Stats {
+        total_ops: read(0),
+        log_bytes_written: read(8),
+        pages_rewritten: read(16),
+        scrub_errors: read(24),
+    }
+}
+
+/// A breakdown of the major contributors to a database's
+/// in-memory footprint, returned by `Db::memory_usage`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryBreakdown {
+    /// The approximate number of bytes of page data admitted to
+    /// the in-memory cache. This is the figure bounded by
+    /// `cache_capacity`. Item sizes are rounded up to powers of
+    /// two on admission, so this slightly overestimates.
+    pub cache_bytes: u64,
+    /// An estimate of the memory occupied by the in-memory page
+    /// table that maps page identifiers to materialized pages.
+    pub index_bytes: u64,
+    /// The size of the in-flight log write buffer. Additional
+    /// buffers of this size may exist transiently while a full
+    /// buffer is being flushed during rotation.
+    pub write_buffer_bytes: u64,
+    /// The number of active event subscribers across all trees.
+    /// Each holds a bounded queue of pending events that are
+    /// retained until received or dropped.
+    pub subscriber_count: u64,
+}
+
+/// Statistics about the experimental speculative prefetcher,
+/// returned by `Db::prefetch_stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PrefetchStats {
+    /// The number of pages whose disk ranges have been read
+    /// ahead of a predicted access.
+    pub issued: u64,
+    /// The number of prefetched pages that were subsequently
+    /// accessed.
+    pub hits: u64,
+}
+
+/// A bounds-checked reader over the decoded body of an archive
+/// produced by `Db::export_archive`.
+struct ArchiveCursor<'a> {
+    buf: &'a [u8],
+    idx: usize,
+}
+
+impl<'a> ArchiveCursor<'a> {
+    fn done(&self) -> bool {
+        self.idx >= self.buf.len()
+    }
+
+    fn read_len(&mut self) -> Result<u64> {
+        if self.idx + 8 > self.buf.len() {
+            return Err(Error::corruption(None));
+        }
+        let mut arr = [0; 8];
+        arr.copy_from_slice(&self.buf[self.idx..self.idx + 8]);
+        self.idx += 8;
+        Ok(u64::from_le_bytes(arr))
+    }
+
+    fn read_sized(&mut self, len: u64) -> Result<&'a [u8]> {
+        let len =
+            usize::try_from(len).map_err(|_| Error::corruption(None))?;
+        if self.idx + len > self.buf.len() {
+            return Err(Error::corruption(None));
+        }
+        let ret = &self.buf[self.idx..self.idx + len];
+        self.idx += len;
+        Ok(ret)
+    }
+
+    fn read_chunk(&mut self) -> Result<&'a [u8]> {
+        let len = self.read_len()?;
+        self.read_sized(len)
+    }
+
+    /// Returns `None` at the per-tree end sentinel.
+    fn read_entry_key(&mut self) -> Result<Option<&'a [u8]>> {
+        let len = self.read_len()?;
+        if len == u64::max_value() {
+            return Ok(None);
+        }
+        self.read_sized(len).map(Some)
     }
 }
 
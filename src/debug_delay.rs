@@ -1,6 +1,6 @@
 #![allow(clippy::float_arithmetic)]
 
-use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering::Relaxed};
 
 use crate::Lazy;
 
@@ -75,13 +75,27 @@ pub fn debug_delay() {
     }
 }
 
+/// Sets a seed that future thread-local random states derive
+/// from, making delay and crash decisions reproducible across
+/// runs. Threads that have already made a random decision keep
+/// their current state.
+pub(crate) fn set_deterministic_seed(seed: u64) {
+    #[allow(clippy::cast_possible_truncation)]
+    let folded = (seed as u32) ^ ((seed >> 32) as u32);
+    SEED.store(folded, Relaxed);
+}
+
+static SEED: AtomicU32 = AtomicU32::new(0);
+
+const DEFAULT_RNG_STATE: u32 = 1_406_868_647;
+
 /// Generates a random number in `0..n`.
 fn random(n: u32) -> u32 {
     use std::cell::Cell;
     use std::num::Wrapping;
 
     thread_local! {
-        static RNG: Cell<Wrapping<u32>> = Cell::new(Wrapping(1_406_868_647));
+        static RNG: Cell<Wrapping<u32>> = Cell::new(Wrapping(0));
     }
 
     #[allow(clippy::cast_possible_truncation)]
@@ -90,6 +104,13 @@ fn random(n: u32) -> u32 {
         //
         // Source: https://en.wikipedia.org/wiki/Xorshift
         let mut x = rng.get();
+        if x.0 == 0 {
+            // first use on this thread. xorshift has no valid
+            // all-zero state, so fall back to the default state
+            // if the configured seed folds to it.
+            let seeded = DEFAULT_RNG_STATE ^ SEED.load(Relaxed);
+            x = Wrapping(if seeded == 0 { DEFAULT_RNG_STATE } else { seeded });
+        }
         x ^= x << 13;
         x ^= x >> 17;
         x ^= x << 5;
@@ -0,0 +1,309 @@
+//! Approximate range statistics from index-node fanout.
+//!
+//! Query planners and progress bars often need "roughly how many
+//! records fall in this range?" where a full scan is exactly the
+//! cost being avoided. Both descent paths of a range touch one
+//! node per level, and each index node records how many children
+//! it has, so the number of leaves between the two paths can be
+//! estimated from the slot distance at the level where the paths
+//! diverge, multiplied by the average fanout of the levels below.
+//! The two boundary leaves are counted exactly, which makes
+//! estimates for ranges inside a single leaf precise.
+
+use std::ops::{Bound, RangeBounds};
+use std::sync::atomic::Ordering::Acquire;
+
+use super::*;
+
+#[cfg(any(test, feature = "lock_free_delays"))]
+const MAX_LOOPS: usize = usize::max_value();
+
+#[cfg(not(any(test, feature = "lock_free_delays")))]
+const MAX_LOOPS: usize = 1_000_000;
+
+// the nodes visited at one level of a descent: which child slot
+// was chosen, and the node's fanout and footprint for averaging
+struct LevelStat {
+    pid: PageId,
+    idx: usize,
+    children: usize,
+    rss: u64,
+}
+
+// evenly spaced keys inside `(lo, hi)`, derived by
+// interpolating the first eight bytes of the bounds. callers
+// clamp the bounds to the tree's occupied key range first, so
+// open-ended ranges do not probe empty byte space. long shared
+// prefixes can collapse the probes onto the bounds, in which
+// case the estimate falls back to the boundary paths alone.
+fn probe_keys(lo: &[u8], hi: Option<&[u8]>) -> Vec<[u8; 8]> {
+    fn first_eight(key: &[u8], fill: u8) -> u64 {
+        let mut buf = [fill; 8];
+        for (slot, byte) in buf.iter_mut().zip(key.iter()) {
+            *slot = *byte;
+        }
+        u64::from_be_bytes(buf)
+    }
+
+    const PROBES: u64 = 3;
+    let lo = first_eight(lo, 0);
+    let hi = hi.map_or(u64::max_value(), |hi| first_eight(hi, 0));
+    if hi <= lo {
+        return Vec::new();
+    }
+    let stride = (hi - lo) / (PROBES + 1);
+    (1..=PROBES)
+        .map(|i| lo + stride * i)
+        .filter(|probe| *probe > lo && *probe < hi)
+        .map(u64::to_be_bytes)
+        .collect()
+}
+
+impl Tree {
+    /// Estimates the number of records whose keys fall in
+    /// `range`, in two tree descents rather than a scan.
+    ///
+    /// The two boundary leaves are counted exactly; leaves in
+    /// between are estimated from index-node fanout, so a range
+    /// contained in a single leaf is precise and wider ranges are
+    /// typically within a small factor. The estimate reflects a
+    /// racy snapshot under concurrent writes, counts records
+    /// whose TTL has lapsed but not yet been reaped, and treats
+    /// an excluded start bound as included (at most one record of
+    /// error).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// for i in 0u8..10 {
+    ///     db.insert(&[i], &[i])?;
+    /// }
+    ///
+    /// // small trees are a single leaf, so estimates are exact
+    /// assert_eq!(db.estimate_count::<&[u8], _>(..)?, 10);
+    /// assert_eq!(db.estimate_count(&[3][..]..&[7][..])?, 4);
+    /// # Ok(()) }
+    /// ```
+    pub fn estimate_count<K, R>(&self, range: R) -> Result<u64>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        Ok(self.range_estimate(range)?.0)
+    }
+
+    /// Estimates the in-memory footprint in bytes of the records
+    /// whose keys fall in `range`, with the same approach and
+    /// caveats as [`Tree::estimate_count`]. The figure includes
+    /// per-node bookkeeping overhead, so it tracks resident size
+    /// rather than the sum of key and value lengths.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// for i in 0u8..10 {
+    ///     db.insert(&[i], vec![0; 64])?;
+    /// }
+    ///
+    /// let whole = db.estimate_size::<&[u8], _>(..)?;
+    /// let half = db.estimate_size(&[0][..]..&[5][..])?;
+    /// assert!(whole > 0);
+    /// assert!(half <= whole);
+    /// # Ok(()) }
+    /// ```
+    pub fn estimate_size<K, R>(&self, range: R) -> Result<u64>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        Ok(self.range_estimate(range)?.1)
+    }
+
+    fn range_estimate<K, R>(&self, range: R) -> Result<(u64, u64)>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let lo: &[u8] = match range.start_bound() {
+            Bound::Included(s) | Bound::Excluded(s) => s.as_ref(),
+            Bound::Unbounded => &[],
+        };
+        let hi: Option<&[u8]> = match range.end_bound() {
+            Bound::Included(e) | Bound::Excluded(e) => Some(e.as_ref()),
+            Bound::Unbounded => None,
+        };
+        if let Some(hi) = hi {
+            if hi <= lo {
+                return Ok((0, 0));
+            }
+        }
+
+        let guard = pin();
+        let start = self.descend_stats(Some(lo), &guard)?;
+        let end = self.descend_stats(hi, &guard)?;
+
+        // the edges of a range are often unrepresentative — an
+        // append workload leaves freshly split nodes on the right
+        // edge — so a few probe descents into the interior of the
+        // range keep the fanout averages honest
+        let first = self.first()?;
+        let last = self.last()?;
+        let lo_probe = match &first {
+            Some((k, _)) if k.as_ref() > lo => k.as_ref(),
+            _ => lo,
+        };
+        let hi_probe: Option<&[u8]> = match (&last, hi) {
+            (Some((k, _)), Some(hi)) if k.as_ref() < hi => Some(k.as_ref()),
+            (Some((k, _)), None) => Some(k.as_ref()),
+            _ => hi,
+        };
+        let mut probes = Vec::new();
+        for key in probe_keys(lo_probe, hi_probe) {
+            probes.push(self.descend_stats(Some(&key), &guard)?);
+        }
+
+        // racing root splits or merges can leave paths with
+        // different depths; align them from the leaf upward and
+        // let the divergence logic absorb the mismatched top
+        let depth = probes
+            .iter()
+            .map(Vec::len)
+            .chain([start.len(), end.len()])
+            .min()
+            .unwrap();
+        let start = &start[start.len() - depth..];
+        let end = &end[end.len() - depth..];
+
+        // typical fanout, record count, and footprint per level
+        // across every sampled path. the median resists the
+        // freshly split or half-drained nodes that accumulate at
+        // the edges of the keyspace
+        let level_avg = |d: usize, f: &dyn Fn(&LevelStat) -> f64| {
+            let mut samples = vec![f(&start[d]), f(&end[d])];
+            for probe in &probes {
+                samples.push(f(&probe[probe.len() - depth + d]));
+            }
+            samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let mid = samples.len() / 2;
+            if samples.len() % 2 == 0 {
+                (samples[mid - 1] + samples[mid]) / 2.
+            } else {
+                samples[mid]
+            }
+        };
+
+        // walk the index levels, accumulating the number of leaf
+        // nodes strictly between the two descent paths
+        let mut between = 0_f64;
+        let mut diverged = false;
+        for (d, (s, e)) in
+            start.iter().zip(end.iter()).take(depth - 1).enumerate()
+        {
+            between *= level_avg(d, &|l| l.children as f64);
+            if diverged {
+                between +=
+                    (s.children - s.idx - 1) as f64 + e.idx as f64;
+            } else if s.pid != e.pid || e.idx > s.idx {
+                between += e.idx.saturating_sub(s.idx + 1) as f64;
+                diverged = true;
+            }
+        }
+
+        let s = &start[depth - 1];
+        let e = &end[depth - 1];
+        let count = if !diverged && s.pid == e.pid {
+            e.idx.saturating_sub(s.idx) as f64
+        } else {
+            let interior = level_avg(depth - 1, &|l| l.children as f64);
+            (s.children - s.idx) as f64
+                + e.idx as f64
+                + between * interior
+        };
+        let per_record = level_avg(depth - 1, &|l| l.rss as f64)
+            / level_avg(depth - 1, &|l| l.children.max(1) as f64);
+
+        #[allow(clippy::cast_sign_loss)]
+        #[allow(clippy::cast_possible_truncation)]
+        Ok((count.round() as u64, (count * per_record).round() as u64))
+    }
+
+    // descends to the leaf responsible for `key` (or the
+    // rightmost leaf for `None`), recording the chosen child
+    // slot and fanout at every level. restarts from the root
+    // when a split or merge moves the key out from under the
+    // descent, like `view_for_key`, but never fixes anything up.
+    fn descend_stats(
+        &self,
+        key: Option<&[u8]>,
+        guard: &Guard,
+    ) -> Result<Vec<LevelStat>> {
+        'retry: for _ in 0..MAX_LOOPS {
+            let mut path = Vec::new();
+            let mut cursor = self.root.load(Acquire);
+            loop {
+                let view = if let Some(view) =
+                    self.view_for_pid(cursor, guard)?
+                {
+                    view
+                } else {
+                    continue 'retry;
+                };
+                if let Some(key) = key {
+                    if key < view.lo()
+                        || view.hi().map_or(false, |hi| key >= hi)
+                    {
+                        continue 'retry;
+                    }
+                } else if view.hi().is_some() {
+                    // a split moved the right edge of the tree
+                    continue 'retry;
+                }
+                let children = view.children();
+                if view.is_index {
+                    if children == 0 {
+                        continue 'retry;
+                    }
+                    let (idx, next) = if let Some(key) = key {
+                        view.index_next_node(key)
+                    } else {
+                        (children - 1, view.index_pid(children - 1))
+                    };
+                    path.push(LevelStat {
+                        pid: cursor,
+                        idx,
+                        children,
+                        rss: view.rss(),
+                    });
+                    cursor = next;
+                } else {
+                    let idx = if let Some(key) = key {
+                        let suffix =
+                            &key[usize::from(view.prefix_len)..];
+                        view.iter_keys()
+                            .take_while(|k| *k < suffix)
+                            .count()
+                    } else {
+                        children
+                    };
+                    path.push(LevelStat {
+                        pid: cursor,
+                        idx,
+                        children,
+                        rss: view.rss(),
+                    });
+                    return Ok(path);
+                }
+            }
+        }
+        panic!(
+            "fucked up tree traversal descend_stats({:?}) on {:?}",
+            key, self
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! A packed layout for trees whose values all share one small
+//! fixed size, layered over a hidden tree.
+//!
+//! Counters, flags, and other fixed-width records are wasteful to
+//! store one per tree entry: every record pays the per-entry key
+//! and length overhead of the leaf format. Here, records are
+//! addressed by a dense `u64` index and packed 64 to a bucket as
+//! a flat array with a single presence bitmap, so the per-record
+//! overhead falls to a fraction of a byte and scans touch far
+//! fewer entries.
+
+use std::convert::TryFrom;
+
+use crate::{Error, IVec, Result, Tree};
+
+/// The number of records packed into each bucket, chosen to
+/// match the width of the presence bitmap.
+const SLOTS_PER_BUCKET: u64 = 64;
+
+/// The hidden key that records the configured value width, so
+/// that reopening with a different width fails instead of
+/// corrupting buckets. Shorter than the 8-byte bucket keys, so it
+/// can never collide with one.
+const WIDTH_KEY: &[u8] = b"width";
+
+/// A tree of fixed-width records opened via
+/// `Db::open_fixed_width_tree`, for values that all share one
+/// small size: counters, flags, timestamps.
+///
+/// Records are addressed by a dense `u64` index rather than
+/// arbitrary byte keys, and are packed 64 per bucket as an inline
+/// array with a presence bitmap instead of per-entry headers.
+/// This improves density and scan speed considerably when record
+/// values are only a few bytes. The value width is fixed at open
+/// time, persisted, and enforced on every write and reopen.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// let counters = db.open_fixed_width_tree("counters", 8)?;
+///
+/// counters.set(0, &42u64.to_le_bytes())?;
+/// counters.set(1_000_000, &7u64.to_le_bytes())?;
+///
+/// assert_eq!(&counters.get(0)?.unwrap(), &42u64.to_le_bytes());
+/// assert_eq!(counters.get(1)?, None);
+///
+/// // writes of the wrong width are rejected
+/// assert!(counters.set(2, b"too small").is_err());
+///
+/// let present: Vec<u64> = counters
+///     .iter()
+///     .map(|res| res.map(|(index, _value)| index))
+///     .collect::<Result<_, _>>()?;
+/// assert_eq!(present, vec![0, 1_000_000]);
+///
+/// assert!(counters.remove(0)?);
+/// assert_eq!(counters.get(0)?, None);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct FixedWidthTree {
+    pub(crate) tree: Tree,
+    width: usize,
+}
+
+impl FixedWidthTree {
+    /// Validates the persisted value width, recording it on first
+    /// open.
+    pub(crate) fn open(tree: Tree, width: usize) -> Result<FixedWidthTree> {
+        if width == 0 {
+            return Err(Error::Unsupported(
+                "FixedWidthTree requires a non-zero value width".into(),
+            ));
+        }
+
+        let encoded = u64::try_from(width).unwrap().to_le_bytes();
+        match tree.get(WIDTH_KEY)? {
+            Some(persisted) if *persisted == encoded[..] => {}
+            Some(persisted) => {
+                return Err(Error::Unsupported(format!(
+                    "this FixedWidthTree was created with a value \
+                     width of {} bytes, but was opened with a width \
+                     of {} bytes",
+                    decode_u64(&persisted),
+                    width
+                )));
+            }
+            None => {
+                tree.insert(WIDTH_KEY, &encoded[..])?;
+            }
+        }
+
+        Ok(FixedWidthTree { tree, width })
+    }
+
+    /// Returns the value width in bytes that every record in this
+    /// tree must have.
+    pub fn value_width(&self) -> usize {
+        self.width
+    }
+
+    /// Sets the record at the given index. Returns an error if
+    /// `value` does not match the configured width.
+    pub fn set(&self, index: u64, value: &[u8]) -> Result<()> {
+        if value.len() != self.width {
+            return Err(Error::Unsupported(format!(
+                "FixedWidthTree::set requires values of exactly {} \
+                 bytes, but {} bytes were provided",
+                self.width,
+                value.len()
+            )));
+        }
+
+        let (bucket_key, slot) = self.locate(index);
+        let offset = self.slot_offset(slot);
+
+        loop {
+            let current = self.tree.get(&bucket_key)?;
+            let mut bucket = match &current {
+                Some(bucket) => bucket.to_vec(),
+                None => self.empty_bucket(),
+            };
+
+            let bitmap = decode_u64(&bucket) | (1 << slot);
+            bucket[..8].copy_from_slice(&bitmap.to_le_bytes());
+            bucket[offset..offset + self.width].copy_from_slice(value);
+
+            if self
+                .tree
+                .compare_and_swap(&bucket_key, current.as_ref(), Some(bucket))?
+                .is_ok()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Retrieves the record at the given index, if one is set.
+    pub fn get(&self, index: u64) -> Result<Option<IVec>> {
+        let (bucket_key, slot) = self.locate(index);
+
+        let bucket = match self.tree.get(&bucket_key)? {
+            Some(bucket) => bucket,
+            None => return Ok(None),
+        };
+
+        if decode_u64(&bucket) & (1 << slot) == 0 {
+            return Ok(None);
+        }
+
+        let offset = self.slot_offset(slot);
+        Ok(Some(bucket.subslice(offset, self.width)))
+    }
+
+    /// Removes the record at the given index, returning `true` if
+    /// one was set. Buckets whose last record is removed are
+    /// deleted entirely.
+    pub fn remove(&self, index: u64) -> Result<bool> {
+        let (bucket_key, slot) = self.locate(index);
+
+        loop {
+            let current = match self.tree.get(&bucket_key)? {
+                Some(bucket) => bucket,
+                None => return Ok(false),
+            };
+
+            let bitmap = decode_u64(&current);
+            if bitmap & (1 << slot) == 0 {
+                return Ok(false);
+            }
+
+            let remaining = bitmap & !(1 << slot);
+            let swap = if remaining == 0 {
+                self.tree.compare_and_swap(
+                    &bucket_key,
+                    Some(&current),
+                    None::<&[u8]>,
+                )?
+            } else {
+                let mut bucket = current.to_vec();
+                bucket[..8].copy_from_slice(&remaining.to_le_bytes());
+                let offset = self.slot_offset(slot);
+                for byte in &mut bucket[offset..offset + self.width] {
+                    *byte = 0;
+                }
+                self.tree.compare_and_swap(
+                    &bucket_key,
+                    Some(&current),
+                    Some(bucket),
+                )?
+            };
+
+            if swap.is_ok() {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Iterates over all set records in index order.
+    pub fn iter(&self) -> impl Iterator<Item = Result<(u64, IVec)>> {
+        let width = self.width;
+        self.tree
+            .iter()
+            .filter(|res| match res {
+                // skip the width metadata record
+                Ok((key, _)) => key.len() == 8,
+                Err(_) => true,
+            })
+            .flat_map(move |res| match res {
+                Ok((key, bucket)) => {
+                    let base = decode_u64_be(&key) * SLOTS_PER_BUCKET;
+                    let bitmap = decode_u64(&bucket);
+                    (0..SLOTS_PER_BUCKET)
+                        .filter(|slot| bitmap & (1 << slot) != 0)
+                        .map(|slot| {
+                            let offset =
+                                8 + usize::try_from(slot).unwrap() * width;
+                            Ok((base + slot, bucket.subslice(offset, width)))
+                        })
+                        .collect::<Vec<_>>()
+                }
+                Err(e) => vec![Err(e)],
+            })
+    }
+
+    fn locate(&self, index: u64) -> ([u8; 8], u64) {
+        let bucket = index / SLOTS_PER_BUCKET;
+        let slot = index % SLOTS_PER_BUCKET;
+        (bucket.to_be_bytes(), slot)
+    }
+
+    fn slot_offset(&self, slot: u64) -> usize {
+        8 + usize::try_from(slot).unwrap() * self.width
+    }
+
+    fn empty_bucket(&self) -> Vec<u8> {
+        vec![0; 8 + usize::try_from(SLOTS_PER_BUCKET).unwrap() * self.width]
+    }
+}
+
+fn decode_u64(bytes: &[u8]) -> u64 {
+    let mut arr = [0; 8];
+    arr.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(arr)
+}
+
+fn decode_u64_be(bytes: &[u8]) -> u64 {
+    let mut arr = [0; 8];
+    arr.copy_from_slice(&bytes[..8]);
+    u64::from_be_bytes(arr)
+}
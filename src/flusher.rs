@@ -1,3 +1,4 @@
+use std::panic::AssertUnwindSafe;
 use std::thread;
 use std::time::Duration;
 
@@ -13,11 +14,11 @@ pub(crate) enum ShutdownState {
 }
 
 impl ShutdownState {
-    fn is_running(self) -> bool {
+    pub(crate) fn is_running(self) -> bool {
         if let ShutdownState::Running = self { true } else { false }
     }
 
-    fn is_shutdown(self) -> bool {
+    pub(crate) fn is_shutdown(self) -> bool {
         if let ShutdownState::ShutDown = self { true } else { false }
     }
 }
@@ -26,7 +27,8 @@ impl ShutdownState {
 pub(crate) struct Flusher {
     shutdown: Arc<Mutex<ShutdownState>>,
     sc: Arc<Condvar>,
-    join_handle: Mutex<Option<std::thread::JoinHandle<()>>>,
+    join_handle: Mutex<Option<thread::JoinHandle<()>>>,
+    interval_ms: Arc<AtomicU64>,
 }
 
 impl Flusher {
@@ -35,21 +37,68 @@ impl Flusher {
         name: String,
         pagecache: PageCache,
         flush_every_ms: u64,
+        progress: Arc<AtomicU64>,
+        expiry_sweeps: tree::WeakExpirySweepRegistry,
+        panic_handler: poison::PanicHandler,
     ) -> Self {
         #[allow(clippy::mutex_atomic)] // mutex used in CondVar below
         let shutdown = Arc::new(Mutex::new(ShutdownState::Running));
         let sc = Arc::new(Condvar::new());
+        let interval_ms = Arc::new(AtomicU64::new(flush_every_ms));
 
         let join_handle = thread::Builder::new()
-            .name(name)
+            .name(name.clone())
             .spawn({
                 let shutdown = shutdown.clone();
                 let sc = sc.clone();
-                move || run(&shutdown, &sc, &pagecache, flush_every_ms)
+                let interval_ms = interval_ms.clone();
+                let hook_name = name.clone();
+                move || {
+                    thread_lifecycle::thread_spawned(&hook_name);
+                    let result =
+                        std::panic::catch_unwind(AssertUnwindSafe(|| {
+                            run(
+                                &shutdown,
+                                &sc,
+                                &pagecache,
+                                &interval_ms,
+                                &progress,
+                                &expiry_sweeps,
+                            )
+                        }));
+                    if let Err(panic) = result {
+                        panic_handler.handle(panic);
+
+                        // unblock any `Drop` handshake waiting for
+                        // the thread to shut down.
+                        *shutdown.lock() = ShutdownState::ShutDown;
+                        let _notified = sc.notify_all();
+                    }
+                    thread_lifecycle::thread_exiting(&hook_name);
+                }
             })
             .unwrap();
 
-        Self { shutdown, sc, join_handle: Mutex::new(Some(join_handle)) }
+        Self {
+            shutdown,
+            sc,
+            join_handle: Mutex::new(Some(join_handle)),
+            interval_ms,
+        }
+    }
+
+    /// Adjusts the interval between flushes, taking effect from
+    /// the next flusher wakeup.
+    pub(crate) fn set_interval_ms(&self, interval_ms: u64) {
+        self.interval_ms.store(interval_ms, SeqCst);
+    }
+}
+
+impl Flusher {
+    /// Returns `true` if the background thread has stopped or is
+    /// in the process of stopping.
+    pub(crate) fn is_stopped(&self) -> bool {
+        !self.shutdown.lock().is_running()
     }
 }
 
@@ -57,13 +106,22 @@ fn run(
     shutdown: &Arc<Mutex<ShutdownState>>,
     sc: &Arc<Condvar>,
     pagecache: &PageCache,
-    flush_every_ms: u64,
+    interval_ms: &Arc<AtomicU64>,
+    progress: &Arc<AtomicU64>,
+    expiry_sweeps: &tree::WeakExpirySweepRegistry,
 ) {
-    let flush_every = Duration::from_millis(flush_every_ms);
     let mut shutdown = shutdown.lock();
     let mut wrote_data = false;
     while shutdown.is_running() || wrote_data {
+        // re-read each iteration so that `Db::calibrate` can
+        // retune flush batching while we run
+        let flush_every = Duration::from_millis(interval_ms.load(SeqCst));
         let before = std::time::Instant::now();
+        progress.store(watchdog::now_millis(), SeqCst);
+
+        #[cfg(feature = "otel")]
+        let span = otel::background_span("sled.flush");
+
         let cc = concurrency_control::read();
         match pagecache.log.roll_iobuf() {
             Ok(0) => {
@@ -134,10 +192,21 @@ fn run(
                 && before.elapsed() < flush_every / 2
         } {}
 
+        // reclaim keys whose TTLs have lapsed, dropping sweeps
+        // whose trees are gone
+        if let Some(registry) = expiry_sweeps.upgrade() {
+            let now = watchdog::now_millis();
+            let mut sweeps = registry.write();
+            sweeps.retain(|sweep| sweep(now));
+        }
+
         if let Err(e) = pagecache.config.file.sync_all() {
             error!("failed to fsync from periodic flush thread: {}", e);
         }
 
+        #[cfg(feature = "otel")]
+        drop(span);
+
         let sleep_duration = flush_every
             .checked_sub(before.elapsed())
             .unwrap_or_else(|| Duration::from_millis(1));
@@ -0,0 +1,308 @@
+//! Declarative referential integrity between trees, built on
+//! secondary indexes and the transactional commit path.
+//!
+//! Applications that spread relational-ish data across trees end
+//! up hand-rolling the same two checks — "does the row I
+//! reference exist?" on insert, and "does anything still
+//! reference me?" on delete — and usually miss one of the edge
+//! cases: updates that change the referenced key, or parents and
+//! children written in the same transaction. A
+//! [`Tree::add_foreign_key`] constraint declares the
+//! relationship once; violations are rejected with an error
+//! naming the constraint, the referencing key, and the missing
+//! or still-referenced record.
+//!
+//! Reverse lookups ("who references this?") are served by a
+//! hidden secondary index on the source tree, maintained by the
+//! same machinery as user-created indexes.
+
+use std::sync::Arc;
+
+use crate::{
+    index::{decode_derived, encode_derived, Index, IndexExtractor},
+    pin, Error, IVec, Result, Tree,
+};
+
+/// An outgoing constraint registered on a source tree: every
+/// record's derived reference must exist as a key in the target
+/// tree.
+pub(crate) struct ForeignKeyConstraint {
+    pub(crate) name: String,
+    pub(crate) target: Tree,
+}
+
+/// The mirror of a constraint on its target tree, used to reject
+/// removals of records that are still referenced. Holds the
+/// source's reverse index rather than the source tree itself, so
+/// the two trees do not keep each other alive in a cycle.
+pub(crate) struct IncomingForeignKey {
+    pub(crate) name: String,
+    pub(crate) source_id: IVec,
+    pub(crate) index: Index,
+}
+
+impl Tree {
+    /// Declares that every record in this tree references a key
+    /// that must exist in `target`, derived from the record by
+    /// `extractor` (returning `None` exempts a record). The
+    /// constraint is enforced in both directions: inserts and
+    /// updates here are rejected when the referenced key is
+    /// missing, and removals from `target` are rejected while a
+    /// record here still references them. Violations surface as
+    /// `Error::Unsupported` naming the constraint and the keys
+    /// involved.
+    ///
+    /// Within a transaction the checks see the whole staged
+    /// write set, so a parent and child may be inserted (or a
+    /// child and its parent removed) atomically in either order.
+    /// Direct writes are checked individually before applying.
+    /// `merge` and range operations are not validated.
+    ///
+    /// Registration scans existing records and fails if any
+    /// already violate the constraint. Reverse lookups are
+    /// served by a hidden secondary index on this tree, so like
+    /// merge operators and indexes, constraints must be
+    /// re-registered each time the database is opened.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let users = db.open_tree("users")?;
+    /// let orders = db.open_tree("orders")?;
+    ///
+    /// // each order's value starts with an 8-byte user id
+    /// orders.add_foreign_key("order_user", &users, |_k, v| {
+    ///     Some(v[..8].to_vec())
+    /// })?;
+    ///
+    /// users.insert(&1u64.to_be_bytes(), b"ada")?;
+    ///
+    /// // referencing an existing user succeeds
+    /// let mut order = 1u64.to_be_bytes().to_vec();
+    /// order.extend_from_slice(b"3 walnuts");
+    /// orders.insert(b"order-1", order)?;
+    ///
+    /// // referencing a missing user is rejected
+    /// let mut bad = 9u64.to_be_bytes().to_vec();
+    /// bad.extend_from_slice(b"7 emus");
+    /// assert!(orders.insert(b"order-2", bad).is_err());
+    ///
+    /// // removing a referenced user is rejected, including via
+    /// // range removals
+    /// assert!(users.remove(&1u64.to_be_bytes()).is_err());
+    /// assert!(users.clear().is_err());
+    ///
+    /// orders.remove(b"order-1")?;
+    /// users.remove(&1u64.to_be_bytes())?;
+    /// # Ok(()) }
+    /// ```
+    pub fn add_foreign_key<N: AsRef<str>>(
+        &self,
+        name: N,
+        target: &Tree,
+        extractor: impl IndexExtractor + 'static,
+    ) -> Result<()> {
+        let name = name.as_ref().to_string();
+
+        let mut index_name = b"__fk__".to_vec();
+        index_name.extend_from_slice(name.as_bytes());
+        let index = self.create_index(index_name, extractor)?;
+
+        // reject registration outright if existing data already
+        // violates the constraint, using the freshly backfilled
+        // reverse index as the source of truth
+        let mut entries = index.0.tree.iter();
+        while let Some(kv) = entries.next_inner() {
+            let (entry, primary) = kv?;
+            let derived = decode_derived(&entry);
+            if !target.contains_key_inner(&derived)? {
+                let mut indexes = self.indexes.write();
+                indexes.retain(|i| i.0.name != index.0.name);
+                return Err(Error::Unsupported(format!(
+                    "foreign key constraint {:?} cannot be added: \
+                     existing record {:?} references a key that \
+                     does not exist in the target tree",
+                    name, primary,
+                )));
+            }
+        }
+
+        let constraint = Arc::new(ForeignKeyConstraint {
+            name: name.clone(),
+            target: target.clone(),
+        });
+
+        let mut outgoing = self.foreign_keys_out.write();
+        outgoing.retain(|(c, _)| c.name != name);
+        outgoing.push((constraint, index.clone()));
+        drop(outgoing);
+
+        let mut incoming = target.foreign_keys_in.write();
+        incoming.retain(|c| {
+            !(c.name == name && c.source_id == self.tree_id)
+        });
+        incoming.push(IncomingForeignKey {
+            name,
+            source_id: self.tree_id.clone(),
+            index,
+        });
+
+        Ok(())
+    }
+
+    /// Checks whether a key is present without taking the
+    /// concurrency control lock, for constraint checks that run
+    /// while a caller already holds it.
+    pub(crate) fn contains_key_inner(&self, key: &[u8]) -> Result<bool> {
+        let mut guard = pin();
+        loop {
+            if let Ok(value) = self.get_inner(key, &mut guard)? {
+                return Ok(value.is_some());
+            }
+        }
+    }
+
+    /// Rejects a single insert or update whose derived reference
+    /// is missing from a constraint's target tree.
+    pub(crate) fn fk_check_insert(
+        &self,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<()> {
+        let outgoing = self.foreign_keys_out.read();
+        for (constraint, index) in outgoing.iter() {
+            if let Some(derived) = (index.0.extractor)(key, value) {
+                if !constraint.target.contains_key_inner(&derived)? {
+                    return Err(Error::Unsupported(format!(
+                        "foreign key constraint {:?} violated: \
+                         record {:?} references key {:?}, which \
+                         does not exist in the target tree",
+                        constraint.name, key, derived,
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates a whole batch against this tree's constraints
+    /// before any of it is applied, resolving references against
+    /// the batch itself first so records may be inserted or
+    /// removed together in any key order. Cross-tree references
+    /// are resolved against the target tree's current state; a
+    /// transaction commit performs this check instead, with every
+    /// staged tree in view.
+    pub(crate) fn fk_check_batch(&self, batch: &crate::Batch) -> Result<()> {
+        let outgoing = self.foreign_keys_out.read();
+        if !outgoing.is_empty() {
+            for (key, value) in batch.iter() {
+                let value = if let Some(value) = value {
+                    value
+                } else {
+                    continue;
+                };
+                for (constraint, index) in outgoing.iter() {
+                    let derived =
+                        if let Some(derived) = (index.0.extractor)(key, value)
+                        {
+                            derived
+                        } else {
+                            continue;
+                        };
+                    let staged = if constraint.target.tree_id == self.tree_id
+                    {
+                        batch.get(&derived).map(|v| v.is_some())
+                    } else {
+                        None
+                    };
+                    let exists = match staged {
+                        Some(exists) => exists,
+                        None => {
+                            constraint.target.contains_key_inner(&derived)?
+                        }
+                    };
+                    if !exists {
+                        return Err(Error::Unsupported(format!(
+                            "foreign key constraint {:?} violated: \
+                             record {:?} references key {:?}, which \
+                             does not exist in the target tree",
+                            constraint.name, key, derived,
+                        )));
+                    }
+                }
+            }
+        }
+        drop(outgoing);
+
+        let incoming = self.foreign_keys_in.read();
+        if !incoming.is_empty() {
+            for (key, value) in batch.iter() {
+                if value.is_some() {
+                    continue;
+                }
+                for constraint in incoming.iter() {
+                    let mut referencing = constraint
+                        .index
+                        .0
+                        .tree
+                        .scan_prefix(encode_derived(key));
+                    while let Some(kv) = referencing.next_inner() {
+                        let (_, primary) = kv?;
+                        // a referencing record removed or
+                        // repointed by this same batch is fine
+                        if constraint.source_id == self.tree_id {
+                            match batch.get(&primary) {
+                                Some(None) => continue,
+                                Some(Some(new_value)) => {
+                                    let derived = (constraint
+                                        .index
+                                        .0
+                                        .extractor)(
+                                        &primary, new_value
+                                    );
+                                    if derived.as_deref() != Some(key) {
+                                        continue;
+                                    }
+                                }
+                                None => {}
+                            }
+                        }
+                        return Err(Error::Unsupported(format!(
+                            "foreign key constraint {:?} violated: \
+                             key {:?} is still referenced by record \
+                             {:?} in tree {:?}",
+                            constraint.name,
+                            key,
+                            primary,
+                            constraint.source_id,
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Rejects a single removal of a key that records in a source
+    /// tree still reference.
+    pub(crate) fn fk_check_remove(&self, key: &[u8]) -> Result<()> {
+        let incoming = self.foreign_keys_in.read();
+        for constraint in incoming.iter() {
+            let mut referencing =
+                constraint.index.0.tree.scan_prefix(encode_derived(key));
+            if let Some(kv) = referencing.next_inner() {
+                let (_, primary) = kv?;
+                return Err(Error::Unsupported(format!(
+                    "foreign key constraint {:?} violated: key \
+                     {:?} is still referenced by record {:?} in \
+                     tree {:?}",
+                    constraint.name, key, primary, constraint.source_id,
+                )));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+//! A tiny built-in HTTP status page for operational triage.
+//!
+//! When the `http_status` feature is enabled, `Db::serve_status`
+//! starts a background thread serving a plain-text rendering of the
+//! database's health, lifetime statistics, disk usage, and memory
+//! footprint over HTTP, so that a deployment can be inspected with
+//! `curl` without writing any integration code.
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{
+    atomic::{AtomicBool, Ordering::SeqCst},
+    Arc,
+};
+use std::thread;
+use std::time::Duration;
+
+use crate::{debug, error, Db, Result};
+
+/// A handle to a running status server, returned by
+/// `Db::serve_status`. The server shuts down when this
+/// handle is dropped.
+pub struct StatusServer {
+    shutdown: Arc<AtomicBool>,
+    local_addr: SocketAddr,
+    join_handle: Option<thread::JoinHandle<()>>,
+}
+
+impl StatusServer {
+    pub(crate) fn start<A: ToSocketAddrs>(
+        db: &Db,
+        addr: A,
+    ) -> Result<StatusServer> {
+        let listener = TcpListener::bind(addr)?;
+        let local_addr = listener.local_addr()?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+
+        let thread_shutdown = shutdown.clone();
+        let thread_db = db.clone();
+        let join_handle = thread::Builder::new()
+            .name("sled-http-status".to_owned())
+            .spawn(move || {
+                run(&thread_db, &listener, &thread_shutdown);
+            })
+            .expect("failed to spawn status server thread");
+
+        Ok(StatusServer {
+            shutdown,
+            local_addr,
+            join_handle: Some(join_handle),
+        })
+    }
+
+    /// Returns the address the server is listening on, which is
+    /// useful when binding to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+impl Drop for StatusServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, SeqCst);
+
+        // wake the accept loop so it observes the shutdown flag
+        let _ = TcpStream::connect(self.local_addr);
+
+        if let Some(join_handle) = self.join_handle.take() {
+            if let Err(e) = join_handle.join() {
+                error!("error joining status server thread: {:?}", e);
+            }
+        }
+    }
+}
+
+fn run(db: &Db, listener: &TcpListener, shutdown: &AtomicBool) {
+    for stream in listener.incoming() {
+        if shutdown.load(SeqCst) {
+            return;
+        }
+        match stream {
+            Ok(stream) => {
+                if let Err(e) = serve_request(db, stream) {
+                    debug!("error serving status request: {:?}", e);
+                }
+            }
+            Err(e) => {
+                debug!("error accepting status connection: {:?}", e);
+            }
+        }
+    }
+}
+
+fn serve_request(db: &Db, mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+
+    // drain the request line and headers; the page is served for
+    // any request, so their content does not matter.
+    let mut buf = [0; 4096];
+    let _ = stream.read(&mut buf)?;
+
+    let body = render_status(db);
+
+    write!(
+        stream,
+        "HTTP/1.0 200 OK\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n",
+        body.len()
+    )?;
+    stream.write_all(body.as_bytes())?;
+    stream.flush()
+}
+
+fn render_status(db: &Db) -> String {
+    use std::fmt::Write;
+
+    let mut out = String::new();
+
+    let _ = writeln!(out, "sled status");
+    let _ = writeln!(out, "===========");
+    let _ = writeln!(out);
+
+    match db.health() {
+        crate::Health::Ok => {
+            let _ = writeln!(out, "health: ok");
+        }
+        crate::Health::Degraded(reasons) => {
+            let _ = writeln!(out, "health: degraded");
+            for reason in reasons {
+                let _ = writeln!(out, "  {}", reason);
+            }
+        }
+        crate::Health::Failed(reasons) => {
+            let _ = writeln!(out, "health: failed");
+            for reason in reasons {
+                let _ = writeln!(out, "  {}", reason);
+            }
+        }
+    }
+    let _ = writeln!(out);
+
+    match db.stats() {
+        Ok(stats) => {
+            let _ = writeln!(out, "lifetime statistics:");
+            let _ = writeln!(out, "  total ops: {}", stats.total_ops);
+            let _ = writeln!(
+                out,
+                "  log bytes written: {}",
+                stats.log_bytes_written
+            );
+            let _ =
+                writeln!(out, "  pages rewritten: {}", stats.pages_rewritten);
+            let _ = writeln!(out, "  scrub errors: {}", stats.scrub_errors);
+        }
+        Err(e) => {
+            let _ = writeln!(out, "lifetime statistics unavailable: {}", e);
+        }
+    }
+    let _ = writeln!(out);
+
+    match db.disk_usage() {
+        Ok(usage) => {
+            let _ = writeln!(out, "disk usage:");
+            let _ =
+                writeln!(out, "  apparent bytes: {}", usage.apparent_bytes);
+            let _ =
+                writeln!(out, "  allocated bytes: {}", usage.allocated_bytes);
+            let _ = writeln!(out, "  logical bytes by tree:");
+            for (name, bytes) in &usage.tree_logical_bytes {
+                let _ = writeln!(
+                    out,
+                    "    {}: {}",
+                    String::from_utf8_lossy(name),
+                    bytes
+                );
+            }
+        }
+        Err(e) => {
+            let _ = writeln!(out, "disk usage unavailable: {}", e);
+        }
+    }
+    let _ = writeln!(out);
+
+    let memory = db.memory_usage();
+    let _ = writeln!(out, "memory usage:");
+    let _ = writeln!(out, "  cache bytes: {}", memory.cache_bytes);
+    let _ = writeln!(out, "  index bytes: {}", memory.index_bytes);
+    let _ = writeln!(
+        out,
+        "  write buffer bytes: {}",
+        memory.write_buffer_bytes
+    );
+    let _ =
+        writeln!(out, "  subscriber count: {}", memory.subscriber_count);
+
+    out
+}
@@ -0,0 +1,561 @@
+//! Secondary indexes maintained as hidden sibling trees.
+//!
+//! An [`Index`] maps a key derived from each record (an email
+//! address, a timestamp, a foreign key) back to the primary key
+//! that produced it, and is updated in lockstep with every
+//! mutation of the primary tree — inserts, removes, CAS, merges,
+//! batches, and transactions all keep it current. Hand-rolling
+//! this with transactions means re-deriving the old entry on
+//! every update and getting the delete-before-insert ordering
+//! right; the index does it once, centrally.
+//!
+//! Entries are stored as `escape(derived) ++ primary` with the
+//! primary key duplicated in the value. The escape makes the
+//! derived portion prefix-free (`0x00` becomes `0x00 0xff`, a
+//! `0x00 0x00` terminator follows), so two records whose derived
+//! and primary keys happen to concatenate to the same bytes
+//! cannot collide, and lookups by derived key cannot over-match
+//! a longer derived key that shares a prefix.
+
+use std::ops::Bound;
+
+use crate::{
+    concurrency_control, meta, pin, Batch, Error, IVec, Iter, Map, Result,
+    Tree, INDEX_TREE_PREFIX,
+};
+
+/// A function that derives an optional secondary key from a
+/// primary key and value. Returning `None` leaves the record out
+/// of the index.
+pub trait IndexExtractor:
+    Send + Sync + Fn(&[u8], &[u8]) -> Option<Vec<u8>>
+{
+}
+impl<F> IndexExtractor for F where
+    F: Send + Sync + Fn(&[u8], &[u8]) -> Option<Vec<u8>>
+{
+}
+
+pub(crate) struct IndexInner {
+    pub(crate) name: IVec,
+    pub(crate) tree: Tree,
+    pub(crate) extractor: Box<dyn IndexExtractor>,
+    pub(crate) unique: bool,
+}
+
+/// A secondary index over a `Tree`, created via
+/// [`Tree::create_index`]. See the
+/// [module documentation](index.html) for the storage layout.
+///
+/// Multiple primary keys may share a derived key; lookups return
+/// all of them in primary-key order.
+#[derive(Clone)]
+pub struct Index(pub(crate) std::sync::Arc<IndexInner>);
+
+impl std::fmt::Debug for Index {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Index {{ name: {:?} }}", self.0.name)
+    }
+}
+
+impl Index {
+    /// The name this index was registered under.
+    pub fn name(&self) -> IVec {
+        self.0.name.clone()
+    }
+
+    /// Returns the primary keys of all records whose derived key
+    /// is exactly `derived`, in primary-key order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let by_email =
+    ///     db.create_index("by_email", |_k, v| Some(v.to_vec()))?;
+    ///
+    /// db.insert(b"user-1", b"ada@example.com")?;
+    /// db.insert(b"user-2", b"ada@example.com")?;
+    ///
+    /// assert_eq!(
+    ///     by_email.get(b"ada@example.com")?,
+    ///     vec![
+    ///         sled::IVec::from(b"user-1"),
+    ///         sled::IVec::from(b"user-2"),
+    ///     ],
+    /// );
+    ///
+    /// db.remove(b"user-1")?;
+    /// assert_eq!(
+    ///     by_email.get(b"ada@example.com")?,
+    ///     vec![sled::IVec::from(b"user-2")],
+    /// );
+    /// # Ok(()) }
+    /// ```
+    pub fn get<K: AsRef<[u8]>>(&self, derived: K) -> Result<Vec<IVec>> {
+        // the escape is prefix-free, so a prefix scan over the
+        // encoded form matches exactly this derived key
+        let mut out = Vec::new();
+        for kv in self.0.tree.scan_prefix(encode_derived(derived.as_ref())) {
+            let (_, primary) = kv?;
+            out.push(primary);
+        }
+        Ok(out)
+    }
+
+    /// Iterates over `(derived key, primary key)` pairs whose
+    /// derived key falls within `range`, ordered by derived key
+    /// and then primary key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let by_len = db.create_index("by_len", |_k, v| {
+    ///     Some(vec![v.len() as u8])
+    /// })?;
+    ///
+    /// db.insert(b"a", b"x".to_vec())?;
+    /// db.insert(b"b", b"xxx".to_vec())?;
+    /// db.insert(b"c", b"xxxxx".to_vec())?;
+    ///
+    /// let short: Vec<_> = by_len
+    ///     .range(..&[4][..])
+    ///     .map(|kv| kv.map(|(_, primary)| primary))
+    ///     .collect::<Result<_, _>>()?;
+    /// assert_eq!(
+    ///     short,
+    ///     vec![sled::IVec::from(b"a"), sled::IVec::from(b"b")],
+    /// );
+    /// # Ok(()) }
+    /// ```
+    pub fn range<K, R>(&self, range: R) -> IndexIter
+    where
+        K: AsRef<[u8]>,
+        R: std::ops::RangeBounds<K>,
+    {
+        let start: Bound<IVec> = match range.start_bound() {
+            Bound::Included(s) => Bound::Included(s.as_ref().into()),
+            Bound::Excluded(s) => Bound::Excluded(s.as_ref().into()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let end: Bound<IVec> = match range.end_bound() {
+            Bound::Included(e) => Bound::Included(e.as_ref().into()),
+            Bound::Excluded(e) => Bound::Excluded(e.as_ref().into()),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        // entries for a derived key form a contiguous run
+        // starting at its encoded form, so the start bound maps
+        // directly; the end bound is enforced per-entry after
+        // decoding, since an encoded upper bound for "all
+        // primaries under this derived key" does not exist
+        let inner = match &start {
+            Bound::Included(s) | Bound::Excluded(s) => {
+                self.0.tree.range(encode_derived(s)..)
+            }
+            Bound::Unbounded => self.0.tree.range::<&[u8], _>(..),
+        };
+
+        IndexIter { inner, start, end, done: false }
+    }
+
+    /// Returns `true` if the index contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.0.tree.is_empty()
+    }
+}
+
+/// An iterator over the `(derived key, primary key)` pairs of an
+/// [`Index`], created via [`Index::range`].
+pub struct IndexIter {
+    inner: Iter,
+    start: Bound<IVec>,
+    end: Bound<IVec>,
+    done: bool,
+}
+
+impl Iterator for IndexIter {
+    type Item = Result<(IVec, IVec)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            let (entry, primary) = match self.inner.next()? {
+                Ok(kv) => kv,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+            let derived = decode_derived(&entry);
+
+            // the underlying scan starts at the encoded start
+            // bound, so only exclusion of the bound itself
+            // remains to be checked
+            if let Bound::Excluded(s) = &self.start {
+                if derived.as_slice() == s.as_ref() {
+                    continue;
+                }
+            }
+            match &self.end {
+                Bound::Included(e) if derived.as_slice() > e.as_ref() => {
+                    self.done = true;
+                    return None;
+                }
+                Bound::Excluded(e) if derived.as_slice() >= e.as_ref() => {
+                    self.done = true;
+                    return None;
+                }
+                _ => {}
+            }
+
+            return Some(Ok((derived.into(), primary)));
+        }
+    }
+}
+
+/// Escapes a derived key into a prefix-free, order-preserving
+/// form: `0x00` bytes become `0x00 0xff`, and a `0x00 0x00`
+/// terminator is appended.
+pub(crate) fn encode_derived(derived: &[u8]) -> IVec {
+    let mut out = Vec::with_capacity(derived.len() + 2);
+    for b in derived {
+        if *b == 0 {
+            out.extend_from_slice(&[0, 0xff]);
+        } else {
+            out.push(*b);
+        }
+    }
+    out.extend_from_slice(&[0, 0]);
+    out.into()
+}
+
+/// Recovers the derived key from the front of an index entry
+/// key, stopping at the terminator.
+pub(crate) fn decode_derived(entry: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(entry.len());
+    let mut i = 0;
+    while i + 1 < entry.len() {
+        if entry[i] == 0 {
+            if entry[i + 1] == 0 {
+                break;
+            }
+            out.push(0);
+            i += 2;
+        } else {
+            out.push(entry[i]);
+            i += 1;
+        }
+    }
+    out
+}
+
+/// Builds the full index entry key for a derived/primary pair.
+pub(crate) fn index_entry_key(derived: &[u8], primary: &[u8]) -> IVec {
+    let encoded = encode_derived(derived);
+    let mut out = Vec::with_capacity(encoded.len() + primary.len());
+    out.extend_from_slice(&encoded);
+    out.extend_from_slice(primary);
+    out.into()
+}
+
+impl Tree {
+    /// Creates (or re-registers) a secondary index named `name`
+    /// over this tree, maintained by `extractor`.
+    ///
+    /// The extractor is called with each record's key and value
+    /// and returns the derived key to index it under, or `None`
+    /// to leave it out. Every subsequent insert, remove,
+    /// compare-and-swap, merge, batch, and transaction on this
+    /// tree updates the index in the same call; crash recovery
+    /// replays both trees together, so the index never lags the
+    /// data it covers.
+    ///
+    /// Extractors are plain closures and cannot be persisted, so
+    /// like merge operators they must be re-registered each time
+    /// the database is opened. Registration scans the tree while
+    /// holding the exclusive write lock: existing records are
+    /// (re-)indexed from scratch, which makes re-registering
+    /// after an extractor change safe but proportional to the
+    /// tree's size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// // index user records by the email stored in the value
+    /// let by_email =
+    ///     db.create_index("by_email", |_k, v| Some(v.to_vec()))?;
+    ///
+    /// db.insert(b"user-1", b"grace@example.com")?;
+    ///
+    /// assert_eq!(
+    ///     by_email.get(b"grace@example.com")?,
+    ///     vec![sled::IVec::from(b"user-1")],
+    /// );
+    ///
+    /// // updates move the entry to the new derived key
+    /// db.insert(b"user-1", b"hopper@example.com")?;
+    /// assert!(by_email.get(b"grace@example.com")?.is_empty());
+    /// assert_eq!(
+    ///     by_email.get(b"hopper@example.com")?,
+    ///     vec![sled::IVec::from(b"user-1")],
+    /// );
+    /// # Ok(()) }
+    /// ```
+    pub fn create_index<N: AsRef<[u8]>>(
+        &self,
+        name: N,
+        extractor: impl IndexExtractor + 'static,
+    ) -> Result<Index> {
+        self.create_index_inner(name.as_ref(), extractor, false)
+    }
+
+    /// Like [`Tree::create_index`], but at most one record may
+    /// occupy each derived key. A write that would give a derived
+    /// key a second owner is rejected with
+    /// [`Error::UniqueViolation`] naming the index, the value,
+    /// and the record that already owns it, so email-to-user
+    /// style lookups carry an integrity guarantee instead of
+    /// relying on every writer checking first.
+    ///
+    /// Uniqueness is enforced on inserts, compare-and-swap,
+    /// batches, and transactions, with writes staged in the same
+    /// batch or transaction taken into account, so a value may
+    /// move between records atomically. `merge` is not validated.
+    /// Registration fails if existing records already collide.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let by_email =
+    ///     db.create_unique_index("by_email", |_k, v| Some(v.to_vec()))?;
+    ///
+    /// db.insert(b"user-1", b"ada@example.com")?;
+    ///
+    /// // a second record claiming the same email is rejected
+    /// match db.insert(b"user-2", b"ada@example.com") {
+    ///     Err(sled::Error::UniqueViolation { owner, .. }) => {
+    ///         assert_eq!(&owner, b"user-1");
+    ///     }
+    ///     other => panic!("unexpected: {:?}", other),
+    /// }
+    ///
+    /// // the owner may update its own record freely
+    /// db.insert(b"user-1", b"ada@example.com")?;
+    ///
+    /// assert_eq!(
+    ///     by_email.get(b"ada@example.com")?,
+    ///     vec![sled::IVec::from(b"user-1")],
+    /// );
+    /// # Ok(()) }
+    /// ```
+    pub fn create_unique_index<N: AsRef<[u8]>>(
+        &self,
+        name: N,
+        extractor: impl IndexExtractor + 'static,
+    ) -> Result<Index> {
+        self.create_index_inner(name.as_ref(), extractor, true)
+    }
+
+    fn create_index_inner(
+        &self,
+        name: &[u8],
+        extractor: impl IndexExtractor + 'static,
+        unique: bool,
+    ) -> Result<Index> {
+        let name = IVec::from(name);
+
+        let mut tree_id = INDEX_TREE_PREFIX.to_vec();
+        tree_id.extend_from_slice(&self.tree_id);
+        tree_id.push(0);
+        tree_id.extend_from_slice(&name);
+
+        let guard = pin();
+        let index_tree =
+            meta::open_tree(&self.context, tree_id, &guard)?;
+        drop(guard);
+
+        let index = Index(std::sync::Arc::new(IndexInner {
+            name: name.clone(),
+            tree: index_tree,
+            extractor: Box::new(extractor),
+            unique,
+        }));
+
+        // backfill under the exclusive lock so no mutation can
+        // interleave between the scan and registration
+        let _cc = concurrency_control::write();
+        let mut guard = pin();
+
+        // stale entries may survive from a previous registration
+        // with a different extractor
+        let mut stale = index.0.tree.iter();
+        while let Some(kv) = stale.next_inner() {
+            let (k, _) = kv?;
+            loop {
+                if index.0.tree.insert_inner(&k, None, true, &mut guard)?.is_ok()
+                {
+                    break;
+                }
+            }
+        }
+
+        let mut records = self.iter();
+        while let Some(kv) = records.next_inner() {
+            let (k, v) = kv?;
+            if let Some(derived) = (index.0.extractor)(&k, &v) {
+                let entry = index_entry_key(&derived, &k);
+                loop {
+                    if index
+                        .0
+                        .tree
+                        .insert_inner(
+                            &entry,
+                            Some(k.clone()),
+                            true,
+                            &mut guard,
+                        )?
+                        .is_ok()
+                    {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // refuse registration of a unique index over records
+        // that already collide; entries sharing a derived key
+        // are adjacent, so one pass over the backfill finds them
+        if unique {
+            let mut prev: Option<(Vec<u8>, IVec)> = None;
+            let mut entries = index.0.tree.iter();
+            while let Some(kv) = entries.next_inner() {
+                let (entry, primary) = kv?;
+                let derived = decode_derived(&entry);
+                if let Some((prev_derived, prev_primary)) = prev {
+                    if prev_derived == derived {
+                        return Err(Error::UniqueViolation {
+                            index: name,
+                            value: derived.into(),
+                            owner: prev_primary,
+                        });
+                    }
+                }
+                prev = Some((derived, primary));
+            }
+        }
+
+        let mut indexes = self.indexes.write();
+        indexes.retain(|i| i.0.name != name);
+        indexes.push(index.clone());
+
+        Ok(index)
+    }
+
+    /// Rejects a single insert or update that would give a
+    /// unique index's derived key a second owner.
+    pub(crate) fn unique_check_insert(
+        &self,
+        key: &[u8],
+        value: &[u8],
+    ) -> Result<()> {
+        let indexes = self.indexes.read();
+        for index in indexes.iter().filter(|i| i.0.unique) {
+            let derived = if let Some(derived) = (index.0.extractor)(key, value)
+            {
+                derived
+            } else {
+                continue;
+            };
+            let mut owners =
+                index.0.tree.scan_prefix(encode_derived(&derived));
+            while let Some(kv) = owners.next_inner() {
+                let (_, owner) = kv?;
+                if owner != key {
+                    return Err(Error::UniqueViolation {
+                        index: index.0.name.clone(),
+                        value: derived.into(),
+                        owner,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Validates a whole batch (or a transaction's staged writes
+    /// for one tree) against this tree's unique indexes before
+    /// any of it is applied. Records removed or repointed by the
+    /// batch release their derived keys, so a value may move
+    /// between records atomically.
+    pub(crate) fn unique_check_batch(&self, batch: &Batch) -> Result<()> {
+        let indexes = self.indexes.read();
+        for index in indexes.iter().filter(|i| i.0.unique) {
+            let mut claimed: Map<Vec<u8>, IVec> = Map::default();
+            for (key, value) in batch.iter() {
+                let value = if let Some(value) = value {
+                    value
+                } else {
+                    continue;
+                };
+                let derived =
+                    if let Some(derived) = (index.0.extractor)(key, value) {
+                        derived
+                    } else {
+                        continue;
+                    };
+
+                if let Some(first) = claimed.get(&derived) {
+                    return Err(Error::UniqueViolation {
+                        index: index.0.name.clone(),
+                        value: derived.into(),
+                        owner: first.clone(),
+                    });
+                }
+
+                let mut owners =
+                    index.0.tree.scan_prefix(encode_derived(&derived));
+                while let Some(kv) = owners.next_inner() {
+                    let (_, owner) = kv?;
+                    if owner == key {
+                        continue;
+                    }
+                    // an owner removed or repointed by this same
+                    // batch releases the derived key
+                    match batch.get(&owner) {
+                        Some(None) => continue,
+                        Some(Some(new_value)) => {
+                            let still =
+                                (index.0.extractor)(&owner, new_value);
+                            if still.as_deref() != Some(derived.as_slice()) {
+                                continue;
+                            }
+                        }
+                        None => {}
+                    }
+                    return Err(Error::UniqueViolation {
+                        index: index.0.name.clone(),
+                        value: derived.into(),
+                        owner,
+                    });
+                }
+
+                claimed.insert(derived, key.clone());
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,7 @@
-use std::ops::{Bound, Deref};
+use std::{
+    ops::{Bound, Deref},
+    time::Instant,
+};
 
 use crate::{Measure, M};
 
@@ -37,8 +40,10 @@ pub struct Iter {
     pub(super) tree: Tree,
     pub(super) hi: Bound<IVec>,
     pub(super) lo: Bound<IVec>,
-    pub(super) cached_node: Option<(PageId, Node)>,
-    pub(super) going_forward: bool,
+    pub(super) cached_forward_node: Option<(PageId, Node)>,
+    pub(super) cached_back_node: Option<(PageId, Node)>,
+    pub(super) cancellation: Option<CancellationToken>,
+    pub(super) deadline: Option<Instant>,
 }
 
 impl Iter {
@@ -56,6 +61,183 @@ impl Iter {
         self.map(|r| r.map(|(_k, v)| v))
     }
 
+    /// Batches this iterator's items into chunks of at most
+    /// `chunk_size` key-value pairs, reducing per-item overhead
+    /// and making it easy to feed downstream batch processors.
+    /// Items are materialized a page at a time internally, so a
+    /// chunk costs roughly the same tree traversal work as the
+    /// items it contains.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chunk_size` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(&[1], vec![10])?;
+    /// db.insert(&[2], vec![20])?;
+    /// db.insert(&[3], vec![30])?;
+    ///
+    /// let mut chunks = db.iter().chunks(2);
+    /// assert_eq!(chunks.next().unwrap()?.len(), 2);
+    /// assert_eq!(chunks.next().unwrap()?.len(), 1);
+    /// assert!(chunks.next().is_none());
+    /// # Ok(()) }
+    /// ```
+    pub fn chunks(self, chunk_size: usize) -> Chunks {
+        assert!(chunk_size > 0, "chunk size must be non-zero");
+        Chunks { inner: self, chunk_size }
+    }
+
+    /// Makes this iterator observe the provided
+    /// [`CancellationToken`], so a long scan can be stopped
+    /// early from another thread. The token is checked before
+    /// each item: once it has been cancelled, the iterator
+    /// yields a single `Err(Error::Cancelled)` and each yielded
+    /// item counts towards the token's reported progress.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use sled::{CancellationToken, Error};
+    ///
+    /// db.insert(b"k1", b"v1")?;
+    /// db.insert(b"k2", b"v2")?;
+    ///
+    /// let token = CancellationToken::new();
+    /// let mut iter = db.iter().with_cancellation(token.clone());
+    ///
+    /// assert!(iter.next().unwrap().is_ok());
+    /// token.cancel();
+    /// assert_eq!(iter.next().unwrap(), Err(Error::Cancelled));
+    /// assert_eq!(token.progress(), 1);
+    /// # Ok(()) }
+    /// ```
+    pub fn with_cancellation(mut self, token: CancellationToken) -> Iter {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Imposes a deadline on this scan. The deadline is checked
+    /// before each item: once it has lapsed, the iterator yields
+    /// a single `Err(Error::TimedOut)`, so latency-sensitive
+    /// services can shed a slow scan instead of queuing behind
+    /// it. An in-flight page read is not interrupted, so an item
+    /// may overshoot the deadline by up to one page fault.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use std::time::{Duration, Instant};
+    ///
+    /// db.insert(b"k1", b"v1")?;
+    ///
+    /// let deadline = Instant::now() + Duration::from_secs(30);
+    /// let hits: Vec<_> =
+    ///     db.iter().with_deadline(deadline).collect::<Result<_, _>>()?;
+    /// assert_eq!(hits.len(), 1);
+    ///
+    /// let lapsed = Instant::now() - Duration::from_secs(1);
+    /// let mut iter = db.iter().with_deadline(lapsed);
+    /// assert_eq!(iter.next().unwrap(), Err(sled::Error::TimedOut));
+    /// # Ok(()) }
+    /// ```
+    pub fn with_deadline(mut self, deadline: Instant) -> Iter {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Repositions the forward cursor so that the next call to
+    /// `next` yields the first key greater than or equal to
+    /// `key`, without re-descending the tree when the target
+    /// falls in the node the iterator is already parked on. Keys
+    /// behind the cursor are never revisited: seeking to a key at
+    /// or before the current position is a no-op, so the
+    /// iterator's range is only ever narrowed.
+    ///
+    /// This makes merge-join style workloads cheap: one iterator
+    /// per tree, each leapfrogging the other's current key
+    /// instead of rebuilding a `Tree::range` for every probe.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// for key in [b"a", b"c", b"e", b"g"] {
+    ///     db.insert(key, b"")?;
+    /// }
+    ///
+    /// let mut iter = db.iter();
+    /// assert_eq!(iter.next().unwrap()?.0, b"a");
+    ///
+    /// iter.seek(b"d");
+    /// assert_eq!(iter.next().unwrap()?.0, b"e");
+    ///
+    /// // seeking backwards does not rewind
+    /// iter.seek(b"b");
+    /// assert_eq!(iter.next().unwrap()?.0, b"g");
+    /// # Ok(()) }
+    /// ```
+    pub fn seek<K: AsRef<[u8]>>(&mut self, key: K) {
+        let key = key.as_ref();
+        let advances = match self.lo {
+            Bound::Unbounded => true,
+            Bound::Included(ref lo) | Bound::Excluded(ref lo) => {
+                key > lo.as_ref()
+            }
+        };
+        if advances {
+            self.lo = Bound::Included(key.into());
+        }
+    }
+
+    /// The backward analogue of [`Iter::seek`]: repositions the
+    /// reverse cursor so that the next call to `next_back` yields
+    /// the last key less than or equal to `key`. Seeking to a key
+    /// at or after the current reverse position is a no-op.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// for key in [b"a", b"c", b"e", b"g"] {
+    ///     db.insert(key, b"")?;
+    /// }
+    ///
+    /// let mut iter = db.iter();
+    /// assert_eq!(iter.next_back().unwrap()?.0, b"g");
+    ///
+    /// iter.seek_for_prev(b"d");
+    /// assert_eq!(iter.next_back().unwrap()?.0, b"c");
+    /// # Ok(()) }
+    /// ```
+    pub fn seek_for_prev<K: AsRef<[u8]>>(&mut self, key: K) {
+        let key = key.as_ref();
+        let retreats = match self.hi {
+            Bound::Unbounded => true,
+            Bound::Included(ref hi) | Bound::Excluded(ref hi) => {
+                key < hi.as_ref()
+            }
+        };
+        if retreats {
+            self.hi = Bound::Included(key.into());
+        }
+    }
+
     fn bounds_collapsed(&self) -> bool {
         match (&self.lo, &self.hi) {
             (Bound::Included(ref start), Bound::Included(ref end))
@@ -85,8 +267,8 @@ impl Iter {
 
     pub(crate) fn next_inner(&mut self) -> Option<<Self as Iterator>::Item> {
         let guard = pin();
-        let (mut pid, mut node) = if let (true, Some((pid, node))) =
-            (self.going_forward, self.cached_node.take())
+        let (mut pid, mut node) = if let Some((pid, node)) =
+            self.cached_forward_node.take()
         {
             (pid, node)
         } else {
@@ -119,8 +301,7 @@ impl Iter {
 
             if let Some((key, value)) = node.successor(&self.lo) {
                 self.lo = Bound::Excluded(key.clone());
-                self.cached_node = Some((pid, node));
-                self.going_forward = true;
+                self.cached_forward_node = Some((pid, node));
 
                 match self.hi {
                     Bound::Unbounded => return Some(Ok((key, value))),
@@ -151,8 +332,63 @@ impl Iterator for Iter {
 
     fn next(&mut self) -> Option<Self::Item> {
         let _measure = Measure::new(&M.tree_scan);
-        let _cc = concurrency_control::read();
-        self.next_inner()
+        let _priority = priority::enter();
+        if let Some(ref token) = self.cancellation {
+            if let Err(e) = token.check() {
+                return Some(Err(e));
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Some(Err(Error::TimedOut));
+            }
+        }
+        loop {
+            let item = {
+                let _cc = concurrency_control::read();
+                self.next_inner()
+            };
+            match item {
+                Some(Ok((key, value))) => {
+                    // skip keys whose TTL has lapsed
+                    match self.tree.key_is_expired(&key) {
+                        Ok(false) => {
+                            if let Some(ref token) = self.cancellation {
+                                token.note_progress(1);
+                            }
+                            return Some(Ok((key, value)));
+                        }
+                        Ok(true) => (),
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.bounds_collapsed() {
+            (0, Some(0))
+        } else if let Some((_, ref node)) = self.cached_forward_node {
+            // when the remainder of the range is known to fall
+            // within the cached leaf, its child count bounds the
+            // items left. without subtree counts in the on-disk
+            // format, no cheap bound exists for wider ranges.
+            let in_cached_node = match self.hi {
+                Bound::Unbounded => node.hi().is_none(),
+                Bound::Included(ref h) | Bound::Excluded(ref h) => {
+                    node.hi().map_or(true, |node_hi| h.as_ref() < node_hi)
+                }
+            };
+            if in_cached_node {
+                (0, Some(usize::from(node.children)))
+            } else {
+                (0, None)
+            }
+        } else {
+            (0, None)
+        }
     }
 
     fn last(mut self) -> Option<Self::Item> {
@@ -163,11 +399,48 @@ impl Iterator for Iter {
 impl DoubleEndedIterator for Iter {
     fn next_back(&mut self) -> Option<Self::Item> {
         let _measure = Measure::new(&M.tree_reverse_scan);
+        let _priority = priority::enter();
+        if let Some(ref token) = self.cancellation {
+            if let Err(e) = token.check() {
+                return Some(Err(e));
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Some(Err(Error::TimedOut));
+            }
+        }
+        loop {
+            let item = {
+                let _cc = concurrency_control::read();
+                self.next_back_inner()
+            };
+            match item {
+                Some(Ok((key, value))) => {
+                    // skip keys whose TTL has lapsed
+                    match self.tree.key_is_expired(&key) {
+                        Ok(false) => {
+                            if let Some(ref token) = self.cancellation {
+                                token.note_progress(1);
+                            }
+                            return Some(Ok((key, value)));
+                        }
+                        Ok(true) => (),
+                        Err(e) => return Some(Err(e)),
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+impl Iter {
+    fn next_back_inner(&mut self) -> Option<<Self as Iterator>::Item> {
         let guard = pin();
-        let _cc = concurrency_control::read();
 
-        let (mut pid, mut node) = if let (false, Some((pid, node))) =
-            (self.going_forward, self.cached_node.take())
+        let (mut pid, mut node) = if let Some((pid, node)) =
+            self.cached_back_node.take()
         {
             (pid, node)
         } else {
@@ -200,8 +473,7 @@ impl DoubleEndedIterator for Iter {
 
             if let Some((key, value)) = node.predecessor(&self.hi) {
                 self.hi = Bound::Excluded(key.clone());
-                self.cached_node = Some((pid, node));
-                self.going_forward = false;
+                self.cached_back_node = Some((pid, node));
 
                 match self.lo {
                     Bound::Unbounded => return Some(Ok((key, value))),
@@ -216,7 +488,19 @@ impl DoubleEndedIterator for Iter {
             } else if node.lo().is_empty() {
                 return None;
             } else {
+                // the predecessor leaf ends exactly where this one
+                // begins. descending with the new upper bound would
+                // land back on this exhausted node (its range still
+                // covers the bound) and force a second descent, so
+                // seek straight to the predecessor instead. without
+                // left sibling links in the storage format this
+                // single descent per leaf is the floor, matching
+                // the forward path's cost.
                 self.hi = Bound::Excluded(node.lo().into());
+                let seek_key = possible_predecessor(node.lo())?;
+                let view = iter_try!(self.tree.view_for_key(seek_key, &guard));
+                pid = view.pid;
+                node = view.deref().clone();
                 continue;
             }
         }
@@ -250,3 +534,30 @@ fn basic_functionality() {
         Some(vec![154, 255, 255, 255, 255])
     );
 }
+
+/// An iterator that yields batches of key-value pairs,
+/// created by `Iter::chunks`.
+pub struct Chunks {
+    inner: Iter,
+    chunk_size: usize,
+}
+
+impl Iterator for Chunks {
+    type Item = Result<Vec<(IVec, IVec)>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut chunk = Vec::with_capacity(self.chunk_size);
+        while chunk.len() < self.chunk_size {
+            match self.inner.next() {
+                Some(Ok(kv)) => chunk.push(kv),
+                Some(Err(e)) => return Some(Err(e)),
+                None => break,
+            }
+        }
+        if chunk.is_empty() {
+            None
+        } else {
+            Some(Ok(chunk))
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use std::{
+    cell::RefCell,
     convert::TryFrom,
     fmt,
     hash::{Hash, Hasher},
+    io,
     iter::FromIterator,
+    mem,
     ops::{Deref, DerefMut},
 };
 
@@ -40,6 +43,151 @@ const fn is_inline_candidate(length: usize) -> bool {
     length <= CUTOFF
 }
 
+// builders that end up inline (or are dropped without being
+// built) return their scratch buffer here so that the next
+// builder on this thread starts with a warm allocation.
+const MAX_POOLED_BUFFERS: usize = 8;
+const MAX_POOLED_CAPACITY: usize = 64 * 1024;
+
+thread_local! {
+    static BUFFER_POOL: RefCell<Vec<Vec<u8>>> = RefCell::new(Vec::new());
+}
+
+fn pool_take(capacity: usize) -> Vec<u8> {
+    BUFFER_POOL
+        .with(|pool| pool.borrow_mut().pop())
+        .map(|mut buf| {
+            buf.reserve(capacity);
+            buf
+        })
+        .unwrap_or_else(|| Vec::with_capacity(capacity))
+}
+
+fn pool_put(mut buf: Vec<u8>) {
+    if buf.capacity() == 0 || buf.capacity() > MAX_POOLED_CAPACITY {
+        // not worth caching: either there is no allocation to
+        // reuse, or holding onto it would pin too much memory
+        return;
+    }
+    buf.clear();
+    BUFFER_POOL.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        if pool.len() < MAX_POOLED_BUFFERS {
+            pool.push(buf);
+        }
+    });
+}
+
+/// An incremental builder for an [`IVec`], backed by a
+/// thread-local pool of reusable buffers so that hot write
+/// paths can construct values without paying for a fresh
+/// allocation on every iteration.
+///
+/// Bytes may be appended through [`std::io::Write`],
+/// [`Extend`], or [`push`](IVecBuilder::push). When the
+/// finished value is small enough to be stored inline, the
+/// scratch buffer is returned to the pool for the next builder
+/// on the same thread; larger values take over the buffer as
+/// their backing allocation.
+///
+/// # Examples
+/// ```
+/// # use sled::{IVec, IVecBuilder};
+/// use std::io::Write;
+///
+/// let mut builder = IVecBuilder::new();
+/// builder.write_all(b"hello ").unwrap();
+/// builder.extend(b"world".iter().copied());
+/// let value: IVec = builder.build();
+/// assert_eq!(&value, b"hello world");
+/// ```
+#[derive(Debug, Default)]
+pub struct IVecBuilder {
+    buf: Vec<u8>,
+}
+
+impl IVecBuilder {
+    /// Create a builder, reusing a pooled buffer
+    /// when one is available.
+    pub fn new() -> IVecBuilder {
+        IVecBuilder { buf: pool_take(0) }
+    }
+
+    /// Create a builder whose buffer can hold at least
+    /// `capacity` bytes before reallocating.
+    pub fn with_capacity(capacity: usize) -> IVecBuilder {
+        IVecBuilder { buf: pool_take(capacity) }
+    }
+
+    /// Append a single byte.
+    pub fn push(&mut self, byte: u8) {
+        self.buf.push(byte);
+    }
+
+    /// Append a slice of bytes.
+    pub fn extend_from_slice(&mut self, slice: &[u8]) {
+        self.buf.extend_from_slice(slice);
+    }
+
+    /// Returns the number of bytes written so far.
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns `true` if no bytes have been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Discard any bytes written so far, keeping
+    /// the buffer for further use.
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Finish the value, recycling the scratch buffer when the
+    /// result is small enough to be stored inline.
+    pub fn build(mut self) -> IVec {
+        let buf = mem::take(&mut self.buf);
+        if is_inline_candidate(buf.len()) {
+            let ret = IVec::inline(&buf);
+            pool_put(buf);
+            ret
+        } else {
+            IVec::remote(Arc::from(buf))
+        }
+    }
+}
+
+impl Drop for IVecBuilder {
+    fn drop(&mut self) {
+        pool_put(mem::take(&mut self.buf));
+    }
+}
+
+impl io::Write for IVecBuilder {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Extend<u8> for IVecBuilder {
+    fn extend<T: IntoIterator<Item = u8>>(&mut self, iter: T) {
+        self.buf.extend(iter);
+    }
+}
+
+impl AsRef<[u8]> for IVecBuilder {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
 impl IVec {
     /// Create a subslice of this `IVec` that shares
     /// the same backing data and reference counter.
@@ -107,6 +255,32 @@ impl IVec {
         IVec(inner)
     }
 
+    /// Build an `IVec` by writing into a pooled scratch
+    /// buffer, avoiding a fresh allocation when the value
+    /// turns out to be small or when a previous builder on
+    /// this thread has already warmed the pool.
+    ///
+    /// # Examples
+    /// ```
+    /// # use sled::IVec;
+    /// let value = IVec::from_writer(|buf| {
+    ///     buf.extend_from_slice(b"key-");
+    ///     buf.extend_from_slice(&42_u64.to_be_bytes());
+    /// });
+    /// assert_eq!(&value[..4], b"key-");
+    /// ```
+    pub fn from_writer<F: FnOnce(&mut Vec<u8>)>(f: F) -> Self {
+        let mut buf = pool_take(0);
+        f(&mut buf);
+        if is_inline_candidate(buf.len()) {
+            let ret = Self::inline(&buf);
+            pool_put(buf);
+            ret
+        } else {
+            Self::remote(Arc::from(buf))
+        }
+    }
+
     fn inline(slice: &[u8]) -> Self {
         assert!(is_inline_candidate(slice.len()));
         let mut data = Inner::default();
@@ -365,6 +539,44 @@ fn subslice_usage_01() {
     let _subslice = iv1.subslice(3, 1);
 }
 
+#[test]
+fn builder_usage() {
+    use std::io::Write;
+
+    let mut builder = IVecBuilder::new();
+    builder.write_all(&[1, 2, 3]).unwrap();
+    builder.push(4);
+    assert_eq!(builder.len(), 4);
+    assert_eq!(builder.build(), vec![1, 2, 3, 4]);
+
+    let mut big = IVecBuilder::with_capacity(128);
+    big.extend_from_slice(&[7; 128]);
+    assert_eq!(big.build(), vec![7; 128]);
+}
+
+#[test]
+fn builder_recycles_buffers() {
+    let mut builder = IVecBuilder::new();
+    builder.extend_from_slice(&[0; 64]);
+    builder.clear();
+    builder.push(1);
+    // the inline result releases the 64-byte allocation
+    // back to this thread's pool
+    let _small = builder.build();
+
+    let recycled = IVecBuilder::new();
+    assert!(recycled.as_ref().is_empty());
+    assert!(recycled.buf.capacity() >= 64);
+}
+
+#[test]
+fn from_writer_usage() {
+    let small = IVec::from_writer(|buf| buf.extend_from_slice(b"yo!"));
+    assert_eq!(small, b"yo!");
+    let large = IVec::from_writer(|buf| buf.resize(4096, 8));
+    assert_eq!(large, vec![8; 4096]);
+}
+
 #[test]
 fn ivec_as_mut_identity() {
     let initial = &[1];
@@ -0,0 +1,345 @@
+//! Domain-specific key ordering through order-preserving
+//! transforms, layered over a tree.
+//!
+//! Trees order keys by raw bytes, which is wrong for many
+//! domains: signed integers sort negatives after positives,
+//! case-insensitive identifiers sort `"Z"` before `"a"`. Rather
+//! than threading a comparator through the node format — where
+//! prefix encoding, splits, and merges all assume bytewise
+//! order — an [`OrderedTree`] encodes every key through an
+//! invertible, order-preserving transform on the way in and
+//! decodes it on the way out, so ranges, `get_lt`, and `get_gt`
+//! follow the domain order while the storage layer stays
+//! untouched.
+//!
+//! Transforms are registered by name via
+//! [`Db::register_key_order`](crate::Db::register_key_order) and
+//! selected per tree with [`Tree::ordered_by`], mirroring how
+//! merge operators are shared across trees.
+
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+
+use crate::{Error, IVec, Iter, Map, Result, Tree};
+
+/// An invertible, order-preserving transform between domain keys
+/// and their stored byte form, registered via
+/// `Db::register_key_order`.
+///
+/// `decode(encode(k))` must return `k`, and `encode` must
+/// preserve the domain's intended order under bytewise
+/// comparison of its output. Equality follows encoding too: two
+/// keys that encode to the same bytes are the same record.
+///
+/// # Examples
+///
+/// Big-endian signed integers become bytewise-comparable by
+/// flipping the sign bit:
+///
+/// ```
+/// use sled::KeyOrder;
+///
+/// struct SignedI64;
+///
+/// impl KeyOrder for SignedI64 {
+///     fn encode(&self, key: &[u8]) -> Vec<u8> {
+///         let mut out = key.to_vec();
+///         out[0] ^= 0x80;
+///         out
+///     }
+///
+///     fn decode(&self, stored: &[u8]) -> Vec<u8> {
+///         let mut out = stored.to_vec();
+///         out[0] ^= 0x80;
+///         out
+///     }
+/// }
+/// ```
+pub trait KeyOrder: Send + Sync {
+    /// Encodes a domain key into its stored form.
+    fn encode(&self, key: &[u8]) -> Vec<u8>;
+
+    /// Recovers the domain key from its stored form.
+    fn decode(&self, stored: &[u8]) -> Vec<u8>;
+}
+
+/// The set of named key orders registered via
+/// `Db::register_key_order`, resolved by name when a tree is
+/// viewed through `Tree::ordered_by`.
+#[derive(Default)]
+pub(crate) struct KeyOrders(
+    pub(crate) RwLock<Map<String, Arc<dyn KeyOrder>>>,
+);
+
+impl std::fmt::Debug for KeyOrders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_set().entries(self.0.read().keys()).finish()
+    }
+}
+
+/// A built-in [`KeyOrder`] that sorts keys as if ASCII letters
+/// were lowercased, while still storing and returning the
+/// original bytes.
+///
+/// Folding case loses information, so the stored form appends the
+/// original key after the folded one: keys differing only in case
+/// remain distinct records, adjacent in iteration order.
+#[derive(Debug, Clone, Copy)]
+pub struct AsciiCaseInsensitive;
+
+impl KeyOrder for AsciiCaseInsensitive {
+    fn encode(&self, key: &[u8]) -> Vec<u8> {
+        // the folded portion is escaped to be prefix-free
+        // (`0x00` becomes `0x00 0xff`, a `0x00 0x00` terminator
+        // follows), so keys containing NUL bytes still order by
+        // their folded form alone
+        let mut out = Vec::with_capacity(2 * key.len() + 2);
+        for b in key {
+            let folded = b.to_ascii_lowercase();
+            if folded == 0 {
+                out.extend_from_slice(&[0, 0xff]);
+            } else {
+                out.push(folded);
+            }
+        }
+        out.extend_from_slice(&[0, 0]);
+        out.extend_from_slice(key);
+        out
+    }
+
+    fn decode(&self, stored: &[u8]) -> Vec<u8> {
+        let mut i = 0;
+        while i + 1 < stored.len() {
+            if stored[i] == 0 {
+                if stored[i + 1] == 0 {
+                    return stored[i + 2..].to_vec();
+                }
+                i += 2;
+            } else {
+                i += 1;
+            }
+        }
+        Vec::new()
+    }
+}
+
+/// A view of a tree under a registered key order, created via
+/// [`Tree::ordered_by`]. Keys are transformed on every operation,
+/// so ranges and ordered lookups follow the domain order rather
+/// than raw byte order.
+///
+/// The view shares the underlying tree, but raw and ordered
+/// handles must not write the same keys: they address different
+/// stored forms.
+#[derive(Clone)]
+pub struct OrderedTree {
+    tree: Tree,
+    order: Arc<dyn KeyOrder>,
+}
+
+impl std::fmt::Debug for OrderedTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "OrderedTree {{ .. }}")
+    }
+}
+
+impl OrderedTree {
+    /// Retrieves a value if it exists.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        self.tree.get(self.order.encode(key.as_ref()))
+    }
+
+    /// Sets a key to a new value, returning the last value if it
+    /// was set.
+    pub fn insert<K, V>(&self, key: K, value: V) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: Into<IVec>,
+    {
+        self.tree.insert(self.order.encode(key.as_ref()), value)
+    }
+
+    /// Deletes a value, returning the old value if it existed.
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        self.tree.remove(self.order.encode(key.as_ref()))
+    }
+
+    /// Returns `true` if there is a value for the specified key.
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> Result<bool> {
+        self.tree.contains_key(self.order.encode(key.as_ref()))
+    }
+
+    /// Returns the greatest key-value pair strictly less than the
+    /// given key in the domain order.
+    pub fn get_lt<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<Option<(IVec, IVec)>> {
+        Ok(self
+            .tree
+            .get_lt(self.order.encode(key.as_ref()))?
+            .map(|(k, v)| (self.order.decode(&k).into(), v)))
+    }
+
+    /// Returns the least key-value pair strictly greater than the
+    /// given key in the domain order.
+    pub fn get_gt<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<Option<(IVec, IVec)>> {
+        Ok(self
+            .tree
+            .get_gt(self.order.encode(key.as_ref()))?
+            .map(|(k, v)| (self.order.decode(&k).into(), v)))
+    }
+
+    /// Returns the first key-value pair in the domain order.
+    pub fn first(&self) -> Result<Option<(IVec, IVec)>> {
+        Ok(self
+            .tree
+            .first()?
+            .map(|(k, v)| (self.order.decode(&k).into(), v)))
+    }
+
+    /// Returns the last key-value pair in the domain order.
+    pub fn last(&self) -> Result<Option<(IVec, IVec)>> {
+        Ok(self
+            .tree
+            .last()?
+            .map(|(k, v)| (self.order.decode(&k).into(), v)))
+    }
+
+    /// Iterates over all key-value pairs in the domain order.
+    pub fn iter(&self) -> OrderedIter {
+        OrderedIter { inner: self.tree.iter(), order: self.order.clone() }
+    }
+
+    /// Iterates over key-value pairs whose keys fall in `range`,
+    /// interpreted in the domain order.
+    pub fn range<K, R>(&self, range: R) -> OrderedIter
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let start = match range.start_bound() {
+            Bound::Included(s) => {
+                Bound::Included(IVec::from(self.order.encode(s.as_ref())))
+            }
+            Bound::Excluded(s) => {
+                Bound::Excluded(IVec::from(self.order.encode(s.as_ref())))
+            }
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(e) => {
+                Bound::Included(IVec::from(self.order.encode(e.as_ref())))
+            }
+            Bound::Excluded(e) => {
+                Bound::Excluded(IVec::from(self.order.encode(e.as_ref())))
+            }
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        OrderedIter {
+            inner: self.tree.range::<IVec, _>((start, end)),
+            order: self.order.clone(),
+        }
+    }
+
+    /// Returns the number of elements in this tree.
+    pub fn len(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Returns `true` if the `Tree` contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+}
+
+/// An iterator over the key-value pairs of an [`OrderedTree`],
+/// yielding keys decoded back into their domain form.
+pub struct OrderedIter {
+    inner: Iter,
+    order: Arc<dyn KeyOrder>,
+}
+
+impl Iterator for OrderedIter {
+    type Item = Result<(IVec, IVec)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let kv = self.inner.next()?;
+        Some(kv.map(|(k, v)| (self.order.decode(&k).into(), v)))
+    }
+}
+
+impl DoubleEndedIterator for OrderedIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let kv = self.inner.next_back()?;
+        Some(kv.map(|(k, v)| (self.order.decode(&k).into(), v)))
+    }
+}
+
+impl Tree {
+    /// Returns a view of this tree under the key order registered
+    /// via `Db::register_key_order` with the given name, so that
+    /// ranges, `get_lt`, and `get_gt` follow the domain order
+    /// instead of raw byte order. Fails loudly if no order is
+    /// registered under the name.
+    ///
+    /// The transform applies to keys written through the view, so
+    /// a tree should be accessed either raw or through one
+    /// consistent order, not both. Like merge operators, key
+    /// orders are plain code and must be re-registered each time
+    /// the database is opened.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.register_key_order("ascii_ci", sled::AsciiCaseInsensitive);
+    /// let names = db.ordered_by("ascii_ci")?;
+    ///
+    /// names.insert(b"Banana", b"1")?;
+    /// names.insert(b"apple", b"2")?;
+    /// names.insert(b"Cherry", b"3")?;
+    ///
+    /// // bytewise, "B" and "C" would sort before "a"
+    /// let keys: Vec<_> = names
+    ///     .iter()
+    ///     .map(|kv| kv.map(|(k, _)| k))
+    ///     .collect::<Result<_, _>>()?;
+    /// assert_eq!(
+    ///     keys,
+    ///     vec![
+    ///         sled::IVec::from(b"apple"),
+    ///         sled::IVec::from(b"Banana"),
+    ///         sled::IVec::from(b"Cherry"),
+    ///     ],
+    /// );
+    ///
+    /// let (prev, _) = names.get_lt(b"cat")?.unwrap();
+    /// assert_eq!(&prev, b"Banana");
+    /// # Ok(()) }
+    /// ```
+    pub fn ordered_by(&self, name: &str) -> Result<OrderedTree> {
+        let order = self
+            .context
+            .key_orders
+            .0
+            .read()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| {
+                Error::Unsupported(format!(
+                    "no key order named \"{}\" has been \
+                     registered via Db::register_key_order",
+                    name
+                ))
+            })?;
+        Ok(OrderedTree { tree: self.clone(), order })
+    }
+}
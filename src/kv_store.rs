@@ -0,0 +1,214 @@
+//! A trait abstraction over the core key-value operations.
+
+use crate::{Batch, IVec};
+
+/// The core read/write operations shared by [`Tree`](crate::Tree),
+/// [`TransactionalTree`](crate::transaction::TransactionalTree), and
+/// [`testing::MockTree`](crate::testing::MockTree).
+///
+/// Application code written against this trait can run over a
+/// durable tree, inside a transaction, or against an in-memory fake
+/// in unit tests. The error type is associated because operations
+/// inside a transaction surface conflicts differently than direct
+/// tree operations do.
+///
+/// # Examples
+///
+/// ```
+/// use sled::{IVec, KvStore};
+///
+/// fn bump<S: KvStore>(store: &S) -> Result<u64, S::Error> {
+///     let old = store.get(b"counter")?;
+///     let next = match old {
+///         Some(v) => {
+///             let mut buf = [0; 8];
+///             buf.copy_from_slice(&v);
+///             u64::from_be_bytes(buf) + 1
+///         }
+///         None => 1,
+///     };
+///     store.insert(b"counter".to_vec(), &next.to_be_bytes()[..])?;
+///     Ok(next)
+/// }
+///
+/// let fake = sled::testing::MockTree::new();
+/// assert_eq!(bump(&fake).unwrap(), 1);
+/// assert_eq!(bump(&fake).unwrap(), 2);
+/// ```
+pub trait KvStore {
+    /// The error type returned by every operation.
+    type Error;
+
+    /// Retrieves a value if it exists.
+    fn get<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<Option<IVec>, Self::Error>;
+
+    /// Sets a key to a new value, returning the last value if it
+    /// was set.
+    fn insert<K, V>(
+        &self,
+        key: K,
+        value: V,
+    ) -> Result<Option<IVec>, Self::Error>
+    where
+        K: AsRef<[u8]> + Into<IVec>,
+        V: Into<IVec>;
+
+    /// Deletes a value, returning the old value if it existed.
+    fn remove<K>(
+        &self,
+        key: K,
+    ) -> Result<Option<IVec>, Self::Error>
+    where
+        K: AsRef<[u8]> + Into<IVec>;
+
+    /// Atomically applies multiple inserts and removals.
+    fn apply_batch(
+        &self,
+        batch: Batch,
+    ) -> Result<(), Self::Error>;
+
+    /// Returns `true` if there is a value for the specified key.
+    fn contains_key<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<bool, Self::Error> {
+        self.get(key).map(|value| value.is_some())
+    }
+}
+
+impl KvStore for crate::Tree {
+    type Error = crate::Error;
+
+    fn get<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<Option<IVec>, Self::Error> {
+        crate::Tree::get(self, key)
+    }
+
+    fn insert<K, V>(
+        &self,
+        key: K,
+        value: V,
+    ) -> Result<Option<IVec>, Self::Error>
+    where
+        K: AsRef<[u8]> + Into<IVec>,
+        V: Into<IVec>,
+    {
+        crate::Tree::insert(self, key, value)
+    }
+
+    fn remove<K>(
+        &self,
+        key: K,
+    ) -> Result<Option<IVec>, Self::Error>
+    where
+        K: AsRef<[u8]> + Into<IVec>,
+    {
+        crate::Tree::remove(self, key)
+    }
+
+    fn apply_batch(
+        &self,
+        batch: Batch,
+    ) -> Result<(), Self::Error> {
+        crate::Tree::apply_batch(self, batch)
+    }
+
+    fn contains_key<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<bool, Self::Error> {
+        crate::Tree::contains_key(self, key)
+    }
+}
+
+impl KvStore for crate::transaction::TransactionalTree {
+    type Error = crate::transaction::UnabortableTransactionError;
+
+    fn get<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<Option<IVec>, Self::Error> {
+        crate::transaction::TransactionalTree::get(self, key)
+    }
+
+    fn insert<K, V>(
+        &self,
+        key: K,
+        value: V,
+    ) -> Result<Option<IVec>, Self::Error>
+    where
+        K: AsRef<[u8]> + Into<IVec>,
+        V: Into<IVec>,
+    {
+        crate::transaction::TransactionalTree::insert(self, key, value)
+    }
+
+    fn remove<K>(
+        &self,
+        key: K,
+    ) -> Result<Option<IVec>, Self::Error>
+    where
+        K: AsRef<[u8]> + Into<IVec>,
+    {
+        crate::transaction::TransactionalTree::remove(self, key)
+    }
+
+    fn apply_batch(
+        &self,
+        batch: Batch,
+    ) -> Result<(), Self::Error> {
+        crate::transaction::TransactionalTree::apply_batch(self, &batch)
+    }
+}
+
+impl KvStore for crate::testing::MockTree {
+    type Error = crate::Error;
+
+    fn get<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<Option<IVec>, Self::Error> {
+        crate::testing::MockTree::get(self, key)
+    }
+
+    fn insert<K, V>(
+        &self,
+        key: K,
+        value: V,
+    ) -> Result<Option<IVec>, Self::Error>
+    where
+        K: AsRef<[u8]> + Into<IVec>,
+        V: Into<IVec>,
+    {
+        crate::testing::MockTree::insert(self, key, value)
+    }
+
+    fn remove<K>(
+        &self,
+        key: K,
+    ) -> Result<Option<IVec>, Self::Error>
+    where
+        K: AsRef<[u8]> + Into<IVec>,
+    {
+        crate::testing::MockTree::remove(self, key)
+    }
+
+    fn apply_batch(
+        &self,
+        batch: Batch,
+    ) -> Result<(), Self::Error> {
+        crate::testing::MockTree::apply_batch(self, batch)
+    }
+
+    fn contains_key<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<bool, Self::Error> {
+        crate::testing::MockTree::contains_key(self, key)
+    }
+}
@@ -0,0 +1,186 @@
+//! An opt-in tree mode for workloads with very large keys, which
+//! indexes a fixed-size key digest instead of the key itself.
+
+use std::convert::TryFrom;
+use std::hash::Hasher;
+
+use fxhash::FxHasher64;
+
+use crate::{IVec, Result, Tree};
+
+const SECOND_HALF_SEED: u64 = 0x6A09_E667_F3BC_C908;
+
+/// A tree for keys that can exceed node-friendly sizes, opened
+/// via `Db::open_large_key_tree`.
+///
+/// Instead of storing long URL or path keys directly in index
+/// nodes - where they cause pathological node splits - the tree
+/// indexes a fixed-size digest of each key and stores the full
+/// key alongside the value. Digest collisions are resolved
+/// transparently by keeping all colliding entries in one record
+/// and comparing full keys on every operation.
+///
+/// Because entries are ordered by digest rather than by key,
+/// range iteration over keys is not available.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// let tree = db.open_large_key_tree("urls")?;
+///
+/// let long_key = "https://example.com/".repeat(500);
+/// tree.insert(&long_key, b"v1")?;
+/// assert_eq!(&tree.get(&long_key)?.unwrap(), b"v1");
+/// assert_eq!(&tree.insert(&long_key, b"v2")?.unwrap(), b"v1");
+/// assert_eq!(&tree.remove(&long_key)?.unwrap(), b"v2");
+/// assert_eq!(tree.get(&long_key)?, None);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct LargeKeyTree {
+    pub(crate) tree: Tree,
+}
+
+impl LargeKeyTree {
+    /// Sets the key to a new value, returning the last value if
+    /// it was set.
+    pub fn insert<K, V>(&self, key: K, value: V) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: Into<IVec>,
+    {
+        let key = key.as_ref();
+        let value = value.into();
+        let digest = key_digest(key);
+        loop {
+            let bucket = self.tree.get(&digest)?;
+            let mut entries = decode_bucket(bucket.as_ref());
+            let last = match entries
+                .iter_mut()
+                .find(|(entry_key, _)| *entry_key == key)
+            {
+                Some((_, entry_value)) => {
+                    Some(std::mem::replace(entry_value, value.clone()))
+                }
+                None => {
+                    entries.push((key.into(), value.clone()));
+                    None
+                }
+            };
+            let new = encode_bucket(&entries);
+            if self
+                .tree
+                .compare_and_swap(&digest, bucket.as_ref(), Some(new))?
+                .is_ok()
+            {
+                return Ok(last);
+            }
+        }
+    }
+
+    /// Retrieves a value if it exists.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        let key = key.as_ref();
+        let bucket = self.tree.get(&key_digest(key))?;
+        Ok(decode_bucket(bucket.as_ref())
+            .into_iter()
+            .find(|(entry_key, _)| *entry_key == key)
+            .map(|(_, entry_value)| entry_value))
+    }
+
+    /// Deletes a value, returning the old value if it existed.
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        let key = key.as_ref();
+        let digest = key_digest(key);
+        loop {
+            let bucket = match self.tree.get(&digest)? {
+                Some(bucket) => bucket,
+                None => return Ok(None),
+            };
+            let mut entries = decode_bucket(Some(&bucket));
+            let position = match entries
+                .iter()
+                .position(|(entry_key, _)| *entry_key == key)
+            {
+                Some(position) => position,
+                None => return Ok(None),
+            };
+            let (_, last) = entries.remove(position);
+            let new = if entries.is_empty() {
+                None
+            } else {
+                Some(encode_bucket(&entries))
+            };
+            if self
+                .tree
+                .compare_and_swap(&digest, Some(&bucket), new)?
+                .is_ok()
+            {
+                return Ok(Some(last));
+            }
+        }
+    }
+
+    /// Returns `true` if the tree contains a value for the
+    /// specified key.
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> Result<bool> {
+        self.get(key).map(|value| value.is_some())
+    }
+}
+
+fn key_digest(key: &[u8]) -> [u8; 16] {
+    let mut first = FxHasher64::default();
+    first.write(key);
+
+    let mut second = FxHasher64::default();
+    second.write_u64(SECOND_HALF_SEED);
+    second.write_u64(u64::try_from(key.len()).unwrap());
+    second.write(key);
+
+    let mut buf = [0; 16];
+    buf[..8].copy_from_slice(&first.finish().to_be_bytes());
+    buf[8..].copy_from_slice(&second.finish().to_be_bytes());
+    buf
+}
+
+fn encode_bucket(entries: &[(IVec, IVec)]) -> Vec<u8> {
+    let mut buf = vec![];
+    for (key, value) in entries {
+        buf.extend_from_slice(
+            &u64::try_from(key.len()).unwrap().to_be_bytes(),
+        );
+        buf.extend_from_slice(key);
+        buf.extend_from_slice(
+            &u64::try_from(value.len()).unwrap().to_be_bytes(),
+        );
+        buf.extend_from_slice(value);
+    }
+    buf
+}
+
+fn decode_bucket(bucket: Option<&IVec>) -> Vec<(IVec, IVec)> {
+    let mut entries = vec![];
+    let buf = match bucket {
+        Some(bucket) => &**bucket,
+        None => return entries,
+    };
+    let mut idx = 0;
+    let read_chunk = |idx: &mut usize| {
+        let mut arr = [0; 8];
+        arr.copy_from_slice(&buf[*idx..*idx + 8]);
+        let len = usize::try_from(u64::from_be_bytes(arr)).unwrap();
+        *idx += 8;
+        let chunk = IVec::from(&buf[*idx..*idx + len]);
+        *idx += len;
+        chunk
+    };
+    while idx < buf.len() {
+        let key = read_chunk(&mut idx);
+        let value = read_chunk(&mut idx);
+        entries.push((key, value));
+    }
+    entries
+}
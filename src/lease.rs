@@ -0,0 +1,162 @@
+//! Named leases with TTLs and fencing tokens, persisted in sled.
+//!
+//! Leases let co-located processes sharing a database, or a single
+//! process's subsystems, coordinate exclusive work safely: only one
+//! holder of a named lease exists at a time, holders must heartbeat
+//! to retain it past its TTL, and every acquisition returns a
+//! monotonically increasing fencing token that downstream systems
+//! can use to reject stale holders.
+
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::{debug, IVec, Result, Tree};
+
+/// An exclusive, named lease acquired via `Db::acquire_lease`.
+///
+/// The lease is released on drop on a best-effort basis; use
+/// [`release`](Lease::release) to observe errors. A holder that
+/// fails to [`heartbeat`](Lease::heartbeat) before the TTL elapses
+/// may lose the lease to another acquirer.
+pub struct Lease {
+    leases: Tree,
+    name: IVec,
+    token: u64,
+    expiry: u64,
+}
+
+impl Lease {
+    /// Returns the fencing token issued with this acquisition.
+    /// Tokens for a given lease name increase monotonically across
+    /// acquisitions, so a downstream system that records the
+    /// highest token it has seen can reject work from stale
+    /// holders.
+    pub fn fencing_token(&self) -> u64 {
+        self.token
+    }
+
+    /// Extends this lease so that it expires `ttl` from now.
+    /// Returns `true` if the lease was extended, or `false` if it
+    /// had already been lost to another acquirer, in which case
+    /// the holder must stop performing the leased work.
+    pub fn heartbeat(&mut self, ttl: Duration) -> Result<bool> {
+        let expiry = now_millis().saturating_add(duration_millis(ttl));
+        let old = encode_lease_record(self.token, self.expiry);
+        let new = encode_lease_record(self.token, expiry);
+        let res = self.leases.compare_and_swap(
+            &self.name,
+            Some(&old),
+            Some(new),
+        )?;
+        if res.is_ok() {
+            self.expiry = expiry;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Releases this lease, letting the next acquirer take it
+    /// immediately rather than after the TTL elapses. Does nothing
+    /// if the lease had already been lost.
+    pub fn release(mut self) -> Result<()> {
+        self.release_inner()
+    }
+
+    fn release_inner(&mut self) -> Result<()> {
+        // the record is marked expired rather than removed, so
+        // that fencing tokens keep increasing monotonically
+        // across releases.
+        let old = encode_lease_record(self.token, self.expiry);
+        let released = encode_lease_record(self.token, 0);
+        let _res = self.leases.compare_and_swap(
+            &self.name,
+            Some(&old),
+            Some(released),
+        )?;
+        Ok(())
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if let Err(e) = self.release_inner() {
+            debug!("failed to release lease during drop: {:?}", e);
+        }
+    }
+}
+
+impl std::fmt::Debug for Lease {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Lease")
+            .field("name", &self.name)
+            .field("token", &self.token)
+            .finish()
+    }
+}
+
+/// Attempts to acquire the named lease, returning `None` if it is
+/// currently held and unexpired.
+pub(crate) fn acquire(
+    leases: &Tree,
+    name: &[u8],
+    ttl: Duration,
+) -> Result<Option<Lease>> {
+    let expiry = now_millis().saturating_add(duration_millis(ttl));
+    loop {
+        let current = leases.get(name)?;
+        let token = match current.as_ref().map(|r| decode_lease_record(r)) {
+            Some((token, current_expiry)) => {
+                if current_expiry > now_millis() {
+                    // held and unexpired
+                    return Ok(None);
+                }
+                token + 1
+            }
+            None => 1,
+        };
+        let new = encode_lease_record(token, expiry);
+        let res = leases.compare_and_swap(name, current, Some(new))?;
+        if res.is_ok() {
+            // the token must be durable before it is handed out:
+            // if a crash rolled the acquisition back, a restarted
+            // process would reissue the same token and break the
+            // monotonic fencing guarantee.
+            leases.flush()?;
+            return Ok(Some(Lease {
+                leases: leases.clone(),
+                name: name.into(),
+                token,
+                expiry,
+            }));
+        }
+        // lost a race with a concurrent acquirer, retry
+    }
+}
+
+fn now_millis() -> u64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is set before the unix epoch");
+    duration_millis(since_epoch)
+}
+
+fn duration_millis(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::max_value())
+}
+
+fn encode_lease_record(token: u64, expiry: u64) -> IVec {
+    let mut buf = [0; 16];
+    buf[..8].copy_from_slice(&token.to_le_bytes());
+    buf[8..].copy_from_slice(&expiry.to_le_bytes());
+    IVec::from(&buf[..])
+}
+
+fn decode_lease_record(record: &IVec) -> (u64, u64) {
+    let read = |idx: usize| {
+        let mut arr = [0; 8];
+        arr.copy_from_slice(&record[idx..idx + 8]);
+        u64::from_le_bytes(arr)
+    };
+    (read(0), read(8))
+}
@@ -178,39 +178,69 @@ macro_rules! testing_assert {
     };
 }
 
+mod append_log;
 mod arc;
+mod arena;
+#[cfg(feature = "tokio")]
+mod async_api;
 mod atomic_shim;
 mod batch;
+mod blob_store;
+mod branch;
+mod cancellation;
+mod changefeed;
+mod checksum;
+mod compression;
 mod concurrency_control;
 mod config;
 mod context;
 mod db;
 mod dll;
+mod estimate;
 mod fastcmp;
 mod fastlock;
+mod fixed_width_tree;
+mod foreign_key;
 mod histogram;
+mod index;
 mod iter;
 mod ivec;
+mod key_order;
+mod kv_store;
+mod large_key_tree;
 mod lazy;
+mod lease;
 mod lru;
+mod manifest;
 mod meta;
 mod metrics;
 mod node;
 mod oneshot;
 mod pagecache;
+mod poison;
+mod priority;
 mod result;
 mod serialization;
+mod snapshot;
 mod stack;
 mod subscriber;
+mod subspace;
 mod sys_limits;
+pub mod testing;
+mod thread_lifecycle;
 pub mod transaction;
 mod tree;
+mod typed_tree;
+mod value_log;
 mod varint;
 
 /// Functionality for conditionally triggering failpoints under test.
 #[cfg(feature = "failpoints")]
 pub mod fail;
 
+#[cfg(feature = "chaos")]
+pub mod chaos;
+
 #[cfg(feature = "docs")]
 pub mod doc;
 
@@ -269,10 +299,50 @@ mod threadpool;
 ))]
 mod flusher;
 
+#[cfg(all(
+    not(miri),
+    any(
+        windows,
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+    )
+))]
+mod scrubber;
+
+#[cfg(all(
+    not(miri),
+    any(
+        windows,
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+    )
+))]
+mod watchdog;
+
 #[cfg(feature = "event_log")]
 /// The event log helps debug concurrency issues.
 pub mod event_log;
 
+#[cfg(feature = "alloc_stats")]
+/// Hooks for observing sled's large allocations.
+pub mod alloc_stats;
+
+#[cfg(feature = "http_status")]
+/// A built-in HTTP status page for operational triage.
+pub mod http_status;
+
+#[cfg(feature = "otel")]
+/// OpenTelemetry export of internal metrics and background spans.
+pub mod otel;
+
 #[cfg(feature = "measure_allocs")]
 mod measure_allocs;
 
@@ -282,6 +352,29 @@ static ALLOCATOR: measure_allocs::TrackingAllocator =
     measure_allocs::TrackingAllocator;
 
 const DEFAULT_TREE_ID: &[u8] = b"__sled__default";
+const TRASH_TREE_PREFIX: &[u8] = b"__sled__trash__";
+const AUDIT_TREE_PREFIX: &[u8] = b"__sled__audit__";
+const CHANGEFEED_TREE_ID: &[u8] = b"__sled__changefeed__";
+const VERSIONS_TREE_PREFIX: &[u8] = b"__sled__versions__";
+const IDEMPOTENCY_TREE_PREFIX: &[u8] = b"__sled__idempotency__";
+const LEASES_TREE_ID: &[u8] = b"__sled__leases__";
+const BLOBS_TREE_PREFIX: &[u8] = b"__sled__blobs__";
+const APPEND_LOG_TREE_PREFIX: &[u8] = b"__sled__append_log__";
+const FIXED_WIDTH_TREE_PREFIX: &[u8] = b"__sled__fixedwidth__";
+const TTL_TREE_PREFIX: &[u8] = b"__sled__ttl__";
+const MERGE_OPERATORS_TREE_ID: &[u8] = b"__sled__merge_operators__";
+const INTERNED_KEYS_TREE_ID: &[u8] = b"__sled__interned_keys__";
+const LARGE_KEYS_TREE_PREFIX: &[u8] = b"__sled__largekeys__";
+const VALUE_LOG_TREE_PREFIX: &[u8] = b"__sled__valuelog__";
+const STREAMS_TREE_PREFIX: &[u8] = b"__sled__streams__";
+const STATS_TREE_ID: &[u8] = b"__sled__stats__";
+const TREE_OPTIONS_TREE_ID: &[u8] = b"__sled__tree_options__";
+const CHECKPOINTS_TREE_ID: &[u8] = b"__sled__checkpoints__";
+const CHECKPOINT_TREE_PREFIX: &[u8] = b"__sled__checkpoint__";
+const COORDINATION_TREE_ID: &[u8] = b"__sled__coordination__";
+const PREPARED_TREE_ID: &[u8] = b"__sled__prepared__";
+const INDEX_TREE_PREFIX: &[u8] = b"__sled__index__";
+const INTERNAL_TREE_PREFIX: &[u8] = b"__sled__";
 
 /// hidden re-export of items for testing purposes
 #[doc(hidden)]
@@ -303,16 +396,55 @@ pub use {
     },
 };
 
+#[cfg(feature = "tokio")]
+pub use self::async_api::{AsyncDb, AsyncTree, Spawn};
+
+#[cfg(feature = "codecs")]
+pub use self::typed_tree::BincodeCodec;
+
 pub use self::{
-    batch::Batch,
-    config::{Config, Mode},
-    db::{open, Db},
-    iter::Iter,
-    ivec::IVec,
+    append_log::{AppendLog, LogEntry},
+    batch::{Batch, BatchChunks},
+    blob_store::{BlobHash, BlobStore},
+    branch::Branch,
+    cancellation::CancellationToken,
+    changefeed::Changefeed,
+    checksum::ChecksumAlgorithm,
+    compression::{
+        register_compressor, CompressionAlgorithm, Compressor,
+        MIN_CUSTOM_COMPRESSOR_ID,
+    },
+    config::{CheckLevel, Config, Mode, StorageCapabilities},
+    db::{
+        open, restore_incremental, Calibration, Db, DirectoryArchive,
+        DiskUsage, GcInfo, Health, MemoryBreakdown, PrefetchStats,
+        SegmentArchive, SegmentInfo, Stats,
+    },
+    fixed_width_tree::FixedWidthTree,
+    index::{Index, IndexExtractor, IndexIter},
+    iter::{Chunks, Iter},
+    ivec::{IVec, IVecBuilder},
+    key_order::{AsciiCaseInsensitive, KeyOrder, OrderedIter, OrderedTree},
+    kv_store::KvStore,
+    large_key_tree::LargeKeyTree,
+    lease::Lease,
+    poison::PoisonReport,
+    priority::{set_thread_priority, Priority},
     result::{Error, Result},
+    snapshot::{Snapshot, SnapshotIter},
     subscriber::{Event, Subscriber},
-    transaction::Transactional,
-    tree::{CompareAndSwapError, Tree},
+    subspace::Subspace,
+    thread_lifecycle::set_thread_lifecycle_hooks,
+    transaction::{PostCommitCallback, PreCommitValidator, Transactional},
+    tree::{
+        CompareAndSwapError, MaintenanceStats, Tree, TreeOptions,
+        ValueReader,
+    },
+    typed_tree::{
+        Codec, DecodeError, TypedEvent, TypedSubscriber, TypedTree,
+        TypedUpdate, U64Codec, Utf8Codec,
+    },
+    value_log::{ValueLogGcStats, ValueLogTree},
 };
 
 use {
@@ -348,6 +480,16 @@ use {
     },
 };
 
+/// Returns the cumulative number of tree node buffers allocated by
+/// this process and the total number of bytes those allocations
+/// requested. Node buffers are allocated at exactly their computed
+/// size, so the byte count carries no spare-capacity overhead, and
+/// these counters can be compared directly against allocator
+/// telemetry when attributing memory use to sled.
+pub fn node_buffer_allocation_stats() -> (u64, u64) {
+    node::allocation_stats()
+}
+
 #[doc(hidden)]
 pub fn pin() -> Guard {
     Guard { inner: crossbeam_pin(), readset: vec![], writeset: vec![] }
@@ -379,19 +521,6 @@ fn crc32(buf: &[u8]) -> u32 {
     hasher.finalize()
 }
 
-fn calculate_message_crc32(header: &[u8], body: &[u8]) -> u32 {
-    trace!(
-        "calculating crc32 for header len {} body len {}",
-        header.len(),
-        body.len()
-    );
-    let mut hasher = crc32fast::Hasher::new();
-    hasher.update(body);
-    hasher.update(&header[4..]);
-    let crc32 = hasher.finalize();
-    crc32 ^ 0xFFFF_FFFF
-}
-
 #[cfg(any(test, feature = "lock_free_delays"))]
 mod debug_delay;
 
@@ -522,6 +651,81 @@ impl<F> MergeOperator for F where
 {
 }
 
+/// Declares an application's trees in one place, generating a struct
+/// with one `Tree` field per declared keyspace and an `open`
+/// constructor that opens or creates all of them up-front. Because
+/// each tree is reached through a named field rather than a
+/// stringly-typed `open_tree` call at each use site, tree-name typos
+/// become compile errors. A merge operator may optionally be declared
+/// per tree with `=>`, and is installed during `open`.
+///
+/// # Examples
+///
+/// ```
+/// fn concatenate(
+///     _key: &[u8],
+///     old_value: Option<&[u8]>,
+///     merged_bytes: &[u8],
+/// ) -> Option<Vec<u8>> {
+///     let mut ret = old_value.map(|ov| ov.to_vec()).unwrap_or_default();
+///     ret.extend_from_slice(merged_bytes);
+///     Some(ret)
+/// }
+///
+/// sled::tree_registry! {
+///     /// The application's keyspaces.
+///     pub struct AppTrees {
+///         users: "users",
+///         event_log: "event_log" => concatenate,
+///     }
+/// }
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// let trees = AppTrees::open(&db)?;
+/// trees.users.insert(b"alice", b"1")?;
+/// trees.event_log.merge(b"k", b"ab")?;
+/// trees.event_log.merge(b"k", b"cd")?;
+/// assert_eq!(
+///     trees.event_log.get(b"k")?,
+///     Some(sled::IVec::from(b"abcd"))
+/// );
+/// # Ok(()) }
+/// ```
+#[macro_export]
+macro_rules! tree_registry {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[$field_attr:meta])*
+                $field:ident : $tree_name:expr $(=> $merge:expr)?
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $(
+                $(#[$field_attr])*
+                $vis $field: $crate::Tree,
+            )*
+        }
+
+        impl $name {
+            /// Open or create all declared trees on the provided
+            /// database, installing any declared merge operators.
+            $vis fn open(db: &$crate::Db) -> $crate::Result<Self> {
+                $(
+                    let $field: $crate::Tree = db.open_tree($tree_name)?;
+                    $( $field.set_merge_operator($merge); )?
+                )*
+                Ok(Self { $( $field, )* })
+            }
+        }
+    };
+}
+
 mod compile_time_assertions {
     use crate::*;
 
@@ -121,7 +121,7 @@ impl AccessQueue {
 
     fn take<'a>(&self, guard: &'a Guard) -> CacheAccessIter<'a> {
         debug_delay();
-        let ptr = self.full_list.swap(std::ptr::null_mut(), Ordering::AcqRel);
+        let ptr = self.full_list.swap(ptr::null_mut(), Ordering::AcqRel);
 
         CacheAccessIter { guard, current_offset: 0, current_block: ptr }
     }
@@ -140,7 +140,7 @@ impl Drop for AccessQueue {
             unsafe {
                 debug_delay();
                 let next =
-                    (*head).next.swap(std::ptr::null_mut(), Ordering::Release);
+                    (*head).next.swap(ptr::null_mut(), Ordering::Release);
                 Box::from_raw(head);
                 head = next;
             }
@@ -233,6 +233,22 @@ impl Lru {
         Self { shards }
     }
 
+    /// Returns the number of bytes currently admitted to the
+    /// cache. Sizes are rounded up to powers of two when items
+    /// are admitted, so this is a slight overestimate.
+    pub(crate) fn cache_bytes(&self) -> u64 {
+        let mut size = 0;
+        for (_, shard_mu) in &self.shards {
+            loop {
+                if let Some(shard) = shard_mu.try_lock() {
+                    size += shard.size;
+                    break;
+                }
+            }
+        }
+        size
+    }
+
     /// Called when an item is accessed. Returns a Vec of items to be
     /// evicted. Uses flat-combining to avoid blocking on what can
     /// be an asynchronous operation.
@@ -248,6 +264,12 @@ impl Lru {
         item_size: u64,
         guard: &Guard,
     ) -> Vec<PageId> {
+        #[cfg(feature = "alloc_stats")]
+        crate::alloc_stats::record(
+            crate::alloc_stats::AllocationKind::CacheEntry,
+            safe_usize(item_size),
+        );
+
         let mut ret = vec![];
         let shards = self.shards.len() as u64;
         let (shard_idx, item_pos) = (id % shards, id / shards);
@@ -0,0 +1,187 @@
+//! An integrity manifest records the name, length and prefix
+//! checksum of every storage file in the database directory, so
+//! that external tooling (and `Db::open`) can immediately detect
+//! missing or truncated files with an error naming the culprit.
+//!
+//! Because the log is append-only, a file that has legitimately
+//! grown since the manifest was written still verifies: its length
+//! is at least the recorded length, and the checksum of its first
+//! recorded-length bytes is unchanged. Snapshot files are excluded,
+//! as they are rotated and deleted during normal operation.
+
+use std::{
+    convert::TryFrom,
+    fs,
+    io::{self, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::*;
+
+pub(crate) const MANIFEST_FILENAME: &str = "manifest";
+const MANIFEST_TMP_FILENAME: &str = "manifest.tmp";
+const MANIFEST_HEADER: &str = "sled manifest v1";
+const SNAPSHOT_PREFIX: &str = "snap.";
+
+fn manifest_error(msg: String) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, msg))
+}
+
+/// Recursively collect the relative paths of all manifest-covered
+/// files under the database directory.
+fn covered_files(dir: &Path, prefix: &Path, out: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let rel = prefix.join(entry.file_name());
+        if file_type.is_dir() {
+            covered_files(&entry.path(), &rel, out)?;
+        } else if file_type.is_file() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name == MANIFEST_FILENAME
+                || name == MANIFEST_TMP_FILENAME
+                || name.starts_with(SNAPSHOT_PREFIX)
+            {
+                continue;
+            }
+            out.push(rel);
+        }
+    }
+    Ok(())
+}
+
+fn file_crc(path: &Path) -> Result<(u64, u32)> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut len = 0;
+    let mut buf = [0; 8192];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        len += n as u64;
+    }
+    Ok((len, hasher.finalize()))
+}
+
+/// Atomically write a manifest covering the current state of all
+/// storage files in the database directory.
+pub(crate) fn write_manifest(dir: &Path) -> Result<()> {
+    let mut files = vec![];
+    covered_files(dir, Path::new(""), &mut files)?;
+    files.sort();
+
+    let mut contents = String::new();
+    contents.push_str(MANIFEST_HEADER);
+    contents.push('\n');
+    for rel in &files {
+        let (len, crc) = file_crc(&dir.join(rel))?;
+        contents.push_str(&format!(
+            "{:08x} {} {}\n",
+            crc,
+            len,
+            rel.display()
+        ));
+    }
+
+    // write to a temporary file and rename into place so a crash
+    // mid-write can never leave a torn manifest behind.
+    let tmp = dir.join(MANIFEST_TMP_FILENAME);
+    let final_path = dir.join(MANIFEST_FILENAME);
+    {
+        use std::io::Write;
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(tmp, final_path)?;
+
+    Ok(())
+}
+
+/// Verify the database directory against its manifest, if one is
+/// present. Returns an error naming the first file found to be
+/// missing, truncated, or corrupted.
+pub(crate) fn verify_manifest(dir: &Path) -> Result<()> {
+    let manifest_path = dir.join(MANIFEST_FILENAME);
+    let contents = match fs::read_to_string(&manifest_path) {
+        Ok(contents) => contents,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut lines = contents.lines();
+    if lines.next() != Some(MANIFEST_HEADER) {
+        return Err(manifest_error(format!(
+            "manifest file {} has an unrecognized header",
+            manifest_path.display()
+        )));
+    }
+
+    for line in lines {
+        let mut parts = line.splitn(3, ' ');
+        let (crc, len, rel) = match (
+            parts.next().and_then(|p| u32::from_str_radix(p, 16).ok()),
+            parts.next().and_then(|p| p.parse::<u64>().ok()),
+            parts.next(),
+        ) {
+            (Some(crc), Some(len), Some(rel)) => (crc, len, rel),
+            _ => {
+                return Err(manifest_error(format!(
+                    "manifest file {} contains a malformed entry: {:?}",
+                    manifest_path.display(),
+                    line
+                )));
+            }
+        };
+
+        let path = dir.join(rel);
+        let mut file = match fs::File::open(&path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Err(manifest_error(format!(
+                    "file {} is listed in the manifest but missing",
+                    path.display()
+                )));
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let actual_len = file.metadata()?.len();
+        if actual_len < len {
+            return Err(manifest_error(format!(
+                "file {} is truncated: expected at least {} bytes, found {}",
+                path.display(),
+                len,
+                actual_len
+            )));
+        }
+
+        // only the first `len` bytes are covered: the file may have
+        // legitimately grown through appends since the manifest was
+        // written.
+        let mut hasher = crc32fast::Hasher::new();
+        let mut remaining = len;
+        let mut buf = [0; 8192];
+        while remaining > 0 {
+            let max = usize::try_from(remaining.min(8192)).unwrap();
+            let n = file.read(&mut buf[..max])?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buf[..n]);
+            remaining -= n as u64;
+        }
+
+        if hasher.finalize() != crc {
+            return Err(manifest_error(format!(
+                "file {} failed checksum verification",
+                path.display()
+            )));
+        }
+    }
+
+    Ok(())
+}
@@ -36,7 +36,7 @@ impl Meta {
         self.inner
             .iter()
             .map(|(k, _pid)| {
-                k.len() as u64 + std::mem::size_of::<PageId>() as u64
+                k.len() as u64 + size_of::<PageId>() as u64
             })
             .sum()
     }
@@ -60,18 +60,41 @@ where
         match context.pagecache.meta_pid_for_name(&name, guard) {
             Ok(root_id) => {
                 assert_ne!(root_id, 0);
-                return Ok(Tree(Arc::new(TreeInner {
+                let tree = Tree(Arc::new(TreeInner {
                     tree_id: name,
                     context: context.clone(),
                     subscribers: Subscribers::default(),
                     root: AtomicU64::new(root_id),
                     merge_operator: RwLock::new(None),
-                })));
+                    indexes: RwLock::new(Vec::new()),
+                    pre_commit_validators: RwLock::new(Vec::new()),
+                    post_commit_callbacks: RwLock::new(Vec::new()),
+                    foreign_keys_out: RwLock::new(Vec::new()),
+                    foreign_keys_in: RwLock::new(Vec::new()),
+                    soft_delete: RwLock::new(None),
+                    audit: RwLock::new(None),
+                    changefeed_log: RwLock::new(None),
+                    idempotency: RwLock::new(None),
+                    versioning: RwLock::new(None),
+                    ttl: RwLock::new(None),
+                    mutation_count: AtomicU64::new(0),
+                    snapshots: RwLock::new(Vec::new()),
+                    split_threshold_override: AtomicU64::new(0),
+                    merge_threshold_override: AtomicU64::new(0),
+                }));
+                tree.attach_persisted_ttl(guard)?;
+                return Ok(tree);
             }
             Err(Error::CollectionNotFound(_)) => {}
             Err(other) => return Err(other),
         }
 
+        // the tree does not exist yet, and creating it is a
+        // mutation
+        if context.read_only {
+            return Err(Error::ReadOnly);
+        }
+
         // set up empty leaf
         let mut leaf = Node::new(&[], None, 0, true, None, &[]);
         leaf.is_index = false;
@@ -118,6 +141,21 @@ where
             context: context.clone(),
             root: AtomicU64::new(root_id),
             merge_operator: RwLock::new(None),
+            indexes: RwLock::new(Vec::new()),
+            pre_commit_validators: RwLock::new(Vec::new()),
+            post_commit_callbacks: RwLock::new(Vec::new()),
+            foreign_keys_out: RwLock::new(Vec::new()),
+            foreign_keys_in: RwLock::new(Vec::new()),
+            soft_delete: RwLock::new(None),
+            audit: RwLock::new(None),
+            changefeed_log: RwLock::new(None),
+            idempotency: RwLock::new(None),
+            versioning: RwLock::new(None),
+            ttl: RwLock::new(None),
+            mutation_count: AtomicU64::new(0),
+            snapshots: RwLock::new(Vec::new()),
+            split_threshold_override: AtomicU64::new(0),
+            merge_threshold_override: AtomicU64::new(0),
         })));
     }
 }
@@ -10,12 +10,28 @@ use std::{
     mem::{align_of, size_of},
     num::NonZeroU64,
     ops::{Bound, Deref, DerefMut},
+    sync::atomic::Ordering::Relaxed,
 };
 
-use crate::{varint, IVec, Link};
+use crate::{varint, AtomicU64, IVec, Link};
 
 const ALIGNMENT: usize = align_of::<Header>();
 
+// Cumulative counters for node buffer allocations. Node buffers are
+// allocated at exactly the size computed during construction, so the
+// byte counter below is also the number of bytes requested - there is
+// no spare-capacity overhead like a `Vec`-backed representation
+// would carry.
+static NODE_BUFFER_ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static NODE_BUFFER_ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+pub(crate) fn allocation_stats() -> (u64, u64) {
+    (
+        NODE_BUFFER_ALLOCATIONS.load(Relaxed),
+        NODE_BUFFER_ALLOCATED_BYTES.load(Relaxed),
+    )
+}
+
 macro_rules! tf {
     ($e:expr) => {
         usize::try_from($e).unwrap()
@@ -29,6 +45,15 @@ macro_rules! tf {
 pub(crate) fn uninitialized_node(len: usize) -> Node {
     let layout = Layout::from_size_align(len, ALIGNMENT).unwrap();
 
+    NODE_BUFFER_ALLOCATIONS.fetch_add(1, Relaxed);
+    NODE_BUFFER_ALLOCATED_BYTES.fetch_add(len as u64, Relaxed);
+
+    #[cfg(feature = "alloc_stats")]
+    crate::alloc_stats::record(
+        crate::alloc_stats::AllocationKind::NodeBuffer,
+        len,
+    );
+
     unsafe {
         let ptr = alloc_zeroed(layout);
         Node { ptr, len }
@@ -1317,8 +1342,62 @@ impl Node {
         ret
     }
 
-    pub(crate) fn should_split(&self) -> bool {
-        let size_check = if cfg!(any(test, feature = "lock_free_delays")) {
+    /// Rebuilds this leaf node with every key covered by the given
+    /// full-key bounds removed, returning the replacement along with
+    /// the removed pairs (keys fully decoded) so that the caller can
+    /// perform subscriber and preimage bookkeeping for them.
+    pub(crate) fn remove_range(
+        &self,
+        lo: &Bound<IVec>,
+        hi: &Bound<IVec>,
+    ) -> (Node, Vec<(IVec, IVec)>) {
+        assert!(!self.is_index);
+        assert!(!self.merging);
+        assert!(self.merging_child.is_none());
+
+        let mut retained: Vec<(&[u8], &[u8])> = Vec::new();
+        let mut removed = Vec::new();
+
+        for (k, v) in self.iter() {
+            let decoded = self.prefix_decode(k);
+            let above_lo = match lo {
+                Bound::Included(start) => decoded >= *start,
+                Bound::Excluded(start) => decoded > *start,
+                Bound::Unbounded => true,
+            };
+            let below_hi = match hi {
+                Bound::Included(end) => decoded <= *end,
+                Bound::Excluded(end) => decoded < *end,
+                Bound::Unbounded => true,
+            };
+            if above_lo && below_hi {
+                removed.push((decoded, IVec::from(v)));
+            } else {
+                retained.push((k, v));
+            }
+        }
+
+        let mut ret = Node::new(
+            self.lo(),
+            self.hi(),
+            self.prefix_len,
+            self.is_index,
+            self.next,
+            &retained,
+        );
+
+        ret.rewrite_generations = self.rewrite_generations;
+
+        testing_assert!(ret.is_sorted());
+
+        (ret, removed)
+    }
+
+    pub(crate) fn should_split(&self, split_threshold: Option<u64>) -> bool {
+        let size_check = if let Some(threshold) = split_threshold {
+            self.len > usize::try_from(threshold).unwrap_or(usize::max_value())
+                && self.children() > 1
+        } else if cfg!(any(test, feature = "lock_free_delays")) {
             self.children() > 4
         /*
         } else if self.is_index {
@@ -1337,7 +1416,8 @@ impl Node {
             };
             */
             let threshold = 1024 - crate::MAX_MSG_HEADER_LEN;
-            self.len > threshold && self.children() > 1
+            self.len > usize::try_from(threshold).unwrap_or(usize::max_value())
+                && self.children() > 1
         };
 
         let safety_checks = self.merging_child.is_none() && !self.merging;
@@ -1345,8 +1425,10 @@ impl Node {
         safety_checks && size_check
     }
 
-    pub(crate) fn should_merge(&self) -> bool {
-        let size_check = if cfg!(any(test, feature = "lock_free_delays")) {
+    pub(crate) fn should_merge(&self, merge_threshold: Option<u64>) -> bool {
+        let size_check = if let Some(threshold) = merge_threshold {
+            self.len < usize::try_from(threshold).unwrap_or(usize::max_value())
+        } else if cfg!(any(test, feature = "lock_free_delays")) {
             self.children() < 2
         /*
         } else if self.is_index {
@@ -1363,7 +1445,7 @@ impl Node {
             };
             */
             let threshold = 256 - crate::MAX_MSG_HEADER_LEN;
-            self.len < threshold
+            self.len < usize::try_from(threshold).unwrap_or(usize::max_value())
         };
 
         let safety_checks = self.merging_child.is_none()
@@ -1592,7 +1674,7 @@ impl Node {
     pub(crate) fn node_kv_pair<'a>(
         &'a self,
         key: &'a [u8],
-    ) -> (&'a [u8], Option<&[u8]>, usize) {
+    ) -> (&'a [u8], Option<&'a [u8]>, usize) {
         assert!(key >= self.lo());
         if let Some(hi) = self.hi() {
             assert!(key < hi);
@@ -0,0 +1,183 @@
+//! Export of sled's internal metrics and background-operation
+//! spans through the OpenTelemetry API.
+//!
+//! When the `otel` feature is enabled, [`register_metrics`] wires
+//! observers for sled's process-wide internal counters into the
+//! globally installed meter provider, and the periodic flusher and
+//! scrubber record a span for each pass through the globally
+//! installed tracer provider, so that sled's background activity
+//! shows up in tracing backends alongside application spans.
+//!
+//! Install your meter and tracer providers with
+//! `opentelemetry::global` before calling [`register_metrics`] or
+//! opening a database. Counts derived from latency histograms are
+//! only populated when the `metrics` feature is also enabled.
+
+use std::sync::atomic::Ordering::Acquire;
+use std::sync::Once;
+
+use crossbeam_utils::CachePadded;
+use opentelemetry::{
+    global,
+    metrics::ObserverResult,
+    trace::{Span, Tracer},
+    KeyValue,
+};
+
+use crate::{Histogram, M};
+
+static REGISTER: Once = Once::new();
+
+/// Registers observers for sled's internal metrics with the
+/// globally installed meter provider. The metrics are process-wide
+/// and cover all databases open in this process. Calling this more
+/// than once has no additional effect.
+pub fn register_metrics() {
+    REGISTER.call_once(|| {
+        let meter = global::meter("sled");
+
+        let _ = meter
+            .u64_sum_observer(
+                "sled.tree.operations",
+                |result: ObserverResult<u64>| {
+                    observe_histogram_count(&result, &M.tree_get, "get");
+                    observe_histogram_count(&result, &M.tree_set, "set");
+                    observe_histogram_count(&result, &M.tree_del, "del");
+                    observe_histogram_count(&result, &M.tree_cas, "cas");
+                    observe_histogram_count(&result, &M.tree_merge, "merge");
+                    observe_histogram_count(&result, &M.tree_scan, "scan");
+                    observe_histogram_count(
+                        &result,
+                        &M.tree_reverse_scan,
+                        "reverse_scan",
+                    );
+                },
+            )
+            .with_description(
+                "completed tree operations by kind, requires the \
+                 metrics feature",
+            )
+            .init();
+
+        let _ = meter
+            .u64_sum_observer(
+                "sled.tree.splits",
+                |result: ObserverResult<u64>| {
+                    observe_counter(
+                        &result,
+                        &M.tree_child_split_attempt,
+                        &[
+                            KeyValue::new("level", "child"),
+                            KeyValue::new("stage", "attempt"),
+                        ],
+                    );
+                    observe_counter(
+                        &result,
+                        &M.tree_child_split_success,
+                        &[
+                            KeyValue::new("level", "child"),
+                            KeyValue::new("stage", "success"),
+                        ],
+                    );
+                    observe_counter(
+                        &result,
+                        &M.tree_parent_split_attempt,
+                        &[
+                            KeyValue::new("level", "parent"),
+                            KeyValue::new("stage", "attempt"),
+                        ],
+                    );
+                    observe_counter(
+                        &result,
+                        &M.tree_parent_split_success,
+                        &[
+                            KeyValue::new("level", "parent"),
+                            KeyValue::new("stage", "success"),
+                        ],
+                    );
+                    observe_counter(
+                        &result,
+                        &M.tree_root_split_attempt,
+                        &[
+                            KeyValue::new("level", "root"),
+                            KeyValue::new("stage", "attempt"),
+                        ],
+                    );
+                    observe_counter(
+                        &result,
+                        &M.tree_root_split_success,
+                        &[
+                            KeyValue::new("level", "root"),
+                            KeyValue::new("stage", "success"),
+                        ],
+                    );
+                },
+            )
+            .with_description("node split attempts and successes")
+            .init();
+
+        let _ = meter
+            .u64_sum_observer(
+                "sled.log.reservations",
+                |result: ObserverResult<u64>| {
+                    observe_counter(
+                        &result,
+                        &M.log_reservation_attempts,
+                        &[KeyValue::new("stage", "attempt")],
+                    );
+                    observe_counter(
+                        &result,
+                        &M.log_reservations,
+                        &[KeyValue::new("stage", "success")],
+                    );
+                },
+            )
+            .with_description("log buffer reservation attempts and successes")
+            .init();
+
+        let _ = meter
+            .u64_sum_observer(
+                "sled.log.written_bytes",
+                |result: ObserverResult<u64>| {
+                    result.observe(M.written_bytes.sum() as u64, &[]);
+                },
+            )
+            .with_description(
+                "bytes written to the log, requires the metrics feature",
+            )
+            .init();
+
+        let _ = meter
+            .u64_sum_observer(
+                "sled.tree.loops",
+                |result: ObserverResult<u64>| {
+                    observe_counter(&result, &M.tree_loops, &[]);
+                },
+            )
+            .with_description("tree operation retries due to contention")
+            .init();
+    });
+}
+
+fn observe_histogram_count(
+    result: &ObserverResult<u64>,
+    histogram: &Histogram,
+    op: &'static str,
+) {
+    result.observe(histogram.count() as u64, &[KeyValue::new("op", op)]);
+}
+
+fn observe_counter(
+    result: &ObserverResult<u64>,
+    counter: &CachePadded<std::sync::atomic::AtomicUsize>,
+    labels: &[KeyValue],
+) {
+    result.observe(counter.load(Acquire) as u64, labels);
+}
+
+/// Starts a span for a background operation through the globally
+/// installed tracer provider. The span ends when the returned
+/// guard is dropped.
+pub(crate) fn background_span(name: &'static str) -> impl Span {
+    global::tracer("sled").start(name)
+}
@@ -0,0 +1,146 @@
+//! A second cache tier that holds recently evicted pages in
+//! compressed form in memory, so that workloads slightly larger
+//! than the first-tier cache can trade a decompression for a disk
+//! read. Pages are keyed by the log state they were materialized
+//! from, so a cached copy is only served while the page has not
+//! been linked to or replaced since eviction.
+
+use std::{collections::VecDeque, convert::TryFrom};
+
+use parking_lot::Mutex;
+
+use crate::pagecache::{Lsn, PageId};
+use crate::FastMap8;
+
+const N_SHARDS: usize = 8;
+
+struct CachedPage {
+    last_lsn: Lsn,
+    frags: usize,
+    buf: Vec<u8>,
+}
+
+#[derive(Default)]
+struct Shard {
+    pages: FastMap8<PageId, CachedPage>,
+    order: VecDeque<PageId>,
+    size: u64,
+}
+
+/// A sharded, budget-bounded map from evicted pids to the
+/// compressed serialization of their materialized state.
+pub(crate) struct CompressedCache {
+    shards: Vec<Mutex<Shard>>,
+    shard_capacity: u64,
+}
+
+impl CompressedCache {
+    pub(crate) fn new(capacity: u64) -> CompressedCache {
+        let shard_capacity = capacity / N_SHARDS as u64;
+        let mut shards = Vec::with_capacity(N_SHARDS);
+        shards.resize_with(N_SHARDS, || Mutex::new(Shard::default()));
+        CompressedCache { shards, shard_capacity }
+    }
+
+    /// Caches the serialized state of a page that is about to be
+    /// evicted, compressing it and making room by dropping the
+    /// oldest cached pages if the budget is exceeded.
+    pub(crate) fn insert(
+        &self,
+        pid: PageId,
+        last_lsn: Lsn,
+        frags: usize,
+        serialized: &[u8],
+    ) {
+        let buf = compress(serialized);
+        let len = buf.len() as u64;
+        if len > self.shard_capacity {
+            return;
+        }
+
+        let mut shard = self.shards[safe_usize(pid) % N_SHARDS].lock();
+
+        if let Some(old) = shard
+            .pages
+            .insert(pid, CachedPage { last_lsn, frags, buf })
+        {
+            shard.size -= old.buf.len() as u64;
+        } else {
+            shard.order.push_back(pid);
+        }
+        shard.size += len;
+
+        while shard.size > self.shard_capacity {
+            let oldest = if let Some(oldest) = shard.order.pop_front() {
+                oldest
+            } else {
+                break;
+            };
+            if oldest == pid {
+                // reinsert the newest entry rather than
+                // evicting it immediately
+                shard.order.push_back(oldest);
+                continue;
+            }
+            if let Some(old) = shard.pages.remove(&oldest) {
+                shard.size -= old.buf.len() as u64;
+            }
+        }
+    }
+
+    /// Removes and returns the cached serialization of a page if it
+    /// still corresponds to the expected log state, identified by
+    /// the lsn of the page's most recent fragment and the fragment
+    /// count.
+    pub(crate) fn take(
+        &self,
+        pid: PageId,
+        last_lsn: Lsn,
+        frags: usize,
+    ) -> Option<Vec<u8>> {
+        let mut shard = self.shards[safe_usize(pid) % N_SHARDS].lock();
+
+        let valid = if let Some(cached) = shard.pages.get(&pid) {
+            cached.last_lsn == last_lsn && cached.frags == frags
+        } else {
+            false
+        };
+
+        if !valid {
+            return None;
+        }
+
+        let cached = shard.pages.remove(&pid).unwrap();
+        shard.size -= cached.buf.len() as u64;
+        shard.order.retain(|other| *other != pid);
+
+        Some(decompress(cached.buf))
+    }
+}
+
+fn compress(buf: &[u8]) -> Vec<u8> {
+    #[cfg(feature = "compression")]
+    {
+        return zstd::stream::encode_all(buf, 1)
+            .expect("failed to compress page for the compressed cache");
+    }
+
+    #[cfg(not(feature = "compression"))]
+    buf.to_vec()
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn decompress(buf: Vec<u8>) -> Vec<u8> {
+    #[cfg(feature = "compression")]
+    {
+        return zstd::stream::decode_all(&buf[..])
+            .expect("failed to decompress page from the compressed cache");
+    }
+
+    #[cfg(not(feature = "compression"))]
+    buf
+}
+
+fn safe_usize(value: PageId) -> usize {
+    usize::try_from(value).unwrap()
+}
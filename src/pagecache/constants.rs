@@ -8,8 +8,13 @@ use super::*;
 /// Log messages have a header that might eb up to this length.
 pub const MAX_MSG_HEADER_LEN: usize = 32;
 
+// crc: u32 4
+// lsn: u64 8
+// max stable lsn: u64 8
+// compression codec id: u8 1
+// reserved: 3
 /// Log segments have a header of this length.
-pub const SEG_HEADER_LEN: usize = 20;
+pub const SEG_HEADER_LEN: usize = 24;
 
 /// During testing, this should never be exceeded.
 // TODO drop this to 3 over time
@@ -21,7 +26,6 @@ pub(crate) const COUNTER_PID: PageId = 1;
 pub(crate) const BATCH_MANIFEST_PID: PageId = PageId::max_value() - 666;
 
 pub(crate) const PAGE_CONSOLIDATION_THRESHOLD: usize = 10;
-pub(crate) const SEGMENT_CLEANUP_THRESHOLD: usize = 50;
 
 // Allows for around 1 trillion items to be stored
 // 2^37 * (assuming 50% node fill, 8 items per leaf)
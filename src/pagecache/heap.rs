@@ -5,19 +5,20 @@ use std::{
     fmt::{self, Debug},
     fs::File,
     mem::{transmute, MaybeUninit},
-    path::Path,
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicU32, Ordering::Acquire},
+        atomic::{AtomicBool, AtomicU32, Ordering::Acquire},
         Arc,
     },
 };
 
 use crossbeam_epoch::pin;
+use parking_lot::Mutex;
 
 use crate::{
     pagecache::{pread_exact, pwrite_all, MessageKind},
     stack::Stack,
-    Error, Lsn, Result,
+    Config, Error, Lsn, Result,
 };
 
 #[cfg(not(feature = "testing"))]
@@ -101,10 +102,59 @@ fn size_to_slab_id(size: u64) -> SlabId {
     u8::try_from(rebased_size.trailing_zeros()).unwrap()
 }
 
+// a least-recently-used cache of open slab file descriptors,
+// shared by all slabs of a heap, so that deployments with tight
+// fd ulimits can bound how many files a database holds open.
+// files are reopened on demand after eviction; an `Arc` handed
+// out for an in-flight operation keeps its file alive even if
+// the cache moves on.
+#[derive(Debug)]
+struct FdCache {
+    directory: PathBuf,
+    capacity: usize,
+    // most recently used last
+    open: Mutex<Vec<(u8, Arc<File>)>>,
+}
+
+impl FdCache {
+    fn new(directory: PathBuf, max_open_files: usize) -> FdCache {
+        let capacity = if max_open_files == 0 {
+            usize::max_value()
+        } else {
+            std::cmp::max(1, max_open_files)
+        };
+        FdCache { directory, capacity, open: Mutex::new(Vec::new()) }
+    }
+
+    fn get(&self, slab_id: u8) -> Result<Arc<File>> {
+        let mut open = self.open.lock();
+        if let Some(pos) = open.iter().position(|(id, _)| *id == slab_id) {
+            let entry = open.remove(pos);
+            let file = entry.1.clone();
+            open.push(entry);
+            return Ok(file);
+        }
+
+        let mut options = std::fs::OpenOptions::new();
+        options.create(true);
+        options.read(true);
+        options.write(true);
+
+        let file = Arc::new(
+            options.open(self.directory.join(format!("{:02}", slab_id)))?,
+        );
+        open.push((slab_id, file.clone()));
+        while open.len() > self.capacity {
+            let _ = open.remove(0);
+        }
+        Ok(file)
+    }
+}
+
 pub(crate) struct Reservation {
     slab_free: Arc<Stack<u32>>,
     completed: bool,
-    file: File,
+    file: Arc<File>,
     pub heap_id: HeapId,
     from_tip: bool,
 }
@@ -186,11 +236,24 @@ pub(crate) struct Heap {
 }
 
 impl Heap {
-    pub fn start<P: AsRef<Path>>(p: P) -> Result<Heap> {
+    pub fn start<P: AsRef<Path>>(
+        p: P,
+        max_open_files: usize,
+        hole_punching: bool,
+    ) -> Result<Heap> {
         let mut slabs: [MaybeUninit<Slab>; 32] = unsafe { std::mem::zeroed() };
 
+        let fds = Arc::new(FdCache::new(
+            p.as_ref().to_path_buf(),
+            max_open_files,
+        ));
+
+        // seeded from the capability probe at open, and
+        // additionally disabled at runtime if a punch ever fails
+        let hole_punching = Arc::new(AtomicBool::new(hole_punching));
+
         for slab_id in 0..32 {
-            let slab = Slab::start(&p, slab_id)?;
+            let slab = Slab::start(&fds, slab_id, &hole_punching)?;
             slabs[slab_id as usize] = MaybeUninit::new(slab);
         }
 
@@ -204,7 +267,7 @@ impl Heap {
             bitmaps.push(vec![0_u64; 1 + (tip / 64)]);
         }
 
-        for page_state in &snapshot.pt {
+        for page_state in snapshot.pt.iter() {
             for heap_id in page_state.heap_ids() {
                 let (slab_id, idx, _lsn) = heap_id.decompose();
 
@@ -237,15 +300,11 @@ impl Heap {
     pub fn read(
         &self,
         heap_id: HeapId,
-        use_compression: bool,
+        config: &Config,
     ) -> Result<(MessageKind, Vec<u8>)> {
         log::trace!("Heap::read({:?})", heap_id);
         let (slab_id, slab_idx, original_lsn) = heap_id.decompose();
-        self.slabs[slab_id as usize].read(
-            slab_idx,
-            original_lsn,
-            use_compression,
-        )
+        self.slabs[slab_id as usize].read(slab_idx, original_lsn, config)
     }
 
     pub fn free(&self, heap_id: HeapId) {
@@ -254,35 +313,39 @@ impl Heap {
         self.slabs[slab_id as usize].free(slab_idx)
     }
 
-    pub fn reserve(&self, size: u64, original_lsn: Lsn) -> Reservation {
+    pub fn reserve(
+        &self,
+        size: u64,
+        original_lsn: Lsn,
+    ) -> Result<Reservation> {
         assert!(size < 1 << 48);
         let slab_id = size_to_slab_id(size);
-        let ret = self.slabs[slab_id as usize].reserve(original_lsn);
+        let ret = self.slabs[slab_id as usize].reserve(original_lsn)?;
         log::trace!("Heap::reserve({}) -> {:?}", size, ret.heap_id);
-        ret
+        Ok(ret)
     }
 }
 
 #[derive(Debug)]
 struct Slab {
-    file: File,
+    fds: Arc<FdCache>,
     slab_id: u8,
     tip: AtomicU32,
     free: Arc<Stack<u32>>,
+    // shared by all slabs of a heap
+    hole_punching: Arc<AtomicBool>,
 }
 
 impl Slab {
-    pub fn start<P: AsRef<Path>>(directory: P, slab_id: u8) -> Result<Slab> {
+    pub fn start(
+        fds: &Arc<FdCache>,
+        slab_id: u8,
+        hole_punching: &Arc<AtomicBool>,
+    ) -> Result<Slab> {
         let bs = slab_id_to_size(slab_id);
         let free = Arc::new(Stack::default());
 
-        let mut options = std::fs::OpenOptions::new();
-        options.create(true);
-        options.read(true);
-        options.write(true);
-
-        let file =
-            options.open(directory.as_ref().join(format!("{:02}", slab_id)))?;
+        let file = fds.get(slab_id)?;
         let len = file.metadata()?.len();
         let max_idx = len / bs;
         log::trace!(
@@ -293,14 +356,20 @@ impl Slab {
         );
         let tip = AtomicU32::new(u32::try_from(max_idx).unwrap());
 
-        Ok(Slab { file, slab_id, tip, free })
+        Ok(Slab {
+            fds: fds.clone(),
+            slab_id,
+            tip,
+            free,
+            hole_punching: hole_punching.clone(),
+        })
     }
 
     fn read(
         &self,
         slab_idx: SlabIdx,
         original_lsn: Lsn,
-        use_compression: bool,
+        config: &Config,
     ) -> Result<(MessageKind, Vec<u8>)> {
         let bs = slab_id_to_size(self.slab_id);
         let offset = u64::from(slab_idx) * bs;
@@ -309,7 +378,8 @@ impl Slab {
 
         let mut heap_buf = vec![0; usize::try_from(bs).unwrap()];
 
-        pread_exact(&self.file, &mut heap_buf, offset)?;
+        let file = self.fds.get(self.slab_id)?;
+        pread_exact(&*file, &mut heap_buf, offset)?;
 
         let stored_crc =
             u32::from_le_bytes(heap_buf[1..5].as_ref().try_into().unwrap());
@@ -332,8 +402,8 @@ impl Slab {
                 return Err(Error::corruption(None));
             }
             let buf = heap_buf[13..].to_vec();
-            let buf = if use_compression {
-                crate::pagecache::decompress(buf)
+            let buf = if config.use_compression {
+                crate::pagecache::decompress(buf, config)?
             } else {
                 buf
             };
@@ -348,7 +418,7 @@ impl Slab {
         }
     }
 
-    fn reserve(&self, original_lsn: Lsn) -> Reservation {
+    fn reserve(&self, original_lsn: Lsn) -> Result<Reservation> {
         let (idx, from_tip) = if let Some(idx) = self.free.pop(&pin()) {
             log::trace!(
                 "reusing heap index {} in slab for sizes of {}",
@@ -372,13 +442,13 @@ impl Slab {
 
         let heap_id = HeapId::compose(self.slab_id, idx, original_lsn);
 
-        Reservation {
+        Ok(Reservation {
             slab_free: self.free.clone(),
             completed: false,
-            file: self.file.try_clone().unwrap(),
+            file: self.fds.get(self.slab_id)?,
             from_tip,
             heap_id,
-        }
+        })
     }
 
     fn free(&self, idx: u32) {
@@ -390,20 +460,25 @@ impl Slab {
         #[cfg(all(target_os = "linux", not(miri)))]
         {
             use std::{
-                os::unix::io::AsRawFd,
-                sync::atomic::{AtomicBool, Ordering::Relaxed},
+                os::unix::io::AsRawFd, sync::atomic::Ordering::Relaxed,
             };
 
             use libc::{fallocate, FALLOC_FL_KEEP_SIZE, FALLOC_FL_PUNCH_HOLE};
 
-            static HOLE_PUNCHING_ENABLED: AtomicBool = AtomicBool::new(true);
             const MODE: i32 = FALLOC_FL_KEEP_SIZE | FALLOC_FL_PUNCH_HOLE;
 
-            if HOLE_PUNCHING_ENABLED.load(Relaxed) {
+            if self.hole_punching.load(Relaxed) {
                 let bs = i64::try_from(slab_id_to_size(self.slab_id)).unwrap();
                 let offset = i64::from(idx) * bs;
 
-                let fd = self.file.as_raw_fd();
+                let file = if let Ok(file) = self.fds.get(self.slab_id) {
+                    file
+                } else {
+                    // hole punching is a space optimization, not
+                    // a correctness requirement
+                    return;
+                };
+                let fd = file.as_raw_fd();
 
                 let ret = unsafe {
                     fallocate(
@@ -422,7 +497,7 @@ impl Slab {
                         "failed to punch hole in heap file: {:?}. disabling hole punching",
                         err
                     );
-                    HOLE_PUNCHING_ENABLED.store(false, Relaxed);
+                    self.hole_punching.store(false, Relaxed);
                 }
             }
         }
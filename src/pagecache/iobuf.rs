@@ -4,7 +4,7 @@ use std::{
     sync::atomic::AtomicPtr,
 };
 
-use crate::{pagecache::*, *};
+use crate::{pagecache::Snapshot, pagecache::*, *};
 
 macro_rules! io_fail {
     ($self:expr, $e:expr) => {
@@ -30,16 +30,44 @@ macro_rules! io_fail {
 struct AlignedBuf(*mut u8, usize);
 
 impl AlignedBuf {
-    fn new(len: usize) -> AlignedBuf {
+    fn new(len: usize, huge_pages: bool) -> AlignedBuf {
         let layout = Layout::from_size_align(len, 8192).unwrap();
         let ptr = unsafe { alloc(layout) };
 
         assert!(!ptr.is_null(), "failed to allocate critical IO buffer");
 
+        if huge_pages {
+            madvise_huge_pages(ptr, len);
+        }
+
         AlignedBuf(ptr, len)
     }
 }
 
+// hints to the kernel that a large allocation should be backed
+// by transparent huge pages, cutting TLB pressure for big write
+// buffers. best-effort: failure only costs the optimization.
+#[allow(unused_variables)]
+fn madvise_huge_pages(ptr: *mut u8, len: usize) {
+    #[cfg(target_os = "linux")]
+    {
+        let ret = unsafe {
+            libc::madvise(
+                ptr as *mut libc::c_void,
+                len,
+                libc::MADV_HUGEPAGE,
+            )
+        };
+        if ret != 0 {
+            debug!(
+                "unable to advise the kernel to use huge pages \
+                 for an IO buffer: {:?}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+}
+
 impl Drop for AlignedBuf {
     fn drop(&mut self) {
         let layout = Layout::from_size_align(self.1, 8192).unwrap();
@@ -114,6 +142,8 @@ impl IoBuf {
         last: Header,
         lsn: Lsn,
         max_stable_lsn: Lsn,
+        codec: u8,
+        checksum_id: u8,
     ) {
         debug!("storing lsn {} in beginning of buffer", lsn);
         assert!(self.capacity >= SEG_HEADER_LEN);
@@ -122,7 +152,13 @@ impl IoBuf {
 
         self.lsn = lsn;
 
-        let header = SegmentHeader { lsn, max_stable_lsn, ok: true };
+        let header = SegmentHeader {
+            lsn,
+            max_stable_lsn,
+            codec,
+            checksum_id,
+            ok: true,
+        };
         let header_bytes: [u8; SEG_HEADER_LEN] = header.into();
 
         #[allow(unsafe_code)]
@@ -311,6 +347,10 @@ pub(crate) struct IoBufs {
     pub segment_accountant: Mutex<SegmentAccountant>,
     pub segment_cleaner: SegmentCleaner,
     deferred_segment_ops: stack::Stack<SegmentOp>,
+
+    // the cumulative number of bytes written to the log by this
+    // instance, for lifetime statistics.
+    pub(crate) bytes_written: AtomicU64,
     #[cfg(feature = "io_uring")]
     pub submission_mutex: Mutex<()>,
     #[cfg(feature = "io_uring")]
@@ -390,7 +430,10 @@ impl IoBufs {
         let base = assert_usize(next_lid % segment_size as LogOffset);
 
         let mut iobuf = IoBuf {
-            buf: Arc::new(UnsafeCell::new(AlignedBuf::new(segment_size))),
+            buf: Arc::new(UnsafeCell::new(AlignedBuf::new(
+                segment_size,
+                config.huge_pages,
+            ))),
             header: CachePadded::new(AtomicU64::new(0)),
             base,
             offset: next_lid,
@@ -401,7 +444,13 @@ impl IoBufs {
         };
 
         if snapshot.active_segment.is_none() {
-            iobuf.store_segment_header(0, next_lsn, stable);
+            iobuf.store_segment_header(
+                0,
+                next_lsn,
+                stable,
+                config.compression_codec_id(),
+                config.checksum_algorithm.id(),
+            );
         }
 
         Ok(IoBufs {
@@ -418,6 +467,7 @@ impl IoBufs {
             segment_accountant: Mutex::new(segment_accountant),
             segment_cleaner,
             deferred_segment_ops: stack::Stack::default(),
+            bytes_written: AtomicU64::new(0),
             #[cfg(feature = "io_uring")]
             submission_mutex: Mutex::new(()),
             #[cfg(feature = "io_uring")]
@@ -527,7 +577,7 @@ impl IoBufs {
         item: &T,
         header: MessageHeader,
         mut out_buf: &mut [u8],
-        heap_reservation: Option<super::heap::Reservation>,
+        heap_reservation: Option<heap::Reservation>,
     ) -> Result<()> {
         // we create this double ref to allow scooting
         // the slice forward without doing anything
@@ -543,7 +593,7 @@ impl IoBufs {
             io_fail!(self, "blob blob write");
             let mut heap_buf = vec![
                 0;
-                usize::try_from(super::heap::slab_size(
+                usize::try_from(heap::slab_size(
                     13 + item.serialized_size()
                 ))
                 .unwrap()
@@ -671,7 +721,8 @@ impl IoBufs {
             }
 
             // this as to stay aligned with the hashing
-            let crc32_arr = u32_to_arr(calculate_message_crc32(
+            let crc32_arr = u32_to_arr(checksum::hash_message(
+                self.config.checksum_algorithm,
                 &header_bytes,
                 &padding_bytes[..pad_len],
             ));
@@ -682,7 +733,7 @@ impl IoBufs {
                     crc32_arr.as_ptr(),
                     // the crc32 is the first part of the buffer
                     data.as_mut_ptr(),
-                    std::mem::size_of::<u32>(),
+                    size_of::<u32>(),
                 );
             }
         } else if maxed {
@@ -760,9 +811,48 @@ impl IoBufs {
             let f = &self.config.file;
             pwrite_all(f, data, log_offset)?;
             if !self.config.temporary {
-                if iobuf.from_tip {
+                if cfg!(windows) && self.config.windows_write_through {
+                    // the log was opened with FILE_FLAG_WRITE_THROUGH,
+                    // which pushes each write and its metadata to
+                    // stable storage before completion, so a
+                    // separate FlushFileBuffers would be redundant
+                } else if cfg!(target_os = "macos")
+                    && self.config.macos_barrier_fsync
+                {
+                    #[cfg(target_os = "macos")]
+                    {
+                        use std::os::unix::io::AsRawFd;
+
+                        // not exposed by the libc crate yet
+                        const F_BARRIERFSYNC: libc::c_int = 85;
+
+                        // routine flushes only order writes through
+                        // the drive cache; sealing a segment
+                        // escalates to F_FULLFSYNC so that every
+                        // completed segment is truly durable
+                        let cmd = if iobuf.from_tip {
+                            libc::F_FULLFSYNC
+                        } else {
+                            F_BARRIERFSYNC
+                        };
+                        let ret = unsafe { libc::fcntl(f.as_raw_fd(), cmd) };
+                        if ret < 0 {
+                            let err = std::io::Error::last_os_error();
+                            if err.raw_os_error() == Some(libc::ENOTSUP) {
+                                // the filesystem does not support
+                                // barrier fsync, so pay for the
+                                // full flush instead
+                                f.sync_all()?;
+                            } else {
+                                return Err(err.into());
+                            }
+                        }
+                    }
+                } else if iobuf.from_tip {
                     f.sync_all()?;
-                } else if cfg!(not(target_os = "linux")) {
+                } else if cfg!(not(target_os = "linux"))
+                    || !self.config.storage_capabilities.sync_file_range
+                {
                     f.sync_data()?;
                 } else {
                     #[allow(clippy::assertions_on_constants)]
@@ -795,8 +885,23 @@ impl IoBufs {
                 }
             }
         }
+        if let Some(mirror) = &self.config.mirror {
+            // the mirror is written after the primary so that a
+            // crash leaves it at most one write behind, never
+            // ahead of what recovery will find on the primary.
+            pwrite_all(mirror, data, log_offset)?;
+            if !self.config.temporary {
+                if iobuf.from_tip {
+                    mirror.sync_all()?;
+                } else {
+                    mirror.sync_data()?;
+                }
+            }
+        }
         io_fail!(self, "buffer write post");
 
+        self.bytes_written.fetch_add(total_len as u64, SeqCst);
+
         if total_len > 0 {
             let complete_len = if maxed {
                 let lsn_idx = base_lsn / segment_size as Lsn;
@@ -1049,6 +1154,21 @@ pub(in crate::pagecache) fn make_stable_inner(
 /// of bytes written during this call.
 pub(in crate::pagecache) fn flush(iobufs: &Arc<IoBufs>) -> Result<usize> {
     let _cc = concurrency_control::read();
+    flush_unprotected(iobufs)
+}
+
+/// A flush variant for callers that already hold a
+/// concurrency-control guard on this thread, which the
+/// public path would deadlock against.
+pub(in crate::pagecache) fn flush_unprotected(
+    iobufs: &Arc<IoBufs>,
+) -> Result<usize> {
+    if iobufs.config.read_only {
+        // a read-only instance reserves nothing, and its write
+        // buffer points at a log tail owned by the writer
+        // process, so flushing it would stomp the shared file
+        return Ok(0);
+    }
     let max_reserved_lsn = iobufs.max_reserved_lsn.load(Acquire);
     make_stable(iobufs, max_reserved_lsn)
 }
@@ -1159,7 +1279,10 @@ pub(in crate::pagecache) fn maybe_seal_and_write_iobuf(
     // its entire life cycle as soon as we do that.
     let next_iobuf = if maxed {
         let mut next_iobuf = IoBuf {
-            buf: Arc::new(UnsafeCell::new(AlignedBuf::new(segment_size))),
+            buf: Arc::new(UnsafeCell::new(AlignedBuf::new(
+                segment_size,
+                iobufs.config.huge_pages,
+            ))),
             header: CachePadded::new(AtomicU64::new(0)),
             base: 0,
             offset: next_offset,
@@ -1169,7 +1292,13 @@ pub(in crate::pagecache) fn maybe_seal_and_write_iobuf(
             stored_max_stable_lsn: -1,
         };
 
-        next_iobuf.store_segment_header(sealed, next_lsn, iobufs.stable());
+        next_iobuf.store_segment_header(
+            sealed,
+            next_lsn,
+            iobufs.stable(),
+            iobufs.config.compression_codec_id(),
+            iobufs.config.checksum_algorithm.id(),
+        );
 
         next_iobuf
     } else {
@@ -2,7 +2,8 @@ use std::fs::File;
 
 use super::{
     arr_to_lsn, arr_to_u32, assert_usize, bump_atomic_lsn, decompress, header,
-    iobuf, lsn_to_arr, pread_exact, pread_exact_or_eof, roll_iobuf, u32_to_arr,
+    iobuf, lsn_to_arr, pread_exact, pread_exact_or_eof, pwrite_all,
+    roll_iobuf, u32_to_arr,
     Arc, BasedBuf, DiskPtr, HeapId, IoBuf, IoBufs, LogKind, LogOffset, Lsn,
     MessageKind, Reservation, Serialize, Snapshot, BATCH_MANIFEST_PID,
     COUNTER_PID, MAX_MSG_HEADER_LEN, META_PID, SEG_HEADER_LEN,
@@ -36,6 +37,12 @@ impl Log {
         iobuf::flush(&self.iobufs)
     }
 
+    /// A flush variant for callers that already hold a
+    /// concurrency-control guard on this thread.
+    pub(crate) fn flush_unprotected(&self) -> Result<usize> {
+        iobuf::flush_unprotected(&self.iobufs)
+    }
+
     /// Return an iterator over the log, starting with
     /// a specified offset.
     pub fn iter_from(&self, lsn: Lsn) -> super::LogIter {
@@ -58,19 +65,62 @@ impl Log {
         iobuf::make_durable(&self.iobufs, lsn)?;
 
         if ptr.is_inline() {
+            let lid = ptr.lid().unwrap();
             let f = &self.config.file;
-            read_message(
-                &**f,
-                ptr.lid().unwrap(),
-                expected_segment_number,
-                &self.config,
-            )
+
+            fn unreadable(read: &Result<LogRead>) -> bool {
+                matches!(
+                    read,
+                    Err(Error::Io(_))
+                        | Err(Error::Corruption { .. })
+                        | Ok(LogRead::Corrupted)
+                )
+            }
+
+            let mut primary_read =
+                read_message(&**f, lid, expected_segment_number, &self.config);
+
+            // a failed or corrupted primary read can be served
+            // from the mirror, if one is configured
+            if unreadable(&primary_read) {
+                if let Some(mirror) = &self.config.mirror {
+                    warn!(
+                        "primary log read at lid {} failed, \
+                         retrying from the mirror",
+                        lid
+                    );
+                    let mirror_read = read_message(
+                        &**mirror,
+                        lid,
+                        expected_segment_number,
+                        &self.config,
+                    );
+                    if !unreadable(&mirror_read) {
+                        return mirror_read;
+                    }
+                }
+            }
+
+            // failing that, an archive backend can supply the
+            // whole segment, repairing it in place
+            if unreadable(&primary_read)
+                && self.repair_segment_from_archive(lsn, lid)?
+            {
+                primary_read = read_message(
+                    &**f,
+                    lid,
+                    expected_segment_number,
+                    &self.config,
+                );
+            }
+
+            primary_read
         } else {
             // we short-circuit the inline read
             // here because it might not still
             // exist in the inline log.
             let heap_id = ptr.heap_id().unwrap();
-            self.config.heap.read(heap_id, self.config.use_compression).map(
+            self.config.heap.read(heap_id, &self.config).map(
                 |(kind, buf)| {
                     let header = MessageHeader {
                         kind,
@@ -90,6 +140,95 @@ impl Log {
         self.iobufs.stable()
     }
 
+    // fetches the segment holding `lid` from the registered
+    // archive backend if there is one, verifying its header and
+    // rewriting it in place so that the rot is repaired for
+    // subsequent reads as well. returns whether a repair was
+    // applied. only sealed segments are repaired, as the archived
+    // copy of the segment currently being appended to could
+    // clobber bytes written after it was archived.
+    fn repair_segment_from_archive(
+        &self,
+        lsn: Lsn,
+        lid: LogOffset,
+    ) -> Result<bool> {
+        let slot = self.config.segment_archive.archive.lock();
+        let archive = if let Some(archive) = &*slot {
+            archive
+        } else {
+            return Ok(false);
+        };
+
+        let segment_lsn = self.config.normalize(lsn);
+        let segment_base = self.config.normalize(lid);
+        let segment_size = self.config.segment_size;
+
+        if segment_lsn + segment_size as Lsn > self.stable_offset() {
+            return Ok(false);
+        }
+
+        let buf = match archive.fetch_segment(segment_lsn) {
+            Ok(Some(buf)) => buf,
+            Ok(None) => {
+                warn!(
+                    "segment with base lsn {} is unreadable locally \
+                     and not present in the registered archive",
+                    segment_lsn
+                );
+                return Ok(false);
+            }
+            Err(e) => {
+                warn!(
+                    "failed to fetch segment with base lsn {} from \
+                     the registered archive: {:?}",
+                    segment_lsn, e
+                );
+                return Ok(false);
+            }
+        };
+
+        if buf.len() < SEG_HEADER_LEN || buf.len() > segment_size {
+            warn!(
+                "registered archive returned a segment of invalid \
+                 length {} for base lsn {}",
+                buf.len(),
+                segment_lsn
+            );
+            return Ok(false);
+        }
+
+        let mut header_buf = [0; SEG_HEADER_LEN];
+        header_buf.copy_from_slice(&buf[..SEG_HEADER_LEN]);
+        let header = SegmentHeader::from(header_buf);
+        if !header.ok || header.lsn != segment_lsn {
+            warn!(
+                "registered archive returned an invalid copy of \
+                 the segment with base lsn {}: {:?}",
+                segment_lsn, header
+            );
+            return Ok(false);
+        }
+
+        pwrite_all(&self.config.file, &buf, segment_base)?;
+        if !self.config.temporary {
+            self.config.file.sync_data()?;
+        }
+        if let Some(mirror) = &self.config.mirror {
+            pwrite_all(mirror, &buf, segment_base)?;
+            if !self.config.temporary {
+                mirror.sync_data()?;
+            }
+        }
+
+        warn!(
+            "repaired unreadable segment with base lsn {} at log \
+             offset {} from the registered archive",
+            segment_lsn, segment_base
+        );
+
+        Ok(true)
+    }
+
     /// blocks until the specified log sequence number has
     /// been made stable on disk. Returns the number of
     /// bytes written during this call. this is appropriate
@@ -131,26 +270,26 @@ impl Log {
         item: &T,
         guard: &Guard,
     ) -> Result<Reservation<'_>> {
-        #[cfg(feature = "compression")]
-        {
-            if self.config.use_compression && pid != BATCH_MANIFEST_PID {
-                use zstd::block::compress;
-
-                let buf = item.serialize();
+        if self.config.use_compression && pid != BATCH_MANIFEST_PID {
+            let buf = item.serialize();
 
-                let _measure = Measure::new(&M.compress);
+            let _measure = Measure::new(&M.compress);
 
-                let compressed_buf =
-                    compress(&buf, self.config.compression_factor).unwrap();
+            let dictionary = self.config.zstd_dictionary.get();
+            let compressed_buf = compression::compress_frame(
+                self.config.compression_algorithm,
+                &buf,
+                self.config.compression_factor,
+                dictionary.as_deref().map(|d| &d[..]),
+            );
 
-                return self.reserve_inner(
-                    log_kind,
-                    pid,
-                    &IVec::from(compressed_buf),
-                    None,
-                    guard,
-                );
-            }
+            return self.reserve_inner(
+                log_kind,
+                pid,
+                &IVec::from(compressed_buf),
+                None,
+                guard,
+            );
         }
 
         self.reserve_inner(log_kind, pid, item, None, guard)
@@ -375,7 +514,7 @@ impl Log {
                 let heap_reservation = self
                     .config
                     .heap
-                    .reserve(serialized_len + 13, reservation_lsn);
+                    .reserve(serialized_len + 13, reservation_lsn)?;
                 let heap_id = heap_reservation.heap_id;
                 (Some(heap_reservation), Some(heap_id))
             } else {
@@ -484,6 +623,9 @@ impl Drop for Log {
 
         if !self.config.temporary {
             self.config.file.sync_all().unwrap();
+            if let Some(mirror) = &self.config.mirror {
+                mirror.sync_all().unwrap();
+            }
         }
 
         debug!("IoBufs dropped");
@@ -514,11 +656,17 @@ impl std::ops::Deref for SegmentNumber {
 }
 
 /// A segment's header contains the new base LSN and a reference
-/// to the previous log segment.
+/// to the previous log segment. It also records the id of the
+/// compression codec and checksum algorithm that were configured
+/// when the segment was first written, as an advisory for
+/// recovery tooling after the configured algorithms change;
+/// individual messages remain self-describing.
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub struct SegmentHeader {
     pub lsn: Lsn,
     pub max_stable_lsn: Lsn,
+    pub codec: u8,
+    pub checksum_id: u8,
     pub ok: bool,
 }
 
@@ -574,7 +722,10 @@ impl From<[u8; SEG_HEADER_LEN]> for SegmentHeader {
             let xor_max_stable_lsn = arr_to_lsn(buf.get_unchecked(12..20));
             let max_stable_lsn = xor_max_stable_lsn ^ 0x7FFF_FFFF_FFFF_FFFF;
 
-            let crc32_tested = crc32(&buf[4..20]);
+            let codec = *buf.get_unchecked(20);
+            let checksum_id = *buf.get_unchecked(21);
+
+            let crc32_tested = crc32(&buf[4..SEG_HEADER_LEN]);
 
             let ok = crc32_tested == crc32_header;
 
@@ -586,7 +737,7 @@ impl From<[u8; SEG_HEADER_LEN]> for SegmentHeader {
                 );
             }
 
-            Self { lsn, max_stable_lsn, ok }
+            Self { lsn, max_stable_lsn, codec, checksum_id, ok }
         }
     }
 }
@@ -606,23 +757,27 @@ impl Into<[u8; SEG_HEADER_LEN]> for SegmentHeader {
             std::ptr::copy_nonoverlapping(
                 lsn_arr.as_ptr(),
                 buf.as_mut_ptr().add(4),
-                std::mem::size_of::<u64>(),
+                size_of::<u64>(),
             );
             std::ptr::copy_nonoverlapping(
                 highest_stable_lsn_arr.as_ptr(),
                 buf.as_mut_ptr().add(12),
-                std::mem::size_of::<u64>(),
+                size_of::<u64>(),
             );
         }
 
-        let crc32 = u32_to_arr(crc32(&buf[4..20]) ^ 0xFFFF_FFFF);
+        // bytes 22..24 remain zero, reserved for future use
+        buf[20] = self.codec;
+        buf[21] = self.checksum_id;
+
+        let crc32 = u32_to_arr(crc32(&buf[4..SEG_HEADER_LEN]) ^ 0xFFFF_FFFF);
 
         #[allow(unsafe_code)]
         unsafe {
             std::ptr::copy_nonoverlapping(
                 crc32.as_ptr(),
                 buf.as_mut_ptr(),
-                std::mem::size_of::<u32>(),
+                size_of::<u32>(),
             );
         }
 
@@ -738,7 +893,7 @@ pub(crate) fn read_message<R: ReadAt>(
     );
 
     let msg_header_buf = &mut [0; 128];
-    let _read_bytes = file.pread_exact_or_eof(msg_header_buf, lid)?;
+    let read_bytes = file.pread_exact_or_eof(msg_header_buf, lid)?;
     let header_cursor = &mut msg_header_buf.as_ref();
     let len_before = header_cursor.len();
     let header = MessageHeader::deserialize(header_cursor)?;
@@ -780,25 +935,36 @@ pub(crate) fn read_message<R: ReadAt>(
     let mut buf = vec![0; header_len];
 
     if header_len > len_after {
-        // we have to read more data from disk
-        file.pread_exact(&mut buf, lid + message_offset as LogOffset)?;
+        if read_bytes == msg_header_buf.len() {
+            // the initial read was fully filled, so splice the
+            // message prefix it already holds with a read of just
+            // the remaining tail, rather than re-reading the
+            // whole message from disk
+            buf[..len_after].copy_from_slice(header_cursor);
+            file.pread_exact(
+                &mut buf[len_after..],
+                lid + (message_offset + len_after) as LogOffset,
+            )?;
+        } else {
+            // we have to read more data from disk
+            file.pread_exact(&mut buf, lid + message_offset as LogOffset)?;
+        }
     } else {
         // we already read this data in the initial read
         buf.copy_from_slice(header_cursor[..header_len].as_ref());
     }
 
-    let crc32 = calculate_message_crc32(
+    if !checksum::verify_message(
+        header.crc32,
         msg_header_buf[..message_offset].as_ref(),
         &buf,
-    );
-
-    if crc32 != header.crc32 {
+        config.checksum_algorithm,
+    ) {
         trace!(
-            "read a message with a bad checksum with header {:?} msg len: {} expected: {} actual: {}",
+            "read a message with a bad checksum with header {:?} msg len: {} expected: {}",
             header,
             header_len,
             header.crc32,
-            crc32
         );
         return Ok(LogRead::Corrupted);
     }
@@ -829,7 +995,7 @@ pub(crate) fn read_message<R: ReadAt>(
             assert_eq!(buf.len(), 16);
             let heap_id = HeapId::deserialize(&mut &buf[..]).unwrap();
 
-            match config.heap.read(heap_id, config.use_compression) {
+            match config.heap.read(heap_id, config) {
                 Ok((kind, buf)) => {
                     assert_eq!(header.kind, kind);
                     trace!(
@@ -853,12 +1019,16 @@ pub(crate) fn read_message<R: ReadAt>(
         | MessageKind::Counter => {
             trace!("read a successful inline message");
             let buf =
-                if config.use_compression { decompress(buf) } else { buf };
+                if config.use_compression {
+                    decompress(buf, config)?
+                } else {
+                    buf
+                };
 
             Ok(LogRead::Inline(header, buf, inline_len))
         }
         MessageKind::BatchManifest => {
-            assert_eq!(buf.len(), std::mem::size_of::<Lsn>());
+            assert_eq!(buf.len(), size_of::<Lsn>());
             let max_lsn = arr_to_lsn(&buf);
             Ok(LogRead::BatchManifest(max_lsn, inline_len))
         }
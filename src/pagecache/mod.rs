@@ -5,6 +5,7 @@
 pub mod constants;
 pub mod logger;
 
+mod compressed_cache;
 mod disk_pointer;
 mod header;
 mod heap;
@@ -17,6 +18,7 @@ mod parallel_io_polyfill;
 mod parallel_io_unix;
 #[cfg(all(windows, not(miri)))]
 mod parallel_io_windows;
+mod prefetch;
 mod reservation;
 mod segment;
 mod snapshot;
@@ -26,18 +28,24 @@ use std::{collections::BinaryHeap, ops::Deref};
 use crate::*;
 
 #[cfg(any(all(not(unix), not(windows)), miri))]
-use parallel_io_polyfill::{pread_exact, pread_exact_or_eof, pwrite_all};
+pub(crate) use parallel_io_polyfill::{
+    pread_exact, pread_exact_or_eof, pwrite_all, pwrite_all_vectored,
+};
 
 #[cfg(all(unix, not(miri)))]
-use parallel_io_unix::{pread_exact, pread_exact_or_eof, pwrite_all};
+pub(crate) use parallel_io_unix::{
+    pread_exact, pread_exact_or_eof, pwrite_all, pwrite_all_vectored,
+};
 
 #[cfg(all(windows, not(miri)))]
-use parallel_io_windows::{pread_exact, pread_exact_or_eof, pwrite_all};
+pub(crate) use parallel_io_windows::{
+    pread_exact, pread_exact_or_eof, pwrite_all, pwrite_all_vectored,
+};
 
 use self::{
     constants::{
         BATCH_MANIFEST_PID, COUNTER_PID, META_PID,
-        PAGE_CONSOLIDATION_THRESHOLD, SEGMENT_CLEANUP_THRESHOLD,
+        PAGE_CONSOLIDATION_THRESHOLD,
     },
     header::Header,
     iobuf::{roll_iobuf, IoBuf, IoBufs},
@@ -53,11 +61,12 @@ pub(crate) use self::{
         SegmentNumber,
     },
     reservation::Reservation,
+    segment::SegmentReport,
     snapshot::{read_snapshot_or_default, PageState, Snapshot},
 };
 
 pub use self::{
-    constants::{MAX_MSG_HEADER_LEN, MAX_SPACE_AMPLIFICATION, SEG_HEADER_LEN},
+    constants::{MAX_MSG_HEADER_LEN, SEG_HEADER_LEN},
     disk_pointer::DiskPtr,
     logger::{Log, LogRead},
 };
@@ -235,6 +244,22 @@ pub(crate) fn arr_to_lsn(arr: &[u8]) -> Lsn {
     Lsn::from_le_bytes(arr.try_into().unwrap())
 }
 
+/// Returns the number of bytes of filesystem blocks actually
+/// backing a file, which may be below its apparent length for
+/// sparse or hole-punched files. Falls back to the apparent
+/// length on platforms without block accounting.
+fn allocated_file_size(metadata: &std::fs::Metadata) -> u64 {
+    #[cfg(all(unix, not(miri)))]
+    {
+        use std::os::unix::fs::MetadataExt;
+        metadata.blocks() * 512
+    }
+    #[cfg(not(all(unix, not(miri))))]
+    {
+        metadata.len()
+    }
+}
+
 #[inline]
 pub(crate) fn u64_to_arr(number: u64) -> [u8; 8] {
     number.to_le_bytes()
@@ -251,28 +276,13 @@ pub(crate) fn u32_to_arr(number: u32) -> [u8; 4] {
 }
 
 #[allow(clippy::needless_pass_by_value)]
-#[allow(clippy::needless_return)]
-pub(crate) fn decompress(in_buf: Vec<u8>) -> Vec<u8> {
-    #[cfg(feature = "compression")]
-    {
-        use zstd::stream::decode_all;
-
-        let scootable_in_buf = &mut &*in_buf;
-        let raw: IVec = IVec::deserialize(scootable_in_buf)
-            .expect("this had to be serialized with an extra length frame");
-        let _measure = Measure::new(&M.decompress);
-        let out_buf = decode_all(&raw[..]).expect(
-            "failed to decompress data. \
-             This is not expected, please open an issue on \
-             https://github.com/spacejam/sled so we can \
-             fix this critical issue ASAP. Thank you :)",
-        );
-
-        return out_buf;
-    }
-
-    #[cfg(not(feature = "compression"))]
-    in_buf
+pub(crate) fn decompress(in_buf: Vec<u8>, config: &Config) -> Result<Vec<u8>> {
+    let scootable_in_buf = &mut &*in_buf;
+    let raw: IVec = IVec::deserialize(scootable_in_buf)
+        .expect("this had to be serialized with an extra length frame");
+    let _measure = Measure::new(&M.decompress);
+    let dictionary = config.zstd_dictionary.get();
+    compression::decompress_frame(&raw, dictionary.as_deref().map(|d| &d[..]))
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -508,6 +518,10 @@ pub struct PageCacheInner {
     #[doc(hidden)]
     pub log: Log,
     lru: Lru,
+    compressed_cache: Option<compressed_cache::CompressedCache>,
+    prefetcher: prefetch::Prefetcher,
+    pinned: Mutex<FastMap8<PageId, u64>>,
+    rewritten_pages: AtomicU64,
 
     idgen: AtomicU64,
     idgen_persists: AtomicU64,
@@ -642,6 +656,14 @@ impl PageCache {
         let cache_capacity = config.cache_capacity;
         let lru = Lru::new(cache_capacity);
 
+        let compressed_cache = if config.compressed_cache_capacity > 0 {
+            Some(compressed_cache::CompressedCache::new(
+                config.compressed_cache_capacity,
+            ))
+        } else {
+            None
+        };
+
         let mut pc = PageCacheInner {
             was_recovered: false,
             config: config.clone(),
@@ -652,6 +674,10 @@ impl PageCache {
             inner: PageTable::default(),
             log: Log::start(config, &snapshot)?,
             lru,
+            compressed_cache,
+            prefetcher: prefetch::Prefetcher::default(),
+            pinned: Mutex::new(FastMap8::default()),
+            rewritten_pages: AtomicU64::new(0),
             next_pid_to_allocate: Mutex::new(0),
             snapshot_min_lsn: AtomicLsn::new(snapshot.stable_lsn.unwrap_or(0)),
             links: AtomicU64::new(0),
@@ -796,11 +822,14 @@ impl PageCache {
                 pub static COUNT: RefCell<u32> = RefCell::new(1);
             }
 
-            let time_now =
-                SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-
             #[allow(clippy::cast_possible_truncation)]
-            let fail_seed = std::cmp::max(3, time_now.as_nanos() as u32 % 128);
+            let fail_seed = if self.config.deterministic_seed == 0 {
+                let time_now =
+                    SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                std::cmp::max(3, time_now.as_nanos() as u32 % 128)
+            } else {
+                std::cmp::max(3, self.config.deterministic_seed as u32 % 128)
+            };
 
             let inject_failure = COUNT.with(|c| {
                 let mut cr = c.borrow_mut();
@@ -1002,7 +1031,7 @@ impl PageCache {
         let snapshot = Snapshot {
             stable_lsn: Some(stable_lsn_before),
             active_segment: None,
-            pt: page_states,
+            pt: page_states.into(),
         };
 
         self.log.make_stable(max_reserved_lsn_after)?;
@@ -1027,6 +1056,12 @@ impl PageCacheInner {
         self.log.flush()
     }
 
+    /// A flush variant for callers that already hold a
+    /// concurrency-control guard on this thread.
+    pub(crate) fn flush_unprotected(&self) -> Result<usize> {
+        self.log.flush_unprotected()
+    }
+
     /// Create a new page, trying to reuse old freed pages if possible
     /// to maximize underlying `PageTable` pointer density. Returns
     /// the page ID and its pointer for use in future atomic `replace`
@@ -1231,6 +1266,10 @@ impl PageCacheInner {
             self.cas_page(pid, old, Update::Free, false, guard)?;
 
         if new_pointer.is_ok() {
+            // stop counting this page against the pinned budget
+            // now that it can never be read again
+            self.unpin_page(pid);
+
             let free = self.free.clone();
             guard.defer(move || {
                 let mut free = free.lock();
@@ -1272,11 +1311,14 @@ impl PageCacheInner {
                 pub static COUNT: RefCell<u32> = RefCell::new(1);
             }
 
-            let time_now =
-                SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-
             #[allow(clippy::cast_possible_truncation)]
-            let fail_seed = std::cmp::max(3, time_now.as_nanos() as u32 % 128);
+            let fail_seed = if self.config.deterministic_seed == 0 {
+                let time_now =
+                    SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                std::cmp::max(3, time_now.as_nanos() as u32 % 128)
+            } else {
+                std::cmp::max(3, self.config.deterministic_seed as u32 % 128)
+            };
 
             let inject_failure = COUNT.with(|c| {
                 let mut cr = c.borrow_mut();
@@ -1451,6 +1493,8 @@ impl PageCacheInner {
 
                     trace!("rewriting pid {} succeeded", pid);
 
+                    self.rewritten_pages.fetch_add(1, SeqCst);
+
                     return Ok(());
                 } else {
                     if let Some(log_reservation) = log_reservation {
@@ -1508,6 +1552,7 @@ impl PageCacheInner {
                     },
                 )?;
                 if res.is_ok() {
+                    self.rewritten_pages.fetch_add(1, SeqCst);
                     return Ok(());
                 }
             }
@@ -1530,8 +1575,149 @@ impl PageCacheInner {
         Ok(on_disk_bytes / logical_size)
     }
 
+    /// Queues every inactive segment for draining regardless of
+    /// its utilization, then synchronously rewrites all queued
+    /// pages. Used by `Db::reclaim_space_now` to trade foreground
+    /// latency for immediate space recovery.
+    pub(crate) fn reclaim_pass(&self) -> Result<()> {
+        self.log.iobufs.with_sa(|sa| sa.drain_all_inactive());
+
+        loop {
+            let guard = pin();
+            let cc = concurrency_control::read();
+            let to_clean = self.log.iobufs.segment_cleaner.pop();
+            let ret = if let Some((pid_to_clean, segment_to_clean)) = to_clean
+            {
+                self.rewrite_page(pid_to_clean, segment_to_clean, &guard)
+                    .map(|_| true)
+            } else {
+                Ok(false)
+            };
+            drop(cc);
+            guard.flush();
+            if !ret? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Describes every log segment from the garbage collector's
+    /// perspective, for `Db::gc_info`.
+    pub(crate) fn segment_report(&self) -> Vec<SegmentReport> {
+        self.log.iobufs.with_sa(|sa| sa.segment_report())
+    }
+
+    /// Prevents freed segments from being reused or truncated
+    /// away while an online backup copies the file. Must be
+    /// matched by a call to `unpin_segments_for_backup`.
+    pub(crate) fn pin_segments_for_backup(&self) {
+        self.log.iobufs.with_sa(|sa| sa.pin_for_backup())
+    }
+
+    /// Releases a pin taken by `pin_segments_for_backup`.
+    pub(crate) fn unpin_segments_for_backup(&self) {
+        self.log.iobufs.with_sa(|sa| sa.unpin_for_backup())
+    }
+
+    /// Returns the stable LSN together with the base LSN and file
+    /// offset of every segment holding data at or after
+    /// `since_lsn`, in LSN order, for incremental backups.
+    pub(crate) fn segments_since(
+        &self,
+        since_lsn: Lsn,
+    ) -> (Lsn, Vec<(Lsn, LogOffset)>) {
+        let stable = self.log.stable_offset();
+        let segments = self
+            .log
+            .iobufs
+            .with_sa(|sa| sa.segment_snapshot_iter_from(since_lsn));
+        (stable, segments.into_iter().collect())
+    }
+
+    /// Read back the contents of the given log segment from disk,
+    /// forcing the storage media to surface latent errors before a
+    /// critical read path encounters them. Returns `false` if the
+    /// segment index is past the end of the file.
+    pub(crate) fn scrub_segment(&self, segment_idx: u64) -> Result<bool> {
+        let segment_size = self.config.segment_size as u64;
+        let len = self.config.file.metadata()?.len();
+        let offset = segment_idx * segment_size;
+        if offset >= len {
+            return Ok(false);
+        }
+        let read_len =
+            usize::try_from(segment_size.min(len - offset)).unwrap();
+        let mut buf = vec![0; read_len];
+        pread_exact_or_eof(&self.config.file, &mut buf, offset)?;
+        Ok(true)
+    }
+
     pub(crate) fn size_on_disk(&self) -> Result<u64> {
-        let mut size = self.config.file.metadata()?.len();
+        self.disk_usage().map(|(apparent, _allocated)| apparent)
+    }
+
+    /// Marks a page as exempt from cache eviction, as long as its
+    /// size fits into the remaining pinned budget configured via
+    /// `pinned_cache_budget`. Returns `false` without pinning if
+    /// the budget would be exceeded.
+    pub(crate) fn pin_page(&self, pid: PageId, size: u64) -> bool {
+        let budget = self.config.pinned_cache_budget;
+        let mut pinned = self.pinned.lock();
+        if pinned.contains_key(&pid) {
+            pinned.insert(pid, size);
+            return true;
+        }
+        let total: u64 = pinned.values().sum();
+        if total + size > budget {
+            return false;
+        }
+        pinned.insert(pid, size);
+        true
+    }
+
+    /// Releases a pin previously placed with `pin_page`, returning
+    /// `true` if the page was pinned.
+    pub(crate) fn unpin_page(&self, pid: PageId) -> bool {
+        self.pinned.lock().remove(&pid).is_some()
+    }
+
+    fn is_pinned(&self, pid: PageId) -> bool {
+        self.pinned.lock().contains_key(&pid)
+    }
+
+    /// Returns the cumulative number of bytes written to the log
+    /// by this instance.
+    pub(crate) fn log_bytes_written(&self) -> u64 {
+        self.log.iobufs.bytes_written.load(SeqCst)
+    }
+
+    /// Returns the number of pages this instance has rewritten in
+    /// the course of garbage collection and segment defragmentation.
+    pub(crate) fn pages_rewritten(&self) -> u64 {
+        self.rewritten_pages.load(SeqCst)
+    }
+
+    /// Returns an approximation of the bytes of page data
+    /// currently admitted to the in-memory cache.
+    pub(crate) fn cache_bytes(&self) -> u64 {
+        self.lru.cache_bytes()
+    }
+
+    /// Returns an estimate of the memory occupied by the in-memory
+    /// page table for all pids allocated so far.
+    pub(crate) fn index_bytes(&self) -> u64 {
+        let max_pid = *self.next_pid_to_allocate.lock();
+        pagetable::approximate_memory(max_pid)
+    }
+
+    /// Returns the `(apparent, allocated)` sizes of all storage
+    /// files in bytes. The allocated size counts filesystem blocks
+    /// actually backing the files, which may be lower than the
+    /// apparent size for sparse or hole-punched segments.
+    pub(crate) fn disk_usage(&self) -> Result<(u64, u64)> {
+        let metadata = self.config.file.metadata()?;
+        let mut size = metadata.len();
+        let mut allocated = allocated_file_size(&metadata);
 
         let base_path = self.config.get_path().join("heap");
         let heap_dir = base_path.parent().expect(
@@ -1550,25 +1736,29 @@ impl PageCacheInner {
             // in the background and no longer exists
             #[cfg(not(miri))]
             {
-                size += slab_file.metadata().map(|m| m.len()).unwrap_or(0);
+                if let Ok(m) = slab_file.metadata() {
+                    size += m.len();
+                    allocated += allocated_file_size(&m);
+                }
             }
 
             // workaround to avoid missing `dirfd` shim
             #[cfg(miri)]
             {
-                size += std::fs::metadata(slab_file.path())
-                    .map(|m| m.len())
-                    .unwrap_or(0);
+                if let Ok(m) = std::fs::metadata(slab_file.path()) {
+                    size += m.len();
+                    allocated += allocated_file_size(&m);
+                }
             }
         }
 
-        Ok(size)
+        Ok((size, allocated))
     }
 
     fn logical_size_of_all_pages(&self) -> Result<u64> {
         let guard = pin();
         let meta_size = self.get_meta(&guard)?.rss();
-        let idgen_size = std::mem::size_of::<u64>() as u64;
+        let idgen_size = size_of::<u64>() as u64;
 
         let mut ret = meta_size + idgen_size;
         let min_pid = COUNTER_PID + 1;
@@ -1781,6 +1971,63 @@ impl PageCacheInner {
         }
     }
 
+    /// Issues asynchronous reads for the log ranges of a page
+    /// that the prefetcher predicts will be accessed next, so
+    /// that a subsequent pull finds them in the OS page cache.
+    /// Pages that are already materialized in the cache, and
+    /// heap ranges, are skipped.
+    fn prefetch(&self, pid: PageId, guard: &Guard) {
+        let page_view = match self.inner.get(pid, guard) {
+            Some(page_view) => page_view,
+            None => return,
+        };
+
+        if page_view.is_free() || page_view.update.is_some() {
+            return;
+        }
+
+        let ranges: Vec<(LogOffset, usize)> = page_view
+            .cache_infos
+            .iter()
+            .filter_map(|cache_info| {
+                cache_info.pointer.lid().map(|lid| {
+                    (lid, usize::try_from(cache_info.log_size).unwrap())
+                })
+            })
+            .collect();
+
+        if ranges.is_empty() {
+            return;
+        }
+
+        self.prefetcher.note_issued(pid);
+
+        let config = self.config.clone();
+        let spawn_result = threadpool::spawn(move || {
+            for (lid, len) in ranges {
+                let mut buf = vec![0; len];
+                let _ = pread_exact_or_eof(&config.file, &mut buf, lid);
+            }
+        });
+
+        if let Err(e) = spawn_result {
+            debug!("failed to spawn prefetch task: {:?}", e);
+        }
+    }
+
+    /// Returns the number of prefetches issued by the speculative
+    /// prefetcher and the number that were followed by an access
+    /// to the prefetched page.
+    pub(crate) fn prefetch_stats(&self) -> (u64, u64) {
+        self.prefetcher.stats()
+    }
+
+    /// Overrides the configured `Config::speculative_prefetch`
+    /// at runtime, used by `Db::calibrate`.
+    pub(crate) fn set_speculative_prefetch(&self, enabled: bool) {
+        self.prefetcher.set_enabled(enabled);
+    }
+
     /// Try to retrieve a page by its logical ID.
     pub(crate) fn get<'g>(
         &self,
@@ -1797,6 +2044,16 @@ impl PageCacheInner {
             );
         }
 
+        if self
+            .prefetcher
+            .enabled_override()
+            .unwrap_or(self.config.speculative_prefetch)
+        {
+            if let Some(predicted) = self.prefetcher.record_access(pid) {
+                self.prefetch(predicted, guard);
+            }
+        }
+
         let mut last_attempted_cache_info = None;
         let mut last_err = None;
         let mut page_view;
@@ -1844,6 +2101,23 @@ impl PageCacheInner {
                     page_view.cache_infos.first().copied();
             }
 
+            // before going to disk, see if the second cache tier
+            // still holds this page's materialized state in
+            // compressed form.
+            if let (Some(compressed_cache), Some(last)) =
+                (&self.compressed_cache, page_view.cache_infos.last())
+            {
+                if let Some(buf) = compressed_cache.take(
+                    pid,
+                    last.lsn,
+                    page_view.cache_infos.len(),
+                ) {
+                    let node = Node::deserialize(&mut buf.as_slice())
+                        .expect("failed to deserialize cached page");
+                    break vec![Update::Node(node)];
+                }
+            }
+
             // need to page-in
             let updates_result: Result<Vec<Update>> = page_view
                 .cache_infos
@@ -2062,12 +2336,30 @@ impl PageCacheInner {
                 // should not page these suckas out
                 continue;
             }
+            if self.is_pinned(pid) {
+                // exempted from eviction via pin_page
+                continue;
+            }
             loop {
                 if let Some(page_view) = self.inner.get(pid, guard) {
                     if page_view.is_free() {
                         // don't page-out Freed suckas
                         break;
                     }
+
+                    if let (Some(compressed_cache), Some(Update::Node(node))) =
+                        (&self.compressed_cache, &page_view.update)
+                    {
+                        if let Some(last) = page_view.cache_infos.last() {
+                            compressed_cache.insert(
+                                pid,
+                                last.lsn,
+                                page_view.cache_infos.len(),
+                                &node.serialize(),
+                            );
+                        }
+                    }
+
                     let new_page = Owned::new(Page {
                         update: None,
                         cache_infos: page_view.cache_infos.clone(),
@@ -2214,7 +2506,7 @@ impl PageCacheInner {
 
             let guard = pin();
 
-            match *state {
+            match state {
                 PageState::Present { base, ref frags } => {
                     cache_infos.push(CacheInfo {
                         lsn: base.0,
@@ -20,6 +20,14 @@ use crate::{
 #[doc(hidden)]
 pub const PAGETABLE_NODE_SZ: usize = size_of::<Node1>();
 
+/// Returns an estimate of the memory occupied by the page table
+/// for a pagecache that has allocated pids below `max_pid`,
+/// assuming the second-level blocks were allocated densely.
+pub(crate) fn approximate_memory(max_pid: PageId) -> u64 {
+    let node2_blocks = (max_pid / NODE2_FAN_OUT as u64) + 1;
+    size_of::<Node1>() as u64 + node2_blocks * size_of::<Node2>() as u64
+}
+
 const NODE2_FAN_FACTOR: usize = 18;
 const NODE1_FAN_OUT: usize = 1 << (MAX_PID_BITS - NODE2_FAN_FACTOR);
 const NODE2_FAN_OUT: usize = 1 << NODE2_FAN_FACTOR;
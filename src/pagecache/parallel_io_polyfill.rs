@@ -1,3 +1,4 @@
+use std::convert::TryFrom;
 use std::fs::File;
 use std::io::{self, Read, Seek, Write};
 
@@ -99,3 +100,18 @@ pub(crate) fn pwrite_all(
     }
     Ok(())
 }
+
+/// Writes several buffers to a contiguous file range, one write
+/// per buffer under the global file lock.
+pub(crate) fn pwrite_all_vectored(
+    file: &File,
+    bufs: &[&[u8]],
+    offset: LogOffset,
+) -> io::Result<()> {
+    let mut offset = offset;
+    for buf in bufs {
+        pwrite_all(file, buf, offset)?;
+        offset += u64::try_from(buf.len()).unwrap();
+    }
+    Ok(())
+}
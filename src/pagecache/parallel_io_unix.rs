@@ -41,3 +41,63 @@ pub(crate) fn pwrite_all(
 ) -> io::Result<()> {
     file.write_all_at(buf, offset)
 }
+
+/// Writes several buffers to a contiguous file range, gathered
+/// into a single `pwritev` syscall per attempt instead of one
+/// `pwrite` per buffer.
+pub(crate) fn pwrite_all_vectored(
+    file: &File,
+    bufs: &[&[u8]],
+    offset: LogOffset,
+) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let total: usize = bufs.iter().map(|buf| buf.len()).sum();
+    let mut written = 0_usize;
+    while written < total {
+        // skip over what has already been written, which may end
+        // partway through a buffer after a short write.
+        let mut iovecs = Vec::with_capacity(bufs.len());
+        let mut to_skip = written;
+        for buf in bufs {
+            if to_skip >= buf.len() {
+                to_skip -= buf.len();
+                continue;
+            }
+            let remaining = &buf[to_skip..];
+            to_skip = 0;
+            iovecs.push(libc::iovec {
+                iov_base: remaining.as_ptr() as *mut libc::c_void,
+                iov_len: remaining.len(),
+            });
+        }
+
+        let ret = unsafe {
+            libc::pwritev(
+                file.as_raw_fd(),
+                iovecs.as_ptr(),
+                libc::c_int::try_from(iovecs.len()).unwrap(),
+                libc::off_t::try_from(
+                    offset + u64::try_from(written).unwrap(),
+                )
+                .unwrap(),
+            )
+        };
+
+        if ret < 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(e);
+        } else if ret == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::WriteZero,
+                "failed to write whole buffer",
+            ));
+        }
+
+        written += usize::try_from(ret).unwrap();
+    }
+    Ok(())
+}
@@ -1,53 +1,159 @@
+//! Positioned I/O for Windows, issued directly through
+//! `ReadFile`/`WriteFile` with an `OVERLAPPED` offset.
+//!
+//! The previous backend went through `FileExt::seek_read` and
+//! `seek_write` on a handle duplicated per operation, because
+//! those calls move the shared file cursor. Duplicating the
+//! handle creates a kernel object for every read and write, and
+//! accounted for much of the throughput gap reported between
+//! Windows and Linux for the same workload. Supplying the offset
+//! through an `OVERLAPPED` block instead keeps the cursor out of
+//! the hot path entirely, the same way `pread`/`pwrite` do on
+//! unix. Combined with `Config::windows_write_through`, which
+//! opens the log with `FILE_FLAG_WRITE_THROUGH` so that completed
+//! writes do not additionally wait on `FlushFileBuffers`, this
+//! substantially narrows that gap. `FILE_FLAG_NO_BUFFERING` is
+//! deliberately not used, because log messages are read and
+//! written at unaligned offsets, and the flag requires all I/O to
+//! be sector-aligned.
+
 use std::convert::TryFrom;
 use std::fs::File;
 use std::io;
-use std::os::windows::fs::FileExt;
+use std::os::windows::io::{AsRawHandle, RawHandle};
 
 use super::LogOffset;
 
-fn seek_read_exact<F: FileExt>(
-    file: &mut F,
+const ERROR_HANDLE_EOF: i32 = 38;
+
+#[repr(C)]
+struct Overlapped {
+    internal: usize,
+    internal_high: usize,
+    offset: u32,
+    offset_high: u32,
+    h_event: RawHandle,
+}
+
+extern "system" {
+    fn ReadFile(
+        handle: RawHandle,
+        buffer: *mut u8,
+        bytes_to_read: u32,
+        bytes_read: *mut u32,
+        overlapped: *mut Overlapped,
+    ) -> i32;
+
+    fn WriteFile(
+        handle: RawHandle,
+        buffer: *const u8,
+        bytes_to_write: u32,
+        bytes_written: *mut u32,
+        overlapped: *mut Overlapped,
+    ) -> i32;
+}
+
+fn overlapped_at(offset: u64) -> Overlapped {
+    Overlapped {
+        internal: 0,
+        internal_high: 0,
+        offset: u32::try_from(offset & 0xFFFF_FFFF).unwrap(),
+        offset_high: u32::try_from(offset >> 32).unwrap(),
+        h_event: std::ptr::null_mut(),
+    }
+}
+
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    let len =
+        u32::try_from(buf.len().min(usize::try_from(u32::max_value()).unwrap()))
+            .unwrap();
+    let mut read = 0_u32;
+    let mut overlapped = overlapped_at(offset);
+
+    let success = unsafe {
+        ReadFile(
+            file.as_raw_handle(),
+            buf.as_mut_ptr(),
+            len,
+            &mut read,
+            &mut overlapped,
+        )
+    };
+
+    if success == 0 {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() == Some(ERROR_HANDLE_EOF) {
+            return Ok(0);
+        }
+        return Err(e);
+    }
+
+    Ok(usize::try_from(read).unwrap())
+}
+
+fn write_at(file: &File, buf: &[u8], offset: u64) -> io::Result<usize> {
+    let len =
+        u32::try_from(buf.len().min(usize::try_from(u32::max_value()).unwrap()))
+            .unwrap();
+    let mut written = 0_u32;
+    let mut overlapped = overlapped_at(offset);
+
+    let success = unsafe {
+        WriteFile(
+            file.as_raw_handle(),
+            buf.as_ptr(),
+            len,
+            &mut written,
+            &mut overlapped,
+        )
+    };
+
+    if success == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(usize::try_from(written).unwrap())
+}
+
+pub(crate) fn pread_exact_or_eof(
+    file: &File,
     mut buf: &mut [u8],
-    mut offset: u64,
-) -> io::Result<()> {
+    offset: LogOffset,
+) -> io::Result<usize> {
+    let mut total = 0_usize;
     while !buf.is_empty() {
-        match file.seek_read(buf, offset) {
+        match read_at(file, buf, offset + u64::try_from(total).unwrap()) {
             Ok(0) => break,
             Ok(n) => {
+                total += n;
                 let tmp = buf;
                 buf = &mut tmp[n..];
-                offset += n as u64;
             }
             Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
             Err(e) => return Err(e),
         }
     }
-    if !buf.is_empty() {
-        Err(io::Error::new(
-            io::ErrorKind::UnexpectedEof,
-            "failed to fill whole buffer",
-        ))
-    } else {
-        Ok(())
-    }
+    Ok(total)
 }
 
-fn seek_write_all<F: FileExt>(
-    file: &mut F,
-    mut buf: &[u8],
-    mut offset: u64,
+pub(crate) fn pread_exact(
+    file: &File,
+    mut buf: &mut [u8],
+    offset: LogOffset,
 ) -> io::Result<()> {
+    let mut total = 0_usize;
     while !buf.is_empty() {
-        match file.seek_write(buf, offset) {
+        match read_at(file, buf, offset + u64::try_from(total).unwrap()) {
             Ok(0) => {
                 return Err(io::Error::new(
-                    io::ErrorKind::WriteZero,
-                    "failed to write whole buffer",
+                    io::ErrorKind::UnexpectedEof,
+                    "failed to fill whole buffer",
                 ));
             }
             Ok(n) => {
-                buf = &buf[n..];
-                offset += n as u64;
+                total += n;
+                let tmp = buf;
+                buf = &mut tmp[n..];
             }
             Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
             Err(e) => return Err(e),
@@ -56,41 +162,43 @@ fn seek_write_all<F: FileExt>(
     Ok(())
 }
 
-pub(crate) fn pread_exact_or_eof(
+pub(crate) fn pwrite_all(
     file: &File,
-    mut buf: &mut [u8],
+    mut buf: &[u8],
     offset: LogOffset,
-) -> io::Result<usize> {
+) -> io::Result<()> {
     let mut total = 0_usize;
     while !buf.is_empty() {
-        match file.seek_read(buf, offset + u64::try_from(total).unwrap()) {
-            Ok(0) => break,
+        match write_at(file, buf, offset + u64::try_from(total).unwrap()) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "failed to write whole buffer",
+                ));
+            }
             Ok(n) => {
                 total += n;
-                let tmp = buf;
-                buf = &mut tmp[n..];
+                buf = &buf[n..];
             }
             Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
             Err(e) => return Err(e),
         }
     }
-    Ok(total)
-}
-
-pub(crate) fn pread_exact(
-    file: &File,
-    buf: &mut [u8],
-    offset: LogOffset,
-) -> io::Result<()> {
-    let mut f = file.try_clone()?;
-    seek_read_exact(&mut f, buf, offset)
+    Ok(())
 }
 
-pub(crate) fn pwrite_all(
+/// Writes several buffers to a contiguous file range. Windows has
+/// no positioned vectored write, so this falls back to one
+/// overlapped `WriteFile` per buffer.
+pub(crate) fn pwrite_all_vectored(
     file: &File,
-    buf: &[u8],
+    bufs: &[&[u8]],
     offset: LogOffset,
 ) -> io::Result<()> {
-    let mut f = file.try_clone()?;
-    seek_write_all(&mut f, buf, offset)
+    let mut offset = offset;
+    for buf in bufs {
+        pwrite_all(file, buf, offset)?;
+        offset += u64::try_from(buf.len()).unwrap();
+    }
+    Ok(())
 }
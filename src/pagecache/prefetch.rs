@@ -0,0 +1,81 @@
+use std::sync::atomic::Ordering::SeqCst;
+
+use super::*;
+
+/// Forget all learned transitions once this many are tracked, to
+/// bound memory use for access patterns that never repeat.
+const MAX_TRACKED_TRANSITIONS: usize = 65_536;
+
+/// The maximum number of prefetched pages tracked while awaiting
+/// the access that would count as a hit.
+const MAX_AWAITING_ACCESS: usize = 1_024;
+
+/// Learns repeated page access sequences, such as an index page
+/// followed by its sibling leaves, so that the disk ranges of the
+/// page predicted to be accessed next can be read asynchronously
+/// ahead of the access. Enabled via the experimental
+/// `Config::speculative_prefetch` option.
+#[derive(Debug, Default)]
+pub(crate) struct Prefetcher {
+    // 0 = follow `Config::speculative_prefetch`,
+    // 1 = forced off, 2 = forced on (set by `Db::calibrate`)
+    enabled_override: AtomicU64,
+    last_pid: AtomicU64,
+    successors: Mutex<FastMap8<PageId, PageId>>,
+    awaiting_access: Mutex<FastSet8<PageId>>,
+    issued: AtomicU64,
+    hits: AtomicU64,
+}
+
+impl Prefetcher {
+    /// Records an access to `pid`, learning the transition from
+    /// the previously accessed page, and returns the page
+    /// predicted to be accessed next, if one has been learned.
+    pub(crate) fn record_access(&self, pid: PageId) -> Option<PageId> {
+        let prev = self.last_pid.swap(pid, SeqCst);
+
+        if self.awaiting_access.lock().remove(&pid) {
+            self.hits.fetch_add(1, SeqCst);
+        }
+
+        let mut successors = self.successors.lock();
+        if successors.len() >= MAX_TRACKED_TRANSITIONS {
+            successors.clear();
+        }
+        if prev != pid {
+            successors.insert(prev, pid);
+        }
+        successors.get(&pid).copied().filter(|next| *next != pid)
+    }
+
+    /// Notes that a prefetch has been issued for `pid`, so that a
+    /// subsequent access to it counts as a hit.
+    pub(crate) fn note_issued(&self, pid: PageId) {
+        let mut awaiting_access = self.awaiting_access.lock();
+        if awaiting_access.len() >= MAX_AWAITING_ACCESS {
+            awaiting_access.clear();
+        }
+        awaiting_access.insert(pid);
+        self.issued.fetch_add(1, SeqCst);
+    }
+
+    /// Returns the number of prefetches issued and the number
+    /// that were followed by an access to the prefetched page.
+    pub(crate) fn stats(&self) -> (u64, u64) {
+        (self.issued.load(SeqCst), self.hits.load(SeqCst))
+    }
+
+    /// Overrides the configured `Config::speculative_prefetch`
+    /// at runtime.
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled_override.store(if enabled { 2 } else { 1 }, SeqCst);
+    }
+
+    pub(crate) fn enabled_override(&self) -> Option<bool> {
+        match self.enabled_override.load(SeqCst) {
+            0 => None,
+            1 => Some(false),
+            _ => Some(true),
+        }
+    }
+}
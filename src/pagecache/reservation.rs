@@ -112,7 +112,8 @@ impl<'a> Reservation<'a> {
             self.buf[4] = MessageKind::Canceled.into();
         }
 
-        let crc32 = calculate_message_crc32(
+        let crc32 = checksum::hash_message(
+            self.log.config.checksum_algorithm,
             self.buf[..self.header_len].as_ref(),
             &self.buf[self.header_len..],
         );
@@ -123,7 +124,7 @@ impl<'a> Reservation<'a> {
             std::ptr::copy_nonoverlapping(
                 crc32_arr.as_ptr(),
                 self.buf.as_mut_ptr(),
-                std::mem::size_of::<u32>(),
+                size_of::<u32>(),
             );
         }
         self.log.exit_reservation(&self.iobuf)?;
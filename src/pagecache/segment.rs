@@ -58,7 +58,7 @@
 
 use std::{collections::BTreeSet, mem};
 
-use super::PageState;
+use super::{PageState, Snapshot};
 
 use crate::pagecache::*;
 use crate::*;
@@ -91,10 +91,12 @@ pub(crate) struct SegmentAccountant {
     // TODO put behind a single mutex
     free: BTreeSet<LogOffset>,
     tip: LogOffset,
+    preallocated_to: LogOffset,
     max_stabilized_lsn: Lsn,
     segment_cleaner: SegmentCleaner,
     ordering: BTreeMap<Lsn, LogOffset>,
     async_truncations: BTreeMap<LogOffset, OneShot<Result<()>>>,
+    backup_pins: usize,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -128,6 +130,22 @@ impl SegmentCleaner {
         let mut inner = self.inner.lock();
         inner.remove(&offset);
     }
+
+    fn pending_pages(&self, offset: LogOffset) -> usize {
+        let inner = self.inner.lock();
+        inner.get(&offset).map_or(0, BTreeSet::len)
+    }
+}
+
+/// A description of one log segment from the garbage
+/// collector's perspective, surfaced through `Db::gc_info`.
+#[derive(Debug, Clone)]
+pub(crate) struct SegmentReport {
+    pub offset: LogOffset,
+    pub state: &'static str,
+    pub live_bytes: u64,
+    pub gc_candidate: bool,
+    pub pages_to_rewrite: usize,
 }
 
 impl Drop for SegmentAccountant {
@@ -465,10 +483,12 @@ impl SegmentAccountant {
             segments: vec![],
             free: BTreeSet::default(),
             tip: 0,
+            preallocated_to: 0,
             max_stabilized_lsn: -1,
             segment_cleaner,
             ordering: BTreeMap::default(),
             async_truncations: BTreeMap::default(),
+            backup_pins: 0,
         };
 
         ret.initialize_from_snapshot(snapshot)?;
@@ -579,13 +599,13 @@ impl SegmentAccountant {
                         &mut segments,
                     );
                     for (lsn, ptr, sz) in frags {
-                        add(pid as PageId, *lsn, *sz, ptr.lid(), &mut segments);
+                        add(pid as PageId, lsn, sz, ptr.lid(), &mut segments);
                     }
                 }
                 PageState::Free(lsn, ptr) => {
                     add(
                         pid as PageId,
-                        *lsn,
+                        lsn,
                         u64::try_from(MAX_MSG_HEADER_LEN).unwrap(),
                         ptr.lid(),
                         &mut segments,
@@ -647,6 +667,16 @@ impl SegmentAccountant {
                 &*vec![MessageKind::Corrupted.into(); self.config.segment_size],
                 segment_base,
             )?;
+            if let Some(mirror) = &self.config.mirror {
+                pwrite_all(
+                    mirror,
+                    &*vec![
+                        MessageKind::Corrupted.into();
+                        self.config.segment_size
+                    ],
+                    segment_base,
+                )?;
+            }
         }
 
         // we want to complete all truncations because
@@ -713,8 +743,14 @@ impl SegmentAccountant {
         // blocking if we allocate a segment that was just truncated.
         let laziness_factor = 1;
 
-        // truncate if possible
-        while self.tip != 0 && self.free.len() > laziness_factor {
+        // truncate if possible, unless a backup is copying the
+        // file and needs its length to remain stable, or we are
+        // a read-only instance that does not own the file
+        while !self.config.read_only
+            && self.backup_pins == 0
+            && self.tip != 0
+            && self.free.len() > laziness_factor
+        {
             let last_segment = self.tip - self.config.segment_size as LogOffset;
             if self.free.contains(&last_segment) {
                 self.free.remove(&last_segment);
@@ -863,12 +899,70 @@ impl SegmentAccountant {
         );
     }
 
+    fn cleanup_threshold_pct(&self) -> usize {
+        (self.config.gc_target_utilization.max(0.01).min(0.99) * 100.)
+            as usize
+    }
+
+    /// Describes every segment for `Db::gc_info`: its state, how
+    /// many live bytes it holds, whether it is at or below the
+    /// cleanup threshold, and how many of its pages are queued
+    /// for rewriting.
+    pub(super) fn segment_report(&self) -> Vec<SegmentReport> {
+        let threshold = self.cleanup_threshold_pct();
+        self.segments
+            .iter()
+            .enumerate()
+            .map(|(idx, segment)| {
+                let offset = (idx * self.config.segment_size) as LogOffset;
+                let (state, live_bytes, gc_candidate) = match segment {
+                    Segment::Free(_) => ("free", 0, false),
+                    Segment::Active(Active { rss, .. }) => {
+                        ("active", *rss, false)
+                    }
+                    Segment::Inactive(Inactive { rss, .. }) => {
+                        let live_pct = rss * 100 / self.config.segment_size;
+                        ("inactive", *rss, live_pct <= threshold)
+                    }
+                    Segment::Draining(_) => ("draining", 0, true),
+                };
+                SegmentReport {
+                    offset,
+                    state,
+                    live_bytes: live_bytes as u64,
+                    gc_candidate,
+                    pages_to_rewrite: self
+                        .segment_cleaner
+                        .pending_pages(offset),
+                }
+            })
+            .collect()
+    }
+
+    /// Queues every inactive segment for draining regardless of
+    /// its utilization, for emergency space recovery. Returns the
+    /// number of segments queued.
+    pub(super) fn drain_all_inactive(&mut self) -> usize {
+        let mut queued = 0;
+        for idx in 0..self.segments.len() {
+            if self.segments[idx].is_inactive() {
+                let segment_start =
+                    (idx * self.config.segment_size) as LogOffset;
+                let lsn = self.segments[idx].lsn();
+                let to_clean = self.segments[idx].inactive_to_draining(lsn);
+                self.segment_cleaner.add_pids(segment_start, to_clean);
+                queued += 1;
+            }
+        }
+        queued
+    }
+
     fn possibly_clean_or_free_segment(
         &mut self,
         idx: usize,
         lsn: Lsn,
     ) -> Result<()> {
-        let cleanup_threshold = SEGMENT_CLEANUP_THRESHOLD;
+        let cleanup_threshold = self.cleanup_threshold_pct();
 
         let segment_start = (idx * self.config.segment_size) as LogOffset;
 
@@ -998,7 +1092,7 @@ impl SegmentAccountant {
             self.segments.iter().filter(|s| s.is_inactive()).count();
         let free_ratio = (free_segs * 100) / (1 + free_segs + inactive_segs);
 
-        if free_ratio >= SEGMENT_CLEANUP_THRESHOLD && inactive_segs > 5 {
+        if free_ratio >= self.cleanup_threshold_pct() && inactive_segs > 5 {
             let last_index =
                 self.segments.iter().rposition(Segment::is_inactive).unwrap();
 
@@ -1029,11 +1123,69 @@ impl SegmentAccountant {
 
         self.tip += self.config.segment_size as LogOffset;
 
+        if self.tip > self.preallocated_to {
+            // grow the file in larger extents than a single segment
+            // if configured, reducing filesystem fragmentation and
+            // the allocation latency spike otherwise paid when each
+            // new segment is created under load.
+            let increment = self.config.segment_growth_increment.max(1);
+            let target = lid
+                + (increment * self.config.segment_size) as LogOffset;
+
+            #[cfg(all(
+                not(miri),
+                any(windows, target_os = "linux", target_os = "macos")
+            ))]
+            {
+                use fs2::FileExt;
+
+                // preallocation is best-effort: not all
+                // filesystems support it.
+                if let Err(e) = self.config.file.allocate(target) {
+                    debug!(
+                        "failed to preallocate file up to length {}: {:?}",
+                        target, e
+                    );
+                }
+                if let Some(mirror) = &self.config.mirror {
+                    if let Err(e) = mirror.allocate(target) {
+                        debug!(
+                            "failed to preallocate mirror up to \
+                             length {}: {:?}",
+                            target, e
+                        );
+                    }
+                }
+            }
+
+            self.preallocated_to = target;
+        }
+
         trace!("advancing file tip from {} to {}", lid, self.tip);
 
         Ok(lid)
     }
 
+    /// Prevents freed segments from being reused or truncated
+    /// away while an online backup copies the file, so that
+    /// offsets that have already been copied are never rewritten
+    /// mid-copy. New segments are allocated from the file tip for
+    /// the duration. Pins nest, and must each be matched by a
+    /// call to `unpin_for_backup`.
+    pub(super) fn pin_for_backup(&mut self) {
+        self.backup_pins += 1;
+    }
+
+    /// Releases a pin taken by `pin_for_backup`, making freed
+    /// segments eligible for reuse and truncation again.
+    pub(super) fn unpin_for_backup(&mut self) {
+        assert!(
+            self.backup_pins > 0,
+            "unpin_for_backup called without a matching pin"
+        );
+        self.backup_pins -= 1;
+    }
+
     /// Returns the next offset to write a new segment in, as well
     /// as whether the corresponding segment must be persisted using
     /// fsync due to having been allocated from the file's tip, rather
@@ -1049,8 +1201,15 @@ impl SegmentAccountant {
 
         trace!("evaluating free list {:?} in SA::next", &self.free);
 
-        // pop free or add to end
-        let safe = self.free.iter().next().copied();
+        // pop free or add to end. while a backup is copying the
+        // file, freed segments must not be reused, as overwriting
+        // an already-copied offset would tear the copy, so new
+        // segments are always allocated from the tip instead.
+        let safe = if self.backup_pins == 0 {
+            self.free.iter().next().copied()
+        } else {
+            None
+        };
 
         let (lid, from_tip) = if let Some(next) = safe {
             self.free.remove(&next);
@@ -1120,6 +1279,7 @@ impl SegmentAccountant {
         );
 
         self.tip = at;
+        self.preallocated_to = self.preallocated_to.min(at);
 
         assert!(!self.free.contains(&at), "double-free of a segment occurred");
 
@@ -1134,6 +1294,13 @@ impl SegmentAccountant {
                 .file
                 .set_len(at)
                 .and_then(|_| config.file.sync_all())
+                .and_then(|_| {
+                    if let Some(mirror) = &config.mirror {
+                        mirror.set_len(at).and_then(|_| mirror.sync_all())
+                    } else {
+                        Ok(())
+                    }
+                })
                 .map_err(|e| e.into());
             completer.fill(res);
         })?;
@@ -1,10 +1,8 @@
-#[cfg(feature = "zstd")]
-use zstd::block::{compress, decompress};
-
 use crate::*;
 
 use super::{
-    arr_to_u32, pwrite_all, raw_segment_iter_from, u32_to_arr, u64_to_arr,
+    arr_to_u32, pwrite_all, pwrite_all_vectored, raw_segment_iter_from,
+    u32_to_arr, u64_to_arr,
     BasedBuf, DiskPtr, HeapId, LogIter, LogKind, LogOffset, Lsn, MessageKind,
 };
 
@@ -18,7 +16,89 @@ pub struct Snapshot {
     /// The last read message lid
     pub active_segment: Option<LogOffset>,
     /// the mapping from pages to (lsn, lid)
-    pub pt: Vec<PageState>,
+    pub pt: SnapshotPageTable,
+}
+
+/// The page table portion of a `Snapshot`. A snapshot read back
+/// from a flat-format snapshot file stays in its on-disk layout,
+/// decoding individual page states on demand, so opening a very
+/// large database does not materialize the whole page table up
+/// front. The dense representation is used while building a new
+/// snapshot in memory, and a flat table converts to it lazily
+/// the first time recovery needs to mutate it.
+#[derive(Clone)]
+pub enum SnapshotPageTable {
+    /// Fully decoded page states, used when building or
+    /// advancing a snapshot.
+    Dense(Vec<PageState>),
+    /// A lazily decoded view over the image of a flat snapshot
+    /// file, possibly memory-mapped directly from disk.
+    Flat { bytes: Arc<SnapshotBytes>, page_count: usize },
+}
+
+impl Default for SnapshotPageTable {
+    fn default() -> SnapshotPageTable {
+        SnapshotPageTable::Dense(vec![])
+    }
+}
+
+impl From<Vec<PageState>> for SnapshotPageTable {
+    fn from(pt: Vec<PageState>) -> SnapshotPageTable {
+        SnapshotPageTable::Dense(pt)
+    }
+}
+
+impl PartialEq for SnapshotPageTable {
+    fn eq(&self, other: &SnapshotPageTable) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl Debug for SnapshotPageTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl SnapshotPageTable {
+    pub fn len(&self) -> usize {
+        match self {
+            SnapshotPageTable::Dense(pt) => pt.len(),
+            SnapshotPageTable::Flat { page_count, .. } => *page_count,
+        }
+    }
+
+    /// Returns the decoded state of one page, or `None` beyond
+    /// the end of the table.
+    pub fn get(&self, pid: usize) -> Option<PageState> {
+        match self {
+            SnapshotPageTable::Dense(pt) => pt.get(pid).cloned(),
+            SnapshotPageTable::Flat { bytes, page_count } => {
+                if pid < *page_count {
+                    Some(decode_flat_page(bytes, pid))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = PageState> + '_ {
+        (0..self.len()).map(move |pid| self.get(pid).unwrap())
+    }
+
+    /// Returns the dense representation, decoding a flat table
+    /// in full the first time a mutation is required.
+    fn dense_mut(&mut self) -> &mut Vec<PageState> {
+        if let SnapshotPageTable::Flat { .. } = self {
+            let dense = self.iter().collect();
+            *self = SnapshotPageTable::Dense(dense);
+        }
+        match self {
+            SnapshotPageTable::Dense(pt) => pt,
+            SnapshotPageTable::Flat { .. } => unreachable!(),
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
@@ -119,6 +199,358 @@ impl PageState {
     }
 }
 
+// The flat snapshot image layout, all integers little-endian:
+//
+//   [magic 8] [stable_lsn i64] [active_segment u64] [page_count u64]
+//   [page_count x u64 record offset]
+//   [records]
+//
+// `stable_lsn` uses `i64::MIN` and `active_segment` uses
+// `u64::MAX` to represent absence. Each record starts with a
+// u32 entry count: 0 marks a free page followed by one
+// [lsn i64][disk ptr] pair, and n > 0 marks a present page
+// followed by n fixed-width [lsn i64][size u64][disk ptr]
+// entries, the first of which is the base. Disk pointers are a
+// fixed 25 bytes: a tag byte, the log offset, and the heap
+// location and original lsn for heap pointers. The fixed-width
+// record offset table is what makes the image usable in place:
+// any page can be decoded directly from the mapped file without
+// deserializing the pages around it.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"sledsnp1";
+const SNAPSHOT_HEADER_LEN: usize = 32;
+const STABLE_LSN_NONE: i64 = i64::MIN;
+const ACTIVE_SEGMENT_NONE: u64 = u64::MAX;
+const DISK_PTR_LEN: usize = 25;
+const FREE_RECORD_LEN: usize = 4 + 8 + DISK_PTR_LEN;
+const PRESENT_ENTRY_LEN: usize = 16 + DISK_PTR_LEN;
+
+fn read_u32_le(buf: &[u8], at: usize) -> u32 {
+    let mut arr = [0; 4];
+    arr.copy_from_slice(&buf[at..at + 4]);
+    u32::from_le_bytes(arr)
+}
+
+fn read_u64_le(buf: &[u8], at: usize) -> u64 {
+    let mut arr = [0; 8];
+    arr.copy_from_slice(&buf[at..at + 8]);
+    u64::from_le_bytes(arr)
+}
+
+pub(in crate::pagecache) fn serialize_flat_snapshot(
+    snapshot: &Snapshot,
+) -> Vec<u8> {
+    let page_count = snapshot.pt.len();
+    let records_base = SNAPSHOT_HEADER_LEN + 8 * page_count;
+
+    let mut records = vec![];
+    let mut offsets = Vec::with_capacity(page_count);
+    for page_state in snapshot.pt.iter() {
+        offsets.push((records_base + records.len()) as u64);
+        encode_flat_page(&mut records, &page_state);
+    }
+
+    let mut buf = Vec::with_capacity(records_base + records.len());
+    buf.extend_from_slice(SNAPSHOT_MAGIC);
+    buf.extend_from_slice(
+        &snapshot.stable_lsn.unwrap_or(STABLE_LSN_NONE).to_le_bytes(),
+    );
+    buf.extend_from_slice(
+        &snapshot
+            .active_segment
+            .unwrap_or(ACTIVE_SEGMENT_NONE)
+            .to_le_bytes(),
+    );
+    buf.extend_from_slice(&(page_count as u64).to_le_bytes());
+    for offset in offsets {
+        buf.extend_from_slice(&offset.to_le_bytes());
+    }
+    buf.extend_from_slice(&records);
+    buf
+}
+
+fn encode_flat_page(buf: &mut Vec<u8>, page_state: &PageState) {
+    match page_state {
+        PageState::Free(lsn, ptr) => {
+            buf.extend_from_slice(&0_u32.to_le_bytes());
+            buf.extend_from_slice(&lsn.to_le_bytes());
+            encode_flat_disk_ptr(buf, ptr);
+        }
+        PageState::Present { base, frags } => {
+            let count = u32::try_from(1 + frags.len()).unwrap();
+            buf.extend_from_slice(&count.to_le_bytes());
+            for (lsn, ptr, sz) in
+                std::iter::once(base).chain(frags.iter())
+            {
+                buf.extend_from_slice(&lsn.to_le_bytes());
+                buf.extend_from_slice(&sz.to_le_bytes());
+                encode_flat_disk_ptr(buf, ptr);
+            }
+        }
+        PageState::Uninitialized => {
+            panic!("tried to serialize {:?}", page_state)
+        }
+    }
+}
+
+fn encode_flat_disk_ptr(buf: &mut Vec<u8>, ptr: &DiskPtr) {
+    match ptr {
+        DiskPtr::Inline(lid) => {
+            buf.push(0);
+            buf.extend_from_slice(&lid.to_le_bytes());
+            buf.extend_from_slice(&[0; 16]);
+        }
+        DiskPtr::Heap(lid, heap_id) => {
+            buf.push(1);
+            buf.extend_from_slice(
+                &lid.map_or(0, std::num::NonZeroU64::get).to_le_bytes(),
+            );
+            buf.extend_from_slice(&heap_id.location.to_le_bytes());
+            buf.extend_from_slice(&heap_id.original_lsn.to_le_bytes());
+        }
+    }
+}
+
+/// Parses and validates the header and record structure of a
+/// flat snapshot image, returning a `Snapshot` whose page table
+/// decodes lazily from the image. Validation only walks the
+/// fixed-width structure; it allocates nothing per page.
+pub(in crate::pagecache) fn deserialize_flat_snapshot(
+    bytes: SnapshotBytes,
+    image_len: usize,
+) -> Result<Snapshot> {
+    let image = &bytes[..image_len];
+    if image.len() < SNAPSHOT_HEADER_LEN
+        || !image.starts_with(SNAPSHOT_MAGIC)
+    {
+        return Err(Error::corruption(None));
+    }
+
+    let stable_lsn = read_u64_le(image, 8) as Lsn;
+    let active_segment = read_u64_le(image, 16);
+    let page_count = if let Ok(page_count) =
+        usize::try_from(read_u64_le(image, 24))
+    {
+        page_count
+    } else {
+        return Err(Error::corruption(None));
+    };
+
+    let records_base = if let Some(records_base) = page_count
+        .checked_mul(8)
+        .and_then(|sz| sz.checked_add(SNAPSHOT_HEADER_LEN))
+    {
+        records_base
+    } else {
+        return Err(Error::corruption(None));
+    };
+    if records_base > image_len {
+        return Err(Error::corruption(None));
+    }
+
+    for pid in 0..page_count {
+        let offset = usize::try_from(read_u64_le(
+            image,
+            SNAPSHOT_HEADER_LEN + 8 * pid,
+        ))
+        .map_err(|_| Error::corruption(None))?;
+        validate_flat_record(image, records_base, offset)?;
+    }
+
+    Ok(Snapshot {
+        stable_lsn: if stable_lsn == STABLE_LSN_NONE {
+            None
+        } else {
+            Some(stable_lsn)
+        },
+        active_segment: if active_segment == ACTIVE_SEGMENT_NONE {
+            None
+        } else {
+            Some(active_segment)
+        },
+        pt: SnapshotPageTable::Flat { bytes: Arc::new(bytes), page_count },
+    })
+}
+
+fn validate_flat_record(
+    image: &[u8],
+    records_base: usize,
+    offset: usize,
+) -> Result<()> {
+    if offset < records_base
+        || offset.checked_add(4).map_or(true, |end| end > image.len())
+    {
+        return Err(Error::corruption(None));
+    }
+    let count = usize::try_from(read_u32_le(image, offset)).unwrap();
+    let record_len = if count == 0 {
+        FREE_RECORD_LEN
+    } else {
+        match count
+            .checked_mul(PRESENT_ENTRY_LEN)
+            .and_then(|sz| sz.checked_add(4))
+        {
+            Some(record_len) => record_len,
+            None => return Err(Error::corruption(None)),
+        }
+    };
+    if offset.checked_add(record_len).map_or(true, |end| end > image.len())
+    {
+        return Err(Error::corruption(None));
+    }
+
+    // the disk pointer tags are the only non-numeric fields, so
+    // checking them here lets decoding be infallible later
+    let ptr_tag_offsets: Vec<usize> = if count == 0 {
+        vec![offset + 12]
+    } else {
+        (0..count)
+            .map(|idx| offset + 4 + idx * PRESENT_ENTRY_LEN + 16)
+            .collect()
+    };
+    for tag_offset in ptr_tag_offsets {
+        if image[tag_offset] > 1 {
+            return Err(Error::corruption(None));
+        }
+    }
+    Ok(())
+}
+
+/// Decodes the state of one page directly from a validated flat
+/// snapshot image.
+fn decode_flat_page(bytes: &SnapshotBytes, pid: usize) -> PageState {
+    let offset = usize::try_from(read_u64_le(
+        bytes,
+        SNAPSHOT_HEADER_LEN + 8 * pid,
+    ))
+    .unwrap();
+    let record = &bytes[offset..];
+    let count = usize::try_from(read_u32_le(record, 0)).unwrap();
+    if count == 0 {
+        let lsn = read_u64_le(record, 4) as Lsn;
+        let ptr = decode_flat_disk_ptr(&record[12..12 + DISK_PTR_LEN]);
+        PageState::Free(lsn, ptr)
+    } else {
+        let entry = |idx: usize| {
+            let base = 4 + idx * PRESENT_ENTRY_LEN;
+            let lsn = read_u64_le(record, base) as Lsn;
+            let sz = read_u64_le(record, base + 8);
+            let ptr = decode_flat_disk_ptr(
+                &record[base + 16..base + 16 + DISK_PTR_LEN],
+            );
+            (lsn, ptr, sz)
+        };
+        PageState::Present {
+            base: entry(0),
+            frags: (1..count).map(entry).collect(),
+        }
+    }
+}
+
+fn decode_flat_disk_ptr(buf: &[u8]) -> DiskPtr {
+    let lid = read_u64_le(buf, 1);
+    match buf[0] {
+        0 => DiskPtr::Inline(lid),
+        1 => DiskPtr::Heap(
+            std::num::NonZeroU64::new(lid),
+            HeapId {
+                location: read_u64_le(buf, 9),
+                original_lsn: read_u64_le(buf, 17) as Lsn,
+            },
+        ),
+        _ => unreachable!("invalid disk pointer tag survived validation"),
+    }
+}
+
+/// The raw bytes backing a flat snapshot image: either buffered
+/// in memory, which is always the case for compressed snapshot
+/// files whose images only exist decompressed in memory, or
+/// memory-mapped directly from the snapshot file.
+pub enum SnapshotBytes {
+    Owned(Vec<u8>),
+    #[cfg(all(unix, not(miri)))]
+    Mapped(Mapping),
+}
+
+impl std::ops::Deref for SnapshotBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            SnapshotBytes::Owned(buf) => buf,
+            #[cfg(all(unix, not(miri)))]
+            SnapshotBytes::Mapped(mapping) => mapping,
+        }
+    }
+}
+
+/// A read-only memory mapping of a snapshot file, unmapped on
+/// drop.
+#[cfg(all(unix, not(miri)))]
+pub struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+#[cfg(all(unix, not(miri)))]
+#[allow(unsafe_code)]
+impl Mapping {
+    fn map(
+        file: &std::fs::File,
+        len: usize,
+    ) -> std::io::Result<Mapping> {
+        use std::os::unix::io::AsRawFd;
+
+        assert_ne!(len, 0);
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            Err(std::io::Error::last_os_error())
+        } else {
+            Ok(Mapping { ptr, len })
+        }
+    }
+}
+
+#[cfg(all(unix, not(miri)))]
+#[allow(unsafe_code)]
+impl std::ops::Deref for Mapping {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe {
+            std::slice::from_raw_parts(self.ptr as *const u8, self.len)
+        }
+    }
+}
+
+#[cfg(all(unix, not(miri)))]
+#[allow(unsafe_code)]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+// the mapping is read-only for its entire lifetime, so it is
+// safe to share across threads
+#[cfg(all(unix, not(miri)))]
+#[allow(unsafe_code)]
+unsafe impl Send for Mapping {}
+
+#[cfg(all(unix, not(miri)))]
+#[allow(unsafe_code)]
+unsafe impl Sync for Mapping {}
+
 impl Snapshot {
     pub fn recovered_coords(
         &self,
@@ -159,8 +591,10 @@ impl Snapshot {
         );
         let _measure = Measure::new(&M.snapshot_apply);
 
-        let pushed = if self.pt.len() <= usize::try_from(pid).unwrap() {
-            self.pt.resize(
+        let pt = self.pt.dense_mut();
+
+        let pushed = if pt.len() <= usize::try_from(pid).unwrap() {
+            pt.resize(
                 usize::try_from(pid + 1).unwrap(),
                 PageState::Uninitialized,
             );
@@ -180,7 +614,7 @@ impl Snapshot {
 
                 let pid_usize = usize::try_from(pid).unwrap();
 
-                self.pt[pid_usize] = PageState::Present {
+                pt[pid_usize] = PageState::Present {
                     base: (lsn, disk_ptr, sz),
                     frags: vec![],
                 };
@@ -190,7 +624,7 @@ impl Snapshot {
                 // a page's initial Compact to a later segment. We should skip
                 // over pages here unless we've encountered a Compact for them.
                 if let Some(lids @ PageState::Present { .. }) =
-                    self.pt.get_mut(usize::try_from(pid).unwrap())
+                    pt.get_mut(usize::try_from(pid).unwrap())
                 {
                     trace!(
                         "append of pid {} at lid {} lsn {}",
@@ -208,7 +642,7 @@ impl Snapshot {
                         lsn,
                     );
                     if pushed {
-                        let old = self.pt.pop().unwrap();
+                        let old = pt.pop().unwrap();
                         if old != PageState::Uninitialized {
                             error!(
                                 "expected previous page state to be uninitialized"
@@ -220,7 +654,7 @@ impl Snapshot {
             }
             LogKind::Free => {
                 trace!("free of pid {} at ptr {} lsn {}", pid, disk_ptr, lsn);
-                self.pt[usize::try_from(pid).unwrap()] =
+                pt[usize::try_from(pid).unwrap()] =
                     PageState::Free(lsn, disk_ptr);
             }
             LogKind::Corrupted | LogKind::Skip => {
@@ -236,7 +670,7 @@ impl Snapshot {
     }
 
     fn filter_inner_heap_ids(&mut self) {
-        for page in &mut self.pt {
+        for page in self.pt.dense_mut() {
             match page {
                 PageState::Free(_lsn, ref mut ptr) => {
                     ptr.forget_heap_log_coordinates()
@@ -376,6 +810,10 @@ fn advance_snapshot(
                 );
                 pwrite_all(&config.file, &shred_zone, shred_base)?;
                 config.file.sync_all()?;
+                if let Some(mirror) = &config.mirror {
+                    pwrite_all(mirror, &shred_zone, shred_base)?;
+                    mirror.sync_all()?;
+                }
             }
             (iterated_lsn, iter.segment_base.map(|bb| bb.offset))
         };
@@ -467,8 +905,18 @@ fn advance_snapshot(
             &*vec![MessageKind::Corrupted.into(); config.segment_size],
             *to_zero,
         )?;
+        if let Some(mirror) = &config.mirror {
+            pwrite_all(
+                mirror,
+                &*vec![MessageKind::Corrupted.into(); config.segment_size],
+                *to_zero,
+            )?;
+        }
         if !config.temporary {
             config.file.sync_all()?;
+            if let Some(mirror) = &config.mirror {
+                mirror.sync_all()?;
+            }
         }
     }
 
@@ -506,28 +954,70 @@ fn read_snapshot(config: &RunningConfig) -> Result<Option<Snapshot>> {
     candidates.sort();
     let path = candidates.pop().unwrap();
 
-    let mut f = std::fs::OpenOptions::new().read(true).open(&path)?;
+    match read_snapshot_from(&path, config) {
+        Ok(snapshot) => Ok(snapshot),
+        Err(primary_error) => {
+            // fall back to the redundant copy written alongside
+            // the primary, which protects against a corrupted
+            // primary snapshot file.
+            let backup_path = backup_snapshot_path(&path);
+            warn!(
+                "failed to read primary snapshot file {:?}: {:?}, \
+                 attempting backup copy {:?}",
+                path, primary_error, backup_path
+            );
+            match read_snapshot_from(&backup_path, config) {
+                Ok(snapshot) => Ok(snapshot),
+                Err(backup_error) => {
+                    warn!(
+                        "failed to read backup snapshot file {:?}: {:?}",
+                        backup_path, backup_error
+                    );
+                    Err(primary_error)
+                }
+            }
+        }
+    }
+}
 
-    let mut buf = vec![];
-    let _read = f.read_to_end(&mut buf)?;
-    let len = buf.len();
+fn backup_snapshot_path(path: &std::path::Path) -> std::path::PathBuf {
+    let mut name = path.file_name().unwrap().to_os_string();
+    name.push(".bak");
+    path.with_file_name(name)
+}
+
+fn read_snapshot_from(
+    path: &std::path::Path,
+    config: &RunningConfig,
+) -> Result<Option<Snapshot>> {
+    let f = std::fs::OpenOptions::new().read(true).open(path)?;
+
+    let len = usize::try_from(f.metadata()?.len()).unwrap();
     if len <= 12 {
         warn!("empty/corrupt snapshot file found at path: {:?}", path);
         return Err(Error::corruption(None));
     }
 
+    // map the file rather than reading it into memory where
+    // possible: for uncompressed flat snapshots the mapping is
+    // retained and decoded from directly, so the page table is
+    // never materialized up front
+    let bytes = read_or_map_snapshot_file(&f, len)?;
+
+    let image_len = len - 12;
+
     let mut len_expected_bytes = [0; 8];
-    len_expected_bytes.copy_from_slice(&buf[len - 12..len - 4]);
+    len_expected_bytes.copy_from_slice(&bytes[len - 12..len - 4]);
 
     let mut crc_expected_bytes = [0; 4];
-    crc_expected_bytes.copy_from_slice(&buf[len - 4..]);
-
-    let _ = buf.split_off(len - 12);
+    crc_expected_bytes.copy_from_slice(&bytes[len - 4..]);
     let crc_expected: u32 = arr_to_u32(&crc_expected_bytes);
 
-    let crc_actual = crc32(&buf);
-
-    if crc_expected != crc_actual {
+    if !checksum::verify(
+        crc_expected,
+        &bytes[..image_len],
+        config.checksum_algorithm,
+    ) {
         warn!(
             "corrupt snapshot file found, crc does not match expected. \
             path: {:?}",
@@ -536,44 +1026,107 @@ fn read_snapshot(config: &RunningConfig) -> Result<Option<Snapshot>> {
         return Err(Error::corruption(None));
     }
 
-    #[cfg(feature = "zstd")]
-    let bytes = if config.use_compression {
+    let (bytes, image_len) = if config.use_compression {
         use std::convert::TryInto;
 
         let len_expected: u64 =
             u64::from_le_bytes(len_expected_bytes.as_ref().try_into().unwrap());
 
-        decompress(&*buf, usize::try_from(len_expected).unwrap()).unwrap()
+        let dictionary = config.zstd_dictionary.get();
+        let decompressed = compression::decompress_frame(
+            &bytes[..image_len],
+            dictionary.as_deref().map(|d| &d[..]),
+        )?;
+
+        if decompressed.len() as u64 != len_expected {
+            warn!(
+                "corrupt snapshot file found, decompressed length \
+                does not match expected. path: {:?}",
+                path
+            );
+            return Err(Error::corruption(None));
+        }
+
+        let decompressed_len = decompressed.len();
+        (SnapshotBytes::Owned(decompressed), decompressed_len)
     } else {
-        buf
+        (bytes, image_len)
     };
 
-    #[cfg(not(feature = "zstd"))]
-    let bytes = buf;
+    if bytes[..image_len].starts_with(SNAPSHOT_MAGIC) {
+        deserialize_flat_snapshot(bytes, image_len).map(Some)
+    } else {
+        // a snapshot written by a version that serialized the
+        // whole page table; decode it densely
+        Snapshot::deserialize(&mut &bytes[..image_len]).map(Some)
+    }
+}
+
+#[cfg(all(unix, not(miri)))]
+fn read_or_map_snapshot_file(
+    f: &std::fs::File,
+    len: usize,
+) -> Result<SnapshotBytes> {
+    match Mapping::map(f, len) {
+        Ok(mapping) => Ok(SnapshotBytes::Mapped(mapping)),
+        Err(e) => {
+            debug!(
+                "failed to map snapshot file, \
+                falling back to a buffered read: {:?}",
+                e
+            );
+            read_snapshot_file(f, len)
+        }
+    }
+}
+
+#[cfg(not(all(unix, not(miri))))]
+fn read_or_map_snapshot_file(
+    f: &std::fs::File,
+    len: usize,
+) -> Result<SnapshotBytes> {
+    read_snapshot_file(f, len)
+}
 
-    Snapshot::deserialize(&mut bytes.as_slice()).map(Some)
+fn read_snapshot_file(
+    mut f: &std::fs::File,
+    len: usize,
+) -> Result<SnapshotBytes> {
+    let mut buf = Vec::with_capacity(len);
+    let _read = f.read_to_end(&mut buf)?;
+    Ok(SnapshotBytes::Owned(buf))
 }
 
 pub(in crate::pagecache) fn write_snapshot(
     config: &RunningConfig,
     snapshot: &Snapshot,
 ) -> Result<()> {
+    if config.read_only {
+        // a read-only instance must not write into a data
+        // directory that a live writer owns; it simply pays
+        // for a longer log read on its next open
+        return Ok(());
+    }
+
     trace!("writing snapshot {:?}", snapshot);
 
-    let raw_bytes = snapshot.serialize();
+    let raw_bytes = serialize_flat_snapshot(snapshot);
     let decompressed_len = raw_bytes.len();
 
-    #[cfg(feature = "zstd")]
     let bytes = if config.use_compression {
-        compress(&*raw_bytes, config.compression_factor).unwrap()
+        let dictionary = config.zstd_dictionary.get();
+        compression::compress_frame(
+            config.compression_algorithm,
+            &raw_bytes,
+            config.compression_factor,
+            dictionary.as_deref().map(|d| &d[..]),
+        )
     } else {
         raw_bytes
     };
 
-    #[cfg(not(feature = "zstd"))]
-    let bytes = raw_bytes;
-
-    let crc32: [u8; 4] = u32_to_arr(crc32(&bytes));
+    let crc32: [u8; 4] =
+        u32_to_arr(checksum::hash(config.checksum_algorithm, &bytes));
     let len_bytes: [u8; 8] = u64_to_arr(decompressed_len as u64);
 
     let path_1_suffix =
@@ -590,16 +1143,15 @@ pub(in crate::pagecache) fn write_snapshot(
 
     let parent = path_1.parent().unwrap();
     std::fs::create_dir_all(parent)?;
-    let mut f =
+    let f =
         std::fs::OpenOptions::new().write(true).create(true).open(&path_1)?;
 
-    // write the snapshot bytes, followed by a crc64 checksum at the end
+    // write the snapshot bytes, followed by a crc64 checksum at
+    // the end, gathered into a single vectored write
     io_fail!(config, "snap write");
-    f.write_all(&*bytes)?;
     io_fail!(config, "snap write len");
-    f.write_all(&len_bytes)?;
     io_fail!(config, "snap write crc");
-    f.write_all(&crc32)?;
+    pwrite_all_vectored(&f, &[&bytes, &len_bytes, &crc32], 0)?;
     io_fail!(config, "snap write post");
     f.sync_all()?;
 
@@ -613,6 +1165,22 @@ pub(in crate::pagecache) fn write_snapshot(
 
     trace!("renamed snapshot to {}", path_2.to_string_lossy());
 
+    // write a redundant copy of the snapshot at a different file,
+    // which read_snapshot falls back to if the primary is corrupt.
+    // the generation is carried in the file name's stable lsn.
+    let backup_path = backup_snapshot_path(&path_2);
+    let backup_tmp_path = backup_path.with_extension("bak.generating");
+    let backup_f = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .open(&backup_tmp_path)?;
+    pwrite_all_vectored(&backup_f, &[&bytes, &len_bytes, &crc32], 0)?;
+    backup_f.sync_all()?;
+    std::fs::rename(&backup_tmp_path, &backup_path)?;
+    maybe_fsync_directory(config.get_path())?;
+
+    trace!("wrote backup snapshot to {}", backup_path.to_string_lossy());
+
     // clean up any old snapshots
     let candidates = config.get_snapshot_files()?;
     for path in candidates {
@@ -629,7 +1197,93 @@ pub(in crate::pagecache) fn write_snapshot(
                     e
                 );
             }
+
+            // remove the redundant copy along with its primary
+            let old_backup_path = backup_snapshot_path(&path);
+            if old_backup_path.exists() {
+                if let Err(e) = std::fs::remove_file(&old_backup_path) {
+                    warn!(
+                        "failed to remove old backup snapshot file,                          maybe snapshot race? {}",
+                        e
+                    );
+                }
+            }
         }
     }
     Ok(())
 }
+
+#[test]
+fn flat_snapshot_roundtrip() {
+    let snapshot = Snapshot {
+        stable_lsn: Some(4096),
+        active_segment: None,
+        pt: vec![
+            PageState::Present {
+                base: (1, DiskPtr::new_inline(77), 42),
+                frags: vec![
+                    (
+                        2,
+                        DiskPtr::new_heap_item(99, HeapId::compose(3, 8, 2)),
+                        11,
+                    ),
+                    (4, DiskPtr::Heap(None, HeapId::compose(1, 2, 3)), 9),
+                ],
+            },
+            PageState::Free(5, DiskPtr::new_inline(123)),
+        ]
+        .into(),
+    };
+
+    let image = serialize_flat_snapshot(&snapshot);
+    let image_len = image.len();
+    let recovered =
+        deserialize_flat_snapshot(SnapshotBytes::Owned(image), image_len)
+            .unwrap();
+
+    assert_eq!(snapshot, recovered);
+    assert_eq!(
+        recovered.pt.get(1),
+        Some(PageState::Free(5, DiskPtr::new_inline(123)))
+    );
+    assert_eq!(recovered.pt.get(2), None);
+
+    let empty = Snapshot::default();
+    let image = serialize_flat_snapshot(&empty);
+    let image_len = image.len();
+    let recovered =
+        deserialize_flat_snapshot(SnapshotBytes::Owned(image), image_len)
+            .unwrap();
+    assert_eq!(empty, recovered);
+}
+
+#[test]
+fn flat_snapshot_rejects_truncation() {
+    let snapshot = Snapshot {
+        stable_lsn: Some(512),
+        active_segment: Some(8192),
+        pt: vec![
+            PageState::Present {
+                base: (1, DiskPtr::new_inline(77), 42),
+                frags: vec![],
+            },
+            PageState::Free(5, DiskPtr::new_inline(123)),
+        ]
+        .into(),
+    };
+
+    let image = serialize_flat_snapshot(&snapshot);
+    for truncated_len in 0..image.len() {
+        let truncated = image[..truncated_len].to_vec();
+        assert!(
+            deserialize_flat_snapshot(
+                SnapshotBytes::Owned(truncated),
+                truncated_len,
+            )
+            .is_err(),
+            "truncation to {} bytes of {} was not detected",
+            truncated_len,
+            image.len(),
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use super::*;
+
+/// A description of the internal thread panic that poisoned a
+/// `Db`, taken via `Db::take_poison_report`.
+#[derive(Debug, Clone)]
+pub struct PoisonReport {
+    /// The name of the internal thread that panicked.
+    pub thread: String,
+    /// The rendered panic message.
+    pub message: String,
+}
+
+/// Handles a panic caught at the boundary of an internal thread,
+/// either poisoning the `Db` or aborting the process, depending
+/// on the `abort_on_internal_panic` configuration option.
+#[cfg(all(
+    not(miri),
+    any(
+        windows,
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+    )
+))]
+#[derive(Debug, Clone)]
+pub(crate) struct PanicHandler {
+    pub(crate) thread: &'static str,
+    pub(crate) config: RunningConfig,
+    pub(crate) report: Arc<Mutex<Option<PoisonReport>>>,
+}
+
+#[cfg(all(
+    not(miri),
+    any(
+        windows,
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+    )
+))]
+impl PanicHandler {
+    pub(crate) fn handle(&self, panic: Box<dyn std::any::Any + Send>) {
+        let message = if let Some(message) = panic.downcast_ref::<&str>() {
+            (*message).to_string()
+        } else if let Some(message) = panic.downcast_ref::<String>() {
+            message.clone()
+        } else {
+            "non-string panic payload".to_string()
+        };
+
+        if self.config.abort_on_internal_panic {
+            error!(
+                "aborting the process because the internal {} \
+                 thread panicked: {}",
+                self.thread, message
+            );
+            std::process::abort();
+        }
+
+        error!(
+            "poisoning the database because the internal {} \
+             thread panicked: {}",
+            self.thread, message
+        );
+
+        *self.report.lock() = Some(PoisonReport {
+            thread: self.thread.to_string(),
+            message: message.clone(),
+        });
+
+        self.config.set_global_error(Error::Poisoned(format!(
+            "the internal {} thread panicked: {}",
+            self.thread, message
+        )));
+    }
+}
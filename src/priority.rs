@@ -0,0 +1,124 @@
+//! Per-thread operation priorities.
+//!
+//! When bulk work — migrations, backfills, rebuilds — shares a
+//! process with interactive traffic, both classes otherwise
+//! compete equally for the tree and the disk, inflating
+//! interactive latency. Tagging the bulk threads with
+//! [`Priority::Batch`] via [`set_thread_priority`] makes their
+//! operations defer briefly to any interactive work in flight,
+//! smoothing interactive tail latency without starving the bulk
+//! work: the deferral per operation is bounded, so batch
+//! progress is only slowed, never stopped.
+
+use std::{
+    cell::Cell,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use parking_lot::{Condvar, Mutex};
+
+use crate::Lazy;
+
+/// The longest a single batch-priority operation will defer to
+/// in-flight interactive work before proceeding anyway.
+const MAX_BATCH_DEFER: Duration = Duration::from_millis(2);
+
+/// The scheduling class for operations issued by a thread, set
+/// via [`set_thread_priority`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Latency-sensitive work. Operations proceed immediately
+    /// and in-flight ones cause concurrent batch-priority
+    /// operations to defer. This is the default for every
+    /// thread.
+    Interactive,
+    /// Throughput-oriented work. Each operation defers briefly
+    /// to in-flight interactive operations before proceeding.
+    Batch,
+}
+
+thread_local! {
+    static PRIORITY: Cell<Priority> = Cell::new(Priority::Interactive);
+}
+
+static INTERACTIVE_INFLIGHT: AtomicU64 = AtomicU64::new(0);
+
+struct Gate {
+    mu: Mutex<()>,
+    cv: Condvar,
+}
+
+static GATE: Lazy<Gate, fn() -> Gate> =
+    Lazy::new(|| Gate { mu: Mutex::new(()), cv: Condvar::new() });
+
+/// Tags the calling thread with a priority that subsequent
+/// operations on any `Tree` will carry, so bulk migrations
+/// running in the same process don't inflate interactive read
+/// latency. The tag lasts until the thread sets a different one.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// use sled::Priority;
+///
+/// // backfill thread: defer to interactive traffic
+/// sled::set_thread_priority(Priority::Batch);
+/// for i in 0..10_u32 {
+///     db.insert(&i.to_be_bytes(), vec![])?;
+/// }
+///
+/// sled::set_thread_priority(Priority::Interactive);
+/// assert!(db.get(&0_u32.to_be_bytes())?.is_some());
+/// # Ok(()) }
+/// ```
+pub fn set_thread_priority(priority: Priority) {
+    PRIORITY.with(|cell| cell.set(priority));
+}
+
+/// Tracks one in-flight operation for priority accounting,
+/// released on drop.
+pub(crate) struct PriorityPermit {
+    interactive: bool,
+}
+
+/// Called at the start of foreground operations. Interactive
+/// threads register themselves so batch work can defer to them;
+/// batch threads wait (briefly, and bounded) for in-flight
+/// interactive operations to drain.
+pub(crate) fn enter() -> PriorityPermit {
+    match PRIORITY.with(Cell::get) {
+        Priority::Interactive => {
+            INTERACTIVE_INFLIGHT.fetch_add(1, Ordering::Release);
+            PriorityPermit { interactive: true }
+        }
+        Priority::Batch => {
+            if INTERACTIVE_INFLIGHT.load(Ordering::Acquire) > 0 {
+                let deadline = Instant::now() + MAX_BATCH_DEFER;
+                let mut guard = GATE.mu.lock();
+                while INTERACTIVE_INFLIGHT.load(Ordering::Acquire) > 0 {
+                    if GATE.cv.wait_until(&mut guard, deadline).timed_out() {
+                        break;
+                    }
+                }
+            }
+            PriorityPermit { interactive: false }
+        }
+    }
+}
+
+impl Drop for PriorityPermit {
+    fn drop(&mut self) {
+        if self.interactive
+            && INTERACTIVE_INFLIGHT.fetch_sub(1, Ordering::AcqRel) == 1
+        {
+            // linearize with batch waiters between their counter
+            // check and their wait
+            drop(GATE.mu.lock());
+            GATE.cv.notify_all();
+        }
+    }
+}
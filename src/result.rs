@@ -54,6 +54,30 @@ pub enum Error {
         #[cfg(not(feature = "testing"))]
         bt: (),
     },
+    /// An internal thread has panicked, and the instance has been
+    /// poisoned to keep subsequent operations from building on
+    /// undefined state. Details about the panic are available via
+    /// `Db::take_poison_report`.
+    Poisoned(String),
+    /// The database was opened with `Config::read_only` and a
+    /// mutation was attempted.
+    ReadOnly,
+    /// A write was rejected because another record already owns
+    /// the derived value in a unique secondary index.
+    UniqueViolation {
+        /// The name of the unique index that rejected the write.
+        index: IVec,
+        /// The derived value that is already present in the index.
+        value: IVec,
+        /// The primary key of the record that owns the value.
+        owner: IVec,
+    },
+    /// The operation was stopped early because its
+    /// `CancellationToken` was cancelled.
+    Cancelled,
+    /// The operation was stopped early because it could not
+    /// complete before its deadline.
+    TimedOut,
     // a failpoint has been triggered for testing purposes
     #[doc(hidden)]
     #[cfg(feature = "failpoints")]
@@ -82,6 +106,15 @@ impl Clone for Error {
             Unsupported(why) => Unsupported(why.clone()),
             ReportableBug(what) => ReportableBug(what.clone()),
             Corruption { at, bt } => Corruption { at: *at, bt: bt.clone() },
+            Poisoned(why) => Poisoned(why.clone()),
+            ReadOnly => ReadOnly,
+            UniqueViolation { index, value, owner } => UniqueViolation {
+                index: index.clone(),
+                value: value.clone(),
+                owner: owner.clone(),
+            },
+            Cancelled => Cancelled,
+            TimedOut => TimedOut,
             #[cfg(feature = "failpoints")]
             FailPoint => FailPoint,
         }
@@ -116,6 +149,46 @@ impl PartialEq for Error {
                     false
                 }
             }
+            Poisoned(ref l) => {
+                if let Poisoned(ref r) = *other {
+                    l == r
+                } else {
+                    false
+                }
+            }
+            ReadOnly => {
+                if let ReadOnly = *other {
+                    true
+                } else {
+                    false
+                }
+            }
+            UniqueViolation { index: ref li, value: ref lv, owner: ref lo } => {
+                if let UniqueViolation {
+                    index: ref ri,
+                    value: ref rv,
+                    owner: ref ro,
+                } = *other
+                {
+                    li == ri && lv == rv && lo == ro
+                } else {
+                    false
+                }
+            }
+            Cancelled => {
+                if let Cancelled = *other {
+                    true
+                } else {
+                    false
+                }
+            }
+            TimedOut => {
+                if let TimedOut = *other {
+                    true
+                } else {
+                    false
+                }
+            }
             #[cfg(feature = "failpoints")]
             FailPoint => {
                 if let FailPoint = *other {
@@ -168,6 +241,26 @@ impl From<Error> for io::Error {
                 ErrorKind::InvalidData,
                 format!("corruption encountered: {:?}", error),
             ),
+            Poisoned(why) => io::Error::new(
+                ErrorKind::Other,
+                format!("poisoned by an internal thread panic: {}", why),
+            ),
+            ReadOnly => io::Error::new(
+                ErrorKind::PermissionDenied,
+                "database opened in read-only mode",
+            ),
+            UniqueViolation { .. } => io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!("unique index violation: {:?}", error),
+            ),
+            Cancelled => io::Error::new(
+                ErrorKind::Interrupted,
+                "operation cancelled via CancellationToken",
+            ),
+            TimedOut => io::Error::new(
+                ErrorKind::TimedOut,
+                "operation missed its deadline",
+            ),
             #[cfg(feature = "failpoints")]
             FailPoint => io::Error::new(ErrorKind::Other, "failpoint"),
         }
@@ -202,6 +295,24 @@ impl Display for Error {
                 "Read corrupted data at file offset {:?} backtrace {:?}",
                 at, bt
             ),
+            Poisoned(ref why) => write!(
+                f,
+                "Poisoned by an internal thread panic: {}",
+                why
+            ),
+            ReadOnly => {
+                write!(f, "Database opened in read-only mode")
+            }
+            UniqueViolation { ref index, ref value, ref owner } => write!(
+                f,
+                "Unique index {:?} violation: value {:?} is already \
+                 owned by record {:?}",
+                index, value, owner
+            ),
+            Cancelled => {
+                write!(f, "Operation cancelled via CancellationToken")
+            }
+            TimedOut => write!(f, "Operation missed its deadline"),
         }
     }
 }
@@ -0,0 +1,156 @@
+use std::panic::AssertUnwindSafe;
+use std::thread;
+use std::time::Duration;
+
+use parking_lot::{Condvar, Mutex};
+
+use super::flusher::ShutdownState;
+use super::*;
+
+const MILLIS_PER_HOUR: u64 = 60 * 60 * 1000;
+
+/// A background thread that reads back log segments from disk at a
+/// configurable hourly rate, surfacing latent media corruption
+/// before a critical read path hits it. Detected problems are
+/// reported through the log and counted, so that operators can
+/// restore the affected data from backups or redundant copies.
+#[derive(Debug)]
+pub(crate) struct Scrubber {
+    shutdown: Arc<Mutex<ShutdownState>>,
+    sc: Arc<Condvar>,
+    join_handle: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl Scrubber {
+    /// Spawns a thread that scrubs `segments_per_hour` segments
+    /// per hour until dropped, incrementing `error_count` for
+    /// every segment that fails to read back.
+    pub(crate) fn new(
+        name: String,
+        pagecache: PageCache,
+        error_count: Arc<AtomicU64>,
+        segments_per_hour: u64,
+        progress: Arc<AtomicU64>,
+        panic_handler: poison::PanicHandler,
+    ) -> Self {
+        #[allow(clippy::mutex_atomic)] // mutex used in CondVar below
+        let shutdown = Arc::new(Mutex::new(ShutdownState::Running));
+        let sc = Arc::new(Condvar::new());
+
+        let join_handle = thread::Builder::new()
+            .name(name.clone())
+            .spawn({
+                let shutdown = shutdown.clone();
+                let sc = sc.clone();
+                let hook_name = name.clone();
+                move || {
+                    thread_lifecycle::thread_spawned(&hook_name);
+                    let result =
+                        std::panic::catch_unwind(AssertUnwindSafe(|| {
+                            run(
+                                &shutdown,
+                                &sc,
+                                &pagecache,
+                                &error_count,
+                                segments_per_hour,
+                                &progress,
+                            )
+                        }));
+                    if let Err(panic) = result {
+                        panic_handler.handle(panic);
+
+                        // unblock any `Drop` handshake waiting for
+                        // the thread to shut down.
+                        *shutdown.lock() = ShutdownState::ShutDown;
+                        let _notified = sc.notify_all();
+                    }
+                    thread_lifecycle::thread_exiting(&hook_name);
+                }
+            })
+            .unwrap();
+
+        Self { shutdown, sc, join_handle: Mutex::new(Some(join_handle)) }
+    }
+}
+
+impl Scrubber {
+    /// Returns `true` if the background thread has stopped or is
+    /// in the process of stopping.
+    pub(crate) fn is_stopped(&self) -> bool {
+        !self.shutdown.lock().is_running()
+    }
+}
+
+fn run(
+    shutdown: &Arc<Mutex<ShutdownState>>,
+    sc: &Arc<Condvar>,
+    pagecache: &PageCache,
+    error_count: &Arc<AtomicU64>,
+    segments_per_hour: u64,
+    progress: &Arc<AtomicU64>,
+) {
+    let interval =
+        Duration::from_millis(MILLIS_PER_HOUR / segments_per_hour.max(1));
+    let mut cursor = 0;
+    let mut shutdown = shutdown.lock();
+    while shutdown.is_running() {
+        progress.store(watchdog::now_millis(), SeqCst);
+        sc.wait_for(&mut shutdown, interval);
+        if !shutdown.is_running() {
+            break;
+        }
+
+        #[cfg(feature = "otel")]
+        let _span = otel::background_span("sled.scrub_segment");
+
+        match pagecache.scrub_segment(cursor) {
+            Ok(true) => {
+                trace!("scrubbed segment {}", cursor);
+                cursor += 1;
+            }
+            Ok(false) => {
+                // wrapped past the end of the file,
+                // start over from the beginning.
+                cursor = 0;
+            }
+            Err(e) => {
+                error!(
+                    "background scrubber failed to read \
+                     back segment {}: {}",
+                    cursor, e
+                );
+                error_count.fetch_add(1, SeqCst);
+                cursor += 1;
+            }
+        }
+    }
+
+    *shutdown = ShutdownState::ShutDown;
+
+    // having held the mutex makes this linearized
+    // with the notify below.
+    drop(shutdown);
+
+    let _notified = sc.notify_all();
+}
+
+impl Drop for Scrubber {
+    fn drop(&mut self) {
+        let mut shutdown = self.shutdown.lock();
+        if shutdown.is_running() {
+            *shutdown = ShutdownState::ShuttingDown;
+            let _notified = self.sc.notify_all();
+        }
+
+        while !shutdown.is_shutdown() {
+            let _ = self.sc.wait_for(&mut shutdown, Duration::from_millis(100));
+        }
+
+        let mut join_handle_opt = self.join_handle.lock();
+        if let Some(join_handle) = join_handle_opt.take() {
+            if let Err(e) = join_handle.join() {
+                error!("error joining Scrubber thread: {:?}", e);
+            }
+        }
+    }
+}
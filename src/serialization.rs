@@ -468,7 +468,7 @@ impl Serialize for Snapshot {
         self.stable_lsn.serialize_into(buf);
         self.active_segment.serialize_into(buf);
         (self.pt.len() as u64).serialize_into(buf);
-        for page_state in &self.pt {
+        for page_state in self.pt.iter() {
             page_state.serialize_into(buf);
         }
     }
@@ -479,7 +479,9 @@ impl Serialize for Snapshot {
             active_segment: Serialize::deserialize(buf)?,
             pt: {
                 let len = u64::deserialize(buf)?;
-                deserialize_bounded_sequence(buf, len)?
+                let pt: Vec<PageState> =
+                    deserialize_bounded_sequence(buf, len)?;
+                pt.into()
             },
         })
     }
@@ -810,7 +812,7 @@ mod qc {
             Snapshot {
                 stable_lsn: g.gen(),
                 active_segment: g.gen(),
-                pt: Arbitrary::arbitrary(g),
+                pt: Vec::<PageState>::arbitrary(g).into(),
             }
         }
     }
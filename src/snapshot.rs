@@ -0,0 +1,208 @@
+//! Consistent point-in-time read snapshots, maintained as
+//! overlays of pre-images rather than copies.
+//!
+//! A snapshot does not copy the tree. Instead, the first write to
+//! each key after the snapshot was taken records the value the
+//! key held at snapshot time into the snapshot's overlay, and
+//! snapshot reads consult the overlay before falling through to
+//! the live tree. The cost of a snapshot is therefore
+//! proportional to what changes while it is alive, not to the
+//! size of the tree.
+
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use parking_lot::RwLock;
+
+use crate::{Conflictable, Guard, IVec, Iter, Result, Tree};
+
+/// The pre-images recorded for one live snapshot. A value of
+/// `None` means the key did not exist when the snapshot was
+/// taken.
+#[derive(Debug, Default)]
+pub(crate) struct SnapshotState {
+    overlay: RwLock<BTreeMap<IVec, Option<IVec>>>,
+}
+
+impl SnapshotState {
+    /// Records the value a key held before its first write since
+    /// the snapshot was taken. Later writes to the same key are
+    /// ignored, preserving the snapshot-time value.
+    pub(crate) fn note(&self, key: &[u8], old: Option<&[u8]>) {
+        let mut overlay = self.overlay.write();
+        if !overlay.contains_key(key) {
+            overlay.insert(key.into(), old.map(IVec::from));
+        }
+    }
+}
+
+/// A read-only view of a tree pinned to the moment it was created
+/// via `Tree::snapshot`, unaffected by subsequent writes. Useful
+/// for long analytical scans that would otherwise observe
+/// concurrent mutations mid-iteration.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// use sled::IVec;
+///
+/// db.insert(b"k1", b"v1")?;
+/// let snapshot = db.snapshot();
+///
+/// db.insert(b"k1", b"v2")?;
+/// db.insert(b"k2", b"new")?;
+///
+/// // the snapshot still observes the state at its creation
+/// assert_eq!(&snapshot.get(b"k1")?.unwrap(), b"v1");
+/// assert_eq!(snapshot.get(b"k2")?, None);
+/// assert_eq!(snapshot.len(), 1);
+///
+/// // even for keys that have since been removed
+/// db.remove(b"k1")?;
+/// let entries: Vec<_> = snapshot.iter().collect::<Result<_, _>>()?;
+/// assert_eq!(entries, vec![(IVec::from("k1"), IVec::from("v1"))]);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub(crate) tree: Tree,
+    pub(crate) state: std::sync::Arc<SnapshotState>,
+}
+
+impl Snapshot {
+    /// Retrieve a value as it was when this snapshot was taken.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        if let Some(entry) = self.state.overlay.read().get(key.as_ref()) {
+            return Ok(entry.clone());
+        }
+
+        let live = self.tree.get(key.as_ref())?;
+
+        // a write may have landed between the overlay check and
+        // the live read; its recorded pre-image takes precedence
+        if let Some(entry) = self.state.overlay.read().get(key.as_ref()) {
+            return Ok(entry.clone());
+        }
+
+        Ok(live)
+    }
+
+    // the lock-free variant of `get` used by the transaction
+    // system, which holds the concurrency control write lock and
+    // must not recursively acquire the read lock.
+    pub(crate) fn get_inner(
+        &self,
+        key: &[u8],
+        guard: &mut Guard,
+    ) -> Result<Conflictable<Option<IVec>>> {
+        if let Some(entry) = self.state.overlay.read().get(key) {
+            return Ok(Ok(entry.clone()));
+        }
+
+        let live = match self.tree.get_inner(key, guard)? {
+            Ok(live) => live,
+            Err(conflict) => return Ok(Err(conflict)),
+        };
+
+        if let Some(entry) = self.state.overlay.read().get(key) {
+            return Ok(Ok(entry.clone()));
+        }
+
+        Ok(Ok(live))
+    }
+
+    /// Returns `true` if the snapshot contains a value for the
+    /// specified key.
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> Result<bool> {
+        self.get(key).map(|opt| opt.is_some())
+    }
+
+    /// Iterates over the keys and values of the snapshot in
+    /// ascending key order.
+    pub fn iter(&self) -> SnapshotIter {
+        SnapshotIter {
+            snapshot: self.clone(),
+            live: self.tree.iter(),
+            live_done: false,
+            pending_live: None,
+            last: None,
+        }
+    }
+
+    /// Returns the number of elements in the snapshot.
+    ///
+    /// This is O(leaves in the tree).
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns `true` if the snapshot contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+}
+
+/// An iterator over a [`Snapshot`], created via
+/// [`Snapshot::iter`].
+pub struct SnapshotIter {
+    snapshot: Snapshot,
+    live: Iter,
+    live_done: bool,
+    pending_live: Option<(IVec, IVec)>,
+    last: Option<IVec>,
+}
+
+impl Iterator for SnapshotIter {
+    type Item = Result<(IVec, IVec)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.pending_live.is_none() && !self.live_done {
+                match self.live.next() {
+                    Some(Ok(kv)) => self.pending_live = Some(kv),
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => self.live_done = true,
+                }
+            }
+
+            // a pre-image between the last yielded key and the
+            // next live key belongs to a key that was removed
+            // after the snapshot was taken, and is resurrected at
+            // its position
+            let resurrected = {
+                let overlay = self.snapshot.state.overlay.read();
+                let lower = match &self.last {
+                    Some(last) => Bound::Excluded(last.clone()),
+                    None => Bound::Unbounded,
+                };
+                let upper = match &self.pending_live {
+                    Some((key, _)) => Bound::Excluded(key.clone()),
+                    None => Bound::Unbounded,
+                };
+                overlay.range((lower, upper)).find_map(|(key, pre)| {
+                    pre.as_ref().map(|pre| (key.clone(), pre.clone()))
+                })
+            };
+
+            if let Some((key, pre)) = resurrected {
+                self.last = Some(key.clone());
+                return Some(Ok((key, pre)));
+            }
+
+            let (key, live_value) = self.pending_live.take()?;
+            self.last = Some(key.clone());
+
+            match self.snapshot.state.overlay.read().get(&key) {
+                // modified after the snapshot: yield the pre-image
+                Some(Some(pre)) => return Some(Ok((key, pre.clone()))),
+                // inserted after the snapshot: skip
+                Some(None) => continue,
+                // untouched since the snapshot
+                None => return Some(Ok((key, live_value))),
+            }
+        }
+    }
+}
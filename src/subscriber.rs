@@ -1,5 +1,6 @@
 use std::{
     future::Future,
+    ops::Bound,
     pin::Pin,
     sync::{
         atomic::{AtomicBool, Ordering::Relaxed},
@@ -29,7 +30,11 @@ impl Event {
     ) -> Event {
         Event::single_batch(
             tree,
-            Batch { writes: vec![(key, value)].into_iter().collect() },
+            Batch {
+                writes: vec![(key, value)].into_iter().collect(),
+                ttls: Map::default(),
+                range_removes: Vec::new(),
+            },
         )
     }
 
@@ -110,10 +115,18 @@ type Senders = Map<usize, (Option<Waker>, SyncSender<OneShot<Option<Event>>>)>;
 /// `Subscription` implements `Future<Output=Option<Event>>`.
 ///
 /// `while let Some(event) = (&mut subscriber).await { /* use it */ }`
+///
+/// When the `stream` feature is enabled, `Subscriber` also
+/// implements `futures::Stream<Item = Event>`, so it can be
+/// plugged directly into async pipelines built on stream
+/// combinators.
 pub struct Subscriber {
     id: usize,
     rx: Receiver<OneShot<Option<Event>>>,
     home: Arc<RwLock<Senders>>,
+    // set by `Tree::watch_range` to drop events whose keys all
+    // fall outside the watched interval
+    filter: Option<(Bound<IVec>, Bound<IVec>)>,
 }
 
 impl Drop for Subscriber {
@@ -143,7 +156,9 @@ impl Subscriber {
 
             let start = Instant::now();
             if let Some(event) = future_rx.wait_timeout(timeout)? {
-                return Ok(event);
+                if self.event_matches(&event) {
+                    return Ok(event);
+                }
             }
             timeout =
                 if let Some(timeout) = timeout.checked_sub(start.elapsed()) {
@@ -153,6 +168,29 @@ impl Subscriber {
                 };
         }
     }
+
+    // returns `true` if any key in the event falls within this
+    // subscriber's watched interval, or if no interval is set
+    fn event_matches(&self, event: &Event) -> bool {
+        let (lo, hi) = match &self.filter {
+            Some(filter) => filter,
+            None => return true,
+        };
+
+        event.iter().any(|(_tree, key, _value)| {
+            let above_lo = match lo {
+                Bound::Included(l) => key >= l,
+                Bound::Excluded(l) => key > l,
+                Bound::Unbounded => true,
+            };
+            let below_hi = match hi {
+                Bound::Included(h) => key <= h,
+                Bound::Excluded(h) => key < h,
+                Bound::Unbounded => true,
+            };
+            above_lo && below_hi
+        })
+    }
 }
 
 impl Future for Subscriber {
@@ -164,10 +202,15 @@ impl Future for Subscriber {
                 Ok(mut future_rx) => {
                     #[allow(unsafe_code)]
                     let future_rx =
-                        unsafe { std::pin::Pin::new_unchecked(&mut future_rx) };
+                        unsafe { Pin::new_unchecked(&mut future_rx) };
 
                     match Future::poll(future_rx, cx) {
                         Poll::Ready(Some(event)) => {
+                            if let Some(ref event) = event {
+                                if !self.event_matches(event) {
+                                    continue;
+                                }
+                            }
                             return Poll::Ready(event);
                         }
                         Poll::Ready(None) => {
@@ -189,6 +232,20 @@ impl Future for Subscriber {
     }
 }
 
+#[cfg(feature = "stream")]
+impl futures_core::Stream for Subscriber {
+    type Item = Event;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Event>> {
+        // the `Future` impl already yields `Option<Event>` per
+        // poll without fusing, which is exactly stream semantics
+        Future::poll(self, cx)
+    }
+}
+
 impl Iterator for Subscriber {
     type Item = Event;
 
@@ -196,7 +253,11 @@ impl Iterator for Subscriber {
         loop {
             let future_rx = self.rx.recv().ok()?;
             match future_rx.wait() {
-                Some(Some(event)) => return Some(event),
+                Some(Some(event)) => {
+                    if self.event_matches(&event) {
+                        return Some(event);
+                    }
+                }
                 Some(None) => return None,
                 None => continue,
             }
@@ -228,6 +289,15 @@ impl Drop for Subscribers {
 }
 
 impl Subscribers {
+    pub(crate) fn count(&self) -> u64 {
+        let watched = self.watched.read();
+        let mut count = 0;
+        for senders in watched.values() {
+            count += senders.read().len() as u64;
+        }
+        count
+    }
+
     pub(crate) fn register(&self, prefix: &[u8]) -> Subscriber {
         self.ever_used.store(true, Relaxed);
         let r_mu = {
@@ -258,7 +328,33 @@ impl Subscribers {
 
         w_senders.insert(id, (None, tx));
 
-        Subscriber { id, rx, home: arc_senders.clone() }
+        Subscriber { id, rx, home: arc_senders.clone(), filter: None }
+    }
+
+    pub(crate) fn register_range(
+        &self,
+        lo: Bound<IVec>,
+        hi: Bound<IVec>,
+    ) -> Subscriber {
+        // every key in the interval shares the common prefix of
+        // its bounds, so delivery can reuse the prefix index and
+        // the subscriber only filters within that neighborhood
+        let prefix: Vec<u8> = match (&lo, &hi) {
+            (
+                Bound::Included(l) | Bound::Excluded(l),
+                Bound::Included(h) | Bound::Excluded(h),
+            ) => l
+                .iter()
+                .zip(h.iter())
+                .take_while(|(a, b)| a == b)
+                .map(|(a, _)| *a)
+                .collect(),
+            _ => vec![],
+        };
+
+        let mut subscriber = self.register(&prefix);
+        subscriber.filter = Some((lo, hi));
+        subscriber
     }
 
     pub(crate) fn reserve_batch(
@@ -0,0 +1,204 @@
+//! Prefix subspaces with interned key components, layered over a
+//! tree and a hidden dictionary tree.
+//!
+//! Multi-tenant keyspaces tend to repeat the same long components
+//! (tenant IDs, namespaces) at the front of every key, and pay
+//! for them in every index node. A [`Subspace`] maps each
+//! component to a short integer code persisted in a per-database
+//! dictionary tree, storing only the 4-byte code in keys and
+//! transparently decoding it back, so the index shrinks without
+//! changing how callers address their data.
+
+use crate::{Error, IVec, Iter, Result, Tree};
+
+/// The number of bytes each interned component occupies in an
+/// encoded key.
+pub(crate) const CODE_LEN: usize = 4;
+
+/// The dictionary key under which the next unassigned code is
+/// stored. Forward and reverse entries are namespaced under `b"f"`
+/// and `b"r"` respectively, so this single-byte key cannot
+/// collide with either.
+const NEXT_CODE_KEY: &[u8] = b"n";
+
+/// A view of a tree under a prefix of interned key components,
+/// created via `Tree::subspace`. Keys written through the
+/// subspace are stored with the 4-byte codes of its components
+/// prepended, and read back with them stripped.
+///
+/// Components are interned in a dictionary tree shared by the
+/// whole database, so the same tenant ID used across many trees
+/// and subspaces costs one dictionary entry and 4 bytes per key.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// let tenant = db.subspace("tenant-3f8a2c9d-very-long-id")?;
+///
+/// tenant.insert(b"k1", b"v1")?;
+/// assert_eq!(&tenant.get(b"k1")?.unwrap(), b"v1");
+///
+/// // only the 4-byte code is stored in the underlying tree
+/// let (raw_key, _) = db.iter().next().unwrap()?;
+/// assert_eq!(raw_key.len(), 4 + 2);
+///
+/// // the component decodes back from the dictionary
+/// assert_eq!(
+///     tenant.components()?,
+///     vec![sled::IVec::from("tenant-3f8a2c9d-very-long-id")]
+/// );
+///
+/// // subspaces nest, sharing codes for repeated components
+/// let inbox = tenant.subspace("inbox")?;
+/// inbox.insert(b"m1", b"hello")?;
+/// assert_eq!(&inbox.get(b"m1")?.unwrap(), b"hello");
+/// assert_eq!(tenant.iter().count(), 2);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Subspace {
+    pub(crate) tree: Tree,
+    pub(crate) dictionary: Tree,
+    pub(crate) prefix: IVec,
+}
+
+impl Subspace {
+    /// Returns a subspace one interned component deeper than this
+    /// one.
+    pub fn subspace<C: AsRef<[u8]>>(&self, component: C) -> Result<Subspace> {
+        let code = intern(&self.dictionary, component.as_ref())?;
+        let mut prefix = self.prefix.to_vec();
+        prefix.extend_from_slice(&code.to_be_bytes());
+        Ok(Subspace {
+            tree: self.tree.clone(),
+            dictionary: self.dictionary.clone(),
+            prefix: prefix.into(),
+        })
+    }
+
+    /// Decodes this subspace's interned components back into the
+    /// byte strings they were created from.
+    pub fn components(&self) -> Result<Vec<IVec>> {
+        let mut components = Vec::with_capacity(self.prefix.len() / CODE_LEN);
+        for chunk in self.prefix.chunks(CODE_LEN) {
+            let mut arr = [0; CODE_LEN];
+            arr.copy_from_slice(chunk);
+            let code = u32::from_be_bytes(arr);
+            let mut key = Vec::with_capacity(1 + CODE_LEN);
+            key.push(b'r');
+            key.extend_from_slice(&code.to_be_bytes());
+            match self.dictionary.get(key)? {
+                Some(component) => components.push(component),
+                None => {
+                    return Err(Error::ReportableBug(format!(
+                        "interned key component code {} is missing \
+                         from the dictionary tree",
+                        code
+                    )));
+                }
+            }
+        }
+        Ok(components)
+    }
+
+    /// Inserts a key to a new value within this subspace,
+    /// returning the last value if it was set.
+    pub fn insert<K, V>(&self, key: K, value: V) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: Into<IVec>,
+    {
+        self.tree.insert(self.encode(key.as_ref()), value)
+    }
+
+    /// Retrieves a value within this subspace, if it is set.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        self.tree.get(self.encode(key.as_ref()))
+    }
+
+    /// Removes a key within this subspace, returning the last
+    /// value if it was set.
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        self.tree.remove(self.encode(key.as_ref()))
+    }
+
+    /// Iterates over the keys and values of this subspace,
+    /// including nested subspaces, with the encoded prefix
+    /// stripped from each key.
+    pub fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = Result<(IVec, IVec)>> + Send + Sync
+    {
+        self.tree.scan_prefix_stripped(self.prefix.clone())
+    }
+
+    /// Iterates over the keys and values of this subspace whose
+    /// decoded keys begin with the given prefix, with the encoded
+    /// subspace prefix stripped from each key.
+    pub fn scan_prefix<P: AsRef<[u8]>>(&self, prefix: P) -> Iter {
+        self.tree.scan_prefix(self.encode(prefix.as_ref()))
+    }
+
+    fn encode(&self, key: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::with_capacity(self.prefix.len() + key.len());
+        encoded.extend_from_slice(&self.prefix);
+        encoded.extend_from_slice(key);
+        encoded
+    }
+}
+
+/// Returns the code for a component, assigning and persisting the
+/// next unused one on first sight. Codes are allocated with
+/// compare-and-swap loops so that concurrent interning of the
+/// same component converges on one code.
+pub(crate) fn intern(dictionary: &Tree, component: &[u8]) -> Result<u32> {
+    let mut forward_key = Vec::with_capacity(1 + component.len());
+    forward_key.push(b'f');
+    forward_key.extend_from_slice(component);
+
+    if let Some(code) = dictionary.get(&forward_key)? {
+        return Ok(decode_code(&code));
+    }
+
+    // claim the next unassigned code
+    let code = loop {
+        let current = dictionary.get(NEXT_CODE_KEY)?;
+        let next = current.as_ref().map_or(0, |c| decode_code(c));
+        if dictionary
+            .compare_and_swap(
+                NEXT_CODE_KEY,
+                current.as_ref(),
+                Some(&next.wrapping_add(1).to_be_bytes()[..]),
+            )?
+            .is_ok()
+        {
+            break next;
+        }
+    };
+
+    match dictionary.compare_and_swap(
+        &forward_key,
+        None::<&[u8]>,
+        Some(&code.to_be_bytes()[..]),
+    )? {
+        Ok(()) => {
+            let mut reverse_key = Vec::with_capacity(1 + CODE_LEN);
+            reverse_key.push(b'r');
+            reverse_key.extend_from_slice(&code.to_be_bytes());
+            dictionary.insert(reverse_key, component)?;
+            Ok(code)
+        }
+        // another thread interned the component first. our claimed
+        // code goes unused, which is harmless.
+        Err(actual) => Ok(decode_code(&actual.current.unwrap())),
+    }
+}
+
+fn decode_code(bytes: &IVec) -> u32 {
+    let mut arr = [0; CODE_LEN];
+    arr.copy_from_slice(&bytes[..CODE_LEN]);
+    u32::from_be_bytes(arr)
+}
@@ -0,0 +1,536 @@
+//! Test doubles and verification harnesses for applications
+//! embedding sled.
+//!
+//! [`MockTree`] mirrors the core public `Tree` operations over an
+//! in-memory map, with scriptable failures and latency injection,
+//! so that application error-handling and timeout paths can be
+//! unit-tested without touching disk or relying on sled's internal
+//! failpoints.
+//!
+//! [`model_check`] runs scripted operations against a subject
+//! under test, possibly from several threads at once, and verifies
+//! that the observed results are linearizable against a
+//! deterministic [`ReferenceModel`]. [`KvModel`] is the reference
+//! model for the raw key-value operations, and wrapper crates
+//! (typed layers, queues) can implement the trait for their own
+//! abstractions.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::ops::{Bound, RangeBounds};
+use std::sync::{
+    atomic::{AtomicU64, Ordering::SeqCst},
+    Arc,
+};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+
+use crate::{
+    tree::CompareAndSwapResult, Batch, CompareAndSwapError, Error, IVec,
+    Result, Tree,
+};
+
+/// An in-memory stand-in for `Tree` with the same core operations,
+/// plus hooks for injecting failures and latency.
+///
+/// Errors queued with [`inject_error`](MockTree::inject_error) are
+/// returned, in order, one per subsequent operation, before the
+/// operation takes effect. A latency set with
+/// [`set_latency`](MockTree::set_latency) is slept before every
+/// operation. Clones share the same underlying state, mirroring
+/// `Tree`'s cheap handle semantics.
+///
+/// # Examples
+///
+/// ```
+/// use sled::testing::MockTree;
+///
+/// let tree = MockTree::new();
+/// tree.insert(b"k", b"v").unwrap();
+///
+/// tree.inject_error(sled::Error::ReportableBug("injected".into()));
+/// assert!(tree.get(b"k").is_err());
+/// assert_eq!(&tree.get(b"k").unwrap().unwrap(), b"v");
+/// ```
+#[derive(Clone, Default)]
+pub struct MockTree {
+    inner: Arc<MockTreeInner>,
+}
+
+#[derive(Default)]
+struct MockTreeInner {
+    data: Mutex<BTreeMap<IVec, IVec>>,
+    injected_errors: Mutex<VecDeque<Error>>,
+    latency: Mutex<Option<Duration>>,
+}
+
+impl MockTree {
+    /// Creates an empty `MockTree`.
+    pub fn new() -> MockTree {
+        MockTree::default()
+    }
+
+    /// Queues an error to be returned by the next operation that
+    /// is not already covered by a previously queued error.
+    pub fn inject_error(&self, error: Error) {
+        self.inner.injected_errors.lock().push_back(error);
+    }
+
+    /// Sets a duration that every subsequent operation sleeps for
+    /// before executing, or removes it when `None`.
+    pub fn set_latency(&self, latency: Option<Duration>) {
+        *self.inner.latency.lock() = latency;
+    }
+
+    fn enter(&self) -> Result<()> {
+        let latency = *self.inner.latency.lock();
+        if let Some(latency) = latency {
+            std::thread::sleep(latency);
+        }
+        if let Some(error) = self.inner.injected_errors.lock().pop_front() {
+            return Err(error);
+        }
+        Ok(())
+    }
+
+    /// Inserts a key to a new value, returning the last value if
+    /// it was set.
+    pub fn insert<K, V>(&self, key: K, value: V) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: Into<IVec>,
+    {
+        self.enter()?;
+        Ok(self
+            .inner
+            .data
+            .lock()
+            .insert(key.as_ref().into(), value.into()))
+    }
+
+    /// Retrieves a value from the tree if it exists.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        self.enter()?;
+        Ok(self.inner.data.lock().get(key.as_ref()).cloned())
+    }
+
+    /// Deletes a value, returning the old value if it existed.
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        self.enter()?;
+        Ok(self.inner.data.lock().remove(key.as_ref()))
+    }
+
+    /// Compare and swap, with the same semantics as
+    /// `Tree::compare_and_swap`.
+    pub fn compare_and_swap<K, OV, NV>(
+        &self,
+        key: K,
+        old: Option<OV>,
+        new: Option<NV>,
+    ) -> CompareAndSwapResult
+    where
+        K: AsRef<[u8]>,
+        OV: AsRef<[u8]>,
+        NV: Into<IVec>,
+    {
+        self.enter()?;
+        let mut data = self.inner.data.lock();
+        let current = data.get(key.as_ref()).cloned();
+        let matches = match (&old, &current) {
+            (None, None) => true,
+            (Some(o), Some(c)) => o.as_ref() == &**c,
+            _ => false,
+        };
+        if !matches {
+            return Ok(Err(CompareAndSwapError {
+                current,
+                proposed: new.map(Into::into),
+            }));
+        }
+        match new {
+            Some(new) => data.insert(key.as_ref().into(), new.into()),
+            None => data.remove(key.as_ref()),
+        };
+        Ok(Ok(()))
+    }
+
+    /// Applies all operations in a `Batch` atomically.
+    pub fn apply_batch(&self, batch: Batch) -> Result<()> {
+        self.enter()?;
+        let mut data = self.inner.data.lock();
+        for (key, value) in batch.writes {
+            match value {
+                Some(value) => data.insert(key, value),
+                None => data.remove(&key),
+            };
+        }
+        Ok(())
+    }
+
+    /// Returns `true` if the tree contains a value for the
+    /// specified key.
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> Result<bool> {
+        self.enter()?;
+        Ok(self.inner.data.lock().contains_key(key.as_ref()))
+    }
+
+    /// Returns the number of elements in this tree.
+    pub fn len(&self) -> usize {
+        self.inner.data.lock().len()
+    }
+
+    /// Returns `true` if the tree contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.inner.data.lock().is_empty()
+    }
+
+    /// Clears the tree, removing all values.
+    pub fn clear(&self) -> Result<()> {
+        self.enter()?;
+        self.inner.data.lock().clear();
+        Ok(())
+    }
+
+    /// Synchronously flushes all dirty IO buffers. A no-op for the
+    /// in-memory mock beyond latency and error injection.
+    pub fn flush(&self) -> Result<usize> {
+        self.enter()?;
+        Ok(0)
+    }
+
+    /// Iterates over a snapshot of the whole tree.
+    pub fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = Result<(IVec, IVec)>> {
+        self.range::<Vec<u8>, _>(..)
+    }
+
+    /// Iterates over a snapshot of the given range of keys.
+    pub fn range<K, R>(
+        &self,
+        range: R,
+    ) -> impl DoubleEndedIterator<Item = Result<(IVec, IVec)>>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let bound = |b: Bound<&K>| match b {
+            Bound::Included(k) => Bound::Included(IVec::from(k.as_ref())),
+            Bound::Excluded(k) => Bound::Excluded(IVec::from(k.as_ref())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let lo = bound(range.start_bound());
+        let hi = bound(range.end_bound());
+        let error = self.enter().err();
+        let snapshot: Vec<_> = if error.is_some() {
+            vec![]
+        } else {
+            self.inner
+                .data
+                .lock()
+                .range((lo, hi))
+                .map(|(k, v)| Ok((k.clone(), v.clone())))
+                .collect()
+        };
+        error.into_iter().map(Err).chain(snapshot)
+    }
+
+    /// Iterates over a snapshot of all keys with the given prefix.
+    pub fn scan_prefix<P: AsRef<[u8]>>(
+        &self,
+        prefix: P,
+    ) -> impl DoubleEndedIterator<Item = Result<(IVec, IVec)>> {
+        let prefix: IVec = prefix.as_ref().into();
+        let error = self.enter().err();
+        let snapshot: Vec<_> = if error.is_some() {
+            vec![]
+        } else {
+            self.inner
+                .data
+                .lock()
+                .range(prefix.clone()..)
+                .take_while(|(k, _)| k.starts_with(&prefix))
+                .map(|(k, v)| Ok((k.clone(), v.clone())))
+                .collect()
+        };
+        error.into_iter().map(Err).chain(snapshot)
+    }
+}
+
+impl std::fmt::Debug for MockTree {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "MockTree {{ len: {} }}", self.len())
+    }
+}
+
+/// The deterministic reference that a subject under test is
+/// checked against by [`model_check`].
+///
+/// The model is cloned at every step of the linearization
+/// search, so implementations should keep their state small and
+/// cheap to copy.
+pub trait ReferenceModel: Clone + Send + 'static {
+    /// One operation in a script.
+    type Op: Clone + Send + std::fmt::Debug + 'static;
+    /// The observable result of an operation.
+    type Out: PartialEq + Send + std::fmt::Debug + 'static;
+
+    /// Applies an operation to the model, returning the result
+    /// the subject should have observed at that point in the
+    /// linear order.
+    fn apply(&mut self, op: &Self::Op) -> Self::Out;
+}
+
+/// One raw key-value operation for checking a `Tree` (or a
+/// wrapper over one) against [`KvModel`].
+#[derive(Debug, Clone)]
+pub enum KvOp {
+    /// Insert the key to the value.
+    Insert(IVec, IVec),
+    /// Remove the key.
+    Remove(IVec),
+    /// Read the key.
+    Get(IVec),
+    /// Compare and swap from the expected old value to the
+    /// proposed new one.
+    CompareAndSwap {
+        /// The key being swapped.
+        key: IVec,
+        /// The expected current value.
+        old: Option<IVec>,
+        /// The proposed new value, or a removal when `None`.
+        new: Option<IVec>,
+    },
+}
+
+/// The observable result of a [`KvOp`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum KvOut {
+    /// The previous value for inserts and removals, or the read
+    /// value for gets.
+    Value(Option<IVec>),
+    /// The compare-and-swap succeeded.
+    CasOk,
+    /// The compare-and-swap failed against this current value.
+    CasFailed(Option<IVec>),
+}
+
+impl KvOp {
+    /// Performs this operation against a real tree, for use as
+    /// the subject side of [`model_check`].
+    pub fn apply_to(&self, tree: &Tree) -> Result<KvOut> {
+        match self {
+            KvOp::Insert(key, value) => {
+                Ok(KvOut::Value(tree.insert(key, value.clone())?))
+            }
+            KvOp::Remove(key) => Ok(KvOut::Value(tree.remove(key)?)),
+            KvOp::Get(key) => Ok(KvOut::Value(tree.get(key)?)),
+            KvOp::CompareAndSwap { key, old, new } => {
+                match tree.compare_and_swap(
+                    key,
+                    old.as_ref(),
+                    new.clone(),
+                )? {
+                    Ok(()) => Ok(KvOut::CasOk),
+                    Err(e) => Ok(KvOut::CasFailed(e.current)),
+                }
+            }
+        }
+    }
+}
+
+/// The reference model for raw key-value operations: a plain
+/// ordered map with the same observable semantics as `Tree`.
+#[derive(Debug, Clone, Default)]
+pub struct KvModel {
+    data: BTreeMap<IVec, IVec>,
+}
+
+impl KvModel {
+    /// Creates an empty model.
+    pub fn new() -> KvModel {
+        KvModel::default()
+    }
+}
+
+impl ReferenceModel for KvModel {
+    type Op = KvOp;
+    type Out = KvOut;
+
+    fn apply(&mut self, op: &KvOp) -> KvOut {
+        match op {
+            KvOp::Insert(key, value) => KvOut::Value(
+                self.data.insert(key.clone(), value.clone()),
+            ),
+            KvOp::Remove(key) => KvOut::Value(self.data.remove(key)),
+            KvOp::Get(key) => KvOut::Value(self.data.get(key).cloned()),
+            KvOp::CompareAndSwap { key, old, new } => {
+                let current = self.data.get(key).cloned();
+                if current == *old {
+                    match new {
+                        Some(new) => {
+                            self.data.insert(key.clone(), new.clone());
+                        }
+                        None => {
+                            self.data.remove(key);
+                        }
+                    }
+                    KvOut::CasOk
+                } else {
+                    KvOut::CasFailed(current)
+                }
+            }
+        }
+    }
+}
+
+// one completed operation in the recorded history, with its
+// real-time invocation and response order
+struct Record<M: ReferenceModel> {
+    op: M::Op,
+    out: M::Out,
+    invoke: u64,
+    ret: u64,
+}
+
+/// Runs scripted operations against a subject under test and
+/// verifies that the observed results are linearizable against
+/// `model`.
+///
+/// Each script in `scripts` runs on its own thread, performing
+/// its operations in order through `subject` while the harness
+/// records a real-time history. The history is then checked for
+/// a linear order that is consistent with real time and under
+/// which the model predicts every observed result, using
+/// exhaustive search. The search is exponential in the number of
+/// concurrently overlapping operations, so scripts should be
+/// small and numerous rather than long.
+///
+/// The subject side is an arbitrary closure, so wrapper crates
+/// can check their own abstractions: a script may even include
+/// an operation whose subject implementation drops and reopens
+/// the database, letting crash recovery participate in the
+/// checked history.
+///
+/// # Errors
+///
+/// Returns the first error that `subject` reports.
+///
+/// # Panics
+///
+/// Panics if the recorded history is not linearizable against
+/// the model.
+///
+/// # Examples
+///
+/// ```
+/// use sled::testing::{model_check, KvModel, KvOp};
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// let k = || sled::IVec::from(b"k");
+/// let scripts = vec![
+///     vec![
+///         KvOp::Insert(k(), b"a".into()),
+///         KvOp::Get(k()),
+///     ],
+///     vec![
+///         KvOp::Insert(k(), b"b".into()),
+///         KvOp::Remove(k()),
+///     ],
+/// ];
+///
+/// let tree = db.clone();
+/// model_check(KvModel::new(), move |op| op.apply_to(&tree), scripts)?;
+/// # Ok(()) }
+/// ```
+pub fn model_check<M, S>(
+    model: M,
+    subject: S,
+    scripts: Vec<Vec<M::Op>>,
+) -> Result<()>
+where
+    M: ReferenceModel,
+    S: Fn(&M::Op) -> Result<M::Out> + Send + Sync + 'static,
+{
+    let clock = Arc::new(AtomicU64::new(0));
+    let subject = Arc::new(subject);
+
+    let mut handles = Vec::new();
+    for script in scripts {
+        let clock = clock.clone();
+        let subject = subject.clone();
+        handles.push(std::thread::spawn(
+            move || -> Result<Vec<Record<M>>> {
+                let mut records = Vec::with_capacity(script.len());
+                for op in script {
+                    let invoke = clock.fetch_add(1, SeqCst);
+                    let out = subject(&op)?;
+                    let ret = clock.fetch_add(1, SeqCst);
+                    records.push(Record { op, out, invoke, ret });
+                }
+                Ok(records)
+            },
+        ));
+    }
+
+    let mut history = Vec::new();
+    for handle in handles {
+        history.extend(handle.join().expect("model check thread panicked")?);
+    }
+
+    let mut done = vec![false; history.len()];
+    if !linearize(&model, &history, &mut done, history.len()) {
+        let mut rendered = String::new();
+        for record in &history {
+            rendered += &format!(
+                "  [{:>4}, {:>4}] {:?} -> {:?}\n",
+                record.invoke, record.ret, record.op, record.out
+            );
+        }
+        panic!(
+            "model check failed: history is not linearizable \
+             against the reference model:\n{}",
+            rendered
+        );
+    }
+
+    Ok(())
+}
+
+// depth-first search for a linearization: an operation may be
+// ordered next only if no other pending operation completed
+// before it was invoked, and only if the model predicts the
+// result it observed
+fn linearize<M: ReferenceModel>(
+    state: &M,
+    history: &[Record<M>],
+    done: &mut Vec<bool>,
+    remaining: usize,
+) -> bool {
+    if remaining == 0 {
+        return true;
+    }
+    let min_ret = history
+        .iter()
+        .zip(done.iter())
+        .filter(|(_, done)| !**done)
+        .map(|(record, _)| record.ret)
+        .min()
+        .unwrap();
+    for i in 0..history.len() {
+        if done[i] || history[i].invoke > min_ret {
+            continue;
+        }
+        let mut next = state.clone();
+        if next.apply(&history[i].op) == history[i].out {
+            done[i] = true;
+            if linearize(&next, history, done, remaining - 1) {
+                return true;
+            }
+            done[i] = false;
+        }
+    }
+    false
+}
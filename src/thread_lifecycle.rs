@@ -0,0 +1,50 @@
+use super::*;
+
+type LifecycleHook = Box<dyn Fn(&str) + Send + Sync>;
+
+#[derive(Default)]
+struct Hooks {
+    on_spawn: Option<LifecycleHook>,
+    on_exit: Option<LifecycleHook>,
+}
+
+static HOOKS: Lazy<RwLock<Hooks>, fn() -> RwLock<Hooks>> =
+    Lazy::new(init_hooks);
+
+fn init_hooks() -> RwLock<Hooks> {
+    RwLock::new(Hooks::default())
+}
+
+/// Sets process-global hooks that are invoked from every thread
+/// sled spawns, with the thread's name, right after it starts and
+/// right before it exits.
+///
+/// The hooks run on the spawned thread itself, so embedders can
+/// apply scheduling priorities, cgroup placement, or profiling
+/// labels to it from within the hook. Thread names are `"log
+/// flusher"`, `"log scrubber"`, `"watchdog"`, and `"sled-io-N"`
+/// for the IO threadpool. Hooks apply to threads spawned after
+/// this call, and replace any previously set pair.
+pub fn set_thread_lifecycle_hooks<S, E>(on_spawn: S, on_exit: E)
+where
+    S: Fn(&str) + Send + Sync + 'static,
+    E: Fn(&str) + Send + Sync + 'static,
+{
+    let mut hooks = HOOKS.write();
+    hooks.on_spawn = Some(Box::new(on_spawn));
+    hooks.on_exit = Some(Box::new(on_exit));
+}
+
+/// Invoked at the top of every thread sled spawns.
+pub(crate) fn thread_spawned(name: &str) {
+    if let Some(hook) = &HOOKS.read().on_spawn {
+        hook(name);
+    }
+}
+
+/// Invoked right before a thread sled spawned exits.
+pub(crate) fn thread_exiting(name: &str) {
+    if let Some(hook) = &HOOKS.read().on_exit {
+        hook(name);
+    }
+}
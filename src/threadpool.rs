@@ -13,7 +13,8 @@ use std::{
 use parking_lot::{Condvar, Mutex};
 
 use crate::{
-    debug_delay, warn, AtomicU64, AtomicUsize, Error, Lazy, OneShot, Result,
+    debug_delay, thread_lifecycle, warn, AtomicU64, AtomicUsize, Error, Lazy,
+    OneShot, Result,
 };
 
 // This is lower for CI reasons.
@@ -23,7 +24,16 @@ const MAX_THREADS: usize = 16;
 #[cfg(not(windows))]
 const MAX_THREADS: usize = 128;
 
-const DESIRED_WAITING_THREADS: usize = 7;
+const DEFAULT_DESIRED_WAITING_THREADS: usize = 7;
+
+static DESIRED_WAITING_THREADS: AtomicUsize =
+    AtomicUsize::new(DEFAULT_DESIRED_WAITING_THREADS);
+
+/// Retunes how many IO threads the pool tries to keep parked and
+/// ready, used by `Db::calibrate` to match device parallelism.
+pub(crate) fn set_desired_waiting_threads(desired: usize) {
+    DESIRED_WAITING_THREADS.store(desired.max(1).min(MAX_THREADS), SeqCst);
+}
 
 static WAITING_THREAD_COUNT: AtomicUsize = AtomicUsize::new(0);
 static TOTAL_THREAD_COUNT: AtomicUsize = AtomicUsize::new(0);
@@ -38,7 +48,7 @@ type Work = Box<dyn FnOnce() + Send + 'static>;
 
 fn init_queue() -> Queue {
     debug_delay();
-    for _ in 0..DESIRED_WAITING_THREADS {
+    for _ in 0..DESIRED_WAITING_THREADS.load(Acquire) {
         debug_delay();
         if let Err(e) = spawn_new_thread(true) {
             log::error!("failed to initialize threadpool: {:?}", e);
@@ -122,7 +132,7 @@ fn perform_work(is_immortal: bool) {
 
         let waiting = WAITING_THREAD_COUNT.load(Acquire);
 
-        if waiting > DESIRED_WAITING_THREADS {
+        if waiting > DESIRED_WAITING_THREADS.load(Acquire) {
             contiguous_overshoots += 1;
         } else {
             contiguous_overshoots = 0;
@@ -139,7 +149,7 @@ fn maybe_spawn_new_thread() -> Result<()> {
     debug_delay();
     let waiting_threads = WAITING_THREAD_COUNT.load(Acquire);
 
-    if waiting_threads >= DESIRED_WAITING_THREADS
+    if waiting_threads >= DESIRED_WAITING_THREADS.load(Acquire)
         || total_workers >= MAX_THREADS
     {
         return Ok(());
@@ -162,15 +172,18 @@ fn spawn_new_thread(is_immortal: bool) -> Result<()> {
     }
 
     let spawn_id = SPAWNS.fetch_add(1, SeqCst);
+    let name = format!("sled-io-{}", spawn_id);
 
     TOTAL_THREAD_COUNT.fetch_add(1, SeqCst);
     let spawn_res = thread::Builder::new()
-        .name(format!("sled-io-{}", spawn_id))
+        .name(name.clone())
         .spawn(move || {
+            thread_lifecycle::thread_spawned(&name);
             SPAWNING.store(false, SeqCst);
             debug_delay();
             let res = std::panic::catch_unwind(|| perform_work(is_immortal));
             TOTAL_THREAD_COUNT.fetch_sub(1, SeqCst);
+            thread_lifecycle::thread_exiting(&name);
             if is_immortal {
                 // IO thread panicked, shut down the system
                 BROKEN.store(true, SeqCst);
@@ -213,7 +226,7 @@ where
 
     let depth = QUEUE.send(Box::new(task));
 
-    if depth > DESIRED_WAITING_THREADS {
+    if depth > DESIRED_WAITING_THREADS.load(Acquire) {
         maybe_spawn_new_thread()?;
     }
 
@@ -72,12 +72,48 @@
 //! # Ok(())
 //! # }
 //! ```
+//!
+//! Transactions may even span trees from independently-opened
+//! `Db` instances, for applications that keep per-tenant
+//! databases but occasionally need an atomic move between them.
+//! A two-phase protocol makes a prepared coordination record
+//! durable in every participating database before any of them
+//! applies writes, so a crash between the participants' flushes
+//! leaves a detectable record rather than silently diverging.
+//! Leftover records from such a crash can be inspected with
+//! `Db::pending_coordination_ids` after reopening.
+//!
+//! ```
+//! # use sled::{transaction::{TransactionResult, Transactional}, Config};
+//! # fn main() -> TransactionResult<()> {
+//! let db_a = Config::new().temporary(true).open().unwrap();
+//! let db_b = Config::new().temporary(true).open().unwrap();
+//!
+//! db_a.insert(b"wallet", b"500").unwrap();
+//!
+//! (&*db_a, &*db_b).transaction(|(a, b)| {
+//!     let funds = a.remove(b"wallet")?.unwrap();
+//!     b.insert(b"wallet", funds)?;
+//!     Ok(())
+//! })?;
+//!
+//! assert_eq!(db_a.get(b"wallet").unwrap(), None);
+//! assert_eq!(&db_b.get(b"wallet").unwrap().unwrap(), b"500");
+//! # Ok(())
+//! # }
+//! ```
 #![allow(clippy::module_name_repetitions)]
-use std::{cell::RefCell, fmt, rc::Rc};
+use std::{
+    cell::RefCell, convert::TryFrom, fmt, rc::Rc,
+    sync::atomic::Ordering::SeqCst,
+};
+
+use parking_lot::MutexGuard;
 
 use crate::{
-    concurrency_control, pin, Batch, Error, Event, Guard, IVec, Map, Protector,
-    Result, Tree,
+    arena, concurrency_control, meta, pin, Batch, Context, Error, Event,
+    Guard, IVec, Map, Protector, Result, Snapshot, Tree,
+    COORDINATION_TREE_ID, PREPARED_TREE_ID,
 };
 
 /// A transaction that will
@@ -88,7 +124,9 @@ pub struct TransactionalTree {
     pub(super) tree: Tree,
     pub(super) writes: Rc<RefCell<Batch>>,
     pub(super) read_cache: Rc<RefCell<Map<IVec, Option<IVec>>>>,
+    pub(super) read_snapshot: Rc<RefCell<Option<Snapshot>>>,
     pub(super) flush_on_commit: Rc<RefCell<bool>>,
+    pub(super) prepared_name: Rc<RefCell<Option<IVec>>>,
 }
 
 /// An error type that is returned from the closure
@@ -292,10 +330,20 @@ impl TransactionalTree {
             return Ok(second_try.clone());
         }
 
-        // not found in a cache, need to hit the backing db
+        // not found in a cache, need to hit the backing db. reads
+        // go through the snapshot pinned when the transaction was
+        // staged, so every tree in a multi-tree transaction
+        // observes the same consistent point even before the
+        // first write.
+        let read_snapshot = self.read_snapshot.borrow();
         let mut guard = pin();
         let get = loop {
-            if let Ok(get) = self.tree.get_inner(key.as_ref(), &mut guard)? {
+            let res = if let Some(snapshot) = &*read_snapshot {
+                snapshot.get_inner(key.as_ref(), &mut guard)?
+            } else {
+                self.tree.get_inner(key.as_ref(), &mut guard)?
+            };
+            if let Ok(get) = res {
                 break get;
             }
         };
@@ -325,6 +373,48 @@ impl TransactionalTree {
         *self.flush_on_commit.borrow_mut() = true;
     }
 
+    /// Marks the surrounding transaction as prepared under the
+    /// given name instead of committing it. Its staged writes are
+    /// made durable without being applied, and survive restarts:
+    /// after a crash, `Db::list_prepared` returns the name and
+    /// the application decides whether to apply the writes via
+    /// `Db::commit_prepared` or discard them via
+    /// `Db::abort_prepared`. This enables outbox and saga
+    /// patterns that need a crash-safe intermediate step.
+    ///
+    /// For transactions spanning several databases, each database
+    /// durably records the writes destined for its own trees
+    /// under the name, and each is committed or aborted
+    /// separately.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sled::transaction::TransactionResult;
+    /// # fn main() -> TransactionResult<()> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.transaction(|tx_db| {
+    ///     tx_db.insert(b"k1", b"queued")?;
+    ///     tx_db.prepare(b"outbox-42");
+    ///     Ok(())
+    /// })?;
+    ///
+    /// // nothing has been applied yet, but the prepared writes
+    /// // are durable and would survive a crash here
+    /// assert_eq!(db.get(b"k1")?, None);
+    /// assert_eq!(db.list_prepared()?, vec![sled::IVec::from("outbox-42")]);
+    ///
+    /// db.commit_prepared(b"outbox-42")?;
+    /// assert_eq!(&db.get(b"k1")?.unwrap(), b"queued");
+    /// assert!(db.list_prepared()?.is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn prepare<N: AsRef<[u8]>>(&self, name: N) {
+        *self.prepared_name.borrow_mut() = Some(name.as_ref().into());
+    }
+
     /// Generate a monotonic ID. Not guaranteed to be
     /// contiguous or idempotent, can produce different values in the
     /// same transaction in case of conflicts.
@@ -359,21 +449,88 @@ impl TransactionalTree {
     fn from_tree(tree: &Tree) -> Self {
         Self {
             tree: tree.clone(),
-            writes: Default::default(),
-            read_cache: Default::default(),
+            // scratch structures come from the thread-local
+            // arena and are reset back to it at commit or abort
+            writes: Rc::new(RefCell::new(arena::take_batch())),
+            read_cache: Rc::new(RefCell::new(arena::take_read_cache())),
+            read_snapshot: Default::default(),
             flush_on_commit: Default::default(),
+            prepared_name: Default::default(),
         }
     }
+
+    fn recycle_scratch(&self) {
+        arena::recycle_batch(self.writes.take());
+        arena::recycle_read_cache(self.read_cache.take());
+    }
 }
 
+/// A validator registered via `Tree::add_pre_commit_validator`,
+/// called with a tree's staged writes before a transaction
+/// commits. Returning an error vetoes the transaction before any
+/// write is applied or persisted.
+pub trait PreCommitValidator:
+    Send + Sync + Fn(&Batch) -> std::result::Result<(), String>
+{
+}
+impl<F> PreCommitValidator for F where
+    F: Send + Sync + Fn(&Batch) -> std::result::Result<(), String>
+{
+}
+
+/// A callback registered via `Tree::add_post_commit_callback`,
+/// called with a tree's committed writes exactly once after the
+/// transaction has been flushed to stable storage.
+pub trait PostCommitCallback: Send + Sync + Fn(&Batch) {}
+impl<F> PostCommitCallback for F where F: Send + Sync + Fn(&Batch) {}
+
 /// A type which allows for pluggable transactional capabilities
 pub struct TransactionalTrees {
     inner: Vec<TransactionalTree>,
+    // hidden tree handles that must outlive the concurrency
+    // control write lock held during commit, as dropping a final
+    // `Tree` handle flushes.
+    held: RefCell<Vec<Tree>>,
 }
 
 impl TransactionalTrees {
-    fn stage(&self) -> Protector<'_> {
-        concurrency_control::write()
+    fn new(inner: Vec<TransactionalTree>) -> TransactionalTrees {
+        TransactionalTrees { inner, held: RefCell::new(Vec::new()) }
+    }
+
+    fn stage(&self) -> (Vec<MutexGuard<'_, ()>>, Protector<'_>) {
+        // the changefeed serialization lock of every involved
+        // database is taken before the concurrency control
+        // protector, matching the lock order of point writes and
+        // batches, and held until the commit's captures land so
+        // that changefeed order matches visibility order.
+        let mut changefeed_contexts: Vec<&Context> = Vec::new();
+        for tree in &self.inner {
+            let context = &tree.tree.context;
+            if context.changefeed.enabled.load(SeqCst)
+                && !changefeed_contexts
+                    .iter()
+                    .any(|c| c.get_path() == context.get_path())
+            {
+                changefeed_contexts.push(context);
+            }
+        }
+        let changefeed_locks: Vec<MutexGuard<'_, ()>> = changefeed_contexts
+            .into_iter()
+            .map(|context| context.changefeed.write_lock.lock())
+            .collect();
+
+        let protector = concurrency_control::write();
+
+        // with the write lock held, no concurrent writer can land
+        // between these snapshots, so every tree in the
+        // transaction shares a single consistent read point
+        // rather than each picking one up at first touch.
+        for tree in &self.inner {
+            *tree.read_snapshot.borrow_mut() = Some(tree.tree.snapshot());
+        }
+
+        (changefeed_locks, protector)
     }
 
     fn unstage(&self) {
@@ -382,6 +539,15 @@ impl TransactionalTrees {
         }
     }
 
+    // returns each tree's scratch structures to the arena so that
+    // the next attempt or transaction on this thread reuses their
+    // backing memory
+    fn recycle_scratch(&self) {
+        for tree in &self.inner {
+            tree.recycle_scratch();
+        }
+    }
+
     fn validate(&self) -> bool {
         for tree in &self.inner {
             if !tree.validate() {
@@ -391,8 +557,72 @@ impl TransactionalTrees {
         true
     }
 
-    fn commit(&self, guard: &Guard) -> Result<()> {
-        let peg = self.inner[0].tree.context.pin_log(guard)?;
+    fn commit(&self, guard: &Guard) -> Result<Option<(IVec, Vec<Tree>)>> {
+        for tree in &self.inner {
+            tree.tree.ensure_writable()?;
+        }
+
+        // pre-commit validators may veto the transaction before
+        // any write is applied or persisted, including the
+        // durable staging of a prepared transaction
+        for tree in &self.inner {
+            let validators = tree.tree.pre_commit_validators.read();
+            for validator in validators.iter() {
+                if let Err(reason) = validator(&tree.writes.borrow()) {
+                    return Err(Error::Unsupported(format!(
+                        "transaction vetoed by pre-commit \
+                         validator: {}",
+                        reason
+                    )));
+                }
+            }
+        }
+
+        self.check_foreign_keys()?;
+
+        // unique indexes are per-tree, so each tree's staged
+        // writes validate independently with the overlay-aware
+        // batch check
+        for tree in &self.inner {
+            tree.tree.unique_check_batch(&tree.writes.borrow())?;
+        }
+
+        let mut contexts: Vec<&Context> = Vec::new();
+        for tree in &self.inner {
+            let context = &tree.tree.context;
+            if !contexts.iter().any(|c| c.get_path() == context.get_path()) {
+                contexts.push(context);
+            }
+        }
+
+        // a transaction marked via `TransactionalTree::prepare`
+        // is not applied: its staged writes are made durable
+        // under the given name for a later `Db::commit_prepared`
+        // or `Db::abort_prepared`.
+        let prepared_name = self
+            .inner
+            .iter()
+            .find_map(|tree| tree.prepared_name.borrow().clone());
+        if let Some(name) = prepared_name {
+            self.persist_prepared(&name, &contexts, guard)?;
+            return Ok(None);
+        }
+
+        // phase 1 for transactions spanning multiple databases:
+        // make a prepared coordination record durable in every
+        // participant before any of them applies writes, so that a
+        // crash between the participants' flushes is detectable by
+        // the leftover record rather than silent.
+        let coordination = if contexts.len() > 1 {
+            Some(self.prepare_coordination(&contexts, guard)?)
+        } else {
+            None
+        };
+
+        let mut pegs = Vec::with_capacity(contexts.len());
+        for context in &contexts {
+            pegs.push(context.pin_log(guard)?);
+        }
 
         let batches = self
             .inner
@@ -406,10 +636,257 @@ impl TransactionalTrees {
             tree.commit(event.clone())?;
         }
 
-        // when the peg drops, it ensures all updates
-        // written to the log since its creation are
+        // when a peg drops, it ensures all updates
+        // written to its log since its creation are
         // recovered atomically
-        peg.seal_batch()
+        for peg in pegs {
+            peg.seal_batch()?;
+        }
+
+        Ok(coordination)
+    }
+
+    // foreign key constraints are checked here rather than
+    // per-write so that the whole staged set is in view: a parent
+    // and child may be inserted, or a child and its parent
+    // removed, in either order within one transaction. references
+    // into trees outside the transaction resolve against their
+    // current contents.
+    fn check_foreign_keys(&self) -> Result<()> {
+        let staged = |tree_id: &IVec, key: &[u8]| -> Option<Option<IVec>> {
+            for tree in &self.inner {
+                if tree.tree.tree_id == *tree_id {
+                    return tree
+                        .writes
+                        .borrow()
+                        .get(key)
+                        .map(|value| value.cloned());
+                }
+            }
+            None
+        };
+
+        for tree in &self.inner {
+            let outgoing = tree.tree.foreign_keys_out.read();
+            let writes = tree.writes.borrow();
+            for (key, value) in writes.iter() {
+                let value = if let Some(value) = value {
+                    value
+                } else {
+                    continue;
+                };
+                for (constraint, index) in outgoing.iter() {
+                    let derived =
+                        if let Some(derived) = (index.0.extractor)(key, value)
+                        {
+                            derived
+                        } else {
+                            continue;
+                        };
+                    let exists = match staged(
+                        &constraint.target.tree_id,
+                        &derived,
+                    ) {
+                        Some(staged_value) => staged_value.is_some(),
+                        None => {
+                            constraint.target.contains_key_inner(&derived)?
+                        }
+                    };
+                    if !exists {
+                        return Err(Error::Unsupported(format!(
+                            "foreign key constraint {:?} violated: \
+                             record {:?} references key {:?}, which \
+                             does not exist in the target tree",
+                            constraint.name, key, derived,
+                        )));
+                    }
+                }
+            }
+            drop(writes);
+            drop(outgoing);
+
+            let incoming = tree.tree.foreign_keys_in.read();
+            if incoming.is_empty() {
+                continue;
+            }
+            let writes = tree.writes.borrow();
+            for (key, value) in writes.iter() {
+                if value.is_some() {
+                    continue;
+                }
+                for constraint in incoming.iter() {
+                    let mut referencing = constraint
+                        .index
+                        .0
+                        .tree
+                        .scan_prefix(crate::index::encode_derived(key));
+                    while let Some(kv) = referencing.next_inner() {
+                        let (_, primary) = kv?;
+                        // a referencing record removed or repointed
+                        // in this same transaction is fine
+                        match staged(&constraint.source_id, &primary) {
+                            Some(None) => continue,
+                            Some(Some(new_value)) => {
+                                let derived = (constraint
+                                    .index
+                                    .0
+                                    .extractor)(
+                                    &primary, &new_value
+                                );
+                                if derived.as_deref() != Some(key) {
+                                    continue;
+                                }
+                            }
+                            None => {}
+                        }
+                        return Err(Error::Unsupported(format!(
+                            "foreign key constraint {:?} violated: \
+                             key {:?} is still referenced by record \
+                             {:?} in tree {:?}",
+                            constraint.name,
+                            key,
+                            primary,
+                            constraint.source_id,
+                        )));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // phase 2: once every participant has applied the writes,
+    // make them durable and clear the coordination records. this
+    // runs after the concurrency control lock is released, as the
+    // writes are already visible and sequenced.
+    fn finish_coordination(
+        key: &IVec,
+        coordination_trees: &[Tree],
+    ) -> Result<()> {
+        for tree in coordination_trees {
+            tree.flush()?;
+        }
+        for tree in coordination_trees {
+            let _old = tree.remove(key)?;
+        }
+        Ok(())
+    }
+
+    // durably records the staged writes of every tree under the
+    // given name, without applying them. each participating
+    // database records the writes destined for its own trees.
+    fn persist_prepared(
+        &self,
+        name: &IVec,
+        contexts: &[&Context],
+        guard: &Guard,
+    ) -> Result<()> {
+        for context in contexts {
+            let registry = meta::open_tree(
+                context,
+                PREPARED_TREE_ID.to_vec(),
+                guard,
+            )?;
+
+            let batches: Vec<(IVec, Batch)> = self
+                .inner
+                .iter()
+                .filter(|tree| {
+                    tree.tree.context.get_path() == context.get_path()
+                })
+                .map(|tree| {
+                    (tree.tree.tree_id.clone(), tree.writes.borrow().clone())
+                })
+                .collect();
+
+            let mut batch = Batch::default();
+            batch.insert(name.clone(), encode_prepared(&batches));
+            let mut batch_guard = pin();
+            registry.apply_batch_inner(batch, None, &mut batch_guard)?;
+            context.pagecache.flush_unprotected()?;
+
+            // the handle must outlive the concurrency control
+            // write lock held by the caller, as dropping a final
+            // `Tree` handle flushes.
+            self.held.borrow_mut().push(registry);
+        }
+        Ok(())
+    }
+
+    fn prepare_coordination(
+        &self,
+        contexts: &[&Context],
+        guard: &Guard,
+    ) -> Result<(IVec, Vec<Tree>)> {
+        let txid =
+            self.inner[0].tree.context.pagecache.generate_id_inner()?;
+        let key: IVec = (&txid.to_be_bytes()[..]).into();
+
+        let mut coordination_trees = Vec::with_capacity(contexts.len());
+        for context in contexts {
+            coordination_trees.push(meta::open_tree(
+                context,
+                COORDINATION_TREE_ID.to_vec(),
+                guard,
+            )?);
+        }
+
+        // the global concurrency control write lock is held here,
+        // so the batches are applied through the internal path
+        // rather than the public one, which would take the read
+        // lock and deadlock.
+        for tree in &coordination_trees {
+            let mut batch = Batch::default();
+            batch.insert(&key, b"prepared");
+            let mut guard = pin();
+            tree.apply_batch_inner(batch, None, &mut guard)?;
+            tree.context.pagecache.flush_unprotected()?;
+        }
+
+        Ok((key, coordination_trees))
+    }
+
+    // clones the staged writes of every tree with a registered
+    // post-commit callback, captured before `commit` drains them.
+    // a transaction routed to durable staging via `prepare` is
+    // not yet committed, so its callbacks do not run here.
+    fn staged_batches_for_callbacks(&self) -> Vec<(Tree, Batch)> {
+        let prepared = self
+            .inner
+            .iter()
+            .any(|tree| tree.prepared_name.borrow().is_some());
+        if prepared {
+            return Vec::new();
+        }
+        self.inner
+            .iter()
+            .filter(|tree| {
+                !tree.tree.post_commit_callbacks.read().is_empty()
+            })
+            .map(|tree| (tree.tree.clone(), tree.writes.borrow().clone()))
+            .collect()
+    }
+
+    // runs post-commit callbacks after making the committed
+    // writes durable, so side effects never refer to state that a
+    // crash could roll back. called once per transaction, after
+    // the concurrency control lock is released.
+    fn run_post_commit_callbacks(
+        staged: &[(Tree, Batch)],
+    ) -> Result<()> {
+        if staged.is_empty() {
+            return Ok(());
+        }
+        for (tree, _) in staged {
+            tree.flush()?;
+        }
+        for (tree, batch) in staged {
+            let callbacks = tree.post_commit_callbacks.read();
+            for callback in callbacks.iter() {
+                callback(batch);
+            }
+        }
+        Ok(())
     }
 
     fn flush_if_configured(&self) -> Result<()> {
@@ -429,6 +906,83 @@ impl TransactionalTrees {
     }
 }
 
+// the durable record format for a prepared transaction: for each
+// tree, a length-prefixed tree id, a write count, and then each
+// staged write as a length-prefixed key followed by a presence
+// byte and, for inserts, a length-prefixed value.
+pub(crate) fn encode_prepared(batches: &[(IVec, Batch)]) -> Vec<u8> {
+    fn push_len(buf: &mut Vec<u8>, len: usize) {
+        let len = u32::try_from(len).unwrap();
+        buf.extend_from_slice(&len.to_be_bytes());
+    }
+
+    let mut buf = Vec::new();
+    for (tree_id, batch) in batches {
+        push_len(&mut buf, tree_id.len());
+        buf.extend_from_slice(tree_id);
+        push_len(&mut buf, batch.writes.len());
+        for (key, value) in &batch.writes {
+            push_len(&mut buf, key.len());
+            buf.extend_from_slice(key);
+            if let Some(value) = value {
+                buf.push(1);
+                push_len(&mut buf, value.len());
+                buf.extend_from_slice(value);
+            } else {
+                buf.push(0);
+            }
+        }
+    }
+    buf
+}
+
+pub(crate) fn decode_prepared(buf: &[u8]) -> Result<Vec<(IVec, Batch)>> {
+    fn corrupted<T>() -> Result<T> {
+        Err(Error::ReportableBug(
+            "prepared transaction record is corrupted".into(),
+        ))
+    }
+
+    fn take<'a>(cursor: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+        if cursor.len() < len {
+            return corrupted();
+        }
+        let (taken, rest) = cursor.split_at(len);
+        *cursor = rest;
+        Ok(taken)
+    }
+
+    fn take_len(cursor: &mut &[u8]) -> Result<usize> {
+        let mut len_buf = [0; 4];
+        len_buf.copy_from_slice(take(cursor, 4)?);
+        Ok(usize::try_from(u32::from_be_bytes(len_buf)).unwrap())
+    }
+
+    let mut batches = Vec::new();
+    let mut cursor = buf;
+    while !cursor.is_empty() {
+        let tree_id_len = take_len(&mut cursor)?;
+        let tree_id: IVec = take(&mut cursor, tree_id_len)?.into();
+        let writes = take_len(&mut cursor)?;
+        let mut batch = Batch::default();
+        for _ in 0..writes {
+            let key_len = take_len(&mut cursor)?;
+            let key: IVec = take(&mut cursor, key_len)?.into();
+            match take(&mut cursor, 1)? {
+                [1] => {
+                    let value_len = take_len(&mut cursor)?;
+                    let value = take(&mut cursor, value_len)?;
+                    batch.insert(key, value);
+                }
+                [0] => batch.remove(key),
+                _ => return corrupted(),
+            }
+        }
+        batches.push((tree_id, batch));
+    }
+    Ok(batches)
+}
+
 /// A simple constructor for `Err(TransactionError::Abort(_))`
 pub fn abort<A, T>(t: T) -> ConflictableTransactionResult<A, T> {
     Err(ConflictableTransactionError::Abort(t))
@@ -470,16 +1024,29 @@ pub trait Transactional<E = ()> {
             }
             match ret {
                 Ok(r) => {
+                    let staged = tt.staged_batches_for_callbacks();
                     let guard = pin();
-                    tt.commit(&guard)?;
+                    let coordination = tt.commit(&guard)?;
                     drop(locks);
+                    if let Some((key, coordination_trees)) = coordination {
+                        TransactionalTrees::finish_coordination(
+                            &key,
+                            &coordination_trees,
+                        )?;
+                    }
                     tt.flush_if_configured()?;
+                    TransactionalTrees::run_post_commit_callbacks(&staged)?;
+                    tt.recycle_scratch();
                     return Ok(r);
                 }
                 Err(ConflictableTransactionError::Abort(e)) => {
+                    tt.recycle_scratch();
                     return Err(TransactionError::Abort(e));
                 }
-                Err(ConflictableTransactionError::Conflict) => continue,
+                Err(ConflictableTransactionError::Conflict) => {
+                    tt.recycle_scratch();
+                    continue;
+                }
                 Err(ConflictableTransactionError::Storage(other)) => {
                     return Err(TransactionError::Storage(other));
                 }
@@ -492,9 +1059,9 @@ impl<E> Transactional<E> for &Tree {
     type View = TransactionalTree;
 
     fn make_overlay(&self) -> Result<TransactionalTrees> {
-        Ok(TransactionalTrees {
-            inner: vec![TransactionalTree::from_tree(self)],
-        })
+        Ok(TransactionalTrees::new(vec![TransactionalTree::from_tree(
+            self,
+        )]))
     }
 
     fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
@@ -506,9 +1073,9 @@ impl<E> Transactional<E> for &&Tree {
     type View = TransactionalTree;
 
     fn make_overlay(&self) -> Result<TransactionalTrees> {
-        Ok(TransactionalTrees {
-            inner: vec![TransactionalTree::from_tree(*self)],
-        })
+        Ok(TransactionalTrees::new(vec![TransactionalTree::from_tree(
+            *self,
+        )]))
     }
 
     fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
@@ -520,9 +1087,9 @@ impl<E> Transactional<E> for Tree {
     type View = TransactionalTree;
 
     fn make_overlay(&self) -> Result<TransactionalTrees> {
-        Ok(TransactionalTrees {
-            inner: vec![TransactionalTree::from_tree(self)],
-        })
+        Ok(TransactionalTrees::new(vec![TransactionalTree::from_tree(
+            self,
+        )]))
     }
 
     fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
@@ -534,23 +1101,9 @@ impl<E> Transactional<E> for [Tree] {
     type View = Vec<TransactionalTree>;
 
     fn make_overlay(&self) -> Result<TransactionalTrees> {
-        let same_db = self.windows(2).all(|w| {
-            let path_1 = w[0].context.get_path();
-            let path_2 = w[1].context.get_path();
-            path_1 == path_2
-        });
-        if !same_db {
-            return Err(Error::Unsupported(
-                "cannot use trees from multiple databases in the same transaction".into(),
-            ));
-        }
-
-        Ok(TransactionalTrees {
-            inner: self
-                .iter()
-                .map(|t| TransactionalTree::from_tree(t))
-                .collect(),
-        })
+        Ok(TransactionalTrees::new(
+            self.iter().map(|t| TransactionalTree::from_tree(t)).collect(),
+        ))
     }
 
     fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
@@ -562,23 +1115,9 @@ impl<E> Transactional<E> for [&Tree] {
     type View = Vec<TransactionalTree>;
 
     fn make_overlay(&self) -> Result<TransactionalTrees> {
-        let same_db = self.windows(2).all(|w| {
-            let path_1 = w[0].context.get_path();
-            let path_2 = w[1].context.get_path();
-            path_1 == path_2
-        });
-        if !same_db {
-            return Err(Error::Unsupported(
-                "cannot use trees from multiple databases in the same transaction".into(),
-            ));
-        }
-
-        Ok(TransactionalTrees {
-            inner: self
-                .iter()
-                .map(|&t| TransactionalTree::from_tree(t))
-                .collect(),
-        })
+        Ok(TransactionalTrees::new(
+            self.iter().map(|&t| TransactionalTree::from_tree(t)).collect(),
+        ))
     }
 
     fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
@@ -610,25 +1149,11 @@ macro_rules! impl_transactional_tuple_trees {
             type View = repeat_type!(TransactionalTree, ($($indices),+));
 
             fn make_overlay(&self) -> Result<TransactionalTrees> {
-                let mut paths = vec![];
-                $(
-                    paths.push(self.$indices.context.get_path());
-                )+
-                if !paths.windows(2).all(|w| {
-                    w[0] == w[1]
-                }) {
-                    return Err(Error::Unsupported(
-                        "cannot use trees from multiple databases in the same transaction".into(),
-                    ));
-                }
-
-                Ok(TransactionalTrees {
-                    inner: vec![
-                        $(
-                            TransactionalTree::from_tree(self.$indices)
-                        ),+
-                    ],
-                })
+                Ok(TransactionalTrees::new(vec![
+                    $(
+                        TransactionalTree::from_tree(self.$indices)
+                    ),+
+                ]))
             }
 
             fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
@@ -1,12 +1,14 @@
 use std::{
     borrow::Cow,
+    convert::TryFrom,
     fmt::{self, Debug},
     num::NonZeroU64,
     ops::{self, Deref, RangeBounds},
     sync::atomic::Ordering::SeqCst,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use parking_lot::RwLock;
+use parking_lot::{Mutex, MutexGuard, RwLock};
 
 use crate::{atomic_shim::AtomicU64, pagecache::NodeView, *};
 
@@ -34,6 +36,336 @@ impl IntoIterator for &'_ Tree {
     }
 }
 
+/// Run-time state for a `Tree` with soft-deletion enabled.
+/// Removed entries are parked in a hidden sibling tree until
+/// their retention period lapses.
+pub(crate) struct SoftDelete {
+    pub(crate) trash: Tree,
+    pub(crate) retention_ms: u64,
+    pub(crate) next_purge_at: AtomicU64,
+}
+
+impl SoftDelete {
+    fn purge_expired(&self, now: u64) -> Result<()> {
+        if self.next_purge_at.load(SeqCst) > now {
+            return Ok(());
+        }
+        let mut next_purge_at = u64::max_value();
+        for kv in &self.trash {
+            let (key, value) = kv?;
+            let expires_at = trash_expiry(&value);
+            if expires_at <= now {
+                self.trash.remove(key)?;
+            } else if expires_at < next_purge_at {
+                next_purge_at = expires_at;
+            }
+        }
+        self.next_purge_at.store(next_purge_at, SeqCst);
+        Ok(())
+    }
+}
+
+/// Run-time state for a `Tree` with key time-to-lives in use.
+/// Expiry times are stored in a hidden sibling tree so that they
+/// survive restarts, and a sweep registered with the flusher
+/// thread reclaims ripe keys in the background.
+pub(crate) struct Ttl {
+    pub(crate) expirations: Tree,
+    pub(crate) next_sweep_at: AtomicU64,
+}
+
+impl Ttl {
+    /// Lowers the sweep cursor so that the next background pass
+    /// notices an expiry at `expires_at`.
+    fn note_expiry(&self, expires_at: u64) {
+        let mut current = self.next_sweep_at.load(SeqCst);
+        while expires_at < current {
+            match self.next_sweep_at.compare_exchange(
+                current,
+                expires_at,
+                SeqCst,
+                SeqCst,
+            ) {
+                Ok(_) => break,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+/// A sweep closure registered by a tree with TTLs in use,
+/// returning `false` once its tree has been deleted.
+pub(crate) type ExpirySweep = Box<dyn Fn(u64) -> bool + Send + Sync>;
+
+/// The sweep registry, owned by the `Db` so that registered
+/// sweeps (which hold `Tree` handles) are released when the
+/// database handle is dropped. The flusher thread and the
+/// `Context` reach it through a `Weak` reference to avoid a
+/// reference cycle.
+pub(crate) type ExpirySweepRegistry =
+    std::sync::Arc<RwLock<Vec<ExpirySweep>>>;
+
+/// A non-owning handle to the sweep registry.
+pub(crate) type WeakExpirySweepRegistry =
+    std::sync::Weak<RwLock<Vec<ExpirySweep>>>;
+
+/// Named merge operators registered via
+/// `Db::register_merge_operator`, resolved when a tree with a
+/// persisted operator name is merged into.
+#[derive(Default)]
+pub(crate) struct MergeOperators(
+    pub(crate) RwLock<Map<String, std::sync::Arc<dyn MergeOperator>>>,
+);
+
+impl Debug for MergeOperators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.read().keys()).finish()
+    }
+}
+
+fn ttl_expiry(record: &[u8]) -> u64 {
+    let mut arr = [0; 8];
+    arr.copy_from_slice(&record[..8]);
+    u64::from_le_bytes(arr)
+}
+
+const AUDIT_OP_INSERT: u8 = 0;
+const AUDIT_OP_REMOVE: u8 = 1;
+const AUDIT_OP_CAS: u8 = 2;
+const AUDIT_OP_MERGE: u8 = 3;
+
+/// Run-time state for a `Tree` with audit mode enabled. Every
+/// mutation is recorded into an append-only sibling tree. Each
+/// record embeds the checksum of its predecessor, making
+/// truncation or in-place tampering of the log detectable via
+/// `Tree::verify_audit`.
+pub(crate) struct Audit {
+    pub(crate) log: Tree,
+    pub(crate) tag: IVec,
+    // the next sequence number to be assigned, and the
+    // checksum of the most recently appended record.
+    pub(crate) cursor: Mutex<(u64, u32)>,
+}
+
+impl Audit {
+    fn record(
+        &self,
+        op: u8,
+        key: &[u8],
+        old_value: Option<&[u8]>,
+        new_value: Option<&[u8]>,
+    ) -> Result<()> {
+        let mut cursor = self.cursor.lock();
+        let (seq, prev_crc) = *cursor;
+
+        let mut value =
+            Vec::with_capacity(27 + self.tag.len() + key.len());
+        value.extend_from_slice(&prev_crc.to_le_bytes());
+        value.extend_from_slice(&now_millis().to_le_bytes());
+        value.push(op);
+        append_value_hash(&mut value, old_value);
+        append_value_hash(&mut value, new_value);
+        let tag_len = u32::try_from(self.tag.len()).unwrap();
+        value.extend_from_slice(&tag_len.to_le_bytes());
+        value.extend_from_slice(&self.tag);
+        value.extend_from_slice(key);
+
+        let seq_arr = seq.to_be_bytes();
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&seq_arr);
+        hasher.update(&value);
+        let crc = hasher.finalize();
+
+        self.log.insert(&seq_arr, value)?;
+        *cursor = (seq + 1, crc);
+        Ok(())
+    }
+}
+
+/// Run-time state for a `Tree` with per-entry versioning enabled.
+/// Versions live in a hidden sibling tree, and conditional updates
+/// are serialized against version bumps via the contained mutex.
+pub(crate) struct Versioning {
+    pub(crate) versions: Tree,
+    pub(crate) lock: Mutex<()>,
+}
+
+impl Versioning {
+    fn current_version(&self, key: &[u8]) -> Result<u64> {
+        if let Some(raw) = self.versions.get(key)? {
+            let mut arr = [0; 8];
+            arr.copy_from_slice(&raw);
+            Ok(u64::from_le_bytes(arr))
+        } else {
+            Ok(0)
+        }
+    }
+}
+
+fn append_value_hash(buf: &mut Vec<u8>, value: Option<&[u8]>) {
+    if let Some(value) = value {
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(value);
+        buf.push(1);
+        buf.extend_from_slice(&hasher.finalize().to_le_bytes());
+    } else {
+        buf.push(0);
+        buf.extend_from_slice(&[0; 4]);
+    }
+}
+
+fn trash_expiry(trash_value: &[u8]) -> u64 {
+    let mut arr = [0; 8];
+    arr.copy_from_slice(&trash_value[..8]);
+    u64::from_le_bytes(arr)
+}
+
+pub(crate) fn duration_to_millis(duration: Duration) -> u64 {
+    duration
+        .as_secs()
+        .saturating_mul(1000)
+        .saturating_add(u64::from(duration.subsec_millis()))
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(duration_to_millis)
+        .unwrap_or(0)
+}
+
+/// Per-tree configuration overrides for use with
+/// `Db::open_tree_with_options`, for workloads where different
+/// trees have different access patterns. The overrides are
+/// persisted in the meta tree and reapplied when the tree is
+/// reopened after a restart.
+///
+/// Compression remains configured globally via
+/// `Config::use_compression`, as pages from all trees share log
+/// segments.
+#[allow(clippy::module_name_repetitions)]
+#[derive(Debug, Clone, Default)]
+pub struct TreeOptions {
+    split_threshold: Option<u64>,
+    merge_threshold: Option<u64>,
+    pin_in_cache: bool,
+    merge_operator_name: Option<String>,
+}
+
+impl TreeOptions {
+    /// Returns a default `TreeOptions` with no overrides set.
+    pub fn new() -> TreeOptions {
+        TreeOptions::default()
+    }
+
+    /// Overrides the node size in bytes beyond which pages of
+    /// this tree are split. Lower values favor trees with many
+    /// small hot keys; higher values reduce index depth for
+    /// large sequentially-scanned values.
+    pub fn split_threshold(mut self, bytes: u64) -> TreeOptions {
+        self.split_threshold = Some(bytes);
+        self
+    }
+
+    /// Overrides the node size in bytes below which pages of
+    /// this tree are merged into their siblings.
+    pub fn merge_threshold(mut self, bytes: u64) -> TreeOptions {
+        self.merge_threshold = Some(bytes);
+        self
+    }
+
+    /// Pins the tree's current leaf pages into the in-memory
+    /// cache at open, exempting them from eviction pressure.
+    /// This is `Tree::pin_range` over the whole keyspace, so it
+    /// shares its requirements and caveats: a
+    /// `pinned_cache_budget` must be configured, and pages
+    /// created by later splits are not automatically pinned.
+    pub fn pin_in_cache(mut self) -> TreeOptions {
+        self.pin_in_cache = true;
+        self
+    }
+
+    /// Selects a merge operator by name, as
+    /// `Tree::set_merge_operator_name` does. The named operator
+    /// must be registered via `Db::register_merge_operator`
+    /// before the tree is opened with these options.
+    pub fn merge_operator_name<N: Into<String>>(
+        mut self,
+        name: N,
+    ) -> TreeOptions {
+        self.merge_operator_name = Some(name.into());
+        self
+    }
+
+    // the persisted record covers the thresholds and the cache
+    // pin; the merge operator name is persisted separately via
+    // the existing named-operator registry
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![0_u8];
+        if let Some(split) = self.split_threshold {
+            buf[0] |= 1;
+            buf.extend_from_slice(&split.to_le_bytes());
+        }
+        if let Some(merge) = self.merge_threshold {
+            buf[0] |= 2;
+            buf.extend_from_slice(&merge.to_le_bytes());
+        }
+        if self.pin_in_cache {
+            buf[0] |= 4;
+        }
+        buf
+    }
+
+    pub(crate) fn decode(buf: &[u8]) -> Result<TreeOptions> {
+        let corrupted = || {
+            Error::ReportableBug(
+                "tree options record is corrupted".to_string(),
+            )
+        };
+
+        let (flags, mut rest) = buf.split_first().ok_or_else(corrupted)?;
+
+        let mut take_u64 = || -> Result<u64> {
+            if rest.len() < 8 {
+                return Err(corrupted());
+            }
+            let mut arr = [0; 8];
+            arr.copy_from_slice(&rest[..8]);
+            rest = &rest[8..];
+            Ok(u64::from_le_bytes(arr))
+        };
+
+        let mut options = TreeOptions::new();
+        if flags & 1 != 0 {
+            options.split_threshold = Some(take_u64()?);
+        }
+        if flags & 2 != 0 {
+            options.merge_threshold = Some(take_u64()?);
+        }
+        options.pin_in_cache = flags & 4 != 0;
+        Ok(options)
+    }
+}
+
+/// Statistics returned by maintenance operations — `compact`,
+/// `clear`, and `remove_range` — so operators can log and alert
+/// on their effectiveness.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MaintenanceStats {
+    /// The number of pages rewritten in the log.
+    pub pages_rewritten: u64,
+    /// The number of records removed.
+    pub keys_removed: u64,
+    /// The log bytes made dead and eligible for garbage
+    /// collection: the footprint of each superseded page version.
+    /// Replacements are written at the head of the log and remain
+    /// live; space is returned to the filesystem lazily as the
+    /// segments holding dead versions drain.
+    pub bytes_reclaimed: u64,
+    /// How long the operation took.
+    pub duration: Duration,
+}
+
 /// A flash-sympathetic persistent lock-free B+ tree.
 ///
 /// A `Tree` represents a single logical keyspace / namespace / bucket.
@@ -93,6 +425,32 @@ pub struct TreeInner {
     pub(crate) subscribers: Subscribers,
     pub(crate) root: AtomicU64,
     pub(crate) merge_operator: RwLock<Option<Box<dyn MergeOperator>>>,
+    pub(crate) indexes: RwLock<Vec<Index>>,
+    pub(crate) pre_commit_validators:
+        RwLock<Vec<Box<dyn PreCommitValidator>>>,
+    pub(crate) post_commit_callbacks:
+        RwLock<Vec<Box<dyn PostCommitCallback>>>,
+    pub(crate) foreign_keys_out: RwLock<
+        Vec<(std::sync::Arc<foreign_key::ForeignKeyConstraint>, Index)>,
+    >,
+    pub(crate) foreign_keys_in:
+        RwLock<Vec<foreign_key::IncomingForeignKey>>,
+    pub(crate) soft_delete: RwLock<Option<SoftDelete>>,
+    pub(crate) audit: RwLock<Option<Audit>>,
+    // cached handle to the database-wide changefeed log,
+    // populated lazily once change capture is enabled
+    pub(crate) changefeed_log: RwLock<Option<Tree>>,
+    // cached handle to this tree's idempotency id registry,
+    // populated lazily on the first `insert_idempotent` call
+    pub(crate) idempotency: RwLock<Option<Tree>>,
+    pub(crate) versioning: RwLock<Option<Versioning>>,
+    pub(crate) ttl: RwLock<Option<Ttl>>,
+    pub(crate) mutation_count: AtomicU64,
+    pub(crate) snapshots:
+        RwLock<Vec<std::sync::Weak<snapshot::SnapshotState>>>,
+    // per-tree node sizing overrides, 0 when unset
+    pub(crate) split_threshold_override: AtomicU64,
+    pub(crate) merge_threshold_override: AtomicU64,
 }
 
 impl Drop for TreeInner {
@@ -149,13 +507,182 @@ impl Tree {
         K: AsRef<[u8]>,
         V: Into<IVec>,
     {
+        let _priority = priority::enter();
+        let _cf = self.changefeed_guard();
+        let value = value.into();
+        let res = self.insert_raw(key.as_ref(), value.clone())?;
+
+        self.ttl_clear(key.as_ref())?;
+        self.bump_version(key.as_ref(), false)?;
+        self.audit_record(
+            AUDIT_OP_INSERT,
+            key.as_ref(),
+            res.as_ref().map(AsRef::as_ref),
+            Some(&value),
+        )?;
+        self.changefeed_record(key.as_ref(), Some(&value))?;
+
+        Ok(res)
+    }
+
+    /// Insert a key to a new value that expires once `ttl` has
+    /// elapsed, returning the last value if it was set.
+    ///
+    /// Expired keys stop being returned from `get` and from
+    /// iterators as soon as their deadline passes, and are
+    /// physically reclaimed in the background by the flusher
+    /// thread. Expiry times are persisted, so they keep being
+    /// enforced after a restart. A plain `insert` or `remove` of
+    /// the same key cancels its TTL.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use std::time::Duration;
+    ///
+    /// db.insert_with_ttl(b"session", b"token", Duration::from_millis(1))?;
+    /// std::thread::sleep(Duration::from_millis(10));
+    /// assert_eq!(db.get(b"session")?, None);
+    ///
+    /// db.insert_with_ttl(b"config", b"v", Duration::from_secs(1000))?;
+    /// assert!(db.get(b"config")?.is_some());
+    /// # Ok(()) }
+    /// ```
+    pub fn insert_with_ttl<K, V>(
+        &self,
+        key: K,
+        value: V,
+        ttl: Duration,
+    ) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: Into<IVec>,
+    {
+        let expirations = self.ttl_expirations()?;
+        let res = self.insert(key.as_ref(), value)?;
+
+        let expires_at =
+            now_millis().saturating_add(duration_to_millis(ttl));
+        expirations
+            .insert(key.as_ref(), &expires_at.to_le_bytes()[..])?;
+        if let Some(t) = &*self.ttl.read() {
+            t.note_expiry(expires_at);
+        }
+
+        Ok(res)
+    }
+
+    /// Inserts a key to a new value at most once for a given
+    /// operation identifier, recording the identifier and the
+    /// operation's result atomically with the write. If the same
+    /// `op_id` is replayed, the previously recorded result is
+    /// returned and the tree is left untouched, making this
+    /// suitable for pipelines built over at-least-once upstream
+    /// delivery.
+    ///
+    /// Recorded identifiers are retained until removed, so
+    /// unbounded streams should use identifiers that are
+    /// periodically recycled or compacted by the application.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// assert_eq!(db.insert_idempotent(b"op-1", b"k", b"v1")?, None);
+    ///
+    /// // a replayed delivery returns the recorded result
+    /// // without applying the write again
+    /// assert_eq!(db.insert_idempotent(b"op-1", b"k", b"v2")?, None);
+    /// assert_eq!(&db.get(b"k")?.unwrap(), b"v1");
+    /// # Ok(()) }
+    /// ```
+    pub fn insert_idempotent<I, K, V>(
+        &self,
+        op_id: I,
+        key: K,
+        value: V,
+    ) -> Result<Option<IVec>>
+    where
+        I: AsRef<[u8]>,
+        K: AsRef<[u8]>,
+        V: Into<IVec>,
+    {
+        use crate::transaction::{TransactionError, Transactional};
+
+        let idempotency = self.idempotency_tree()?;
+
         let value = value.into();
+
+        let result: std::result::Result<Option<IVec>, TransactionError> =
+            (self, &idempotency).transaction(|(tree, ids)| {
+                if let Some(recorded) = ids.get(op_id.as_ref())? {
+                    // a replay: return the recorded result
+                    // without applying the write
+                    return Ok(decode_idempotency_record(&recorded));
+                }
+                let old = tree.insert(key.as_ref(), value.clone())?;
+                ids.insert(
+                    op_id.as_ref(),
+                    encode_idempotency_record(old.as_ref()),
+                )?;
+                Ok(old)
+            });
+
+        match result {
+            Ok(old) => Ok(old),
+            Err(TransactionError::Abort(error))
+            | Err(TransactionError::Storage(error)) => Err(error),
+        }
+    }
+
+    /// Returns this tree's idempotency id registry, opening and
+    /// caching a handle on first use. Caching matters here:
+    /// dropping a freshly-opened handle flushes the entire
+    /// pagecache via `TreeInner::drop`.
+    fn idempotency_tree(&self) -> Result<Tree> {
+        {
+            let idempotency = self.idempotency.read();
+            if let Some(idempotency) = &*idempotency {
+                return Ok(idempotency.clone());
+            }
+        }
+        let mut idempotency_name = IDEMPOTENCY_TREE_PREFIX.to_vec();
+        idempotency_name.extend_from_slice(&self.tree_id);
+        let guard = pin();
+        let idempotency =
+            meta::open_tree(&self.context, idempotency_name, &guard)?;
+        *self.idempotency.write() = Some(idempotency.clone());
+        Ok(idempotency)
+    }
+
+    /// Rejects mutations on instances opened in read-only mode.
+    pub(crate) fn ensure_writable(&self) -> Result<()> {
+        if self.context.read_only {
+            Err(Error::ReadOnly)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The raw insertion loop, without any of the optional
+    /// bookkeeping (versioning, audit) layered on top.
+    fn insert_raw(&self, key: &[u8], value: IVec) -> Result<Option<IVec>> {
+        self.ensure_writable()?;
+        #[cfg(feature = "chaos")]
+        chaos::inject(chaos::Op::Write)?;
+        self.fk_check_insert(key, &value)?;
+        self.unique_check_insert(key, &value)?;
         let mut guard = pin();
         let _cc = concurrency_control::read();
         loop {
-            trace!("setting key {:?}", key.as_ref());
+            trace!("setting key {:?}", key);
             if let Ok(res) = self.insert_inner(
-                key.as_ref(),
+                key,
                 Some(value.clone()),
                 false,
                 &mut guard,
@@ -210,8 +737,22 @@ impl Tree {
 
         if link.is_ok() {
             // success
+            self.mutation_count.fetch_add(1, SeqCst);
+            self.bump_total_ops();
+
+            self.note_preimage(
+                key.as_ref(),
+                last_value.as_ref().map(AsRef::as_ref),
+            );
+
+            self.maintain_indexes(
+                key.as_ref(),
+                last_value.as_ref().map(AsRef::as_ref),
+                value.as_ref().map(AsRef::as_ref),
+            )?;
+
             if let Some(Some(res)) = subscriber_reservation.take() {
-                let event = subscriber::Event::single_update(
+                let event = Event::single_update(
                     self.clone(),
                     key.as_ref().into(),
                     value,
@@ -374,9 +915,163 @@ impl Tree {
     /// # Ok(()) }
     /// ```
     pub fn apply_batch(&self, batch: Batch) -> Result<()> {
+        let _priority = priority::enter();
+        self.ensure_writable()?;
+        #[cfg(feature = "chaos")]
+        chaos::inject(chaos::Op::Write)?;
+        let ttl_bookkeeping =
+            if batch.ttls.is_empty() && self.ttl.read().is_none() {
+                None
+            } else {
+                Some((
+                    batch.writes.keys().cloned().collect::<Vec<IVec>>(),
+                    batch.ttls.clone(),
+                ))
+            };
+
+        {
+            let _cf = self.changefeed_guard();
+            let _cc = concurrency_control::write();
+            let mut guard = pin();
+            self.apply_batch_inner(batch, None, &mut guard)?;
+        }
+
+        if let Some((keys, ttls)) = ttl_bookkeeping {
+            for key in &keys {
+                if !ttls.contains_key(key) {
+                    self.ttl_clear(key)?;
+                }
+            }
+            if !ttls.is_empty() {
+                let expirations = self.ttl_expirations()?;
+                let now = now_millis();
+                for (key, ttl_ms) in ttls {
+                    let expires_at = now.saturating_add(ttl_ms);
+                    expirations
+                        .insert(&key, &expires_at.to_le_bytes()[..])?;
+                    if let Some(t) = &*self.ttl.read() {
+                        t.note_expiry(expires_at);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Applies a sequence of batches, then flushes once at the
+    /// end, pairing with [`Batch::from_iter_chunked`] to make
+    /// large imports simple and memory-bounded. Each batch is
+    /// atomic on its own, but the sequence as a whole is not: a
+    /// crash mid-way leaves a prefix of the batches applied.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let pairs = (0u32..1000).map(|i| (i.to_be_bytes().to_vec(), b"imported".to_vec()));
+    ///
+    /// // at most ~64KiB of keys and values staged at a time
+    /// db.apply_batches(sled::Batch::from_iter_chunked(pairs, 64 << 10))?;
+    ///
+    /// assert_eq!(db.len(), 1000);
+    /// # Ok(()) }
+    /// ```
+    pub fn apply_batches<I>(&self, batches: I) -> Result<()>
+    where
+        I: IntoIterator<Item = Batch>,
+    {
+        for batch in batches {
+            self.apply_batch(batch)?;
+        }
+        self.flush()?;
+        Ok(())
+    }
+
+    /// Returns the number of successful mutations applied to this
+    /// tree since it was opened. The counter is in-memory only and
+    /// restarts from `0` on recovery. It is intended for use with
+    /// `apply_batch_if_unchanged`, where only equality with a
+    /// previously observed value is meaningful.
+    pub fn mutation_count(&self) -> u64 {
+        self.mutation_count.load(SeqCst)
+    }
+
+    /// Atomically apply a `Batch`, but only if no mutation has been
+    /// applied to this tree since `observed_count` was returned from
+    /// `mutation_count`. This validates a read-compute-write cycle
+    /// over many keys with a single comparison, rather than
+    /// requiring per-key expectations as `compare_and_swap` does.
+    ///
+    /// On success the inner result is `Ok(())`; if the tree has
+    /// changed in the meantime, the current mutation count is
+    /// returned in the inner `Err` and nothing is applied.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let observed = db.mutation_count();
+    ///
+    /// let mut batch = sled::Batch::default();
+    /// batch.insert("key_a", "val_a");
+    /// batch.insert("key_b", "val_b");
+    ///
+    /// // nothing else has written, so the batch applies
+    /// assert!(db.apply_batch_if_unchanged(batch, observed)?.is_ok());
+    ///
+    /// let mut stale_batch = sled::Batch::default();
+    /// stale_batch.insert("key_a", "clobber");
+    ///
+    /// // the previous batch advanced the counter,
+    /// // so the stale observation is rejected
+    /// assert!(db.apply_batch_if_unchanged(stale_batch, observed)?.is_err());
+    /// # Ok(()) }
+    /// ```
+    pub fn apply_batch_if_unchanged(
+        &self,
+        batch: Batch,
+        observed_count: u64,
+    ) -> Result<std::result::Result<(), u64>> {
+        let _cf = self.changefeed_guard();
         let _cc = concurrency_control::write();
+
+        let current = self.mutation_count.load(SeqCst);
+        if current != observed_count {
+            return Ok(Err(current));
+        }
+
         let mut guard = pin();
-        self.apply_batch_inner(batch, None, &mut guard)
+        self.apply_batch_inner(batch, None, &mut guard)?;
+        Ok(Ok(()))
+    }
+
+    // resolves any `Batch::remove_range` entries against the
+    // tree's current contents, queueing a point deletion for
+    // every covered key that the batch doesn't already write.
+    fn expand_range_removes(&self, mut batch: Batch) -> Result<Batch> {
+        let range_removes = std::mem::take(&mut batch.range_removes);
+        for (lo, hi) in range_removes {
+            let mut iter = Iter {
+                tree: self.clone(),
+                hi,
+                lo,
+                cached_forward_node: None,
+                cached_back_node: None,
+                cancellation: None,
+                deadline: None,
+            };
+
+            while let Some(kv) = iter.next_inner() {
+                let (k, _v) = kv?;
+                batch.writes.entry(k).or_insert(None);
+            }
+        }
+        Ok(batch)
     }
 
     pub(crate) fn apply_batch_inner(
@@ -393,6 +1088,15 @@ impl Tree {
 
         trace!("applying batch {:?}", batch);
 
+        let batch = self.expand_range_removes(batch)?;
+
+        // transactional batches are validated at commit time with
+        // the full cross-tree staged write set in view
+        if transaction_batch.is_none() {
+            self.fk_check_batch(&batch)?;
+            self.unique_check_batch(&batch)?;
+        }
+
         let mut subscriber_reservation = self.subscribers.reserve_batch(&batch);
 
         for (k, v_opt) in &batch.writes {
@@ -411,6 +1115,10 @@ impl Tree {
             }
         }
 
+        for (k, v_opt) in &batch.writes {
+            self.changefeed_record(k, v_opt.as_ref().map(AsRef::as_ref))?;
+        }
+
         if let Some(res) = subscriber_reservation.take() {
             if let Some(transaction_batch) = transaction_batch {
                 res.complete(&transaction_batch);
@@ -443,20 +1151,35 @@ impl Tree {
     /// # Ok(()) }
     /// ```
     pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
-        let mut guard = pin();
-        let _cc = concurrency_control::read();
-        loop {
-            if let Ok(get) = self.get_inner(key.as_ref(), &mut guard)? {
-                return Ok(get);
+        let _priority = priority::enter();
+        #[cfg(feature = "chaos")]
+        chaos::inject(chaos::Op::Read)?;
+        let val = {
+            let mut guard = pin();
+            let _cc = concurrency_control::read();
+            loop {
+                if let Ok(get) = self.get_inner(key.as_ref(), &mut guard)? {
+                    break get;
+                }
             }
+        };
+
+        if val.is_some() && self.key_is_expired(key.as_ref())? {
+            return Ok(None);
         }
+
+        Ok(val)
     }
 
-    /// Pass the result of getting a key's value to a closure
-    /// without making a new allocation. This effectively
-    /// "pushes" your provided code to the data without ever copying
-    /// the data, rather than "pulling" a copy of the data to whatever code
-    /// is calling `get`.
+    /// Like `Tree::get`, but gives up with `Error::TimedOut` if
+    /// the value has not been retrieved by `deadline`, so
+    /// latency-sensitive services can shed load instead of
+    /// queuing behind a slow disk.
+    ///
+    /// The deadline is checked before each traversal attempt: an
+    /// individual disk read that is already in flight is not
+    /// interrupted, so the call may overshoot the deadline by up
+    /// to one page fault.
     ///
     /// # Examples
     ///
@@ -464,14 +1187,164 @@ impl Tree {
     /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
     /// # let config = sled::Config::new().temporary(true);
     /// # let db = config.open()?;
+    /// use std::time::{Duration, Instant};
+    ///
     /// db.insert(&[0], vec![0])?;
-    /// db.get_zero_copy(&[0], |value_opt| {
-    ///     assert_eq!(
-    ///         value_opt,
-    ///         Some(&[0][..])
-    ///     )
-    /// });
-    /// db.get_zero_copy(&[1], |value_opt| assert!(value_opt.is_none()));
+    ///
+    /// let deadline = Instant::now() + Duration::from_secs(30);
+    /// assert_eq!(
+    ///     db.get_with_deadline(&[0], deadline)?,
+    ///     Some(sled::IVec::from(vec![0])),
+    /// );
+    ///
+    /// let lapsed = Instant::now() - Duration::from_secs(1);
+    /// assert_eq!(
+    ///     db.get_with_deadline(&[0], lapsed),
+    ///     Err(sled::Error::TimedOut),
+    /// );
+    /// # Ok(()) }
+    /// ```
+    pub fn get_with_deadline<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+        deadline: Instant,
+    ) -> Result<Option<IVec>> {
+        let val = {
+            let mut guard = pin();
+            let _cc = concurrency_control::read();
+            loop {
+                if Instant::now() >= deadline {
+                    return Err(Error::TimedOut);
+                }
+                if let Ok(get) = self.get_inner(key.as_ref(), &mut guard)? {
+                    break get;
+                }
+            }
+        };
+
+        if val.is_some() && self.key_is_expired(key.as_ref())? {
+            return Ok(None);
+        }
+
+        Ok(val)
+    }
+
+    /// Retrieves many values in one call, returned in the same
+    /// order as the provided keys, with `None` for keys that are
+    /// absent.
+    ///
+    /// The keys are visited in sorted order so that each leaf node
+    /// is traversed once no matter how many of the keys it holds,
+    /// rather than paying the full root-to-leaf traversal and
+    /// cache-lookup cost per key the way a loop over
+    /// [`get`](Tree::get) does. This makes point-reading large key
+    /// sets substantially cheaper, especially when the keys
+    /// cluster into few leaves.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"a", vec![1])?;
+    /// db.insert(b"b", vec![2])?;
+    /// db.insert(b"c", vec![3])?;
+    ///
+    /// let values = db.multi_get([b"c", b"x", b"a"].iter())?;
+    /// assert_eq!(values[0], Some(sled::IVec::from(vec![3])));
+    /// assert_eq!(values[1], None);
+    /// assert_eq!(values[2], Some(sled::IVec::from(vec![1])));
+    /// # Ok(()) }
+    /// ```
+    pub fn multi_get<K, I>(&self, keys: I) -> Result<Vec<Option<IVec>>>
+    where
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = K>,
+    {
+        let _priority = priority::enter();
+        let _measure = Measure::new(&M.tree_get);
+
+        let keys: Vec<K> = keys.into_iter().collect();
+
+        let mut order: Vec<usize> = (0..keys.len()).collect();
+        order.sort_unstable_by(|a, b| {
+            keys[*a].as_ref().cmp(keys[*b].as_ref())
+        });
+
+        let mut results: Vec<Option<IVec>> = vec![None; keys.len()];
+
+        {
+            let mut guard = pin();
+            let _cc = concurrency_control::read();
+
+            let mut position = 0;
+            while position < order.len() {
+                let first_key = keys[order[position]].as_ref();
+
+                let (pid, consumed) = {
+                    let view = self.view_for_key(first_key, &guard)?;
+
+                    // resolve every remaining key covered by this
+                    // leaf before moving on. the traversal above
+                    // guarantees the first key is covered, and the
+                    // keys are sorted, so each leaf is visited at
+                    // most once.
+                    let mut consumed = 0;
+                    while position + consumed < order.len() {
+                        let index = order[position + consumed];
+                        let key = keys[index].as_ref();
+                        let covered = match view.hi() {
+                            Some(hi) => key < hi,
+                            None => true,
+                        };
+                        if !covered {
+                            break;
+                        }
+                        let pair = view.node_kv_pair(key);
+                        results[index] = pair.1.map(IVec::from);
+                        consumed += 1;
+                    }
+
+                    (view.pid, consumed)
+                };
+
+                guard.readset.push(pid);
+                position += consumed;
+            }
+        }
+
+        for (index, key) in keys.iter().enumerate() {
+            if results[index].is_some()
+                && self.key_is_expired(key.as_ref())?
+            {
+                results[index] = None;
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Pass the result of getting a key's value to a closure
+    /// without making a new allocation. This effectively
+    /// "pushes" your provided code to the data without ever copying
+    /// the data, rather than "pulling" a copy of the data to whatever code
+    /// is calling `get`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(&[0], vec![0])?;
+    /// db.get_zero_copy(&[0], |value_opt| {
+    ///     assert_eq!(
+    ///         value_opt,
+    ///         Some(&[0][..])
+    ///     )
+    /// });
+    /// db.get_zero_copy(&[1], |value_opt| assert!(value_opt.is_none()));
     /// # Ok(()) }
     /// ```
     pub fn get_zero_copy<K: AsRef<[u8]>, B, F: FnOnce(Option<&[u8]>) -> B>(&self, key: K, f: F) -> Result<B> {
@@ -501,50 +1374,1119 @@ impl Tree {
     ) -> Result<Conflictable<Option<IVec>>> {
         let _measure = Measure::new(&M.tree_get);
 
-        trace!("getting key {:?}", key);
+        trace!("getting key {:?}", key);
+
+        let View { node_view, pid, .. } =
+            self.view_for_key(key.as_ref(), guard)?;
+
+        let pair = node_view.node_kv_pair(key.as_ref());
+        let val = pair.1.map(IVec::from);
+
+        guard.readset.push(pid);
+
+        Ok(Ok(val))
+    }
+
+    #[doc(hidden)]
+    #[deprecated(since = "0.24.2", note = "replaced by `Tree::remove`")]
+    pub fn del<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        self.remove(key)
+    }
+
+    /// Delete a value, returning the old value if it existed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(&[1], vec![1]);
+    /// assert_eq!(db.remove(&[1]), Ok(Some(sled::IVec::from(vec![1]))));
+    /// assert_eq!(db.remove(&[1]), Ok(None));
+    /// # Ok(()) }
+    /// ```
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        let _priority = priority::enter();
+        let _cf = self.changefeed_guard();
+        self.ensure_writable()?;
+        #[cfg(feature = "chaos")]
+        chaos::inject(chaos::Op::Write)?;
+        self.fk_check_remove(key.as_ref())?;
+        let old = {
+            let mut guard = pin();
+            let _cc = concurrency_control::read();
+            loop {
+                trace!("removing key {:?}", key.as_ref());
+
+                if let Ok(res) =
+                    self.insert_inner(key.as_ref(), None, false, &mut guard)?
+                {
+                    break res;
+                }
+            }
+        };
+
+        if let Some(old_value) = &old {
+            self.move_to_trash(key.as_ref(), old_value)?;
+        }
+
+        self.ttl_clear(key.as_ref())?;
+        self.bump_version(key.as_ref(), true)?;
+        self.audit_record(
+            AUDIT_OP_REMOVE,
+            key.as_ref(),
+            old.as_ref().map(AsRef::as_ref),
+            None,
+        )?;
+        self.changefeed_record(key.as_ref(), None)?;
+
+        Ok(old)
+    }
+
+    /// Writes a value under the given key by pulling `len` bytes
+    /// from a reader, chunking it through the pagecache so that
+    /// the whole value is never materialized as one contiguous
+    /// allocation. Read it back with
+    /// [`get_reader`](Tree::get_reader) and remove it with
+    /// [`remove_reader`](Tree::remove_reader).
+    ///
+    /// Streamed values live in a hidden sibling tree keyed by
+    /// chunk, in a namespace separate from [`get`](Tree::get),
+    /// [`insert`](Tree::insert), and iteration, so a streamed
+    /// value and an ordinary value may coexist under the same
+    /// key.
+    ///
+    /// Returns an error if the reader ends before producing
+    /// `len` bytes; bytes beyond `len` are left unread.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let bytes = vec![7; 100_000];
+    /// // any std::io::Read source works, such as a file
+    /// db.insert_reader(b"blob", &bytes[..], 100_000)?;
+    ///
+    /// let mut reader = db.get_reader(b"blob")?.unwrap();
+    /// assert_eq!(reader.len(), 100_000);
+    /// let mut out = vec![];
+    /// std::io::Read::read_to_end(&mut reader, &mut out)?;
+    /// assert_eq!(out, bytes);
+    ///
+    /// assert!(db.remove_reader(b"blob")?);
+    /// assert!(db.get_reader(b"blob")?.is_none());
+    /// # Ok(()) }
+    /// ```
+    pub fn insert_reader<K, R>(
+        &self,
+        key: K,
+        mut reader: R,
+        len: u64,
+    ) -> Result<()>
+    where
+        K: AsRef<[u8]>,
+        R: Read,
+    {
+        self.ensure_writable()?;
+
+        let streams = self.streams()?;
+        let prefix = stream_key_prefix(key.as_ref());
+
+        // replacing a longer streamed value must drop its now
+        // stale tail chunks
+        let old_chunks =
+            match streams.get(&prefix)? {
+                Some(manifest) => {
+                    let (old_len, old_chunk_size) =
+                        decode_stream_manifest(&manifest)?;
+                    stream_chunk_count(old_len, old_chunk_size)
+                }
+                None => 0,
+            };
+
+        let mut chunk = vec![
+            0;
+            usize::try_from(STREAM_CHUNK_SIZE.min(len)).unwrap()
+        ];
+        let mut position = 0;
+        let mut chunk_idx = 0;
+        while position < len {
+            let chunk_len =
+                usize::try_from(STREAM_CHUNK_SIZE.min(len - position))
+                    .unwrap();
+            let buf = &mut chunk[..chunk_len];
+            let mut filled = 0;
+            while filled < chunk_len {
+                let n = reader.read(&mut buf[filled..])?;
+                if n == 0 {
+                    return Err(Error::Io(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "reader ended before producing the \
+                         promised number of bytes",
+                    )));
+                }
+                filled += n;
+            }
+            streams.insert(
+                stream_chunk_key(&prefix, chunk_idx),
+                &buf[..],
+            )?;
+            position += u64::try_from(chunk_len).unwrap();
+            chunk_idx += 1;
+        }
+
+        streams.insert(
+            &prefix,
+            encode_stream_manifest(len, STREAM_CHUNK_SIZE),
+        )?;
+
+        for stale_idx in chunk_idx..old_chunks {
+            streams.remove(stream_chunk_key(&prefix, stale_idx))?;
+        }
+
+        Ok(())
+    }
+
+    /// Retrieves a value stored via
+    /// [`insert_reader`](Tree::insert_reader) as a
+    /// [`ValueReader`] implementing `std::io::Read`, which
+    /// fetches one chunk at a time rather than materializing the
+    /// whole value. See [`insert_reader`](Tree::insert_reader)
+    /// for an example.
+    pub fn get_reader<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<Option<ValueReader>> {
+        let streams = self.streams()?;
+        let prefix = stream_key_prefix(key.as_ref());
+        let manifest = match streams.get(&prefix)? {
+            Some(manifest) => manifest,
+            None => return Ok(None),
+        };
+        let (total_len, chunk_size) = decode_stream_manifest(&manifest)?;
+        Ok(Some(ValueReader {
+            tree: streams,
+            prefix,
+            total_len,
+            chunk_size,
+            position: 0,
+            chunk_idx: 0,
+            chunk: None,
+        }))
+    }
+
+    /// Removes a value stored via
+    /// [`insert_reader`](Tree::insert_reader), returning `true`
+    /// if one existed. See [`insert_reader`](Tree::insert_reader)
+    /// for an example.
+    pub fn remove_reader<K: AsRef<[u8]>>(&self, key: K) -> Result<bool> {
+        self.ensure_writable()?;
+
+        let streams = self.streams()?;
+        let prefix = stream_key_prefix(key.as_ref());
+        let manifest = match streams.remove(&prefix)? {
+            Some(manifest) => manifest,
+            None => return Ok(false),
+        };
+        let (total_len, chunk_size) = decode_stream_manifest(&manifest)?;
+        for chunk_idx in 0..stream_chunk_count(total_len, chunk_size) {
+            streams.remove(stream_chunk_key(&prefix, chunk_idx))?;
+        }
+        Ok(true)
+    }
+
+    // the hidden sibling tree holding streamed value chunks
+    fn streams(&self) -> Result<Tree> {
+        let mut name = STREAMS_TREE_PREFIX.to_vec();
+        name.extend_from_slice(&self.tree_id);
+        let guard = pin();
+        meta::open_tree(&self.context, name, &guard)
+    }
+
+    /// Removes every key in a range of keys, returning statistics
+    /// about what the removal accomplished.
+    ///
+    /// Instead of logging one deletion per key the way a loop
+    /// over [`remove`](Tree::remove) would, each covered leaf node
+    /// is rewritten in a single page replacement with the in-range
+    /// keys dropped, so clearing a large range costs one log write
+    /// per touched leaf rather than one per key.
+    ///
+    /// Like [`clear`](Tree::clear), this is not atomic: each leaf
+    /// is rewritten atomically, but a concurrent reader may
+    /// observe the range partially removed. For an atomic range
+    /// removal, use [`Batch::remove_range`] instead, which resolves
+    /// the range into point deletions when the batch is applied.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// for i in 0..10_u32 {
+    ///     db.insert(&i.to_be_bytes(), vec![])?;
+    /// }
+    ///
+    /// let start = 3_u32.to_be_bytes();
+    /// let end = 7_u32.to_be_bytes();
+    /// let stats = db.remove_range(start.as_ref()..end.as_ref())?;
+    /// assert_eq!(stats.keys_removed, 4);
+    /// assert!(stats.pages_rewritten >= 1);
+    /// assert_eq!(db.len(), 6);
+    /// assert_eq!(db.get(&3_u32.to_be_bytes())?, None);
+    /// assert!(db.get(&7_u32.to_be_bytes())?.is_some());
+    /// # Ok(()) }
+    /// ```
+    pub fn remove_range<K, R>(&self, range: R) -> Result<MaintenanceStats>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let _priority = priority::enter();
+        let _measure = Measure::new(&M.tree_del);
+        self.ensure_writable()?;
+        let start_time = Instant::now();
+        let mut stats = MaintenanceStats::default();
+
+        let lo = match range.start_bound() {
+            ops::Bound::Included(start) => {
+                ops::Bound::Included(IVec::from(start.as_ref()))
+            }
+            ops::Bound::Excluded(start) => {
+                ops::Bound::Excluded(IVec::from(start.as_ref()))
+            }
+            ops::Bound::Unbounded => ops::Bound::Included(IVec::from(&[])),
+        };
+
+        let hi = match range.end_bound() {
+            ops::Bound::Included(end) => {
+                ops::Bound::Included(IVec::from(end.as_ref()))
+            }
+            ops::Bound::Excluded(end) => {
+                ops::Bound::Excluded(IVec::from(end.as_ref()))
+            }
+            ops::Bound::Unbounded => ops::Bound::Unbounded,
+        };
+
+        // incoming foreign keys are enforced here as on `remove`:
+        // scan the covered keys up front and reject the whole
+        // range removal if any of them is still referenced.
+        if !self.foreign_keys_in.read().is_empty() {
+            let mut covered = self.range((lo.clone(), hi.clone()));
+            while let Some(kv) = covered.next_inner() {
+                let (key, _) = kv?;
+                self.fk_check_remove(&key)?;
+            }
+        }
+
+        let mut all_removed: Vec<(IVec, IVec)> = Vec::new();
+
+        let _cf = self.changefeed_guard();
+
+        {
+            let mut guard = pin();
+            let _cc = concurrency_control::read();
+
+            let mut cursor: IVec = match &lo {
+                ops::Bound::Included(start) | ops::Bound::Excluded(start) => {
+                    start.clone()
+                }
+                ops::Bound::Unbounded => IVec::from(&[]),
+            };
+
+            loop {
+                let (pid, next_hi, node_removed) = {
+                    let view = self.view_for_key(&cursor, &guard)?;
+                    let next_hi = view.hi().map(IVec::from);
+
+                    let (replacement, node_removed) =
+                        view.deref().remove_range(&lo, &hi);
+
+                    if !node_removed.is_empty() {
+                        let mut reservations: Vec<_> = node_removed
+                            .iter()
+                            .map(|(k, _)| self.subscribers.reserve(k))
+                            .collect();
+
+                        let replace = self.context.pagecache.replace(
+                            view.pid,
+                            view.node_view.0,
+                            replacement,
+                            &guard,
+                        )?;
+
+                        if replace.is_err() {
+                            // retry this leaf from a fresh view
+                            M.tree_looped();
+                            continue;
+                        }
+
+                        stats.pages_rewritten += 1;
+                        stats.bytes_reclaimed += view.size;
+
+                        for (reservation, (key, _)) in
+                            reservations.iter_mut().zip(node_removed.iter())
+                        {
+                            if let Some(res) = reservation.take() {
+                                let event = Event::single_update(
+                                    self.clone(),
+                                    key.clone(),
+                                    None,
+                                );
+                                res.complete(&event);
+                            }
+                        }
+                    }
+
+                    (view.pid, next_hi, node_removed)
+                };
+
+                if !node_removed.is_empty() {
+                    self.mutation_count
+                        .fetch_add(node_removed.len() as u64, SeqCst);
+                    for (key, old) in &node_removed {
+                        self.note_preimage(key, Some(old.as_ref()));
+                        self.maintain_indexes(key, Some(old.as_ref()), None)?;
+                        self.bump_total_ops();
+                    }
+                    guard.writeset.push(pid);
+                    all_removed.extend(node_removed);
+                }
+
+                let reached_end = match (&next_hi, &hi) {
+                    (None, _) => true,
+                    (Some(h), ops::Bound::Included(end)) => *h > *end,
+                    (Some(h), ops::Bound::Excluded(end)) => *h >= *end,
+                    (Some(_), ops::Bound::Unbounded) => false,
+                };
+
+                if reached_end {
+                    break;
+                }
+
+                cursor = next_hi.unwrap();
+            }
+        }
+
+        for (key, old) in &all_removed {
+            self.move_to_trash(key, old)?;
+            self.ttl_clear(key)?;
+            self.bump_version(key, true)?;
+            self.audit_record(AUDIT_OP_REMOVE, key, Some(old.as_ref()), None)?;
+            self.changefeed_record(key, None)?;
+        }
+
+        stats.keys_removed = all_removed.len() as u64;
+        stats.duration = start_time.elapsed();
+        Ok(stats)
+    }
+
+    /// Moves a freshly removed value into the trash keyspace if
+    /// soft-deletion is enabled, and opportunistically purges
+    /// trash entries whose retention period has lapsed.
+    fn move_to_trash(&self, key: &[u8], value: &IVec) -> Result<()> {
+        let soft_delete = self.soft_delete.read();
+        if let Some(sd) = &*soft_delete {
+            let now = now_millis();
+            let expires_at = now.saturating_add(sd.retention_ms);
+            let mut trash_value = Vec::with_capacity(8 + value.len());
+            trash_value.extend_from_slice(&expires_at.to_le_bytes());
+            trash_value.extend_from_slice(value);
+            sd.trash.insert(key, trash_value)?;
+            sd.purge_expired(now)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the hidden expiration tree for this tree, opening
+    /// it and registering the background sweep on first use.
+    fn ttl_expirations(&self) -> Result<Tree> {
+        {
+            let ttl = self.ttl.read();
+            if let Some(t) = &*ttl {
+                return Ok(t.expirations.clone());
+            }
+        }
+
+        let mut name = TTL_TREE_PREFIX.to_vec();
+        name.extend_from_slice(&self.tree_id);
+        let guard = pin();
+        let expirations = meta::open_tree(&self.context, name, &guard)?;
+        Ok(self.install_ttl_state(expirations))
+    }
+
+    /// Re-attaches TTL state persisted by a previous process, so
+    /// that expirations keep being enforced after a restart.
+    /// Applies per-tree overrides to this handle, persisting
+    /// nothing itself: thresholds land in the shared inner
+    /// state, a cache pin is taken out immediately, and a merge
+    /// operator name goes through the named-operator path.
+    pub(crate) fn apply_options(&self, options: &TreeOptions) -> Result<()> {
+        if let Some(split) = options.split_threshold {
+            self.split_threshold_override.store(split, SeqCst);
+        }
+        if let Some(merge) = options.merge_threshold {
+            self.merge_threshold_override.store(merge, SeqCst);
+        }
+        if let Some(name) = &options.merge_operator_name {
+            self.set_merge_operator_name(name)?;
+        }
+        if options.pin_in_cache {
+            self.pin_range::<&[u8], _>(..)?;
+        }
+        Ok(())
+    }
+
+    fn split_threshold(&self) -> Option<u64> {
+        NonZeroU64::new(self.split_threshold_override.load(SeqCst))
+            .map(NonZeroU64::get)
+    }
+
+    fn merge_threshold(&self) -> Option<u64> {
+        NonZeroU64::new(self.merge_threshold_override.load(SeqCst))
+            .map(NonZeroU64::get)
+    }
+
+    /// Reapplies any overrides recorded for this tree via
+    /// `Db::open_tree_with_options`, called when a handle is
+    /// created at startup.
+    pub(crate) fn attach_persisted_options(
+        &self,
+        guard: &Guard,
+    ) -> Result<()> {
+        if self.tree_id.starts_with(INTERNAL_TREE_PREFIX)
+            && self.tree_id != DEFAULT_TREE_ID
+        {
+            return Ok(());
+        }
+
+        match self
+            .context
+            .pagecache
+            .meta_pid_for_name(TREE_OPTIONS_TREE_ID, guard)
+        {
+            Ok(_) => {}
+            // no tree has ever persisted options
+            Err(Error::CollectionNotFound(_)) => return Ok(()),
+            Err(other) => return Err(other),
+        }
+
+        let records = meta::open_tree(
+            &self.context,
+            TREE_OPTIONS_TREE_ID.to_vec(),
+            guard,
+        )?;
+        if let Some(record) = records.get(&self.tree_id)? {
+            let options = TreeOptions::decode(&record)?;
+            self.apply_options(&options)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn attach_persisted_ttl(&self, guard: &Guard) -> Result<()> {
+        if self.tree_id.starts_with(TTL_TREE_PREFIX) {
+            return Ok(());
+        }
+
+        let mut name = TTL_TREE_PREFIX.to_vec();
+        name.extend_from_slice(&self.tree_id);
+        match self.context.pagecache.meta_pid_for_name(&name, guard) {
+            Ok(_) => {
+                let expirations =
+                    meta::open_tree(&self.context, name, guard)?;
+                self.install_ttl_state(expirations);
+                Ok(())
+            }
+            Err(Error::CollectionNotFound(_)) => Ok(()),
+            Err(other) => Err(other),
+        }
+    }
+
+    fn install_ttl_state(&self, expirations: Tree) -> Tree {
+        let mut ttl = self.ttl.write();
+        if let Some(t) = &*ttl {
+            return t.expirations.clone();
+        }
+
+        *ttl = Some(Ttl {
+            expirations: expirations.clone(),
+            next_sweep_at: AtomicU64::new(0),
+        });
+
+        if let Some(registry) = self.context.expiry_sweeps.read().upgrade()
+        {
+            let tree = self.clone();
+            registry.write().push(Box::new(move |now| {
+                match tree.sweep_expired(now) {
+                    Ok(()) => true,
+                    Err(Error::CollectionNotFound(_)) => false,
+                    Err(e) => {
+                        error!("failed to sweep expired keys: {:?}", e);
+                        true
+                    }
+                }
+            }));
+        }
+
+        expirations
+    }
+
+    /// Returns `true` if the key's TTL has lapsed, lazily
+    /// reclaiming it on the way out.
+    pub(crate) fn key_is_expired(&self, key: &[u8]) -> Result<bool> {
+        let expirations = {
+            let ttl = self.ttl.read();
+            match &*ttl {
+                Some(t) => t.expirations.clone(),
+                None => return Ok(false),
+            }
+        };
+
+        if let Some(record) = expirations.get(key)? {
+            if ttl_expiry(&record) <= now_millis() {
+                self.expire_key(&expirations, key, &record)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Removes a key whose TTL has lapsed. The expiry record is
+    /// claimed first so that concurrent readers and the
+    /// background sweep perform exactly one removal between them.
+    fn expire_key(
+        &self,
+        expirations: &Tree,
+        key: &[u8],
+        record: &IVec,
+    ) -> Result<()> {
+        if expirations
+            .compare_and_swap(key, Some(record), None::<&[u8]>)?
+            .is_ok()
+        {
+            self.remove(key)?;
+        }
+        Ok(())
+    }
+
+    /// Cancels any TTL recorded for the key. A no-op unless TTLs
+    /// are in use on this tree.
+    fn ttl_clear(&self, key: &[u8]) -> Result<()> {
+        let expirations = {
+            let ttl = self.ttl.read();
+            match &*ttl {
+                Some(t) => t.expirations.clone(),
+                None => return Ok(()),
+            }
+        };
+
+        if expirations.get(key)?.is_some() {
+            expirations.remove(key)?;
+        }
+        Ok(())
+    }
+
+    /// Reclaims expired keys, driven by the flusher thread. The
+    /// scan is skipped entirely until the earliest known expiry
+    /// comes due.
+    pub(crate) fn sweep_expired(&self, now: u64) -> Result<()> {
+        let (expirations, start_cursor) = {
+            let ttl = self.ttl.read();
+            match &*ttl {
+                Some(t) => (
+                    t.expirations.clone(),
+                    t.next_sweep_at.load(SeqCst),
+                ),
+                None => return Ok(()),
+            }
+        };
+        if start_cursor > now {
+            return Ok(());
+        }
+
+        let mut next_sweep_at = u64::max_value();
+        for kv in &expirations {
+            let (key, record) = kv?;
+            let expires_at = ttl_expiry(&record);
+            if expires_at <= now {
+                self.expire_key(&expirations, &key, &record)?;
+            } else if expires_at < next_sweep_at {
+                next_sweep_at = expires_at;
+            }
+        }
+
+        if let Some(t) = &*self.ttl.read() {
+            // a TTL recorded during the scan may be earlier than
+            // anything we observed; only advance the cursor if
+            // nobody lowered it in the meantime
+            let _ = t.next_sweep_at.compare_exchange(
+                start_cursor,
+                next_sweep_at,
+                SeqCst,
+                SeqCst,
+            );
+        }
+        Ok(())
+    }
+
+    /// Enable soft-deletion for this tree. While enabled, `remove`
+    /// parks the removed value in a hidden trash keyspace instead of
+    /// forgetting it. The value remains restorable via `Tree::restore`
+    /// until the provided retention period has passed. Expired trash
+    /// entries are purged automatically during subsequent calls to
+    /// `remove` and `restore`.
+    ///
+    /// Note that only `remove` participates in soft-deletion. Other
+    /// deletion paths such as `pop_min`, `pop_max`, `clear`, batches
+    /// and transactions bypass the trash keyspace.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use std::time::Duration;
+    ///
+    /// db.enable_soft_delete(Duration::from_secs(60))?;
+    /// db.insert(&[1], vec![10])?;
+    /// db.remove(&[1])?;
+    /// assert_eq!(db.get(&[1])?, None);
+    ///
+    /// // the removed value can be restored until
+    /// // the retention period has passed.
+    /// assert_eq!(db.restore(&[1])?, Some(sled::IVec::from(vec![10])));
+    /// assert_eq!(db.get(&[1])?, Some(sled::IVec::from(vec![10])));
+    /// # Ok(()) }
+    /// ```
+    pub fn enable_soft_delete(&self, retention: Duration) -> Result<()> {
+        let mut trash_name = TRASH_TREE_PREFIX.to_vec();
+        trash_name.extend_from_slice(&self.tree_id);
+
+        let guard = pin();
+        let trash = meta::open_tree(&self.context, trash_name, &guard)?;
+
+        let mut soft_delete = self.soft_delete.write();
+        *soft_delete = Some(SoftDelete {
+            trash,
+            retention_ms: duration_to_millis(retention),
+            next_purge_at: AtomicU64::new(0),
+        });
+        Ok(())
+    }
+
+    /// Disable soft-deletion for this tree, restoring the default
+    /// `remove` behavior. Entries already in the trash keyspace are
+    /// retained on disk, and become visible again if soft-deletion
+    /// is later re-enabled.
+    pub fn disable_soft_delete(&self) {
+        let mut soft_delete = self.soft_delete.write();
+        *soft_delete = None;
+    }
+
+    /// Restore a key that was previously soft-deleted via `remove`,
+    /// returning the restored value if it was still present in the
+    /// trash keyspace. Returns `Ok(None)` if the key was never
+    /// removed or its retention period has already passed.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unsupported` if soft-deletion is not
+    /// currently enabled on this tree.
+    pub fn restore<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        let soft_delete = self.soft_delete.read();
+        let sd = if let Some(sd) = &*soft_delete {
+            sd
+        } else {
+            return Err(Error::Unsupported(
+                "Tree::restore requires soft-deletion to be \
+                 enabled via Tree::enable_soft_delete"
+                    .into(),
+            ));
+        };
+
+        let now = now_millis();
+        sd.purge_expired(now)?;
+
+        if let Some(trash_value) = sd.trash.remove(key.as_ref())? {
+            if trash_expiry(&trash_value) > now {
+                let value = IVec::from(&trash_value[8..]);
+                self.insert(key.as_ref(), value.clone())?;
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Enable audit mode for this tree. While enabled, every mutation
+    /// performed through `insert`, `remove`, `compare_and_swap` and
+    /// `merge` is recorded into a dedicated append-only audit tree,
+    /// tagged with the provided identifier (for example a user or
+    /// service name). Records store the mutated key, a timestamp,
+    /// and checksums of the old and new values, and each record is
+    /// chained to its predecessor so that tampering with the log can
+    /// be detected via `Tree::verify_audit`.
+    ///
+    /// The audit log survives restarts: re-enabling audit mode on a
+    /// recovered tree continues the existing chain.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.enable_audit("batch-importer")?;
+    /// db.insert(&[1], vec![10])?;
+    /// db.remove(&[1])?;
+    ///
+    /// // two chained records have been written,
+    /// // and the chain verifies.
+    /// assert_eq!(db.verify_audit()?, 2);
+    /// # Ok(()) }
+    /// ```
+    pub fn enable_audit<T: AsRef<[u8]>>(&self, tag: T) -> Result<()> {
+        let mut log_name = AUDIT_TREE_PREFIX.to_vec();
+        log_name.extend_from_slice(&self.tree_id);
+
+        let guard = pin();
+        let log = meta::open_tree(&self.context, log_name, &guard)?;
+
+        // pick up where a previous instance left off, so the
+        // chain stays unbroken across restarts.
+        let cursor = if let Some((seq_arr, value)) = log.last()? {
+            let mut arr = [0; 8];
+            arr.copy_from_slice(&seq_arr);
+            let seq = u64::from_be_bytes(arr);
+
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(&seq_arr);
+            hasher.update(&value);
+            (seq + 1, hasher.finalize())
+        } else {
+            (0, 0)
+        };
+
+        let mut audit = self.audit.write();
+        *audit = Some(Audit {
+            log,
+            tag: tag.as_ref().into(),
+            cursor: Mutex::new(cursor),
+        });
+        Ok(())
+    }
+
+    /// Disable audit mode for this tree. Already-written audit
+    /// records are retained on disk.
+    pub fn disable_audit(&self) {
+        let mut audit = self.audit.write();
+        *audit = None;
+    }
+
+    /// Walk the audit log written while audit mode was enabled,
+    /// verifying that the records form an unbroken chain, and
+    /// return the number of records seen.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unsupported` if audit mode is not currently
+    /// enabled, and `Error::Corruption` if the chain does not
+    /// verify.
+    pub fn verify_audit(&self) -> Result<u64> {
+        let audit = self.audit.read();
+        let audit_ref = if let Some(audit_ref) = &*audit {
+            audit_ref
+        } else {
+            return Err(Error::Unsupported(
+                "Tree::verify_audit requires audit mode to be \
+                 enabled via Tree::enable_audit"
+                    .into(),
+            ));
+        };
+
+        let mut expected_seq = 0;
+        let mut expected_prev_crc = 0_u32;
+
+        for kv in &audit_ref.log {
+            let (seq_arr, value) = kv?;
+
+            let mut arr = [0; 8];
+            arr.copy_from_slice(&seq_arr);
+            let seq = u64::from_be_bytes(arr);
+
+            let mut prev_crc_arr = [0; 4];
+            prev_crc_arr.copy_from_slice(&value[..4]);
+            let prev_crc = u32::from_le_bytes(prev_crc_arr);
+
+            if seq != expected_seq || prev_crc != expected_prev_crc {
+                return Err(Error::corruption(None));
+            }
+
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(&seq_arr);
+            hasher.update(&value);
+            expected_prev_crc = hasher.finalize();
+            expected_seq += 1;
+        }
+
+        Ok(expected_seq)
+    }
+
+    /// Enable per-entry versioning for this tree. While enabled,
+    /// every write advances a persistent version counter for the
+    /// mutated key, retrievable via `get_with_meta` and usable for
+    /// HTTP-style conditional updates via `insert_if_version`,
+    /// without comparing full value bytes as `compare_and_swap`
+    /// does. Removing a key clears its counter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.enable_versioning()?;
+    /// db.insert(&[1], vec![10])?;
+    ///
+    /// let (value, version) = db.get_with_meta(&[1])?.unwrap();
+    /// assert_eq!(value, sled::IVec::from(vec![10]));
+    /// assert_eq!(version, 1);
+    ///
+    /// // conditional update with the observed version succeeds
+    /// assert_eq!(db.insert_if_version(&[1], vec![11], 1)?, Ok(2));
+    ///
+    /// // a stale version is rejected, returning the current one
+    /// assert_eq!(db.insert_if_version(&[1], vec![12], 1)?, Err(2));
+    /// # Ok(()) }
+    /// ```
+    pub fn enable_versioning(&self) -> Result<()> {
+        let mut versions_name = VERSIONS_TREE_PREFIX.to_vec();
+        versions_name.extend_from_slice(&self.tree_id);
+
+        let guard = pin();
+        let versions =
+            meta::open_tree(&self.context, versions_name, &guard)?;
+
+        let mut versioning = self.versioning.write();
+        *versioning = Some(Versioning { versions, lock: Mutex::new(()) });
+        Ok(())
+    }
+
+    /// Disable per-entry versioning for this tree. Version counters
+    /// already on disk are retained and resume advancing if
+    /// versioning is later re-enabled.
+    pub fn disable_versioning(&self) {
+        let mut versioning = self.versioning.write();
+        *versioning = None;
+    }
+
+    /// Retrieve a value along with its current version counter.
+    /// Keys written before versioning was first enabled report
+    /// version `0`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unsupported` if versioning is not currently
+    /// enabled on this tree.
+    pub fn get_with_meta<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<Option<(IVec, u64)>> {
+        let versioning = self.versioning.read();
+        let vs = if let Some(vs) = &*versioning {
+            vs
+        } else {
+            return Err(Error::Unsupported(
+                "Tree::get_with_meta requires versioning to be \
+                 enabled via Tree::enable_versioning"
+                    .into(),
+            ));
+        };
+
+        let _lock = vs.lock.lock();
+        if let Some(value) = self.get(key.as_ref())? {
+            let version = vs.current_version(key.as_ref())?;
+            Ok(Some((value, version)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Set a key to a new value only if its version counter still
+    /// matches `expected_version`, as previously observed via
+    /// `get_with_meta`. An expected version of `0` requires that the
+    /// key is absent (or predates versioning). On success the new
+    /// version is returned in the inner `Ok`; on a version mismatch
+    /// the current version is returned in the inner `Err` and no
+    /// change is made.
+    ///
+    /// Conditional updates are serialized with respect to each other
+    /// and to version bumps performed by unconditional writes.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unsupported` if versioning is not currently
+    /// enabled on this tree.
+    pub fn insert_if_version<K, V>(
+        &self,
+        key: K,
+        value: V,
+        expected_version: u64,
+    ) -> Result<std::result::Result<u64, u64>>
+    where
+        K: AsRef<[u8]>,
+        V: Into<IVec>,
+    {
+        let value = value.into();
+        let _cf = self.changefeed_guard();
+        let versioning = self.versioning.read();
+        let vs = if let Some(vs) = &*versioning {
+            vs
+        } else {
+            return Err(Error::Unsupported(
+                "Tree::insert_if_version requires versioning to be \
+                 enabled via Tree::enable_versioning"
+                    .into(),
+            ));
+        };
+
+        let _lock = vs.lock.lock();
+        let current = vs.current_version(key.as_ref())?;
+        if current != expected_version {
+            return Ok(Err(current));
+        }
 
-        let View { node_view, pid, .. } =
-            self.view_for_key(key.as_ref(), guard)?;
+        let old = self.insert_raw(key.as_ref(), value.clone())?;
 
-        let pair = node_view.node_kv_pair(key.as_ref());
-        let val = pair.1.map(IVec::from);
+        let next = current + 1;
+        vs.versions.insert(key.as_ref(), &next.to_le_bytes())?;
 
-        guard.readset.push(pid);
+        self.audit_record(
+            AUDIT_OP_INSERT,
+            key.as_ref(),
+            old.as_ref().map(AsRef::as_ref),
+            Some(&value),
+        )?;
+        self.changefeed_record(key.as_ref(), Some(&value))?;
 
-        Ok(Ok(val))
+        Ok(Ok(next))
     }
 
-    #[doc(hidden)]
-    #[deprecated(since = "0.24.2", note = "replaced by `Tree::remove`")]
-    pub fn del<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
-        self.remove(key)
+    /// Advances (or clears, for removals) the version counter of a
+    /// key if versioning is enabled.
+    fn bump_version(&self, key: &[u8], removed: bool) -> Result<()> {
+        let versioning = self.versioning.read();
+        if let Some(vs) = &*versioning {
+            let _lock = vs.lock.lock();
+            if removed {
+                vs.versions.remove(key)?;
+            } else {
+                let next = vs.current_version(key)? + 1;
+                vs.versions.insert(key, &next.to_le_bytes())?;
+            }
+        }
+        Ok(())
     }
 
-    /// Delete a value, returning the old value if it existed.
-    ///
-    /// # Examples
-    ///
-    /// ```
-    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
-    /// # let config = sled::Config::new().temporary(true);
-    /// # let db = config.open()?;
-    /// db.insert(&[1], vec![1]);
-    /// assert_eq!(db.remove(&[1]), Ok(Some(sled::IVec::from(vec![1]))));
-    /// assert_eq!(db.remove(&[1]), Ok(None));
-    /// # Ok(()) }
-    /// ```
-    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+    /// Takes the database-wide changefeed serialization lock if
+    /// writes to this tree are being captured. Write paths hold
+    /// it across both the data write and the `changefeed_record`
+    /// call, so that log order matches the order the updates
+    /// became visible in. It must always be acquired before the
+    /// concurrency control protector, to keep the lock order
+    /// consistent between point writes, batches, and
+    /// transactions.
+    pub(crate) fn changefeed_guard(&self) -> Option<MutexGuard<'_, ()>> {
+        if !self.context.changefeed.enabled.load(SeqCst) {
+            return None;
+        }
+        if self.tree_id.starts_with(INTERNAL_TREE_PREFIX)
+            && self.tree_id != DEFAULT_TREE_ID
+        {
+            return None;
+        }
+        Some(self.context.changefeed.write_lock.lock())
+    }
+
+    /// Appends a record to the database-wide changefeed log if
+    /// change capture is enabled. Writes to hidden bookkeeping
+    /// trees are not captured, and the log is written through
+    /// `insert_inner` so that this is safe to call from batch
+    /// application, which already holds the concurrency control
+    /// protector. Callers serialize capture with the data write
+    /// by holding the guard from `changefeed_guard` across both.
+    fn changefeed_record(
+        &self,
+        key: &[u8],
+        new_value: Option<&[u8]>,
+    ) -> Result<()> {
+        if !self.context.changefeed.enabled.load(SeqCst) {
+            return Ok(());
+        }
+        if self.tree_id.starts_with(INTERNAL_TREE_PREFIX)
+            && self.tree_id != DEFAULT_TREE_ID
+        {
+            return Ok(());
+        }
+
+        let log = self.changefeed_log()?;
+        let record: IVec =
+            changefeed::encode_record(&self.tree_id, key, new_value).into();
+
+        let lsn = self.context.changefeed.cursor.fetch_add(1, SeqCst);
         let mut guard = pin();
-        let _cc = concurrency_control::read();
         loop {
-            trace!("removing key {:?}", key.as_ref());
-
-            if let Ok(res) =
-                self.insert_inner(key.as_ref(), None, false, &mut guard)?
+            if log
+                .insert_inner(
+                    &lsn.to_be_bytes(),
+                    Some(record.clone()),
+                    false,
+                    &mut guard,
+                )?
+                .is_ok()
             {
-                return Ok(res);
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the shared changefeed log tree, opening and
+    /// caching a handle on first use.
+    fn changefeed_log(&self) -> Result<Tree> {
+        {
+            let log = self.changefeed_log.read();
+            if let Some(log) = &*log {
+                return Ok(log.clone());
             }
         }
+        let guard = pin();
+        let log = meta::open_tree(
+            &self.context,
+            CHANGEFEED_TREE_ID.to_vec(),
+            &guard,
+        )?;
+        *self.changefeed_log.write() = Some(log.clone());
+        Ok(log)
+    }
+
+    /// Appends a record to the audit log if audit mode is enabled.
+    fn audit_record(
+        &self,
+        op: u8,
+        key: &[u8],
+        old_value: Option<&[u8]>,
+        new_value: Option<&[u8]>,
+    ) -> Result<()> {
+        let audit = self.audit.read();
+        if let Some(audit_ref) = &*audit {
+            audit_ref.record(op, key, old_value, new_value)?;
+        }
+        Ok(())
     }
 
     /// Compare and swap. Capable of unique creation, conditional modification,
@@ -612,14 +2554,27 @@ impl Tree {
         trace!("cas'ing key {:?}", key.as_ref());
         let _measure = Measure::new(&M.tree_cas);
 
-        let guard = pin();
-        let _cc = concurrency_control::read();
+        self.ensure_writable()?;
+
+        #[cfg(feature = "chaos")]
+        chaos::inject(chaos::Op::Write)?;
 
         let new = new.map(Into::into);
 
+        if let Some(new) = &new {
+            self.fk_check_insert(key.as_ref(), new)?;
+            self.unique_check_insert(key.as_ref(), new)?;
+        } else {
+            self.fk_check_remove(key.as_ref())?;
+        }
+
+        let _cf = self.changefeed_guard();
+        let guard = pin();
+        let _cc = concurrency_control::read();
+
         // we need to retry caps until old != cur, since just because
         // cap fails it doesn't mean our value was changed.
-        loop {
+        let ret = loop {
             let View { pid, node_view, .. } =
                 self.view_for_key(key.as_ref(), &guard)?;
 
@@ -643,7 +2598,7 @@ impl Tree {
                 // because we verified that the input matches, so
                 // doing the work has the same semantic effect as not
                 // doing it in this case.
-                return Ok(Ok(()))
+                return Ok(Ok(()));
             }
 
             let mut subscriber_reservation = self.subscribers.reserve(&key);
@@ -661,20 +2616,48 @@ impl Tree {
                 self.context.pagecache.link(pid, node_view.0, frag, &guard)?;
 
             if link.is_ok() {
+                self.mutation_count.fetch_add(1, SeqCst);
+                self.bump_total_ops();
+
+                self.note_preimage(key.as_ref(), current_value);
+
+                self.maintain_indexes(
+                    key.as_ref(),
+                    current_value,
+                    new.as_ref().map(AsRef::as_ref),
+                )?;
+
                 if let Some(res) = subscriber_reservation.take() {
-                    let event = subscriber::Event::single_update(
+                    let event = Event::single_update(
                         self.clone(),
                         key.as_ref().into(),
-                        new,
+                        new.clone(),
                     );
 
                     res.complete(&event);
                 }
 
-                return Ok(Ok(()));
+                break new;
             }
             M.tree_looped();
-        }
+        };
+
+        drop(_cc);
+        drop(guard);
+
+        self.bump_version(key.as_ref(), ret.is_none())?;
+        self.audit_record(
+            AUDIT_OP_CAS,
+            key.as_ref(),
+            old.as_ref().map(AsRef::as_ref),
+            ret.as_ref().map(AsRef::as_ref),
+        )?;
+        self.changefeed_record(
+            key.as_ref(),
+            ret.as_ref().map(AsRef::as_ref),
+        )?;
+
+        Ok(Ok(()))
     }
 
     /// Fetch the value, apply a function to it and return the result.
@@ -884,6 +2867,60 @@ impl Tree {
         self.subscribers.register(prefix.as_ref())
     }
 
+    /// Subscribe to `Event`s that happen to keys that fall
+    /// within the specified range, for event consumers whose
+    /// interval of interest doesn't align with a key prefix.
+    /// Shares `Tree::watch_prefix`'s delivery guarantees,
+    /// blocking behavior, and buffer size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use sled::IVec;
+    ///
+    /// let start: &[u8] = b"b";
+    /// let end: &[u8] = b"d";
+    /// let mut subscriber = db.watch_range(start..end);
+    ///
+    /// db.insert(b"a", vec![1])?; // outside the range
+    /// db.insert(b"c", vec![2])?; // inside the range
+    ///
+    /// let event = subscriber.next().unwrap();
+    /// let (_tree, key, _value) = event.iter().next().unwrap();
+    /// assert_eq!(key, &IVec::from(b"c"));
+    /// # Ok(()) }
+    /// ```
+    pub fn watch_range<K, R>(&self, range: R) -> Subscriber
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let lo = match range.start_bound() {
+            ops::Bound::Included(start) => {
+                ops::Bound::Included(IVec::from(start.as_ref()))
+            }
+            ops::Bound::Excluded(start) => {
+                ops::Bound::Excluded(IVec::from(start.as_ref()))
+            }
+            ops::Bound::Unbounded => ops::Bound::Included(IVec::from(&[])),
+        };
+
+        let hi = match range.end_bound() {
+            ops::Bound::Included(end) => {
+                ops::Bound::Included(IVec::from(end.as_ref()))
+            }
+            ops::Bound::Excluded(end) => {
+                ops::Bound::Excluded(IVec::from(end.as_ref()))
+            }
+            ops::Bound::Unbounded => ops::Bound::Unbounded,
+        };
+
+        self.subscribers.register_range(lo, hi)
+    }
+
     /// Synchronously flushes all dirty IO buffers and calls
     /// fsync. If this succeeds, it is guaranteed that all
     /// previous writes will be recovered if the system
@@ -895,6 +2932,8 @@ impl Tree {
     /// realistic sustained workloads running on realistic
     /// hardware.
     pub fn flush(&self) -> Result<usize> {
+        #[cfg(feature = "chaos")]
+        chaos::inject(chaos::Op::Flush)?;
         self.context.pagecache.flush()
     }
 
@@ -1110,12 +3149,37 @@ impl Tree {
         K: AsRef<[u8]>,
         V: AsRef<[u8]>,
     {
-        let _cc = concurrency_control::read();
-        loop {
-            if let Ok(merge) = self.merge_inner(key.as_ref(), value.as_ref())? {
-                return Ok(merge);
+        self.ensure_writable()?;
+        self.resolve_merge_operator()?;
+
+        #[cfg(feature = "chaos")]
+        chaos::inject(chaos::Op::Write)?;
+
+        let _cf = self.changefeed_guard();
+        let merge = {
+            let _cc = concurrency_control::read();
+            loop {
+                if let Ok(merge) =
+                    self.merge_inner(key.as_ref(), value.as_ref())?
+                {
+                    break merge;
+                }
             }
-        }
+        };
+
+        self.bump_version(key.as_ref(), merge.is_none())?;
+        self.audit_record(
+            AUDIT_OP_MERGE,
+            key.as_ref(),
+            None,
+            merge.as_ref().map(AsRef::as_ref),
+        )?;
+        self.changefeed_record(
+            key.as_ref(),
+            merge.as_ref().map(AsRef::as_ref),
+        )?;
+
+        Ok(merge)
     }
 
     pub(crate) fn merge_inner(
@@ -1169,8 +3233,19 @@ impl Tree {
                 self.context.pagecache.link(pid, node_view.0, frag, &guard)?;
 
             if link.is_ok() {
+                self.mutation_count.fetch_add(1, SeqCst);
+                self.bump_total_ops();
+
+                self.note_preimage(key.as_ref(), tmp);
+
+                self.maintain_indexes(
+                    key.as_ref(),
+                    tmp,
+                    new.as_ref().map(AsRef::as_ref),
+                )?;
+
                 if let Some(res) = subscriber_reservation.take() {
-                    let event = subscriber::Event::single_update(
+                    let event = Event::single_update(
                         self.clone(),
                         key.as_ref().into(),
                         new.clone(),
@@ -1204,49 +3279,281 @@ impl Tree {
     /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
     /// # let config = sled::Config::new().temporary(true);
     /// # let db = config.open()?;
-    /// use sled::IVec;
-    ///
-    /// fn concatenate_merge(
-    ///   _key: &[u8],               // the key being merged
-    ///   old_value: Option<&[u8]>,  // the previous value, if one existed
-    ///   merged_bytes: &[u8]        // the new bytes being merged in
-    /// ) -> Option<Vec<u8>> {       // set the new value, return None to delete
-    ///   let mut ret = old_value
-    ///     .map(|ov| ov.to_vec())
-    ///     .unwrap_or_else(|| vec![]);
-    ///
-    ///   ret.extend_from_slice(merged_bytes);
-    ///
-    ///   Some(ret)
-    /// }
-    ///
-    /// db.set_merge_operator(concatenate_merge);
-    ///
-    /// let k = b"k1";
+    /// use sled::IVec;
+    ///
+    /// fn concatenate_merge(
+    ///   _key: &[u8],               // the key being merged
+    ///   old_value: Option<&[u8]>,  // the previous value, if one existed
+    ///   merged_bytes: &[u8]        // the new bytes being merged in
+    /// ) -> Option<Vec<u8>> {       // set the new value, return None to delete
+    ///   let mut ret = old_value
+    ///     .map(|ov| ov.to_vec())
+    ///     .unwrap_or_else(|| vec![]);
+    ///
+    ///   ret.extend_from_slice(merged_bytes);
+    ///
+    ///   Some(ret)
+    /// }
+    ///
+    /// db.set_merge_operator(concatenate_merge);
+    ///
+    /// let k = b"k1";
+    ///
+    /// db.insert(k, vec![0]);
+    /// db.merge(k, vec![1]);
+    /// db.merge(k, vec![2]);
+    /// assert_eq!(db.get(k), Ok(Some(IVec::from(vec![0, 1, 2]))));
+    ///
+    /// // Replace previously merged data. The merge function will not be called.
+    /// db.insert(k, vec![3]);
+    /// assert_eq!(db.get(k), Ok(Some(IVec::from(vec![3]))));
+    ///
+    /// // Merges on non-present values will cause the merge function to be called
+    /// // with `old_value == None`. If the merge function returns something (which it
+    /// // does, in this case) a new value will be inserted.
+    /// db.remove(k);
+    /// db.merge(k, vec![4]);
+    /// assert_eq!(db.get(k), Ok(Some(IVec::from(vec![4]))));
+    /// # Ok(()) }
+    /// ```
+    pub fn set_merge_operator(
+        &self,
+        merge_operator: impl MergeOperator + 'static,
+    ) {
+        let mut mo_write = self.merge_operator.write();
+        *mo_write = Some(Box::new(merge_operator));
+    }
+
+    /// Selects a merge operator by name for use with the `merge`
+    /// function, and persists the name so that this tree remembers
+    /// which operator it requires across restarts.
+    ///
+    /// The operator must first be registered on the `Db` via
+    /// [`Db::register_merge_operator`](struct.Db.html#method.register_merge_operator).
+    /// After reopening the database, the first call to `merge`
+    /// resolves the persisted name against the operators registered
+    /// on the new handle, and returns an `Unsupported` error naming
+    /// the missing operator if it has not been provided.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// fn concatenate_merge(
+    ///   _key: &[u8],
+    ///   old_value: Option<&[u8]>,
+    ///   merged_bytes: &[u8]
+    /// ) -> Option<Vec<u8>> {
+    ///   let mut ret = old_value
+    ///     .map(|ov| ov.to_vec())
+    ///     .unwrap_or_else(|| vec![]);
+    ///
+    ///   ret.extend_from_slice(merged_bytes);
+    ///
+    ///   Some(ret)
+    /// }
+    ///
+    /// db.register_merge_operator("concatenate", concatenate_merge);
+    ///
+    /// let tree = db.open_tree("events")?;
+    ///
+    /// // selecting an unregistered name fails loudly
+    /// assert!(tree.set_merge_operator_name("minimize").is_err());
+    ///
+    /// tree.set_merge_operator_name("concatenate")?;
+    ///
+    /// tree.merge(b"k1", vec![1])?;
+    /// tree.merge(b"k1", vec![2])?;
+    /// assert_eq!(tree.get(b"k1")?, Some(sled::IVec::from(vec![1, 2])));
+    /// # Ok(()) }
+    /// ```
+    pub fn set_merge_operator_name(&self, name: &str) -> Result<()> {
+        let operator = self
+            .context
+            .merge_operators
+            .0
+            .read()
+            .get(name)
+            .cloned()
+            .ok_or_else(|| {
+                Error::Unsupported(format!(
+                    "no merge operator named \"{}\" has been \
+                     registered via Db::register_merge_operator",
+                    name
+                ))
+            })?;
+
+        self.install_named_merge_operator(operator);
+
+        let guard = pin();
+        let names = meta::open_tree(
+            &self.context,
+            MERGE_OPERATORS_TREE_ID.to_vec(),
+            &guard,
+        )?;
+        names.insert(&self.tree_id, name.as_bytes())?;
+        Ok(())
+    }
+
+    /// Registers a pre-commit validator for this tree. Every
+    /// transaction involving the tree calls each registered
+    /// validator with the tree's staged writes just before
+    /// committing; if any validator returns an error, the
+    /// transaction is vetoed before a single write is applied or
+    /// persisted, and surfaces as `Error::Unsupported` carrying
+    /// the validator's reason.
+    ///
+    /// Validators enforce invariants that individual operations
+    /// cannot see in isolation, like referential integrity
+    /// between a tree and the records its values point at. Like
+    /// merge operators, validators are plain closures and must be
+    /// re-registered each time the database is opened.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// // values must never be empty
+    /// db.add_pre_commit_validator(|batch: &sled::Batch| {
+    ///     for (_key, value) in batch.iter() {
+    ///         if value == Some(&sled::IVec::from(b"")) {
+    ///             return Err("empty values are forbidden".into());
+    ///         }
+    ///     }
+    ///     Ok(())
+    /// });
+    ///
+    /// let vetoed = db.transaction::<_, _, ()>(|tx_db| {
+    ///     tx_db.insert(b"k1", b"")?;
+    ///     Ok(())
+    /// });
+    /// assert!(vetoed.is_err());
+    /// assert_eq!(db.get(b"k1")?, None);
+    ///
+    /// db.transaction::<_, _, ()>(|tx_db| {
+    ///     tx_db.insert(b"k1", b"v1")?;
+    ///     Ok(())
+    /// }).unwrap();
+    /// assert_eq!(&db.get(b"k1")?.unwrap(), b"v1");
+    /// # Ok(()) }
+    /// ```
+    pub fn add_pre_commit_validator(
+        &self,
+        validator: impl PreCommitValidator + 'static,
+    ) {
+        self.pre_commit_validators.write().push(Box::new(validator));
+    }
+
+    /// Registers a post-commit callback for this tree. After a
+    /// transaction involving the tree commits, its writes are
+    /// flushed to stable storage and then each registered
+    /// callback is called exactly once with the tree's committed
+    /// batch, so side effects like notifying other subsystems
+    /// never refer to state that a crash could roll back.
+    ///
+    /// Callbacks run on the committing thread after the
+    /// transaction's locks are released, and do not run for
+    /// transactions staged via `TransactionalTree::prepare` until
+    /// they are actually committed. Like merge operators,
+    /// callbacks must be re-registered each time the database is
+    /// opened.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
     ///
-    /// db.insert(k, vec![0]);
-    /// db.merge(k, vec![1]);
-    /// db.merge(k, vec![2]);
-    /// assert_eq!(db.get(k), Ok(Some(IVec::from(vec![0, 1, 2]))));
+    /// let committed = Arc::new(AtomicUsize::new(0));
+    /// let counter = committed.clone();
+    /// db.add_post_commit_callback(move |batch: &sled::Batch| {
+    ///     counter.fetch_add(batch.iter().count(), Ordering::SeqCst);
+    /// });
     ///
-    /// // Replace previously merged data. The merge function will not be called.
-    /// db.insert(k, vec![3]);
-    /// assert_eq!(db.get(k), Ok(Some(IVec::from(vec![3]))));
+    /// db.transaction::<_, _, ()>(|tx_db| {
+    ///     tx_db.insert(b"k1", b"v1")?;
+    ///     tx_db.insert(b"k2", b"v2")?;
+    ///     Ok(())
+    /// }).unwrap();
     ///
-    /// // Merges on non-present values will cause the merge function to be called
-    /// // with `old_value == None`. If the merge function returns something (which it
-    /// // does, in this case) a new value will be inserted.
-    /// db.remove(k);
-    /// db.merge(k, vec![4]);
-    /// assert_eq!(db.get(k), Ok(Some(IVec::from(vec![4]))));
+    /// assert_eq!(committed.load(Ordering::SeqCst), 2);
     /// # Ok(()) }
     /// ```
-    pub fn set_merge_operator(
+    pub fn add_post_commit_callback(
         &self,
-        merge_operator: impl MergeOperator + 'static,
+        callback: impl PostCommitCallback + 'static,
+    ) {
+        self.post_commit_callbacks.write().push(Box::new(callback));
+    }
+
+    /// Resolves a persisted merge operator name if no operator has
+    /// been set on this handle yet. Called before each merge so
+    /// that trees which recorded a named operator fail loudly when
+    /// it has not been registered on this `Db`.
+    fn resolve_merge_operator(&self) -> Result<()> {
+        if self.merge_operator.read().is_some() {
+            return Ok(());
+        }
+
+        let guard = pin();
+        match self
+            .context
+            .pagecache
+            .meta_pid_for_name(MERGE_OPERATORS_TREE_ID, &guard)
+        {
+            Ok(_) => {}
+            // no tree has ever persisted an operator name
+            Err(Error::CollectionNotFound(_)) => return Ok(()),
+            Err(other) => return Err(other),
+        }
+
+        let names = meta::open_tree(
+            &self.context,
+            MERGE_OPERATORS_TREE_ID.to_vec(),
+            &guard,
+        )?;
+        let name = match names.get(&self.tree_id)? {
+            Some(name) => String::from_utf8_lossy(&name).into_owned(),
+            None => return Ok(()),
+        };
+
+        let operator = self
+            .context
+            .merge_operators
+            .0
+            .read()
+            .get(&name)
+            .cloned()
+            .ok_or_else(|| {
+                Error::Unsupported(format!(
+                    "this tree requires the merge operator named \
+                     \"{}\"; register it via \
+                     Db::register_merge_operator before calling merge",
+                    name
+                ))
+            })?;
+
+        self.install_named_merge_operator(operator);
+        Ok(())
+    }
+
+    fn install_named_merge_operator(
+        &self,
+        operator: std::sync::Arc<dyn MergeOperator>,
     ) {
         let mut mo_write = self.merge_operator.write();
-        *mo_write = Some(Box::new(merge_operator));
+        *mo_write = Some(Box::new(
+            move |key: &[u8], old: Option<&[u8]>, value: &[u8]| {
+                operator(key, old, value)
+            },
+        ));
     }
 
     /// Create a double-ended iterator over the tuples of keys and
@@ -1282,6 +3589,43 @@ impl Tree {
         self.range::<Vec<u8>, _>(..)
     }
 
+    /// Returns a read-only [`Snapshot`] of this tree, pinned to
+    /// the moment of creation. Gets, iterators, and `len` on the
+    /// snapshot are unaffected by writes applied after it was
+    /// taken.
+    ///
+    /// Snapshots are maintained as overlays of pre-images rather
+    /// than copies: the first write to each key after the snapshot
+    /// was taken records the value the key held at snapshot time.
+    /// Memory usage is therefore proportional to what changes
+    /// while the snapshot is alive, and writers pay a small cost
+    /// per mutation for as long as any snapshot exists.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"k1", b"v1")?;
+    ///
+    /// let snapshot = db.snapshot();
+    /// db.insert(b"k1", b"v2")?;
+    /// db.insert(b"k2", b"v2")?;
+    ///
+    /// assert_eq!(&snapshot.get(b"k1")?.unwrap(), b"v1");
+    /// assert_eq!(snapshot.get(b"k2")?, None);
+    /// assert_eq!(snapshot.len(), 1);
+    /// # Ok(()) }
+    /// ```
+    pub fn snapshot(&self) -> Snapshot {
+        let state = std::sync::Arc::new(snapshot::SnapshotState::default());
+        let mut snapshots = self.snapshots.write();
+        snapshots.retain(|weak| weak.strong_count() > 0);
+        snapshots.push(std::sync::Arc::downgrade(&state));
+        Snapshot { tree: self.clone(), state }
+    }
+
     /// Create a double-ended iterator over tuples of keys and values,
     /// where the keys fall within the specified range.
     ///
@@ -1341,8 +3685,147 @@ impl Tree {
             tree: self.clone(),
             hi,
             lo,
-            cached_node: None,
-            going_forward: true,
+            cached_forward_node: None,
+            cached_back_node: None,
+            cancellation: None,
+            deadline: None,
+        }
+    }
+
+    /// Pins the leaf pages currently covering a key range into the
+    /// in-memory cache, exempting them from eviction pressure so
+    /// that latency-critical lookup tables stay resident while
+    /// embedded in a larger database. Requires a non-zero
+    /// `pinned_cache_budget` to be configured, and stops early
+    /// once pinning another page would exceed that budget.
+    ///
+    /// Returns the number of pages that were pinned, including
+    /// pages that were already pinned and had their budget
+    /// accounting refreshed.
+    ///
+    /// Pins attach to the pages as they exist now. Splits, merges,
+    /// and rewrites produce new pages that are not automatically
+    /// pinned (pins on pages that are freed are released), so
+    /// long-lived pins over mutating ranges should be refreshed
+    /// periodically.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new()
+    /// #     .temporary(true)
+    /// #     .pinned_cache_budget(10 * 1024 * 1024);
+    /// # let db = config.open()?;
+    /// db.insert(b"hot/a", b"1")?;
+    /// db.insert(b"hot/b", b"2")?;
+    ///
+    /// let pinned = db.pin_range(b"hot/".as_ref()..b"hot0".as_ref())?;
+    /// assert!(pinned >= 1);
+    ///
+    /// db.unpin_range(b"hot/".as_ref()..b"hot0".as_ref())?;
+    /// # Ok(()) }
+    /// ```
+    pub fn pin_range<K, R>(&self, range: R) -> Result<u64>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        if self.context.pinned_cache_budget == 0 {
+            return Err(Error::Unsupported(
+                "Tree::pin_range requires the pinned_cache_budget \
+                 configurable to be set to a non-zero number of bytes"
+                    .into(),
+            ));
+        }
+
+        let mut pinned = 0;
+        self.for_each_leaf_in_range(range, &mut |view| {
+            if self.context.pagecache.pin_page(view.pid, view.size) {
+                pinned += 1;
+                true
+            } else {
+                false
+            }
+        })?;
+        Ok(pinned)
+    }
+
+    /// Releases pins placed by `Tree::pin_range` on the pages
+    /// currently covering a key range, returning the number of
+    /// pages that were unpinned.
+    pub fn unpin_range<K, R>(&self, range: R) -> Result<u64>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let mut unpinned = 0;
+        self.for_each_leaf_in_range(range, &mut |view| {
+            if self.context.pagecache.unpin_page(view.pid) {
+                unpinned += 1;
+            }
+            true
+        })?;
+        Ok(unpinned)
+    }
+
+    // Visits the leaf pages that currently cover a key range in
+    // order, completing any observed partial splits or merges
+    // along the way, until the visitor returns `false` or the
+    // range is exhausted.
+    fn for_each_leaf_in_range<K, R>(
+        &self,
+        range: R,
+        visit: &mut dyn FnMut(&View<'_>) -> bool,
+    ) -> Result<()>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let start = match range.start_bound() {
+            ops::Bound::Included(k) | ops::Bound::Excluded(k) => {
+                IVec::from(k.as_ref())
+            }
+            ops::Bound::Unbounded => IVec::from(&[]),
+        };
+
+        let _cc = concurrency_control::read();
+        let guard = pin();
+
+        let mut view = self.view_for_key(&start, &guard)?;
+        loop {
+            if !visit(&view) {
+                return Ok(());
+            }
+
+            let next_pid = if let Some(next) = view.next {
+                next.get()
+            } else {
+                return Ok(());
+            };
+
+            // stop if the end of the range falls within this node
+            match (view.hi(), range.end_bound()) {
+                (Some(hi), ops::Bound::Included(end)) => {
+                    if hi > end.as_ref() {
+                        return Ok(());
+                    }
+                }
+                (Some(hi), ops::Bound::Excluded(end)) => {
+                    if hi >= end.as_ref() {
+                        return Ok(());
+                    }
+                }
+                _ => {}
+            }
+
+            view = if let Some(view) =
+                self.view_for_pid(next_pid, &guard)?
+            {
+                view
+            } else {
+                return Ok(());
+            };
         }
     }
 
@@ -1401,6 +3884,68 @@ impl Tree {
         self.range(prefix..)
     }
 
+    /// Like `scan_prefix`, but yields keys with the prefix
+    /// stripped, as zero-copy subslices that share the backing
+    /// data of the full keys. This saves consumers from
+    /// re-slicing every key and makes subspace-style layering
+    /// cheaper.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use sled::IVec;
+    /// db.insert(&[0, 0, 1], vec![10])?;
+    /// db.insert(&[0, 0, 2], vec![20])?;
+    /// db.insert(&[0, 1, 0], vec![30])?;
+    ///
+    /// let prefix: &[u8] = &[0, 0];
+    /// let mut r = db.scan_prefix_stripped(prefix);
+    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[1]), IVec::from(&[10])))));
+    /// assert_eq!(r.next(), Some(Ok((IVec::from(&[2]), IVec::from(&[20])))));
+    /// assert_eq!(r.next(), None);
+    /// # Ok(()) }
+    /// ```
+    pub fn scan_prefix_stripped<P>(
+        &self,
+        prefix: P,
+    ) -> impl DoubleEndedIterator<Item = Result<(IVec, IVec)>> + Send + Sync
+    where
+        P: AsRef<[u8]>,
+    {
+        let prefix_len = prefix.as_ref().len();
+        self.scan_prefix(prefix).map(move |r| {
+            r.map(|(k, v)| {
+                let stripped = k.subslice(prefix_len, k.len() - prefix_len);
+                (stripped, v)
+            })
+        })
+    }
+
+    /// Returns a [`Subspace`] of this tree under the given key
+    /// component, interning the component in a dictionary tree
+    /// shared by the whole database so that only a short integer
+    /// code is stored in keys. See the [`Subspace`] documentation
+    /// for usage.
+    pub fn subspace<C: AsRef<[u8]>>(&self, component: C) -> Result<Subspace> {
+        let guard = pin();
+        let dictionary = meta::open_tree(
+            &self.context,
+            INTERNED_KEYS_TREE_ID.to_vec(),
+            &guard,
+        )?;
+        drop(guard);
+
+        let code = subspace::intern(&dictionary, component.as_ref())?;
+        Ok(Subspace {
+            tree: self.clone(),
+            dictionary,
+            prefix: code.to_be_bytes().to_vec().into(),
+        })
+    }
+
     /// Returns the first key and value in the `Tree`, or
     /// `None` if the `Tree` is empty.
     pub fn first(&self) -> Result<Option<(IVec, IVec)>> {
@@ -1413,6 +3958,126 @@ impl Tree {
         self.iter().next_back().transpose()
     }
 
+    /// Returns the first key and value whose key starts with the
+    /// given prefix, or `None` if no keys have that prefix.
+    ///
+    /// The successor bound for the prefix is computed internally,
+    /// including the carry when the prefix ends in `0xFF` bytes,
+    /// so callers don't need to hand-roll that logic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use sled::IVec;
+    /// db.insert(&[0, 1], vec![10])?;
+    /// db.insert(&[0, 2], vec![20])?;
+    /// db.insert(&[1, 0], vec![30])?;
+    ///
+    /// assert_eq!(
+    ///     db.first_with_prefix(&[0])?,
+    ///     Some((IVec::from(&[0, 1]), IVec::from(&[10])))
+    /// );
+    /// assert_eq!(db.first_with_prefix(&[2])?, None);
+    /// # Ok(()) }
+    /// ```
+    pub fn first_with_prefix<P>(
+        &self,
+        prefix: P,
+    ) -> Result<Option<(IVec, IVec)>>
+    where
+        P: AsRef<[u8]>,
+    {
+        self.scan_prefix(prefix).next().transpose()
+    }
+
+    /// Returns the last key and value whose key starts with the
+    /// given prefix, or `None` if no keys have that prefix.
+    ///
+    /// The successor bound for the prefix is computed internally,
+    /// including the carry when the prefix ends in `0xFF` bytes,
+    /// so callers don't need to hand-roll that logic.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use sled::IVec;
+    /// db.insert(&[0, 1], vec![10])?;
+    /// db.insert(&[0, 2], vec![20])?;
+    /// db.insert(&[1, 0], vec![30])?;
+    ///
+    /// assert_eq!(
+    ///     db.last_with_prefix(&[0])?,
+    ///     Some((IVec::from(&[0, 2]), IVec::from(&[20])))
+    /// );
+    /// assert_eq!(db.last_with_prefix(&[2])?, None);
+    /// # Ok(()) }
+    /// ```
+    pub fn last_with_prefix<P>(
+        &self,
+        prefix: P,
+    ) -> Result<Option<(IVec, IVec)>>
+    where
+        P: AsRef<[u8]>,
+    {
+        self.scan_prefix(prefix).next_back().transpose()
+    }
+
+    /// Removes all keys beginning with `prefix`, returning the
+    /// number of keys removed.
+    ///
+    /// The deletion is paced: keys are removed in bounded batches
+    /// with the tree's write lock released and the thread yielded
+    /// between batches, so removing millions of keys neither
+    /// buffers them all in memory nor starves concurrent traffic.
+    /// Keys inserted under the prefix while the call is running
+    /// may or may not be removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"session/1", b"a")?;
+    /// db.insert(b"session/2", b"b")?;
+    /// db.insert(b"user/1", b"c")?;
+    ///
+    /// assert_eq!(db.remove_prefix(b"session/")?, 2);
+    /// assert_eq!(db.len(), 1);
+    /// # Ok(()) }
+    /// ```
+    pub fn remove_prefix<P: AsRef<[u8]>>(&self, prefix: P) -> Result<u64> {
+        const BATCH_SIZE: usize = 1024;
+
+        let prefix = prefix.as_ref();
+        let mut removed = 0;
+        loop {
+            let mut batch = Batch::default();
+            let mut in_batch = 0;
+            for key_res in self.scan_prefix(prefix).keys().take(BATCH_SIZE) {
+                batch.remove(key_res?);
+                in_batch += 1;
+            }
+            if in_batch == 0 {
+                return Ok(removed);
+            }
+            self.apply_batch(batch)?;
+            removed += in_batch;
+
+            // hint the epoch GC that a pile of replaced pages is
+            // now reclaimable, and let concurrent writers in
+            // before scanning for the next batch
+            pin().flush();
+            std::thread::yield_now();
+        }
+    }
+
     /// Atomically removes the maximum item in the `Tree` instance.
     ///
     /// # Examples
@@ -1498,14 +4163,121 @@ impl Tree {
                 }
             // try again
             } else {
-                return Ok(None);
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Returns the number of elements in this tree.
+    ///
+    /// Beware: performs a full O(n) scan under the hood.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"a", vec![0]);
+    /// db.insert(b"b", vec![1]);
+    /// assert_eq!(db.len(), 2);
+    /// # Ok(()) }
+    /// ```
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns `true` if the `Tree` contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Rewrites every page of this tree at the head of the log,
+    /// making the superseded versions dead so that the segments
+    /// holding them can be garbage collected. Useful after bulk
+    /// deletions, which leave sparse segments pinned by the few
+    /// pages still live in them; relocating those pages lets the
+    /// space be reclaimed. Returns statistics for logging and
+    /// alerting on maintenance effectiveness.
+    ///
+    /// Pages that a concurrent writer replaces mid-walk are
+    /// skipped — the racing write relocated them just as well —
+    /// and are not counted in the statistics.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// for i in 0u32..100 {
+    ///     db.insert(&i.to_be_bytes(), vec![0; 64])?;
+    /// }
+    ///
+    /// let stats = db.compact()?;
+    /// assert!(stats.pages_rewritten >= 1);
+    /// assert!(stats.bytes_reclaimed > 0);
+    /// assert_eq!(stats.keys_removed, 0);
+    /// # Ok(()) }
+    /// ```
+    pub fn compact(&self) -> Result<MaintenanceStats> {
+        let start_time = Instant::now();
+        let mut stats = MaintenanceStats::default();
+
+        let guard = pin();
+        let mut visited = FastSet8::default();
+        let mut stack = vec![self.root.load(Acquire)];
+        while let Some(pid) = stack.pop() {
+            if !visited.insert(pid) {
+                continue;
+            }
+            let view = if let Some(view) = self.view_for_pid(pid, &guard)? {
+                view
+            } else {
+                continue;
+            };
+            if view.is_index {
+                stack.extend(view.iter_index_pids());
+            }
+            let replacement = view.deref().clone();
+            let replace = self.context.pagecache.replace(
+                pid,
+                view.node_view.0,
+                replacement,
+                &guard,
+            )?;
+            if replace.is_ok() {
+                stats.pages_rewritten += 1;
+                stats.bytes_reclaimed += view.size;
             }
         }
+
+        stats.duration = start_time.elapsed();
+        Ok(stats)
     }
 
-    /// Returns the number of elements in this tree.
+    /// Clears the `Tree`, removing all values, and returns
+    /// statistics about what the removal accomplished.
     ///
-    /// Beware: performs a full O(n) scan under the hood.
+    /// Note that this is not atomic.
+    pub fn clear(&self) -> Result<MaintenanceStats> {
+        self.remove_range::<&[u8], _>(..)
+    }
+
+    /// Returns the number of live log bytes attributed to this
+    /// tree's pages, found by walking its page table entries from
+    /// the root. Unlike `Db::size_on_disk`, which reports the
+    /// whole shared storage file, this can bill or cap individual
+    /// trees in a multi-tenant database.
+    ///
+    /// The figure counts the latest version of each page as it
+    /// sits in the log, so it excludes dead versions awaiting
+    /// garbage collection and shared overhead like segment
+    /// headers and snapshots — summing it over every tree comes
+    /// out lower than `Db::size_on_disk`. Companion trees
+    /// (secondary indexes, TTL bookkeeping) are separate trees
+    /// with their own accounting. The walk is a racy snapshot:
+    /// pages rewritten mid-walk may be missed or double-counted.
     ///
     /// # Examples
     ///
@@ -1513,29 +4285,37 @@ impl Tree {
     /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
     /// # let config = sled::Config::new().temporary(true);
     /// # let db = config.open()?;
-    /// db.insert(b"a", vec![0]);
-    /// db.insert(b"b", vec![1]);
-    /// assert_eq!(db.len(), 2);
+    /// let tenant = db.open_tree("tenant")?;
+    /// let before = tenant.size_on_disk()?;
+    ///
+    /// for i in 0u32..100 {
+    ///     tenant.insert(&i.to_be_bytes(), vec![0; 128])?;
+    /// }
+    /// tenant.flush()?;
+    ///
+    /// assert!(tenant.size_on_disk()? > before);
     /// # Ok(()) }
     /// ```
-    pub fn len(&self) -> usize {
-        self.iter().count()
-    }
-
-    /// Returns `true` if the `Tree` contains no elements.
-    pub fn is_empty(&self) -> bool {
-        self.iter().next().is_none()
-    }
-
-    /// Clears the `Tree`, removing all values.
-    ///
-    /// Note that this is not atomic.
-    pub fn clear(&self) -> Result<()> {
-        for k in self.iter().keys() {
-            let key = k?;
-            let _old = self.remove(key)?;
+    pub fn size_on_disk(&self) -> Result<u64> {
+        let guard = pin();
+        let mut total = 0_u64;
+        let mut visited = FastSet8::default();
+        let mut stack = vec![self.root.load(Acquire)];
+        while let Some(pid) = stack.pop() {
+            if !visited.insert(pid) {
+                continue;
+            }
+            let view = if let Some(view) = self.view_for_pid(pid, &guard)? {
+                view
+            } else {
+                continue;
+            };
+            total += view.size;
+            if view.is_index {
+                stack.extend(view.iter_index_pids());
+            }
         }
-        Ok(())
+        Ok(total)
     }
 
     /// Returns the name of the tree.
@@ -1560,6 +4340,434 @@ impl Tree {
         Ok(hasher.finalize())
     }
 
+    /// Writes the entire tree to `writer` as a sorted run: a
+    /// sequence of key-value records in key order, each encoded
+    /// as a big-endian `u64` key length, the key bytes, a
+    /// big-endian `u64` value length, and the value bytes.
+    /// Returns the number of records written. The format is
+    /// intended for external-sort and ETL pipelines, and runs can
+    /// be loaded back with
+    /// [`ingest_sorted_runs`](Tree::ingest_sorted_runs).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"a", b"1")?;
+    /// db.insert(b"b", b"2")?;
+    ///
+    /// let mut run = vec![];
+    /// assert_eq!(db.export_sorted_run(&mut run)?, 2);
+    ///
+    /// let other = db.open_tree(b"other")?;
+    /// assert_eq!(other.ingest_sorted_runs(vec![&run[..]])?, 2);
+    /// assert_eq!(&other.get(b"b")?.unwrap(), b"2");
+    /// # Ok(()) }
+    /// ```
+    pub fn export_sorted_run<W: Write>(
+        &self,
+        writer: &mut W,
+    ) -> Result<u64> {
+        let mut records = 0;
+        for kv_res in self.iter() {
+            let (k, v) = kv_res?;
+            writer.write_all(&u64::try_from(k.len()).unwrap().to_be_bytes())?;
+            writer.write_all(&k)?;
+            writer.write_all(&u64::try_from(v.len()).unwrap().to_be_bytes())?;
+            writer.write_all(&v)?;
+            records += 1;
+        }
+        writer.flush()?;
+        Ok(records)
+    }
+
+    /// Merge-ingests several sorted runs in the format written by
+    /// [`export_sorted_run`](Tree::export_sorted_run), returning
+    /// the number of records inserted. Each reader must yield
+    /// records in key order; when the same key appears in several
+    /// runs, the value from the latest reader in `readers` wins.
+    pub fn ingest_sorted_runs<R: Read>(
+        &self,
+        readers: Vec<R>,
+    ) -> Result<u64> {
+        let mut readers = readers;
+        let mut heads: Vec<Option<(Vec<u8>, Vec<u8>)>> = readers
+            .iter_mut()
+            .map(read_run_record)
+            .collect::<Result<_>>()?;
+
+        let mut records = 0;
+        loop {
+            // find the smallest key, preferring the latest run
+            // that holds it
+            let mut winner: Option<usize> = None;
+            for (idx, head) in heads.iter().enumerate() {
+                if let Some((key, _)) = head {
+                    let smaller = match &winner {
+                        Some(best_idx) => {
+                            let (best_key, _) =
+                                heads[*best_idx].as_ref().unwrap();
+                            key < best_key
+                        }
+                        None => true,
+                    };
+                    if smaller {
+                        winner = Some(idx);
+                    }
+                }
+            }
+
+            let winner = match winner {
+                Some(winner) => winner,
+                None => return Ok(records),
+            };
+
+            // take the winning record from the latest run holding
+            // its key, and advance every run past that key
+            let (key, mut value) =
+                std::mem::take(&mut heads[winner]).unwrap();
+            heads[winner] = read_run_record(&mut readers[winner])?;
+            for idx in winner + 1..readers.len() {
+                while let Some((other_key, other_value)) = &heads[idx] {
+                    if *other_key != key {
+                        break;
+                    }
+                    value = other_value.clone();
+                    heads[idx] = read_run_record(&mut readers[idx])?;
+                }
+            }
+
+            self.insert(key, value)?;
+            records += 1;
+        }
+    }
+
+    /// Loads pre-sorted key-value pairs by building leaf and index
+    /// nodes directly, bypassing per-key tree descent and page CAS
+    /// retries, returning the number of records loaded.
+    ///
+    /// Keys must be strictly ascending and unique, or
+    /// `Error::Unsupported` is returned. The direct build only
+    /// applies when this `Tree` is empty, since directly built
+    /// leaves cannot be interleaved with existing ones; a
+    /// non-empty tree falls back to ordinary point inserts, still
+    /// under a single exclusive lock acquisition, and a sortedness
+    /// violation detected partway through the fallback leaves the
+    /// earlier records inserted. Subscribers are
+    /// not notified of directly built records, and the input is
+    /// buffered so that the tree can be assembled bottom-up, so
+    /// the load transiently holds the full data set in memory.
+    ///
+    /// Other threads observe either none of the loaded records or
+    /// all of them, as the built tree is installed with a single
+    /// root swap.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let records: Vec<(Vec<u8>, Vec<u8>)> = (0..1000_u32)
+    ///     .map(|i| (i.to_be_bytes().to_vec(), b"value".to_vec()))
+    ///     .collect();
+    ///
+    /// assert_eq!(db.bulk_load(records)?, 1000);
+    /// assert_eq!(db.len(), 1000);
+    /// assert_eq!(
+    ///     db.get(&500_u32.to_be_bytes())?,
+    ///     Some(sled::IVec::from(b"value"))
+    /// );
+    /// # Ok(()) }
+    /// ```
+    pub fn bulk_load<K, V, I>(&self, records: I) -> Result<u64>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let _priority = priority::enter();
+        self.ensure_writable()?;
+
+        let mut guard = pin();
+        let _cc = concurrency_control::write();
+
+        let mut probe = self.iter();
+        let is_empty = match probe.next_inner() {
+            None => true,
+            Some(Err(e)) => return Err(e),
+            Some(Ok(_)) => false,
+        };
+
+        // secondary indexes are maintained by the per-key write
+        // path, so a tree with registered indexes takes the
+        // insert-based route even when empty
+        if !is_empty || !self.indexes.read().is_empty() {
+            let mut count = 0_u64;
+            let mut last_key: Option<IVec> = None;
+            for (key, value) in records {
+                if let Some(last) = &last_key {
+                    if last.as_ref() >= key.as_ref() {
+                        return Err(Error::Unsupported(
+                            "Tree::bulk_load requires strictly \
+                             ascending unique keys"
+                                .to_owned(),
+                        ));
+                    }
+                }
+                last_key = Some(IVec::from(key.as_ref()));
+                loop {
+                    if self
+                        .insert_inner(
+                            key.as_ref(),
+                            Some(IVec::from(value.as_ref())),
+                            false,
+                            &mut guard,
+                        )?
+                        .is_ok()
+                    {
+                        break;
+                    }
+                }
+                count += 1;
+            }
+            return Ok(count);
+        }
+
+        // group the sorted input into leaf-sized chunks, aiming
+        // below the split threshold so that the built leaves don't
+        // get immediately split or merged by foreground traffic
+        let leaf_budget = self
+            .split_threshold()
+            .and_then(|threshold| usize::try_from(threshold).ok())
+            .unwrap_or(1024 - MAX_MSG_HEADER_LEN)
+            .saturating_mul(2)
+            / 3;
+        let max_items = if cfg!(any(test, feature = "lock_free_delays")) {
+            4
+        } else {
+            usize::from(u16::max_value()) - 1
+        };
+
+        let mut chunks: Vec<Vec<(IVec, IVec)>> = vec![];
+        let mut current: Vec<(IVec, IVec)> = vec![];
+        let mut current_bytes = 0;
+        let mut last_key: Option<IVec> = None;
+        let mut count = 0_u64;
+
+        for (key, value) in records {
+            let key = IVec::from(key.as_ref());
+            let value = IVec::from(value.as_ref());
+
+            if let Some(last) = &last_key {
+                if *last >= key {
+                    return Err(Error::Unsupported(
+                        "Tree::bulk_load requires strictly \
+                         ascending unique keys"
+                            .to_owned(),
+                    ));
+                }
+            }
+            last_key = Some(key.clone());
+
+            let record_bytes = key.len() + value.len() + MAX_MSG_HEADER_LEN;
+            if !current.is_empty()
+                && (current_bytes + record_bytes > leaf_budget
+                    || current.len() >= max_items)
+            {
+                chunks.push(std::mem::take(&mut current));
+                current_bytes = 0;
+            }
+            current_bytes += record_bytes;
+            current.push((key, value));
+            count += 1;
+        }
+        if !current.is_empty() {
+            chunks.push(current);
+        }
+        if chunks.is_empty() {
+            return Ok(0);
+        }
+
+        // build leaves right-to-left so that each node's next
+        // pointer and hi bound can be installed at construction
+        // time, as traversal requires next to be present whenever
+        // hi is bounded
+        let mut level: Vec<(IVec, PageId)> = Vec::with_capacity(chunks.len());
+        let mut next_pid: Option<NonZeroU64> = None;
+        let mut next_lo: Option<IVec> = None;
+
+        for (idx, chunk) in chunks.iter().enumerate().rev() {
+            let lo = if idx == 0 {
+                IVec::from(&[])
+            } else {
+                chunk[0].0.clone()
+            };
+            let items: Vec<(&[u8], &[u8])> = chunk
+                .iter()
+                .map(|(key, value)| (key.as_ref(), value.as_ref()))
+                .collect();
+            let node = Node::new(
+                &lo,
+                next_lo.as_ref().map(AsRef::as_ref),
+                0,
+                false,
+                next_pid,
+                &items,
+            );
+            let (pid, _ptr) = self.context.pagecache.allocate(node, &guard)?;
+            next_pid = Some(NonZeroU64::new(pid).unwrap());
+            next_lo = Some(lo.clone());
+            level.push((lo, pid));
+        }
+        level.reverse();
+
+        // build index levels bottom-up until a single root remains
+        let mut is_leaf_level = true;
+        while level.len() > 1 || is_leaf_level {
+            let mut groups: Vec<Vec<(IVec, PageId)>> = vec![];
+            let mut current: Vec<(IVec, PageId)> = vec![];
+            let mut current_bytes = 0;
+
+            for (lo, pid) in level {
+                let entry_bytes =
+                    lo.len() + size_of::<u64>() + MAX_MSG_HEADER_LEN;
+                if !current.is_empty()
+                    && (current_bytes + entry_bytes > leaf_budget
+                        || current.len() >= max_items)
+                {
+                    groups.push(std::mem::take(&mut current));
+                    current_bytes = 0;
+                }
+                current_bytes += entry_bytes;
+                current.push((lo, pid));
+            }
+            if !current.is_empty() {
+                groups.push(current);
+            }
+
+            let mut next_level: Vec<(IVec, PageId)> =
+                Vec::with_capacity(groups.len());
+            let mut next_pid: Option<NonZeroU64> = None;
+            let mut next_lo: Option<IVec> = None;
+
+            for (idx, group) in groups.iter().enumerate().rev() {
+                let lo = if idx == 0 {
+                    IVec::from(&[])
+                } else {
+                    group[0].0.clone()
+                };
+                let pid_bytes: Vec<[u8; 8]> = group
+                    .iter()
+                    .map(|(_, pid)| pid.to_le_bytes())
+                    .collect();
+                let items: Vec<(&[u8], &[u8])> = group
+                    .iter()
+                    .zip(pid_bytes.iter())
+                    .map(|((child_lo, _), pid)| (child_lo.as_ref(), &pid[..]))
+                    .collect();
+                let node = Node::new(
+                    &lo,
+                    next_lo.as_ref().map(AsRef::as_ref),
+                    0,
+                    true,
+                    next_pid,
+                    &items,
+                );
+                let (pid, _ptr) =
+                    self.context.pagecache.allocate(node, &guard)?;
+                next_pid = Some(NonZeroU64::new(pid).unwrap());
+                next_lo = Some(lo.clone());
+                next_level.push((lo, pid));
+            }
+            next_level.reverse();
+
+            level = next_level;
+            is_leaf_level = false;
+        }
+
+        let new_root_pid = level[0].1;
+        let old_root_pid = self.root.load(Acquire);
+
+        // collect the pages of the empty structure being replaced
+        // before swapping it out
+        let mut stale_pids = vec![];
+        let mut stack = vec![old_root_pid];
+        while let Some(pid) = stack.pop() {
+            let view = self.view_for_pid(pid, &guard)?.ok_or_else(|| {
+                Error::ReportableBug(
+                    "page of an exclusively held tree disappeared \
+                     during bulk_load"
+                        .to_owned(),
+                )
+            })?;
+            if view.is_index {
+                stack.extend(view.iter_index_pids());
+            }
+            stale_pids.push(pid);
+        }
+
+        let cas = self.context.pagecache.cas_root_in_meta(
+            &self.tree_id,
+            Some(old_root_pid),
+            Some(new_root_pid),
+            &guard,
+        )?;
+        if cas.is_err() {
+            return Err(Error::ReportableBug(
+                "tree root changed during bulk_load despite the \
+                 exclusive lock"
+                    .to_owned(),
+            ));
+        }
+
+        while self
+            .root
+            .compare_exchange(old_root_pid, new_root_pid, SeqCst, SeqCst)
+            .is_err()
+        {
+            std::sync::atomic::spin_loop_hint();
+        }
+
+        for pid in stale_pids {
+            let view = self.view_for_pid(pid, &guard)?.ok_or_else(|| {
+                Error::ReportableBug(
+                    "page of an exclusively held tree disappeared \
+                     during bulk_load"
+                        .to_owned(),
+                )
+            })?;
+            let _ = self
+                .context
+                .pagecache
+                .free(pid, view.node_view.0, &guard)?
+                .expect("could not free replaced page");
+        }
+
+        {
+            let snapshots = self.snapshots.read();
+            if !snapshots.is_empty() {
+                drop(snapshots);
+                for chunk in &chunks {
+                    for (key, _) in chunk {
+                        self.note_preimage(key, None);
+                    }
+                }
+            }
+        }
+
+        self.mutation_count.fetch_add(count, SeqCst);
+        if self.tree_id.as_ref() == DEFAULT_TREE_ID
+            || !self.tree_id.starts_with(INTERNAL_TREE_PREFIX)
+        {
+            self.context.total_ops.fetch_add(count, SeqCst);
+        }
+
+        Ok(count)
+    }
+
     fn split_node<'g>(
         &self,
         view: &View<'g>,
@@ -1683,6 +4891,88 @@ impl Tree {
         }
     }
 
+    // counts an applied mutation toward the database's lifetime
+    // statistics, excluding writes to internal bookkeeping trees
+    // so that statistics maintenance doesn't count itself.
+    fn bump_total_ops(&self) {
+        if self.tree_id.as_ref() == DEFAULT_TREE_ID
+            || !self.tree_id.starts_with(INTERNAL_TREE_PREFIX)
+        {
+            self.context.total_ops.fetch_add(1, SeqCst);
+        }
+    }
+
+    // records the pre-image of a key into the overlays of any
+    // live snapshots. called by every successful write path at
+    // the moment its page link succeeds, so each snapshot keeps
+    // the first value observed for the key after its creation.
+    /// Applies the effect of a single primary-tree mutation to
+    /// every registered secondary index: the entry derived from
+    /// the old value is removed and the entry derived from the
+    /// new value is inserted. Called from each write path after
+    /// its page link succeeds, so indexes track exactly the
+    /// mutations that took effect.
+    pub(crate) fn maintain_indexes(
+        &self,
+        key: &[u8],
+        old: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<()> {
+        let indexes = self.indexes.read();
+        if indexes.is_empty() {
+            return Ok(());
+        }
+        let mut guard = pin();
+        for index in indexes.iter() {
+            let old_derived = old.and_then(|v| (index.0.extractor)(key, v));
+            let new_derived = new.and_then(|v| (index.0.extractor)(key, v));
+            if old_derived == new_derived {
+                continue;
+            }
+            if let Some(derived) = &old_derived {
+                let entry = index::index_entry_key(derived, key);
+                loop {
+                    if index
+                        .0
+                        .tree
+                        .insert_inner(&entry, None, true, &mut guard)?
+                        .is_ok()
+                    {
+                        break;
+                    }
+                }
+            }
+            if let Some(derived) = &new_derived {
+                let entry = index::index_entry_key(derived, key);
+                loop {
+                    if index
+                        .0
+                        .tree
+                        .insert_inner(
+                            &entry,
+                            Some(key.into()),
+                            true,
+                            &mut guard,
+                        )?
+                        .is_ok()
+                    {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn note_preimage(&self, key: &[u8], old: Option<&[u8]>) {
+        let snapshots = self.snapshots.read();
+        for weak in snapshots.iter() {
+            if let Some(state) = weak.upgrade() {
+                state.note(key, old);
+            }
+        }
+    }
+
     pub(crate) fn view_for_pid<'g>(
         &self,
         pid: PageId,
@@ -1795,7 +5085,7 @@ impl Tree {
                 retry!();
             }
 
-            if view.should_split() {
+            if view.should_split(self.split_threshold()) {
                 self.split_node(&view, &parent_view, root_pid, guard)?;
                 retry!();
             }
@@ -1866,7 +5156,7 @@ impl Tree {
             // would be merged into a different index, which
             // would add considerable complexity to this already
             // fairly complex implementation.
-            if !took_leftmost_branch && parent_view.is_some() && view.should_merge() {
+            if !took_leftmost_branch && parent_view.is_some() && view.should_merge(self.merge_threshold()) {
                 let parent = parent_view.as_mut().unwrap();
                 assert!(parent.merging_child.is_none());
                 if parent.can_merge_child(cursor) {
@@ -2431,6 +5721,193 @@ impl Debug for Tree {
 ///       proposed values.
 ///     - `Err(Error::Unsupported)` if the database is opened in read-only mode.
 ///       otherwise.
+fn encode_idempotency_record(old: Option<&IVec>) -> IVec {
+    match old {
+        Some(old) => {
+            let mut buf = Vec::with_capacity(1 + old.len());
+            buf.push(1);
+            buf.extend_from_slice(old);
+            buf.into()
+        }
+        None => IVec::from(&[0][..]),
+    }
+}
+
+/// Reads a single record from a sorted run, returning `None` on a
+/// clean end-of-stream at a record boundary and an error if the
+/// stream ends in the middle of a record.
+fn read_run_record<R: Read>(
+    reader: &mut R,
+) -> Result<Option<(Vec<u8>, Vec<u8>)>> {
+    let mut len_buf = [0; 8];
+    let mut filled = 0;
+    while filled < len_buf.len() {
+        let read = reader.read(&mut len_buf[filled..])?;
+        if read == 0 {
+            if filled == 0 {
+                return Ok(None);
+            }
+            return Err(Error::Io(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "sorted run ended in the middle of a record",
+            )));
+        }
+        filled += read;
+    }
+
+    let key_len = usize::try_from(u64::from_be_bytes(len_buf)).unwrap();
+    let mut key = vec![0; key_len];
+    reader.read_exact(&mut key)?;
+
+    reader.read_exact(&mut len_buf)?;
+    let value_len = usize::try_from(u64::from_be_bytes(len_buf)).unwrap();
+    let mut value = vec![0; value_len];
+    reader.read_exact(&mut value)?;
+
+    Ok(Some((key, value)))
+}
+
+fn decode_idempotency_record(record: &IVec) -> Option<IVec> {
+    match record.first() {
+        Some(1) => Some(record.subslice(1, record.len() - 1)),
+        _ => None,
+    }
+}
+
+#[cfg(not(feature = "testing"))]
+const STREAM_CHUNK_SIZE: u64 = 1024 * 1024;
+
+#[cfg(feature = "testing")]
+const STREAM_CHUNK_SIZE: u64 = 1024;
+
+// streamed values are keyed by a length-prefixed copy of the user
+// key, so that one key can never alias the chunk space of another
+fn stream_key_prefix(key: &[u8]) -> Vec<u8> {
+    let mut prefix = Vec::with_capacity(4 + key.len());
+    prefix.extend_from_slice(
+        &u32::try_from(key.len()).unwrap().to_be_bytes(),
+    );
+    prefix.extend_from_slice(key);
+    prefix
+}
+
+fn stream_chunk_key(prefix: &[u8], chunk_idx: u64) -> Vec<u8> {
+    let mut chunk_key = Vec::with_capacity(prefix.len() + 8);
+    chunk_key.extend_from_slice(prefix);
+    chunk_key.extend_from_slice(&chunk_idx.to_be_bytes());
+    chunk_key
+}
+
+fn stream_chunk_count(total_len: u64, chunk_size: u64) -> u64 {
+    (total_len + chunk_size - 1) / chunk_size
+}
+
+// the chunk size is recorded per value rather than assumed, so
+// values written with a different build's chunk size still read
+// back correctly
+fn encode_stream_manifest(total_len: u64, chunk_size: u64) -> Vec<u8> {
+    let mut manifest = Vec::with_capacity(16);
+    manifest.extend_from_slice(&total_len.to_be_bytes());
+    manifest.extend_from_slice(&chunk_size.to_be_bytes());
+    manifest
+}
+
+fn decode_stream_manifest(manifest: &[u8]) -> Result<(u64, u64)> {
+    if manifest.len() != 16 {
+        return Err(Error::corruption(None));
+    }
+    let mut arr = [0; 8];
+    arr.copy_from_slice(&manifest[..8]);
+    let total_len = u64::from_be_bytes(arr);
+    arr.copy_from_slice(&manifest[8..]);
+    let chunk_size = u64::from_be_bytes(arr);
+    if chunk_size == 0 {
+        return Err(Error::corruption(None));
+    }
+    Ok((total_len, chunk_size))
+}
+
+/// A streaming handle to a value stored via
+/// [`Tree::insert_reader`], created by [`Tree::get_reader`].
+/// Implements `std::io::Read`, fetching one chunk at a time
+/// through the pagecache so that the whole value is never held
+/// in memory at once.
+pub struct ValueReader {
+    tree: Tree,
+    prefix: Vec<u8>,
+    total_len: u64,
+    chunk_size: u64,
+    position: u64,
+    chunk_idx: u64,
+    chunk: Option<IVec>,
+}
+
+impl Debug for ValueReader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValueReader")
+            .field("total_len", &self.total_len)
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl ValueReader {
+    /// Returns the total length of the streamed value in bytes.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u64 {
+        self.total_len
+    }
+}
+
+impl Read for ValueReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position >= self.total_len || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let chunk_idx = self.position / self.chunk_size;
+        let within =
+            usize::try_from(self.position % self.chunk_size).unwrap();
+
+        if self.chunk.is_none() || self.chunk_idx != chunk_idx {
+            let chunk_key = stream_chunk_key(&self.prefix, chunk_idx);
+            let chunk = self
+                .tree
+                .get(chunk_key)
+                .map_err(|error| {
+                    std::io::Error::new(std::io::ErrorKind::Other, error)
+                })?
+                .ok_or_else(|| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        "streamed value is missing a chunk",
+                    )
+                })?;
+            self.chunk = Some(chunk);
+            self.chunk_idx = chunk_idx;
+        }
+
+        let chunk = self.chunk.as_ref().unwrap();
+        if within >= chunk.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "streamed value chunk is shorter than expected",
+            ));
+        }
+
+        let remaining_in_value =
+            usize::try_from(self.total_len - self.position).unwrap();
+        let take = buf
+            .len()
+            .min(chunk.len() - within)
+            .min(remaining_in_value);
+        buf[..take].copy_from_slice(&chunk[within..within + take]);
+        self.position += u64::try_from(take).unwrap();
+
+        Ok(take)
+    }
+}
+
 pub type CompareAndSwapResult =
     Result<std::result::Result<(), CompareAndSwapError>>;
 
@@ -0,0 +1,387 @@
+//! Typed keys and values with pluggable codecs, layered over a
+//! tree.
+//!
+//! A [`TypedTree`] pairs a tree with a key codec and a value
+//! codec so that callers work with their own types instead of
+//! byte slices, and so that decoding logic lives in exactly one
+//! place. Subscriptions come pre-decoded too: every consumer of
+//! `watch_prefix` otherwise duplicates the same decode-and-handle
+//! boilerplate, usually with inconsistent error handling.
+//!
+//! Decode failures are surfaced where they occur. Direct reads
+//! return an error for the affected key; subscription events
+//! carry a per-field `Result`, so one undecodable record does not
+//! hide the rest of a batch.
+
+use std::marker::PhantomData;
+use std::time::Duration;
+
+use crate::{Error, Event, Result, Subscriber, Tree};
+
+/// A stateless encoding between a caller-owned type and the bytes
+/// stored in a tree.
+///
+/// Codecs are zero-sized types selected at the type level, so a
+/// `TypedTree` costs nothing to construct and cannot mix codecs
+/// between operations.
+pub trait Codec: Send + Sync + 'static {
+    /// The decoded type this codec produces and consumes.
+    type Item;
+
+    /// Encodes an item to the bytes stored in the tree.
+    fn encode(item: &Self::Item) -> Vec<u8>;
+
+    /// Decodes stored bytes back into an item.
+    fn decode(bytes: &[u8]) -> std::result::Result<Self::Item, DecodeError>;
+}
+
+/// An error produced by a [`Codec`] when stored bytes do not
+/// decode to the expected type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    /// A description of why the bytes could not be decoded.
+    pub message: String,
+}
+
+impl DecodeError {
+    /// Creates a new decode error with the given description.
+    pub fn new<M: Into<String>>(message: M) -> DecodeError {
+        DecodeError { message: message.into() }
+    }
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "decode error: {}", self.message)
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// A codec for `u64` stored as 8 big-endian bytes, so that the
+/// stored order matches numeric order.
+#[derive(Debug, Clone, Copy)]
+pub struct U64Codec;
+
+impl Codec for U64Codec {
+    type Item = u64;
+
+    fn encode(item: &u64) -> Vec<u8> {
+        item.to_be_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> std::result::Result<u64, DecodeError> {
+        if bytes.len() != 8 {
+            return Err(DecodeError::new(format!(
+                "expected 8 bytes for a u64, got {}",
+                bytes.len()
+            )));
+        }
+        let mut buf = [0; 8];
+        buf.copy_from_slice(bytes);
+        Ok(u64::from_be_bytes(buf))
+    }
+}
+
+/// A codec for `String` stored as UTF-8 bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct Utf8Codec;
+
+impl Codec for Utf8Codec {
+    type Item = String;
+
+    fn encode(item: &String) -> Vec<u8> {
+        item.as_bytes().to_vec()
+    }
+
+    fn decode(bytes: &[u8]) -> std::result::Result<String, DecodeError> {
+        String::from_utf8(bytes.to_vec())
+            .map_err(|e| DecodeError::new(e.to_string()))
+    }
+}
+
+/// A codec for any type implementing serde's `Serialize` and
+/// `Deserialize`, stored in the bincode wire format. Only
+/// available with the `codecs` feature.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// use serde::{Deserialize, Serialize};
+/// use sled::{BincodeCodec, U64Codec};
+///
+/// #[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// struct User {
+///     name: String,
+///     logins: u64,
+/// }
+///
+/// let users = db.typed::<U64Codec, BincodeCodec<User>>();
+///
+/// users.insert(&1, &User { name: "peggy".into(), logins: 0 })?;
+/// assert_eq!(users.get(&1)?.unwrap().name, "peggy");
+/// # Ok(()) }
+/// ```
+#[cfg(feature = "codecs")]
+#[derive(Debug, Clone, Copy)]
+pub struct BincodeCodec<T>(PhantomData<fn() -> T>);
+
+#[cfg(feature = "codecs")]
+impl<T> Codec for BincodeCodec<T>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned + 'static,
+{
+    type Item = T;
+
+    fn encode(item: &T) -> Vec<u8> {
+        bincode::serialize(item)
+            .expect("value must be serializable through bincode")
+    }
+
+    fn decode(bytes: &[u8]) -> std::result::Result<T, DecodeError> {
+        bincode::deserialize(bytes)
+            .map_err(|e| DecodeError::new(e.to_string()))
+    }
+}
+
+/// A typed view of a `Tree`, created via [`Tree::typed`], that
+/// encodes keys and values through the chosen codecs on every
+/// operation.
+///
+/// The view is free to construct and shares the underlying tree:
+/// raw and typed handles may be used side by side.
+pub struct TypedTree<KC, VC> {
+    tree: Tree,
+    marker: PhantomData<fn() -> (KC, VC)>,
+}
+
+impl<KC, VC> Clone for TypedTree<KC, VC> {
+    fn clone(&self) -> TypedTree<KC, VC> {
+        TypedTree { tree: self.tree.clone(), marker: PhantomData }
+    }
+}
+
+impl<KC, VC> std::fmt::Debug for TypedTree<KC, VC> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TypedTree {{ .. }}")
+    }
+}
+
+impl<KC: Codec, VC: Codec> TypedTree<KC, VC> {
+    fn decode_value(bytes: &[u8]) -> Result<VC::Item> {
+        VC::decode(bytes).map_err(|e| {
+            Error::Unsupported(format!(
+                "stored value did not decode through the \
+                 configured codec: {}",
+                e
+            ))
+        })
+    }
+
+    fn decode_key(bytes: &[u8]) -> Result<KC::Item> {
+        KC::decode(bytes).map_err(|e| {
+            Error::Unsupported(format!(
+                "stored key did not decode through the \
+                 configured codec: {}",
+                e
+            ))
+        })
+    }
+
+    /// Iterates over the decoded key-value pairs of the tree in
+    /// key order. Entries whose bytes do not decode through the
+    /// codecs yield `Error::Unsupported`.
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<Item = Result<(KC::Item, VC::Item)>> {
+        self.tree.iter().map(|kv| {
+            let (k, v) = kv?;
+            Ok((Self::decode_key(&k)?, Self::decode_value(&v)?))
+        })
+    }
+
+    /// Retrieves the decoded value for a key, if it exists.
+    /// Returns `Error::Unsupported` if the stored bytes do not
+    /// decode through the value codec.
+    pub fn get(&self, key: &KC::Item) -> Result<Option<VC::Item>> {
+        match self.tree.get(KC::encode(key))? {
+            Some(v) => Ok(Some(Self::decode_value(&v)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Encodes and inserts a key and value, returning the decoded
+    /// previous value if one was set.
+    pub fn insert(
+        &self,
+        key: &KC::Item,
+        value: &VC::Item,
+    ) -> Result<Option<VC::Item>> {
+        match self.tree.insert(KC::encode(key), VC::encode(value))? {
+            Some(old) => Ok(Some(Self::decode_value(&old)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Removes a key, returning the decoded previous value if one
+    /// was set.
+    pub fn remove(&self, key: &KC::Item) -> Result<Option<VC::Item>> {
+        match self.tree.remove(KC::encode(key))? {
+            Some(old) => Ok(Some(Self::decode_value(&old)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns `true` if there is a value for the specified key.
+    pub fn contains_key(&self, key: &KC::Item) -> Result<bool> {
+        self.tree.contains_key(KC::encode(key))
+    }
+
+    /// Subscribes to updates on keys whose *encoded* form has the
+    /// specified prefix, yielding events with keys and values
+    /// already decoded through this view's codecs.
+    ///
+    /// Decode errors are surfaced per field inside each event
+    /// rather than failing the subscription, so a single record
+    /// written outside the codec (or with an older encoding) does
+    /// not hide the other updates in a batch. Delivery guarantees
+    /// are those of [`Tree::watch_prefix`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use sled::{U64Codec, Utf8Codec};
+    ///
+    /// let names = db.typed::<U64Codec, Utf8Codec>();
+    /// let mut events = names.watch_prefix_typed(vec![]);
+    ///
+    /// names.insert(&7, &"kangaroo".to_string())?;
+    ///
+    /// // a raw write that does not fit the key codec
+    /// db.insert(vec![1, 2, 3], b"wombat".to_vec())?;
+    ///
+    /// let event = events.next().unwrap();
+    /// assert_eq!(event.updates[0].key, Ok(7));
+    /// assert_eq!(
+    ///     event.updates[0].value,
+    ///     Some(Ok("kangaroo".to_string())),
+    /// );
+    ///
+    /// let event = events.next().unwrap();
+    /// assert!(event.updates[0].key.is_err());
+    /// # Ok(()) }
+    /// ```
+    pub fn watch_prefix_typed<P: AsRef<[u8]>>(
+        &self,
+        prefix: P,
+    ) -> TypedSubscriber<KC, VC> {
+        TypedSubscriber {
+            inner: self.tree.watch_prefix(prefix),
+            tree: self.tree.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+/// A single decoded update within a [`TypedEvent`]. A `None`
+/// value is a removal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypedUpdate<K, V> {
+    /// The decoded key, or the error its bytes produced.
+    pub key: std::result::Result<K, DecodeError>,
+    /// The decoded value for an insert, `None` for a removal.
+    pub value: Option<std::result::Result<V, DecodeError>>,
+}
+
+/// An atomically applied group of decoded updates, yielded by
+/// [`TypedSubscriber`]. Updates from a single batch or
+/// transaction arrive in one event, as with raw [`Event`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypedEvent<K, V> {
+    /// The decoded updates that were applied together.
+    pub updates: Vec<TypedUpdate<K, V>>,
+}
+
+/// A subscriber yielding [`TypedEvent`]s with keys and values
+/// decoded through a [`TypedTree`]'s codecs, created via
+/// [`TypedTree::watch_prefix_typed`].
+pub struct TypedSubscriber<KC, VC> {
+    inner: Subscriber,
+    tree: Tree,
+    marker: PhantomData<fn() -> (KC, VC)>,
+}
+
+impl<KC, VC> std::fmt::Debug for TypedSubscriber<KC, VC> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "TypedSubscriber {{ .. }}")
+    }
+}
+
+impl<KC: Codec, VC: Codec> TypedSubscriber<KC, VC> {
+    fn decode_event(&self, event: &Event) -> TypedEvent<KC::Item, VC::Item> {
+        // a transactional event carries the batches of every tree
+        // it touched; only this view's tree decodes meaningfully
+        let updates = event
+            .iter()
+            .filter(|(tree, _, _)| tree.tree_id == self.tree.tree_id)
+            .map(|(_, k, v)| TypedUpdate {
+                key: KC::decode(k),
+                value: v.as_ref().map(|v| VC::decode(v)),
+            })
+            .collect();
+        TypedEvent { updates }
+    }
+
+    /// Attempts to wait for a value on this subscriber, returning
+    /// an error if no event arrives within the provided `Duration`
+    /// or if the backing `Db` shuts down.
+    pub fn next_timeout(
+        &self,
+        timeout: Duration,
+    ) -> std::result::Result<
+        TypedEvent<KC::Item, VC::Item>,
+        std::sync::mpsc::RecvTimeoutError,
+    > {
+        let event = self.inner.next_timeout(timeout)?;
+        Ok(self.decode_event(&event))
+    }
+}
+
+impl<KC: Codec, VC: Codec> Iterator for TypedSubscriber<KC, VC> {
+    type Item = TypedEvent<KC::Item, VC::Item>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.inner.next()?;
+        Some(self.decode_event(&event))
+    }
+}
+
+impl Tree {
+    /// Returns a typed view of this tree that encodes keys and
+    /// values through the given codecs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use sled::{U64Codec, Utf8Codec};
+    ///
+    /// let names = db.typed::<U64Codec, Utf8Codec>();
+    ///
+    /// names.insert(&1, &"tortoise".to_string())?;
+    /// assert_eq!(names.get(&1)?, Some("tortoise".to_string()));
+    /// assert_eq!(names.get(&2)?, None);
+    /// # Ok(()) }
+    /// ```
+    pub fn typed<KC: Codec, VC: Codec>(&self) -> TypedTree<KC, VC> {
+        TypedTree { tree: self.clone(), marker: PhantomData }
+    }
+}
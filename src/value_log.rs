@@ -0,0 +1,527 @@
+//! WiscKey-style key/value separation: values above a threshold
+//! live in an append-only value log, and the tree stores only a
+//! small pointer to them.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fs,
+    fs::File,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    pagecache::{pread_exact, pwrite_all},
+    Error, IVec, Result, Tree,
+};
+
+#[cfg(not(feature = "testing"))]
+const SEGMENT_SIZE: u64 = 8 * 1024 * 1024;
+
+#[cfg(feature = "testing")]
+const SEGMENT_SIZE: u64 = 4096;
+
+// index records are either the value itself or a pointer into
+// the value log
+const TAG_INLINE: u8 = 0;
+const TAG_POINTER: u8 = 1;
+
+// a pointer record is [tag][segment][value offset][value len]
+const POINTER_RECORD_LEN: usize = 1 + 8 + 8 + 8;
+
+// a log record is [crc][key len][value len][key][value], with
+// the crc covering everything after itself. the key is stored
+// alongside the value so that the GC pass can check an entry's
+// liveness against the index without any auxiliary state.
+const RECORD_HEADER_LEN: usize = 4 + 8 + 8;
+
+/// Statistics returned by [`ValueLogTree::gc`] describing how
+/// much space a garbage collection pass reclaimed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValueLogGcStats {
+    /// the number of sealed value log segment files that were
+    /// removed
+    pub segments_removed: u64,
+    /// the number of live entries that were relocated to the
+    /// active segment
+    pub entries_relocated: u64,
+    /// the number of file bytes released back to the filesystem
+    pub bytes_reclaimed: u64,
+}
+
+/// A tree for blob-heavy workloads, opened via
+/// `Db::open_value_log_tree`, which stores values at or above a
+/// configurable threshold in an append-only value log beside the
+/// database rather than in tree nodes.
+///
+/// Tree nodes then hold a fixed-size pointer for each large
+/// value, so node splits, merges, and rewrites stop copying
+/// multi-megabyte blobs and write amplification drops for
+/// blob-heavy workloads. Values below the threshold are stored
+/// inline as usual.
+///
+/// Overwritten and removed values leave garbage behind in the
+/// log, which is reclaimed by calling [`gc`](ValueLogTree::gc):
+/// live entries in sealed segments are relocated to the tail of
+/// the log and the emptied segment files are deleted.
+///
+/// Log appends are synced before the pointer becomes visible in
+/// the tree, so a recovered pointer never refers to data that
+/// did not reach disk.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// let tree = db.open_value_log_tree("artifacts", 1024)?;
+///
+/// // small values are stored inline in tree nodes
+/// tree.insert(b"name", b"blobby")?;
+///
+/// // large values go to the value log
+/// let blob = vec![7; 1024 * 1024];
+/// tree.insert(b"blob", blob.clone())?;
+/// assert_eq!(&tree.get(b"blob")?.unwrap(), &blob);
+///
+/// // overwriting leaves garbage in the log until the next gc
+/// tree.insert(b"blob", vec![8; 1024 * 1024])?;
+/// let stats = tree.gc()?;
+/// assert_eq!(&tree.get(b"blob")?.unwrap(), &vec![8; 1024 * 1024]);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ValueLogTree {
+    pub(crate) tree: Tree,
+    log: Arc<ValueLog>,
+    threshold: usize,
+}
+
+impl ValueLogTree {
+    pub(crate) fn open(
+        tree: Tree,
+        directory: PathBuf,
+        threshold: usize,
+    ) -> Result<ValueLogTree> {
+        let log = Arc::new(ValueLog::open(directory)?);
+        Ok(ValueLogTree { tree, log, threshold })
+    }
+
+    /// Sets the key to a new value, returning the last value if
+    /// it was set.
+    pub fn insert<K, V>(&self, key: K, value: V) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: Into<IVec>,
+    {
+        let key = key.as_ref();
+        let value = value.into();
+
+        let record: IVec = if value.len() >= self.threshold {
+            let pointer = self.log.append(key, &value)?;
+            encode_pointer(pointer).into()
+        } else {
+            let mut buf = Vec::with_capacity(1 + value.len());
+            buf.push(TAG_INLINE);
+            buf.extend_from_slice(&value);
+            buf.into()
+        };
+
+        loop {
+            let old = self.tree.get(key)?;
+            let last = self.materialize(old.clone())?;
+            if self
+                .tree
+                .compare_and_swap(key, old.as_ref(), Some(record.clone()))?
+                .is_ok()
+            {
+                return Ok(last);
+            }
+        }
+    }
+
+    /// Retrieves a value if it exists.
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        let record = self.tree.get(key)?;
+        self.materialize(record)
+    }
+
+    /// Deletes a value, returning the old value if it existed.
+    pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        let key = key.as_ref();
+        loop {
+            let old = match self.tree.get(key)? {
+                Some(old) => old,
+                None => return Ok(None),
+            };
+            let last = self.materialize(Some(old.clone()))?;
+            if self
+                .tree
+                .compare_and_swap(key, Some(&old), None::<&[u8]>)?
+                .is_ok()
+            {
+                return Ok(last);
+            }
+        }
+    }
+
+    /// Returns `true` if the tree contains a value for the
+    /// specified key, without reading the value log.
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> Result<bool> {
+        self.tree.get(key).map(|record| record.is_some())
+    }
+
+    /// Garbage-collects the value log by scanning sealed
+    /// segments, relocating entries that the index still points
+    /// at to the tail of the log, and deleting the emptied
+    /// segment files. Returns statistics about the space
+    /// reclaimed.
+    ///
+    /// Entries made garbage while a pass is running are left for
+    /// the next pass.
+    pub fn gc(&self) -> Result<ValueLogGcStats> {
+        let mut stats = ValueLogGcStats::default();
+
+        for segment in self.log.sealed_segments()? {
+            let buf = self.log.read_segment(segment)?;
+            let mut offset = 0;
+            let mut scan_failed = false;
+
+            while offset + RECORD_HEADER_LEN <= buf.len() {
+                let (key, value, value_offset, record_len) =
+                    match decode_record(&buf, offset) {
+                        Some(decoded) => decoded,
+                        None => {
+                            // segments are sealed on an exact
+                            // record boundary, so a short or
+                            // mismatched record here indicates
+                            // corruption
+                            scan_failed = true;
+                            break;
+                        }
+                    };
+
+                let old_record =
+                    encode_pointer((segment, value_offset, value.len()));
+
+                if self.tree.get(key)?.map_or(false, |current| {
+                    *current == old_record[..]
+                }) {
+                    // still live: move it to the tail of the log
+                    // and swing the index pointer over. a failed
+                    // swap means a writer got there first, and
+                    // the relocated copy simply becomes garbage
+                    // for a future pass.
+                    let pointer = self.log.append(key, value)?;
+                    let new_record = encode_pointer(pointer);
+                    let _ = self.tree.compare_and_swap(
+                        key,
+                        Some(&old_record[..]),
+                        Some(new_record),
+                    )?;
+                    stats.entries_relocated += 1;
+                }
+
+                offset += record_len;
+            }
+
+            if scan_failed {
+                // leave a corrupt segment in place rather than
+                // destroying evidence and any entries after the
+                // damage
+                log::error!(
+                    "corrupt record encountered while garbage \
+                     collecting value log segment {}, skipping it",
+                    segment
+                );
+                continue;
+            }
+
+            stats.bytes_reclaimed += u64::try_from(buf.len()).unwrap();
+            stats.segments_removed += 1;
+            self.log.remove_segment(segment)?;
+        }
+
+        Ok(stats)
+    }
+
+    /// Blocks until all tree updates have been written to disk.
+    /// Value log appends are synced as they happen, so this only
+    /// needs to flush the index.
+    pub fn flush(&self) -> Result<usize> {
+        self.tree.flush()
+    }
+
+    fn materialize(&self, record: Option<IVec>) -> Result<Option<IVec>> {
+        let record = match record {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        match record.first() {
+            Some(&TAG_INLINE) => {
+                Ok(Some(record.subslice(1, record.len() - 1)))
+            }
+            Some(&TAG_POINTER) => {
+                let (segment, offset, len) = decode_pointer(&record)?;
+                self.log.read(segment, offset, len).map(Some)
+            }
+            _ => Err(Error::corruption(None)),
+        }
+    }
+}
+
+fn encode_pointer(
+    (segment, offset, len): (u64, u64, usize),
+) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(POINTER_RECORD_LEN);
+    buf.push(TAG_POINTER);
+    buf.extend_from_slice(&segment.to_be_bytes());
+    buf.extend_from_slice(&offset.to_be_bytes());
+    buf.extend_from_slice(&u64::try_from(len).unwrap().to_be_bytes());
+    buf
+}
+
+fn decode_pointer(record: &[u8]) -> Result<(u64, u64, usize)> {
+    if record.len() != POINTER_RECORD_LEN {
+        return Err(Error::corruption(None));
+    }
+    let read_u64 = |at: usize| {
+        let mut arr = [0; 8];
+        arr.copy_from_slice(&record[at..at + 8]);
+        u64::from_be_bytes(arr)
+    };
+    Ok((
+        read_u64(1),
+        read_u64(9),
+        usize::try_from(read_u64(17)).unwrap(),
+    ))
+}
+
+// decodes the record starting at `offset`, returning the key,
+// value, absolute offset of the value bytes, and total record
+// length, or `None` if the record is short or fails its crc
+fn decode_record(
+    buf: &[u8],
+    offset: usize,
+) -> Option<(&[u8], &[u8], u64, usize)> {
+    let header = &buf[offset..offset + RECORD_HEADER_LEN];
+
+    let read_u64 = |at: usize| {
+        let mut arr = [0; 8];
+        arr.copy_from_slice(&header[at..at + 8]);
+        u64::from_be_bytes(arr)
+    };
+
+    let stored_crc =
+        u32::from_be_bytes(header[..4].try_into().ok()?);
+    let key_len = usize::try_from(read_u64(4)).ok()?;
+    let value_len = usize::try_from(read_u64(12)).ok()?;
+
+    let record_len = RECORD_HEADER_LEN
+        .checked_add(key_len)?
+        .checked_add(value_len)?;
+    if offset.checked_add(record_len)? > buf.len() {
+        return None;
+    }
+
+    let covered = &buf[offset + 4..offset + record_len];
+    if crate::crc32(covered) != stored_crc {
+        return None;
+    }
+
+    let key_at = offset + RECORD_HEADER_LEN;
+    let value_at = key_at + key_len;
+
+    Some((
+        &buf[key_at..value_at],
+        &buf[value_at..value_at + value_len],
+        u64::try_from(value_at).unwrap(),
+        record_len,
+    ))
+}
+
+fn encode_record(key: &[u8], value: &[u8]) -> Vec<u8> {
+    let mut buf =
+        Vec::with_capacity(RECORD_HEADER_LEN + key.len() + value.len());
+    buf.extend_from_slice(&[0; 4]);
+    buf.extend_from_slice(
+        &u64::try_from(key.len()).unwrap().to_be_bytes(),
+    );
+    buf.extend_from_slice(
+        &u64::try_from(value.len()).unwrap().to_be_bytes(),
+    );
+    buf.extend_from_slice(key);
+    buf.extend_from_slice(value);
+    let crc = crate::crc32(&buf[4..]);
+    buf[..4].copy_from_slice(&crc.to_be_bytes());
+    buf
+}
+
+#[derive(Debug)]
+struct ValueLog {
+    directory: PathBuf,
+    active: Mutex<ActiveSegment>,
+}
+
+#[derive(Debug)]
+struct ActiveSegment {
+    id: u64,
+    file: File,
+    offset: u64,
+}
+
+impl ValueLog {
+    fn open(directory: PathBuf) -> Result<ValueLog> {
+        if !directory.exists() {
+            fs::create_dir_all(&directory)?;
+        }
+
+        let mut max_segment: Option<u64> = None;
+        for entry in fs::read_dir(&directory)? {
+            let entry = entry?;
+            if let Some(id) = segment_id_from_name(&entry.file_name()) {
+                max_segment =
+                    Some(max_segment.map_or(id, |max| max.max(id)));
+            }
+        }
+
+        let id = max_segment.unwrap_or(0);
+        let path = directory.join(segment_name(id));
+
+        let mut options = fs::OpenOptions::new();
+        let _ = options.create(true);
+        let _ = options.read(true);
+        let _ = options.write(true);
+        let file = options.open(&path)?;
+
+        // a crash can leave a torn record at the tail of the
+        // active segment. records behind torn writes were never
+        // pointed at by the index, so truncating at the first
+        // invalid record loses nothing and keeps the segment
+        // scannable for gc.
+        let buf = {
+            let len = usize::try_from(file.metadata()?.len()).unwrap();
+            let mut buf = vec![0; len];
+            pread_exact(&file, &mut buf, 0)?;
+            buf
+        };
+        let mut offset = 0;
+        while offset + RECORD_HEADER_LEN <= buf.len() {
+            match decode_record(&buf, offset) {
+                Some((_, _, _, record_len)) => offset += record_len,
+                None => break,
+            }
+        }
+        if u64::try_from(offset).unwrap() != file.metadata()?.len() {
+            file.set_len(u64::try_from(offset).unwrap())?;
+            file.sync_all()?;
+        }
+
+        let active = ActiveSegment {
+            id,
+            file,
+            offset: u64::try_from(offset).unwrap(),
+        };
+
+        Ok(ValueLog { directory, active: Mutex::new(active) })
+    }
+
+    // appends a record and syncs it, returning the segment id,
+    // absolute offset of the value bytes, and value length
+    fn append(&self, key: &[u8], value: &[u8]) -> Result<(u64, u64, usize)> {
+        let record = encode_record(key, value);
+
+        let mut active = self.active.lock();
+
+        if active.offset >= SEGMENT_SIZE {
+            // seal the current segment and roll to the next one
+            active.file.sync_all()?;
+
+            let id = active.id + 1;
+            let mut options = fs::OpenOptions::new();
+            let _ = options.create_new(true);
+            let _ = options.read(true);
+            let _ = options.write(true);
+            let file =
+                options.open(self.directory.join(segment_name(id)))?;
+
+            *active = ActiveSegment { id, file, offset: 0 };
+        }
+
+        let record_offset = active.offset;
+        pwrite_all(&active.file, &record, record_offset)?;
+
+        // the pointer only becomes visible in the index after
+        // this sync, so recovery can never observe a pointer to
+        // unwritten data
+        active.file.sync_data()?;
+
+        active.offset += u64::try_from(record.len()).unwrap();
+
+        let value_offset = record_offset
+            + u64::try_from(RECORD_HEADER_LEN + key.len()).unwrap();
+
+        Ok((active.id, value_offset, value.len()))
+    }
+
+    fn read(&self, segment: u64, offset: u64, len: usize) -> Result<IVec> {
+        let mut buf = vec![0; len];
+
+        let active = self.active.lock();
+        if active.id == segment {
+            pread_exact(&active.file, &mut buf, offset)?;
+        } else {
+            drop(active);
+            let file =
+                File::open(self.directory.join(segment_name(segment)))?;
+            pread_exact(&file, &mut buf, offset)?;
+        }
+
+        Ok(buf.into())
+    }
+
+    // returns the ids of all segments other than the active one,
+    // in ascending order
+    fn sealed_segments(&self) -> Result<Vec<u64>> {
+        let active_id = self.active.lock().id;
+        let mut segments = vec![];
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            if let Some(id) = segment_id_from_name(&entry.file_name()) {
+                if id != active_id {
+                    segments.push(id);
+                }
+            }
+        }
+        segments.sort_unstable();
+        Ok(segments)
+    }
+
+    fn read_segment(&self, segment: u64) -> Result<Vec<u8>> {
+        let file = File::open(self.directory.join(segment_name(segment)))?;
+        let len = usize::try_from(file.metadata()?.len()).unwrap();
+        let mut buf = vec![0; len];
+        pread_exact(&file, &mut buf, 0)?;
+        Ok(buf)
+    }
+
+    fn remove_segment(&self, segment: u64) -> Result<()> {
+        fs::remove_file(self.directory.join(segment_name(segment)))?;
+        Ok(())
+    }
+}
+
+fn segment_name(id: u64) -> String {
+    format!("{:016x}", id)
+}
+
+fn segment_id_from_name(name: &std::ffi::OsStr) -> Option<u64> {
+    let name = name.to_str()?;
+    if name.len() != 16 {
+        return None;
+    }
+    u64::from_str_radix(name, 16).ok()
+}
@@ -0,0 +1,186 @@
+use std::convert::TryFrom;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::{AtomicBool, Ordering::SeqCst};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use parking_lot::{Condvar, Mutex};
+
+use super::flusher::ShutdownState;
+use super::*;
+
+/// A callback invoked with a human-readable reason when the
+/// watchdog detects that a background thread has stalled, set via
+/// `Db::set_stall_callback`.
+pub type StallCallback = Box<dyn Fn(&str) + Send>;
+
+/// Shared storage for the optional stall callback.
+#[derive(Default)]
+pub(crate) struct CallbackSlot {
+    pub(crate) callback: Mutex<Option<StallCallback>>,
+}
+
+impl Debug for CallbackSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CallbackSlot {{ .. }}")
+    }
+}
+
+/// A background thread that is expected to make progress at a
+/// known cadence, watched via a timestamp it refreshes on every
+/// pass.
+pub(crate) struct WatchedThread {
+    pub(crate) name: &'static str,
+    /// Milliseconds since the unix epoch of the thread's last
+    /// pass, or 0 if it has not started yet.
+    pub(crate) last_progress: Arc<AtomicU64>,
+    /// The expected milliseconds between passes.
+    pub(crate) cadence: u64,
+    /// Set while the thread is considered stalled, read by
+    /// `Db::health`.
+    pub(crate) stalled: Arc<AtomicBool>,
+}
+
+/// A background thread that detects when the periodic flusher or
+/// scrubber has made no progress for a configurable period beyond
+/// its expected cadence - whether due to deadlock, a panicked
+/// thread, or IO that never returns - and surfaces it through
+/// `Db::health` and an optional callback, instead of the database
+/// silently ceasing to persist writes.
+#[derive(Debug)]
+pub(crate) struct Watchdog {
+    shutdown: Arc<Mutex<ShutdownState>>,
+    sc: Arc<Condvar>,
+    join_handle: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl Watchdog {
+    /// Spawns a thread that checks the watched threads for
+    /// progress until dropped, considering one stalled when it has
+    /// made no progress for `stall_threshold_ms` beyond its
+    /// expected cadence.
+    pub(crate) fn new(
+        name: String,
+        watched: Vec<WatchedThread>,
+        stall_callback: Arc<CallbackSlot>,
+        stall_threshold_ms: u64,
+        panic_handler: poison::PanicHandler,
+    ) -> Self {
+        #[allow(clippy::mutex_atomic)] // mutex used in CondVar below
+        let shutdown = Arc::new(Mutex::new(ShutdownState::Running));
+        let sc = Arc::new(Condvar::new());
+
+        let join_handle = thread::Builder::new()
+            .name(name.clone())
+            .spawn({
+                let shutdown = shutdown.clone();
+                let sc = sc.clone();
+                let hook_name = name.clone();
+                move || {
+                    thread_lifecycle::thread_spawned(&hook_name);
+                    let result =
+                        std::panic::catch_unwind(AssertUnwindSafe(|| {
+                            run(
+                                &shutdown,
+                                &sc,
+                                &watched,
+                                &stall_callback,
+                                stall_threshold_ms,
+                            )
+                        }));
+                    if let Err(panic) = result {
+                        panic_handler.handle(panic);
+
+                        // unblock any `Drop` handshake waiting for
+                        // the thread to shut down.
+                        *shutdown.lock() = ShutdownState::ShutDown;
+                        let _notified = sc.notify_all();
+                    }
+                    thread_lifecycle::thread_exiting(&hook_name);
+                }
+            })
+            .unwrap();
+
+        Self { shutdown, sc, join_handle: Mutex::new(Some(join_handle)) }
+    }
+}
+
+fn run(
+    shutdown: &Arc<Mutex<ShutdownState>>,
+    sc: &Arc<Condvar>,
+    watched: &[WatchedThread],
+    stall_callback: &Arc<CallbackSlot>,
+    stall_threshold_ms: u64,
+) {
+    let interval = Duration::from_millis((stall_threshold_ms / 2).max(10));
+    let mut shutdown = shutdown.lock();
+    while shutdown.is_running() {
+        sc.wait_for(&mut shutdown, interval);
+        if !shutdown.is_running() {
+            break;
+        }
+
+        for thread in watched {
+            let last = thread.last_progress.load(SeqCst);
+            if last == 0 {
+                // the thread has not started yet
+                continue;
+            }
+            let deadline = last
+                .saturating_add(thread.cadence)
+                .saturating_add(stall_threshold_ms);
+            if now_millis() > deadline {
+                if !thread.stalled.swap(true, SeqCst) {
+                    let reason = format!(
+                        "the background {} has made no progress for \
+                         longer than the configured stall threshold",
+                        thread.name
+                    );
+                    error!("{}", reason);
+                    if let Some(callback) = &*stall_callback.callback.lock()
+                    {
+                        callback(&reason);
+                    }
+                }
+            } else {
+                thread.stalled.store(false, SeqCst);
+            }
+        }
+    }
+
+    *shutdown = ShutdownState::ShutDown;
+
+    // having held the mutex makes this linearized
+    // with the notify below.
+    drop(shutdown);
+
+    let _notified = sc.notify_all();
+}
+
+pub(crate) fn now_millis() -> u64 {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is set before the unix epoch");
+    u64::try_from(since_epoch.as_millis()).unwrap_or(u64::max_value())
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        let mut shutdown = self.shutdown.lock();
+        if shutdown.is_running() {
+            *shutdown = ShutdownState::ShuttingDown;
+            let _notified = self.sc.notify_all();
+        }
+
+        while !shutdown.is_shutdown() {
+            let _ = self.sc.wait_for(&mut shutdown, Duration::from_millis(100));
+        }
+
+        let mut join_handle_opt = self.join_handle.lock();
+        if let Some(join_handle) = join_handle_opt.take() {
+            if let Err(e) = join_handle.join() {
+                error!("error joining watchdog thread: {:?}", e);
+            }
+        }
+    }
+}
@@ -620,7 +620,7 @@ fn tree_flush_in_transaction() {
 }
 
 #[test]
-fn incorrect_multiple_db_transactions() -> TransactionResult<()> {
+fn multiple_db_transactions() -> TransactionResult<()> {
     common::setup_logger();
 
     let db1 =
@@ -628,10 +628,21 @@ fn incorrect_multiple_db_transactions() -> TransactionResult<()> {
     let db2 =
         Config::new().temporary(true).flush_every_ms(Some(1)).open().unwrap();
 
-    let result: TransactionResult<()> =
-        (&*db1, &*db2).transaction::<_, ()>(|_| Ok(()));
+    (&*db1, &*db2).transaction(|(t1, t2)| {
+        t1.insert(b"k1", b"cats")?;
+        t2.insert(b"k2", b"dogs")?;
+        Ok(())
+    })?;
+
+    assert_eq!(&db1.get(b"k1").unwrap().unwrap(), b"cats");
+    assert_eq!(db1.get(b"k2").unwrap(), None);
+    assert_eq!(&db2.get(b"k2").unwrap().unwrap(), b"dogs");
+    assert_eq!(db2.get(b"k1").unwrap(), None);
 
-    assert!(result.is_err());
+    // a clean commit leaves no pending coordination records for
+    // crash recovery to worry about.
+    assert!(db1.pending_coordination_ids()?.is_empty());
+    assert!(db2.pending_coordination_ids()?.is_empty());
 
     Ok(())
 }